@@ -0,0 +1,37 @@
+#!/usr/bin/env bash
+# Generate TypeScript and Python client stubs from the service's OpenAPI document.
+#
+# Usage: scripts/generate-clients.sh [out-dir] [spec-url]
+#
+# Without a spec URL the document is taken from the current source tree via
+# `ingestion-service openapi`; pass e.g. http://localhost:3000/openapi.json to
+# generate against a running deployment instead. Requires Docker.
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+out="${1:-clients}"
+spec_url="${2:-}"
+generator_image="openapitools/openapi-generator-cli:v7.10.0"
+
+mkdir -p "$out"
+
+if [ -n "$spec_url" ]; then
+    curl -fsSL "$spec_url" -o "$out/openapi.json"
+else
+    cargo run --quiet -- openapi > "$out/openapi.json"
+fi
+
+generate() {
+    local generator="$1" dir="$2" props="$3"
+    docker run --rm -u "$(id -u):$(id -g)" -v "$PWD/$out:/local" "$generator_image" generate \
+        -i /local/openapi.json \
+        -g "$generator" \
+        -o "/local/$dir" \
+        --additional-properties "$props"
+}
+
+generate typescript-fetch typescript "npmName=@chimera/ingestion-client,supportsES6=true"
+generate python python "packageName=chimera_ingestion_client,projectName=chimera-ingestion-client"
+
+echo "Clients written to $out/typescript and $out/python"
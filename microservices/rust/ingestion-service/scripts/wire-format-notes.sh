@@ -0,0 +1,33 @@
+#!/usr/bin/env bash
+# Draft migration notes for wire format snapshots that changed since a git ref.
+#
+# Usage: scripts/wire-format-notes.sh [base-ref]   (defaults to origin/main)
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+base="${1:-origin/main}"
+snapshots="src/snapshots"
+
+changed=$(git diff --name-only --relative "$base" -- "$snapshots")
+
+if [ -z "$changed" ]; then
+    echo "No wire format changes since $base."
+    exit 0
+fi
+
+echo "## $(date -u +%Y-%m-%d): <summary of the change>"
+echo
+echo "<Why the shape changed and what consumers must do.>"
+echo
+
+for file in $changed; do
+    name=$(basename "$file" .snap)
+    name=${name#ingestion_service__wire_format__}
+    echo "### \`$name\`"
+    echo
+    echo '```diff'
+    git diff "$base" -- "$file" | sed -n '/^@@/,$p'
+    echo '```'
+    echo
+done
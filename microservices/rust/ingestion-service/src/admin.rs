@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Query, Request},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{info, instrument, warn};
+
+use crate::config::AppConfig;
+use crate::error::{AppError, Result};
+use crate::flow::{FlowControl, PauseScope};
+use crate::pipeline::Pipeline;
+use crate::sources::{ManifestFormat, SourceManifest, SourceRegistry};
+
+/// Require the configured admin bearer token on admin routes
+pub async fn require_admin(
+    Extension(config): Extension<Arc<AppConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    let authorized = match (&config.admin_token, provided) {
+        (Some(expected), Some(provided)) => expected.matches(provided),
+        _ => false,
+    };
+
+    if !authorized {
+        warn!(
+            "Rejected unauthorized admin request to {}",
+            request.uri().path()
+        );
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({
+                "error": {
+                    "message": "Admin token required",
+                    "code": StatusCode::UNAUTHORIZED.as_u16()
+                }
+            })),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Query parameters for manifest import
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+    /// `merge` (default) adds to and updates existing sources, `replace` swaps the whole set
+    #[serde(default)]
+    pub mode: Option<String>,
+}
+
+/// Import a source manifest in JSON or YAML
+#[instrument(skip_all)]
+pub async fn import_sources(
+    Extension(registry): Extension<Arc<SourceRegistry>>,
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Query(params): Query<ImportParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<serde_json::Value>> {
+    let replace = match params.mode.as_deref() {
+        None | Some("merge") => false,
+        Some("replace") => true,
+        Some(other) => {
+            return Err(AppError::ValidationError(format!(
+                "Unknown import mode: {}",
+                other
+            )));
+        }
+    };
+
+    let format = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(ManifestFormat::Json, ManifestFormat::from_mime);
+
+    let manifest = SourceManifest::parse(&body, format)?;
+    if let Some(schema_registry) = pipeline.schema_registry() {
+        schema_registry.check_manifest(&manifest).await?;
+    }
+    let imported = registry.import(manifest, replace)?;
+    let total = registry.export().sources.len();
+
+    info!(
+        "Source manifest imported: {} sources, {} registered",
+        imported, total
+    );
+
+    Ok(Json(json!({
+        "status": "success",
+        "imported": imported,
+        "total": total,
+    })))
+}
+
+/// Query parameters for manifest export
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    /// `json` (default) or `yaml`
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// Export all registered sources as a manifest
+#[instrument(skip_all)]
+pub async fn export_sources(
+    Extension(registry): Extension<Arc<SourceRegistry>>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response> {
+    let format = params
+        .format
+        .as_deref()
+        .map_or(ManifestFormat::Json, ManifestFormat::from_mime);
+
+    let body = registry.export().render(format)?;
+
+    Ok(([(header::CONTENT_TYPE, format.mime())], body).into_response())
+}
+
+/// Request body for pausing or resuming ingestion; exactly one of `source` and `content_type`
+#[derive(Debug, Deserialize)]
+pub struct PauseRequest {
+    #[serde(default)]
+    pub source: Option<String>,
+
+    #[serde(default)]
+    pub content_type: Option<String>,
+
+    /// Explanation returned to affected producers
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl PauseRequest {
+    fn scope(&self) -> Result<PauseScope> {
+        match (&self.source, &self.content_type) {
+            (Some(source), None) => Ok(PauseScope::Source(source.clone())),
+            (None, Some(content_type)) => Ok(PauseScope::ContentType(content_type.clone())),
+            _ => Err(AppError::ValidationError(
+                "Exactly one of source or content_type must be given".to_string(),
+            )),
+        }
+    }
+}
+
+/// Pause ingestion for a source or content type
+#[instrument(skip_all)]
+pub async fn pause_ingestion(
+    Extension(flow): Extension<Arc<FlowControl>>,
+    Json(request): Json<PauseRequest>,
+) -> Result<Json<serde_json::Value>> {
+    let pause = flow.pause(request.scope()?, request.reason);
+
+    Ok(Json(json!({
+        "status": "paused",
+        "pause": pause,
+    })))
+}
+
+/// Resume ingestion for a source or content type
+#[instrument(skip_all)]
+pub async fn resume_ingestion(
+    Extension(flow): Extension<Arc<FlowControl>>,
+    Json(request): Json<PauseRequest>,
+) -> Result<Json<serde_json::Value>> {
+    let scope = request.scope()?;
+
+    if !flow.resume(&scope) {
+        return Err(AppError::ValidationError(format!(
+            "Ingestion for {} is not paused",
+            scope
+        )));
+    }
+
+    Ok(Json(json!({
+        "status": "resumed",
+        "scope": scope,
+    })))
+}
+
+/// List active pauses
+pub async fn list_pauses(Extension(flow): Extension<Arc<FlowControl>>) -> Json<serde_json::Value> {
+    Json(json!({ "pauses": flow.pauses() }))
+}
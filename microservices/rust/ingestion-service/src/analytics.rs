@@ -0,0 +1,195 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::config::Secret;
+use crate::error::{AppError, Result};
+use crate::http::{self, ProxyConfig};
+use crate::models::RawData;
+use crate::readiness::Dependency;
+use crate::stats::Outcome;
+
+/// Settings for the ClickHouse analytics sink
+#[derive(Debug, Clone)]
+pub struct AnalyticsConfig {
+    /// ClickHouse HTTP interface, e.g. `http://clickhouse:8123`
+    pub url: String,
+
+    /// Table rows are inserted into, optionally database-qualified
+    pub table: String,
+
+    /// ClickHouse user
+    pub user: Option<String>,
+
+    /// ClickHouse password
+    pub password: Option<Secret>,
+
+    /// Most rows per insert
+    pub batch_size: usize,
+
+    /// Longest a row waits before its batch is inserted
+    pub flush_interval: Duration,
+
+    /// Rows buffered for inserting before new ones are dropped
+    pub queue_capacity: usize,
+}
+
+/// Metadata about one processed item; payloads are never written
+#[derive(Debug, Serialize)]
+struct MetadataRow {
+    id: Uuid,
+    timestamp: DateTime<Utc>,
+    ingested_at: DateTime<Utc>,
+    source: String,
+    content_type: String,
+    payload_bytes: u64,
+    processing_ms: f64,
+    outcome: &'static str,
+    reason: Option<String>,
+}
+
+/// Writes per-item metadata rows to ClickHouse in batches.
+///
+/// Analytics are best effort: rows are dropped when the queue is full or an insert fails,
+/// so a slow or unavailable ClickHouse never holds up ingestion.
+pub struct AnalyticsSink {
+    tx: mpsc::Sender<MetadataRow>,
+}
+
+impl AnalyticsSink {
+    /// Create the sink and spawn its background insert task
+    pub fn spawn(
+        config: AnalyticsConfig,
+        proxy: &ProxyConfig,
+        clickhouse: Arc<Dependency>,
+    ) -> Result<Self> {
+        let http = http::client_builder(proxy)?
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| {
+                AppError::InternalError(format!("Failed to create ClickHouse client: {}", e))
+            })?;
+
+        let (tx, rx) = mpsc::channel(config.queue_capacity.max(1));
+        tokio::spawn(insert_batches(config, http, rx, clickhouse));
+
+        Ok(Self { tx })
+    }
+
+    /// Queue a metadata row for an item's outcome
+    pub fn record(
+        &self,
+        item: &RawData,
+        outcome: Outcome,
+        elapsed: Duration,
+        reason: Option<&AppError>,
+    ) {
+        let row = MetadataRow {
+            id: item.id,
+            timestamp: item.timestamp,
+            ingested_at: Utc::now(),
+            source: item.source.clone(),
+            content_type: item.content_type.clone(),
+            payload_bytes: serde_json::to_vec(&item.payload).map_or(0, |bytes| bytes.len() as u64),
+            processing_ms: elapsed.as_secs_f64() * 1000.0,
+            outcome: outcome.as_str(),
+            reason: reason.map(|e| e.to_string()),
+        };
+
+        if self.tx.try_send(row).is_err() {
+            debug!("Analytics queue full, dropping row for item {}", item.id);
+        }
+    }
+}
+
+async fn insert_batches(
+    config: AnalyticsConfig,
+    http: reqwest::Client,
+    mut rx: mpsc::Receiver<MetadataRow>,
+    clickhouse: Arc<Dependency>,
+) {
+    let query = format!("INSERT INTO {} FORMAT JSONEachRow", config.table);
+    let mut batch = Vec::with_capacity(config.batch_size);
+    let mut deadline = Instant::now() + config.flush_interval;
+
+    loop {
+        let closed = tokio::select! {
+            row = rx.recv() => match row {
+                Some(row) => {
+                    if batch.is_empty() {
+                        deadline = Instant::now() + config.flush_interval;
+                    }
+                    batch.push(row);
+                    if batch.len() < config.batch_size {
+                        continue;
+                    }
+                    false
+                }
+                None => true,
+            },
+            _ = tokio::time::sleep_until(deadline), if !batch.is_empty() => false,
+        };
+
+        if !batch.is_empty() {
+            let rows = std::mem::take(&mut batch);
+            let result = insert(&config, &http, &query, &rows).await;
+            clickhouse.record(&result);
+            if let Err(e) = result {
+                warn!("Dropped {} analytics rows: {}", rows.len(), e);
+            }
+        }
+
+        if closed {
+            return;
+        }
+    }
+}
+
+async fn insert(
+    config: &AnalyticsConfig,
+    http: &reqwest::Client,
+    query: &str,
+    rows: &[MetadataRow],
+) -> Result<()> {
+    let mut body = Vec::new();
+    for row in rows {
+        serde_json::to_writer(&mut body, row)
+            .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))?;
+        body.push(b'\n');
+    }
+
+    let mut request = http
+        .post(&config.url)
+        // RFC 3339 timestamps need best-effort parsing
+        .query(&[("query", query), ("date_time_input_format", "best_effort")])
+        .body(body);
+    if let Some(user) = &config.user {
+        request = request.header("X-ClickHouse-User", user);
+    }
+    if let Some(password) = &config.password {
+        request = request.header("X-ClickHouse-Key", password.expose());
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| AppError::FetchError(format!("ClickHouse insert failed: {}", e)))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let detail = response.text().await.unwrap_or_default();
+        return Err(AppError::FetchError(format!(
+            "ClickHouse insert returned {}: {}",
+            status,
+            detail.trim()
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::error::{AppError, Result};
+
+/// Settings for the audit log
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// JSON lines file entries are appended to
+    pub path: PathBuf,
+}
+
+/// An action taken through the service that operators may need to account for later
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+
+    /// What was done, e.g. `retract`
+    pub action: String,
+
+    /// Who asked for it, as stated in the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+
+    /// Action-specific details
+    pub details: serde_json::Value,
+}
+
+/// Append-only record of operator actions.
+///
+/// Entries are written as JSON lines and synced to disk before the action is reported as
+/// done. Without a file, entries are only logged.
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    /// Open the audit log, creating the file if needed
+    pub async fn open(config: Option<AuditConfig>) -> Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self { file: None });
+        };
+
+        if let Some(dir) = config
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            tokio::fs::create_dir_all(dir).await.map_err(audit_error)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)
+            .await
+            .map_err(audit_error)?;
+
+        info!("Writing audit log to {}", config.path.display());
+
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+        })
+    }
+
+    /// Record an entry
+    pub async fn record(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry).map_err(audit_error)?;
+        line.push(b'\n');
+
+        info!(target: "audit", action = %entry.action, actor = ?entry.actor, details = %entry.details, "Audit entry");
+
+        if let Some(file) = &self.file {
+            let mut file = file.lock().await;
+            file.write_all(&line).await.map_err(audit_error)?;
+            file.sync_data().await.map_err(audit_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn audit_error(e: impl std::fmt::Display) -> AppError {
+    AppError::InternalError(format!("Audit log error: {}", e))
+}
@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use axum::{extract::Request, middleware::Next, response::Response, Extension};
+use chrono::{DateTime, Utc};
+use opentelemetry::trace::TraceContextExt;
+use serde::{Deserialize, Serialize};
+use tracing::{info, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::bus::MessageBus;
+use crate::config::AppConfig;
+use crate::encoding::{WireFormat, FORMAT_HEADER};
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::RawData;
+use crate::nats::{Headers, NatsClient, PublishRetryPolicy, MSG_ID_HEADER};
+use crate::stats::Outcome;
+use crate::subject::{SubjectTemplate, TokenPolicy};
+
+/// Subject template audit records are published to unless `AUDIT_MIRROR_SUBJECT` is set
+pub const DEFAULT_AUDIT_SUBJECT: &str = "ingest.audit";
+
+/// Header the requester is read from unless `AUDIT_MIRROR_REQUESTER_HEADER` is set
+pub const DEFAULT_REQUESTER_HEADER: &str = "x-forwarded-user";
+
+/// Header carrying the ID an ingress or client gave the request
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Settings for mirroring accepted items to an audit subject
+#[derive(Debug, Clone)]
+pub struct AuditMirrorConfig {
+    /// Template of the subject records are published to, with the placeholders of
+    /// `NATS_SUBJECT_TEMPLATE`
+    pub subject: String,
+
+    /// Request header naming who sent the items, as set by an authenticating proxy
+    pub requester_header: String,
+
+    /// JetStream stream created to keep the records, if any
+    pub stream: Option<String>,
+}
+
+/// Who sent the request being handled, for the records of its items
+#[derive(Debug, Clone)]
+struct RequestContext {
+    request_id: String,
+    requester: Option<String>,
+}
+
+tokio::task_local! {
+    static REQUEST: RequestContext;
+}
+
+/// Note who sent each HTTP request and its ID, for the audit records of its items
+pub async fn capture_request(
+    Extension(config): Extension<Arc<AppConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(mirror_config) = &config.audit_mirror else {
+        return next.run(request).await;
+    };
+
+    let headers = request.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    // The trace ID also identifies the request in its summary line
+    let context = RequestContext {
+        request_id: header(REQUEST_ID_HEADER).unwrap_or_else(current_trace_id),
+        requester: header(&mirror_config.requester_header),
+    };
+
+    REQUEST.scope(context, next.run(request)).await
+}
+
+/// Trace ID of the request being handled, taken outside the middleware future as the span's
+/// context cannot be held across an await
+fn current_trace_id() -> String {
+    Span::current()
+        .context()
+        .span()
+        .span_context()
+        .trace_id()
+        .to_string()
+}
+
+/// Record of an item that entered the system, published apart from the processing stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// The item as it was accepted, after pre-processing
+    pub item: RawData,
+
+    /// Subject the item was published to
+    pub subject: String,
+
+    /// `published`, or `spooled` when the item waits on disk for NATS
+    pub outcome: String,
+
+    /// ID of the request that sent the item; absent for items that did not arrive over HTTP
+    /// or were published later, such as scheduled items
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+
+    /// Who sent the request, from the requester header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requester: Option<String>,
+
+    /// When the item was accepted
+    pub accepted_at: DateTime<Utc>,
+}
+
+/// Publishes a record of every accepted item to an audit subject, so compliance keeps a
+/// copy of what entered the system that consumers of the processing stream cannot touch
+pub struct AuditMirror {
+    subject: SubjectTemplate,
+    stream: Option<String>,
+    bus: Arc<dyn MessageBus>,
+    publish_retry: PublishRetryPolicy,
+}
+
+impl AuditMirror {
+    /// Create the mirror, rejecting an invalid subject template
+    pub fn new(
+        config: &AuditMirrorConfig,
+        environment: &str,
+        bus: Arc<dyn MessageBus>,
+        publish_retry: PublishRetryPolicy,
+    ) -> Result<Self> {
+        let subject = SubjectTemplate::parse(&config.subject, environment, TokenPolicy::Escape)?;
+        info!("Mirroring accepted items to {}", config.subject);
+
+        Ok(Self {
+            subject,
+            stream: config.stream.clone(),
+            bus,
+            publish_retry,
+        })
+    }
+
+    /// Create the audit stream, if one is configured, with deletes and purges denied
+    pub async fn provision(&self, nats_client: &NatsClient) -> Result<()> {
+        match &self.stream {
+            Some(stream) => {
+                nats_client
+                    .ensure_sealed_stream(stream, &self.subject.stream_subject())
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Publish the record of an accepted item. Failures are logged, as the item is already
+    /// on its way.
+    pub async fn record(&self, item: &RawData, subject: &str, outcome: Outcome) {
+        if let Err(e) = self.publish(item, subject, outcome).await {
+            throttled!(error!(
+                "Failed to mirror item {} to the audit subject: {}",
+                item.id, e
+            ));
+        }
+    }
+
+    async fn publish(&self, item: &RawData, subject: &str, outcome: Outcome) -> Result<()> {
+        let audit_subject = self.subject.render(item)?;
+        let context = REQUEST.try_with(RequestContext::clone).ok();
+        let record = AuditRecord {
+            item: item.clone(),
+            subject: subject.to_string(),
+            outcome: outcome.as_str().to_string(),
+            request_id: context.as_ref().map(|c| c.request_id.clone()),
+            requester: context.and_then(|c| c.requester),
+            accepted_at: Utc::now(),
+        };
+
+        let body = serde_json::to_vec(&record)
+            .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))?;
+        // The item's ID, so JetStream stores the record of a retried item once
+        let headers: Headers = vec![
+            (MSG_ID_HEADER.to_string(), item.id.to_string()),
+            (
+                FORMAT_HEADER.to_string(),
+                WireFormat::Json.as_str().to_string(),
+            ),
+        ];
+
+        self.bus
+            .publish_with_retry(&audit_subject, &headers, body.into(), &self.publish_retry)
+            .await?;
+        Ok(())
+    }
+}
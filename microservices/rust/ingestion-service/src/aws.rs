@@ -0,0 +1,712 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_config::meta::region::RegionProviderChain;
+use aws_config::timeout::TimeoutConfig;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_sns::error::{DisplayErrorContext, SdkError};
+use axum::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use tokio::sync::Notify;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::bus::{InFlight, MessageBus, OutgoingMessage};
+use crate::encoding::{CONTENT_TYPE_HEADER, SOURCE_HEADER};
+use crate::error::{AppError, Result};
+use crate::http::{self, ProxyConfig};
+use crate::logging::throttled;
+use crate::models::NatsStats;
+use crate::nats::{Headers, PublishAck, MSG_ID_HEADER};
+
+/// Message attribute holding the item's source
+pub const SOURCE_ATTRIBUTE: &str = "source";
+
+/// Message attribute holding the item's content type
+pub const CONTENT_TYPE_ATTRIBUTE: &str = "content_type";
+
+/// Message attribute holding the NATS subject the message would have been published on
+pub const SUBJECT_ATTRIBUTE: &str = "subject";
+
+/// Message attribute set to `base64` when the body is not text and was encoded
+pub const BODY_ENCODING_ATTRIBUTE: &str = "body_encoding";
+
+/// Most message attributes SNS and SQS accept on one message
+const MAX_ATTRIBUTES: usize = 10;
+
+/// Most messages in one batch request
+const MAX_BATCH_MESSAGES: usize = 10;
+
+/// Largest message, and largest batch request, SNS accepts
+const MAX_MESSAGE_BYTES: usize = 256 * 1024;
+
+/// Longest ID SNS and SQS accept for FIFO deduplication
+const MAX_DEDUPLICATION_ID_LEN: usize = 128;
+
+/// How often the target is checked while unreachable, at first
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the target is checked while unreachable, at most
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Topic or queue messages are sent to
+#[derive(Debug, Clone)]
+pub enum AwsTarget {
+    /// SNS topic, by ARN
+    Sns(String),
+
+    /// SQS queue, by URL
+    Sqs(String),
+}
+
+impl AwsTarget {
+    /// ARN of the topic or URL of the queue
+    pub fn address(&self) -> &str {
+        match self {
+            Self::Sns(arn) => arn,
+            Self::Sqs(url) => url,
+        }
+    }
+
+    /// Whether the topic or queue keeps order per message group and deduplicates
+    fn is_fifo(&self) -> bool {
+        self.address().trim_end_matches('/').ends_with(".fifo")
+    }
+
+    /// Region named by the ARN or URL, for when the credential chain has none
+    fn region(&self) -> Option<Region> {
+        let region = match self {
+            // arn:aws:sns:eu-west-1:123456789012:ingest
+            Self::Sns(arn) => arn.split(':').nth(3).map(str::to_string),
+            // https://sqs.eu-west-1.amazonaws.com/123456789012/ingest
+            Self::Sqs(url) => url::Url::parse(url).ok().and_then(|url| {
+                let host = url.host_str()?.strip_prefix("sqs.")?;
+                host.split('.').next().map(str::to_string)
+            }),
+        };
+        region.filter(|region| !region.is_empty()).map(Region::new)
+    }
+}
+
+/// Settings for publishing to an SNS topic or SQS queue instead of NATS
+#[derive(Debug, Clone)]
+pub struct AwsConfig {
+    /// Topic or queue messages are sent to
+    pub target: AwsTarget,
+
+    /// Longest wait for one publish, retries by the SDK included
+    pub publish_timeout: Duration,
+}
+
+impl AwsConfig {
+    /// Check the topic ARN or queue URL, so a typo is reported as such rather than as an
+    /// unreachable service
+    pub fn check(&self) -> Result<()> {
+        match &self.target {
+            AwsTarget::Sns(arn) => {
+                let parts: Vec<&str> = arn.split(':').collect();
+                if parts.len() != 6 || parts[0] != "arn" || parts[2] != "sns" || parts[5].is_empty()
+                {
+                    return Err(AppError::ValidationError(format!(
+                        "Invalid AWS_SNS_TOPIC_ARN {}, expected arn:aws:sns:region:account:topic",
+                        arn
+                    )));
+                }
+            }
+            AwsTarget::Sqs(url) => {
+                let parsed = url::Url::parse(url).map_err(|e| {
+                    AppError::ValidationError(format!("Invalid AWS_SQS_QUEUE_URL {}: {}", url, e))
+                })?;
+                if !matches!(parsed.scheme(), "http" | "https")
+                    || parsed.path().trim_matches('/').is_empty()
+                {
+                    return Err(AppError::ValidationError(format!(
+                        "Invalid AWS_SQS_QUEUE_URL {}, expected https://sqs.region.amazonaws.com/account/queue",
+                        url
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Client of the service the target belongs to
+enum AwsClient {
+    Sns(aws_sdk_sns::Client),
+    Sqs(aws_sdk_sqs::Client),
+}
+
+/// A message ready to send, as either service takes it
+struct AwsMessage {
+    body: String,
+    attributes: Vec<(String, String)>,
+    group_id: Option<String>,
+    deduplication_id: Option<String>,
+}
+
+/// [`MessageBus`] sending messages to an SNS topic or SQS queue, for consumers such as
+/// Lambda functions that read from SQS.
+///
+/// Credentials and region come from the standard AWS chain: environment, profile, SSO,
+/// web identity or instance metadata. The region falls back to the one in the topic ARN or
+/// queue URL. Each message's body is its payload, base64-encoded when it is not text, and
+/// its `source`, `content_type` and `subject` are message attributes, followed by as many of
+/// its headers as fit. Messages to a FIFO topic or queue are grouped by source and
+/// deduplicated by message ID. Failures use the NATS error variants so they map to the same
+/// statuses; while the service is unreachable, messages fail fast so they are spooled or
+/// buffered, and the target is checked in the background until it answers again. The
+/// `subject` attribute is namespaced like NATS subjects; the topic or queue is not, so each
+/// environment needs its own.
+pub struct AwsBus {
+    config: AwsConfig,
+    subject_prefix: String,
+    client: AwsClient,
+    connected: AtomicBool,
+    reconnected: Notify,
+    reconnects: AtomicU64,
+    pending_bytes: AtomicU64,
+}
+
+impl AwsBus {
+    /// Load credentials, check the target exists, then watch for outages in the background.
+    /// `subject` attributes are prefixed with `subject_prefix`, as from
+    /// [`subject_prefix`](crate::nats::subject_prefix). Requests, including those fetching
+    /// credentials, go through the outbound proxy.
+    pub async fn connect(
+        config: AwsConfig,
+        proxy: &ProxyConfig,
+        subject_prefix: String,
+    ) -> Result<Arc<Self>> {
+        let region = RegionProviderChain::default_provider().or_else(config.target.region());
+        let mut loader = aws_config::defaults(BehaviorVersion::latest())
+            .region(region)
+            .timeout_config(
+                TimeoutConfig::builder()
+                    .operation_timeout(config.publish_timeout)
+                    .build(),
+            );
+        if let Some(http_client) = http::aws_http_client(proxy)? {
+            loader = loader.http_client(http_client);
+        }
+        let sdk_config = loader.load().await;
+        let client = match &config.target {
+            AwsTarget::Sns(_) => AwsClient::Sns(aws_sdk_sns::Client::new(&sdk_config)),
+            AwsTarget::Sqs(_) => AwsClient::Sqs(aws_sdk_sqs::Client::new(&sdk_config)),
+        };
+        info!(
+            "Connecting to AWS in {}",
+            sdk_config
+                .region()
+                .map(|region| region.as_ref())
+                .unwrap_or("no region")
+        );
+
+        let bus = Arc::new(Self {
+            config,
+            subject_prefix,
+            client,
+            connected: AtomicBool::new(true),
+            reconnected: Notify::new(),
+            reconnects: AtomicU64::new(0),
+            pending_bytes: AtomicU64::new(0),
+        });
+        bus.check_target().await?;
+        info!("Publishing to {}", bus.config.target.address());
+
+        tokio::spawn(bus.clone().supervise());
+        Ok(bus)
+    }
+
+    /// Check the target while it is unreachable, backing off, until it answers again
+    async fn supervise(self: Arc<Self>) {
+        let mut interval = CHECK_INTERVAL;
+        loop {
+            tokio::time::sleep(interval).await;
+            if self.is_connected() {
+                interval = CHECK_INTERVAL;
+                continue;
+            }
+            match self.check_target().await {
+                Ok(()) => {
+                    self.connected.store(true, Ordering::Relaxed);
+                    self.reconnects.fetch_add(1, Ordering::Relaxed);
+                    info!("Reconnected to AWS");
+                    self.reconnected.notify_one();
+                    interval = CHECK_INTERVAL;
+                }
+                Err(e) => {
+                    debug!("AWS still unreachable: {}", e);
+                    interval = (interval * 2).min(MAX_CHECK_INTERVAL);
+                }
+            }
+        }
+    }
+
+    /// Read the attributes of the topic or queue, which also proves the credentials work
+    async fn check_target(&self) -> Result<()> {
+        let address = self.config.target.address();
+        match &self.client {
+            AwsClient::Sns(client) => {
+                client
+                    .get_topic_attributes()
+                    .topic_arn(address)
+                    .send()
+                    .await
+                    .map_err(|e| self.error(address, e))?;
+            }
+            AwsClient::Sqs(client) => {
+                client
+                    .get_queue_attributes()
+                    .queue_url(address)
+                    .send()
+                    .await
+                    .map_err(|e| self.error(address, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail fast while AWS is unreachable, so the message is spooled or buffered
+    fn check_connected(&self) -> Result<()> {
+        if self.is_connected() {
+            Ok(())
+        } else {
+            Err(AppError::NatsConnectionError(
+                "AWS is unreachable".to_string(),
+            ))
+        }
+    }
+
+    /// Body, attributes and FIFO IDs of a message
+    fn message(&self, subject: &str, headers: &Headers, payload: &[u8]) -> AwsMessage {
+        let subject = format!("{}{}", self.subject_prefix, subject);
+        let subject = subject.as_str();
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+
+        let (body, encoded) = match std::str::from_utf8(payload) {
+            // SNS and SQS reject most control characters, even in valid UTF-8
+            Ok(text)
+                if !text
+                    .chars()
+                    .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r')) =>
+            {
+                (text.to_string(), false)
+            }
+            _ => (STANDARD.encode(payload), true),
+        };
+
+        let mut attributes = Vec::with_capacity(MAX_ATTRIBUTES);
+        if let Some(source) = header(SOURCE_HEADER) {
+            attributes.push((SOURCE_ATTRIBUTE.to_string(), source.to_string()));
+        }
+        if let Some(content_type) = header(CONTENT_TYPE_HEADER) {
+            attributes.push((CONTENT_TYPE_ATTRIBUTE.to_string(), content_type.to_string()));
+        }
+        attributes.push((SUBJECT_ATTRIBUTE.to_string(), subject.to_string()));
+        if encoded {
+            attributes.push((BODY_ENCODING_ATTRIBUTE.to_string(), "base64".to_string()));
+        }
+        for (name, value) in headers {
+            if attributes.len() >= MAX_ATTRIBUTES {
+                debug!(
+                    "Dropping headers past the {} message attributes AWS allows",
+                    MAX_ATTRIBUTES
+                );
+                break;
+            }
+            if name == SOURCE_HEADER
+                || name == CONTENT_TYPE_HEADER
+                || value.is_empty()
+                || !is_attribute_name(name)
+            {
+                continue;
+            }
+            attributes.push((name.clone(), value.clone()));
+        }
+
+        let (group_id, deduplication_id) = if self.config.target.is_fifo() {
+            let deduplication_id = match header(MSG_ID_HEADER) {
+                Some(id) if id.len() <= MAX_DEDUPLICATION_ID_LEN => id.to_string(),
+                Some(id) => Uuid::new_v5(&Uuid::NAMESPACE_OID, id.as_bytes()).to_string(),
+                None => Uuid::new_v5(&Uuid::NAMESPACE_OID, payload).to_string(),
+            };
+            (
+                Some(header(SOURCE_HEADER).unwrap_or(subject).to_string()),
+                Some(deduplication_id),
+            )
+        } else {
+            (None, None)
+        };
+
+        AwsMessage {
+            body,
+            attributes,
+            group_id,
+            deduplication_id,
+        }
+    }
+
+    /// Send one message
+    async fn send(&self, message: AwsMessage) -> Result<()> {
+        let address = self.config.target.address();
+        match &self.client {
+            AwsClient::Sns(client) => {
+                client
+                    .publish()
+                    .topic_arn(address)
+                    .message(message.body)
+                    .set_message_attributes(Some(sns_attributes(message.attributes)))
+                    .set_message_group_id(message.group_id)
+                    .set_message_deduplication_id(message.deduplication_id)
+                    .send()
+                    .await
+                    .map_err(|e| self.error(address, e))?;
+            }
+            AwsClient::Sqs(client) => {
+                client
+                    .send_message()
+                    .queue_url(address)
+                    .message_body(message.body)
+                    .set_message_attributes(Some(sqs_attributes(message.attributes)))
+                    .set_message_group_id(message.group_id)
+                    .set_message_deduplication_id(message.deduplication_id)
+                    .send()
+                    .await
+                    .map_err(|e| self.error(address, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send up to ten messages as one batch request, giving one result per message
+    async fn send_batch(&self, messages: Vec<AwsMessage>) -> Vec<Result<Option<PublishAck>>> {
+        let address = self.config.target.address();
+        let count = messages.len();
+        // Entries are identified by their position in the batch
+        let failed: Result<Vec<(String, String)>> = match &self.client {
+            AwsClient::Sns(client) => {
+                let entries = messages
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, message)| {
+                        aws_sdk_sns::types::PublishBatchRequestEntry::builder()
+                            .id(id.to_string())
+                            .message(message.body)
+                            .set_message_attributes(Some(sns_attributes(message.attributes)))
+                            .set_message_group_id(message.group_id)
+                            .set_message_deduplication_id(message.deduplication_id)
+                            .build()
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|e| {
+                        AppError::InternalError(format!("Failed to build SNS batch: {}", e))
+                    });
+                match entries {
+                    Ok(entries) => client
+                        .publish_batch()
+                        .topic_arn(address)
+                        .set_publish_batch_request_entries(Some(entries))
+                        .send()
+                        .await
+                        .map(|output| {
+                            output
+                                .failed()
+                                .iter()
+                                .map(|entry| {
+                                    (
+                                        entry.id().to_string(),
+                                        failure(entry.code(), entry.message()),
+                                    )
+                                })
+                                .collect()
+                        })
+                        .map_err(|e| self.error(address, e)),
+                    Err(e) => Err(e),
+                }
+            }
+            AwsClient::Sqs(client) => {
+                let entries = messages
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, message)| {
+                        aws_sdk_sqs::types::SendMessageBatchRequestEntry::builder()
+                            .id(id.to_string())
+                            .message_body(message.body)
+                            .set_message_attributes(Some(sqs_attributes(message.attributes)))
+                            .set_message_group_id(message.group_id)
+                            .set_message_deduplication_id(message.deduplication_id)
+                            .build()
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|e| {
+                        AppError::InternalError(format!("Failed to build SQS batch: {}", e))
+                    });
+                match entries {
+                    Ok(entries) => client
+                        .send_message_batch()
+                        .queue_url(address)
+                        .set_entries(Some(entries))
+                        .send()
+                        .await
+                        .map(|output| {
+                            output
+                                .failed()
+                                .iter()
+                                .map(|entry| {
+                                    (
+                                        entry.id().to_string(),
+                                        failure(entry.code(), entry.message()),
+                                    )
+                                })
+                                .collect()
+                        })
+                        .map_err(|e| self.error(address, e)),
+                    Err(e) => Err(e),
+                }
+            }
+        };
+
+        match failed {
+            Ok(failed) => {
+                let failed: HashMap<String, String> = failed.into_iter().collect();
+                (0..count)
+                    .map(|id| match failed.get(&id.to_string()) {
+                        Some(reason) => {
+                            throttled!(error!("Failed to publish to {}: {}", address, reason));
+                            Err(AppError::NatsPublishError(format!(
+                                "Failed to publish to {}: {}",
+                                address, reason
+                            )))
+                        }
+                        None => Ok(None),
+                    })
+                    .collect()
+            }
+            // The request itself failed, so every message shares its error
+            Err(e) => {
+                let connection_lost = matches!(e, AppError::NatsConnectionError(_));
+                let reason = e.to_string();
+                (0..count)
+                    .map(|_| match connection_lost {
+                        true => Err(AppError::NatsConnectionError(reason.clone())),
+                        false => Err(AppError::NatsPublishError(reason.clone())),
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Error of a failed call, marking AWS unreachable when the call never got an answer
+    fn error<E, R>(&self, address: &str, e: SdkError<E, R>) -> AppError
+    where
+        E: std::error::Error + 'static,
+        R: std::fmt::Debug,
+    {
+        match e {
+            SdkError::DispatchFailure(_) | SdkError::TimeoutError(_) => {
+                if self.connected.swap(false, Ordering::Relaxed) {
+                    warn!(
+                        "AWS unreachable, checking {} until it answers: {}",
+                        address,
+                        DisplayErrorContext(&e)
+                    );
+                }
+                AppError::NatsConnectionError(format!(
+                    "AWS unreachable: {}",
+                    DisplayErrorContext(&e)
+                ))
+            }
+            e => {
+                throttled!(error!(
+                    "Failed to publish to {}: {}",
+                    address,
+                    DisplayErrorContext(&e)
+                ));
+                AppError::NatsPublishError(format!(
+                    "Failed to publish to {}: {}",
+                    address,
+                    DisplayErrorContext(&e)
+                ))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MessageBus for AwsBus {
+    #[instrument(skip(self, headers, payload), fields(subject = %subject))]
+    async fn publish(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+    ) -> Result<Option<PublishAck>> {
+        self.check_connected()?;
+        let _in_flight = InFlight::new(&self.pending_bytes, payload.len());
+
+        debug!("Publishing message to {}", self.config.target.address());
+
+        self.send(self.message(subject, headers, &payload)).await?;
+        Ok(None)
+    }
+
+    /// Messages are sent in batch requests of up to ten messages and 256 KiB each
+    async fn publish_batch(
+        &self,
+        messages: Vec<OutgoingMessage>,
+    ) -> Vec<Result<Option<PublishAck>>> {
+        if !self.is_connected() {
+            return messages
+                .iter()
+                .map(|_| {
+                    Err(AppError::NatsConnectionError(
+                        "AWS is unreachable".to_string(),
+                    ))
+                })
+                .collect();
+        }
+        let bytes = messages.iter().map(|message| message.payload.len()).sum();
+        let _in_flight = InFlight::new(&self.pending_bytes, bytes);
+
+        let mut results = Vec::with_capacity(messages.len());
+        let mut batch = Vec::with_capacity(MAX_BATCH_MESSAGES);
+        let mut batch_bytes = 0;
+        for message in &messages {
+            let message = self.message(&message.subject, &message.headers, &message.payload);
+            let size = message_size(&message);
+            if !batch.is_empty()
+                && (batch.len() == MAX_BATCH_MESSAGES || batch_bytes + size > MAX_MESSAGE_BYTES)
+            {
+                results.extend(self.send_batch(std::mem::take(&mut batch)).await);
+                batch_bytes = 0;
+            }
+            batch_bytes += size;
+            batch.push(message);
+        }
+        if !batch.is_empty() {
+            results.extend(self.send_batch(batch).await);
+        }
+        results
+    }
+
+    /// SNS and SQS have no replies to wait for
+    async fn request(
+        &self,
+        _subject: &str,
+        _headers: &Headers,
+        _payload: Bytes,
+        _timeout: Duration,
+    ) -> Result<Option<Bytes>> {
+        Err(AppError::ValidationError(
+            "Processor probes are not supported when publishing to SNS or SQS".to_string(),
+        ))
+    }
+
+    async fn health(&self) -> NatsStats {
+        NatsStats {
+            state: if self.is_connected() {
+                "connected"
+            } else {
+                "disconnected"
+            }
+            .to_string(),
+            server_name: None,
+            server_version: None,
+            server_address: Some(self.config.target.address().to_string()),
+            rtt_ms: None,
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            pending_bytes: self.pending_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    async fn reconnected(&self) {
+        self.reconnected.notified().await
+    }
+
+    fn max_payload(&self) -> Option<usize> {
+        Some(MAX_MESSAGE_BYTES)
+    }
+
+    /// Every publish already waits for AWS to accept it, so nothing is left in flight
+    async fn drain(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Whether SNS and SQS accept a header name as a message attribute name
+fn is_attribute_name(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    !name.is_empty()
+        && name.len() <= 256
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        && !name.starts_with('.')
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && !lower.starts_with("aws.")
+        && !lower.starts_with("amazon.")
+}
+
+/// Bytes a message counts for against the batch size limit
+fn message_size(message: &AwsMessage) -> usize {
+    message.body.len()
+        + message
+            .attributes
+            .iter()
+            .map(|(name, value)| name.len() + "String".len() + value.len())
+            .sum::<usize>()
+}
+
+/// Reason a batch entry failed
+fn failure(code: &str, message: Option<&str>) -> String {
+    match message {
+        Some(message) => format!("{}: {}", code, message),
+        None => code.to_string(),
+    }
+}
+
+fn sns_attributes(
+    attributes: Vec<(String, String)>,
+) -> HashMap<String, aws_sdk_sns::types::MessageAttributeValue> {
+    attributes
+        .into_iter()
+        .filter_map(|(name, value)| {
+            aws_sdk_sns::types::MessageAttributeValue::builder()
+                .data_type("String")
+                .string_value(value)
+                .build()
+                .ok()
+                .map(|value| (name, value))
+        })
+        .collect()
+}
+
+fn sqs_attributes(
+    attributes: Vec<(String, String)>,
+) -> HashMap<String, aws_sdk_sqs::types::MessageAttributeValue> {
+    attributes
+        .into_iter()
+        .filter_map(|(name, value)| {
+            aws_sdk_sqs::types::MessageAttributeValue::builder()
+                .data_type("String")
+                .string_value(value)
+                .build()
+                .ok()
+                .map(|value| (name, value))
+        })
+        .collect()
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::flow::{FlowControl, PauseScope};
+use crate::nats::NatsClient;
+
+/// Fraction of a threshold the backlog must drop below before ingestion resumes
+const RESUME_FRACTION: f64 = 0.8;
+
+/// Settings for pausing ingestion when downstream streams fall behind
+#[derive(Debug, Clone)]
+pub struct BacklogConfig {
+    /// Content types and the JetStream stream their messages land in
+    pub streams: Vec<(String, String)>,
+
+    /// Pause when a stream holds more messages than this
+    pub max_messages: Option<u64>,
+
+    /// Pause when any consumer of a stream has more pending messages than this
+    pub max_pending: Option<u64>,
+
+    /// Pause between polls of the stream stats
+    pub poll_interval: Duration,
+}
+
+/// Polls JetStream stream depth and consumer lag, pausing content types whose backlog is
+/// over a threshold and resuming them once it has drained
+pub struct BacklogMonitor {
+    config: BacklogConfig,
+    nats_client: Arc<NatsClient>,
+    flow: Arc<FlowControl>,
+}
+
+impl BacklogMonitor {
+    /// Create a new monitor
+    pub fn new(
+        config: BacklogConfig,
+        nats_client: Arc<NatsClient>,
+        flow: Arc<FlowControl>,
+    ) -> Self {
+        Self {
+            config,
+            nats_client,
+            flow,
+        }
+    }
+
+    /// Spawn the background polling task
+    pub fn spawn(self) {
+        tokio::spawn(async move {
+            loop {
+                for (content_type, stream) in &self.config.streams {
+                    self.poll(content_type, stream).await;
+                }
+                tokio::time::sleep(self.config.poll_interval).await;
+            }
+        });
+    }
+
+    async fn poll(&self, content_type: &str, stream: &str) {
+        let (messages, pending) = match self.nats_client.stream_backlog(stream).await {
+            Ok(backlog) => backlog,
+            Err(e) => {
+                warn!("Failed to read backlog of stream {}: {}", stream, e);
+                return;
+            }
+        };
+
+        let scope = PauseScope::ContentType(content_type.to_string());
+        let over = |value: u64, limit: Option<u64>, fraction: f64| {
+            limit.is_some_and(|limit| value as f64 > limit as f64 * fraction)
+        };
+
+        if over(messages, self.config.max_messages, 1.0)
+            || over(pending, self.config.max_pending, 1.0)
+        {
+            if !self.flow.is_paused(&scope) {
+                warn!(
+                    "Stream {} is backlogged ({} messages, {} pending), pausing {}",
+                    stream, messages, pending, content_type
+                );
+                self.flow.pause_automatic(
+                    scope,
+                    format!("downstream stream {} is backlogged, retry later", stream),
+                );
+            }
+        } else if !over(messages, self.config.max_messages, RESUME_FRACTION)
+            && !over(pending, self.config.max_pending, RESUME_FRACTION)
+            && self.flow.resume_automatic(&scope)
+        {
+            info!(
+                "Stream {} backlog drained, resumed {}",
+                stream, content_type
+            );
+        }
+    }
+}
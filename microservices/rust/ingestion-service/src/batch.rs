@@ -0,0 +1,60 @@
+use axum::http::HeaderMap;
+use serde_json::{json, Value};
+
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+
+/// Header naming the batch of an item, on ingest requests and on the published messages
+pub const BATCH_HEADER: &str = "Ingest-Batch-Id";
+
+/// Longest accepted batch ID
+const MAX_BATCH_ID_LEN: usize = 128;
+
+/// Batch ID sent with an ingest request, if any
+pub fn from_headers(headers: &HeaderMap) -> Result<Option<String>> {
+    let Some(value) = headers.get(BATCH_HEADER) else {
+        return Ok(None);
+    };
+
+    let id = value
+        .to_str()
+        .map_err(|_| {
+            AppError::ValidationError(format!("{} must be printable ASCII", BATCH_HEADER))
+        })?
+        .trim();
+    check(id)?;
+
+    Ok(Some(id.to_string()))
+}
+
+/// Check that a batch ID can be carried in a header and tracked in stats
+pub fn check(id: &str) -> Result<()> {
+    if id.is_empty() || id.len() > MAX_BATCH_ID_LEN || !id.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(AppError::ValidationError(format!(
+            "Batch IDs are 1 to {} printable ASCII characters without spaces",
+            MAX_BATCH_ID_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+/// Batch ID an item belongs to, kept in `metadata.batch.id`
+pub fn batch_id(item: &RawData) -> Option<&str> {
+    item.metadata
+        .get("batch")
+        .and_then(|batch| batch.get("id"))
+        .and_then(Value::as_str)
+        .filter(|id| check(id).is_ok())
+}
+
+/// Assign an item to a batch, replacing any batch ID it carried
+pub fn tag(item: &mut RawData, id: &str) {
+    if !item.metadata.is_object() {
+        item.metadata = json!({});
+    }
+    if !item.metadata["batch"].is_object() {
+        item.metadata["batch"] = json!({});
+    }
+    item.metadata["batch"]["id"] = json!(id);
+}
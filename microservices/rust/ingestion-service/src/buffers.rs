@@ -0,0 +1,167 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::header,
+    response::{IntoResponse, Response},
+    Json, RequestExt,
+};
+use bytes::{BufMut, BytesMut};
+use futures::StreamExt;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::{AppError, Result};
+
+/// Settings for the pool of reusable byte buffers
+#[derive(Debug, Clone)]
+pub struct BufferPoolConfig {
+    /// Most idle buffers kept for reuse
+    pub buffers: usize,
+
+    /// Buffers that grew beyond this many bytes are freed instead of pooled, so one large
+    /// request doesn't pin its memory
+    pub max_capacity: usize,
+}
+
+/// Pool of byte buffers reused for request bodies and NATS payloads.
+///
+/// Serialized payloads are split off their pooled buffer as `Bytes`. Once NATS has written
+/// and dropped them, the next `reserve` on the pooled buffer reclaims the same allocation.
+pub struct BufferPool {
+    config: BufferPoolConfig,
+    idle: Mutex<Vec<BytesMut>>,
+}
+
+impl BufferPool {
+    /// Create an empty pool; buffers are allocated on first use
+    pub fn new(config: BufferPoolConfig) -> Self {
+        Self {
+            idle: Mutex::new(Vec::with_capacity(config.buffers)),
+            config,
+        }
+    }
+
+    /// Borrow a cleared buffer, returned to the pool when dropped
+    pub fn take(&self) -> PooledBuffer<'_> {
+        let buffer = self
+            .idle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop()
+            .unwrap_or_default();
+
+        PooledBuffer { pool: self, buffer }
+    }
+
+    /// Serialize a value as JSON into a pooled buffer
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<Bytes> {
+        let mut buffer = self.take();
+        serde_json::to_writer((&mut *buffer).writer(), value)
+            .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))?;
+        Ok(buffer.split().freeze())
+    }
+
+    fn give_back(&self, mut buffer: BytesMut) {
+        buffer.clear();
+        if buffer.capacity() > self.config.max_capacity {
+            return;
+        }
+
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < self.config.buffers {
+            idle.push(buffer);
+        }
+    }
+}
+
+/// A buffer borrowed from a `BufferPool`
+pub struct PooledBuffer<'a> {
+    pool: &'a BufferPool,
+    buffer: BytesMut,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &BytesMut {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut BytesMut {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buffer));
+    }
+}
+
+/// JSON extractor that collects the body into a pooled buffer.
+///
+/// Behaves like `Json`, including its rejections and the default body limit, but
+/// reuses the buffer the body is read into across requests.
+pub struct PooledJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for PooledJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> std::result::Result<Self, Response> {
+        let pool = request.extensions().get::<Arc<BufferPool>>().cloned();
+        let is_json = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<mime::Mime>().ok())
+            .is_some_and(|mime| {
+                mime.type_() == "application"
+                    && (mime.subtype() == "json"
+                        || mime.suffix().is_some_and(|suffix| suffix == "json"))
+            });
+
+        // Leave unpooled requests and content type rejections to `Json`
+        let Some(pool) = pool.filter(|_| is_json) else {
+            return Json::<T>::from_request(request, state)
+                .await
+                .map(|Json(value)| Self(value))
+                .map_err(IntoResponse::into_response);
+        };
+
+        let mut buffer = pool.take();
+        let mut body = request.with_limited_body().into_body().into_data_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| body_error(e).into_response())?;
+            buffer.extend_from_slice(&chunk);
+        }
+
+        Json::<T>::from_bytes(&buffer)
+            .map(|Json(value)| Self(value))
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+fn body_error(e: axum::Error) -> AppError {
+    let mut source = std::error::Error::source(&e);
+    let mut too_large = false;
+    while let Some(error) = source {
+        too_large |= error.is::<http_body_util::LengthLimitError>();
+        source = error.source();
+    }
+
+    if too_large {
+        AppError::PayloadTooLarge("Request body exceeds the size limit".to_string())
+    } else {
+        AppError::ValidationError(format!("Failed to read request body: {}", e))
+    }
+}
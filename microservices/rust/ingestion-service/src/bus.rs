@@ -0,0 +1,224 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use axum::async_trait;
+use bytes::Bytes;
+
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::NatsStats;
+use crate::nats::{Headers, NatsClient, PublishAck, PublishRetryPolicy};
+
+/// Messaging backend ingested items are published to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusBackend {
+    /// NATS, optionally through JetStream
+    Nats,
+
+    /// RabbitMQ, with publisher confirms
+    Amqp,
+
+    /// Redis Streams, without connecting to NATS
+    Redis,
+
+    /// An AWS SNS topic or SQS queue
+    Aws,
+}
+
+impl BusBackend {
+    /// Parse a backend name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "nats" => Some(Self::Nats),
+            "amqp" | "rabbitmq" => Some(Self::Amqp),
+            "redis" => Some(Self::Redis),
+            "aws" | "sns" | "sqs" => Some(Self::Aws),
+            _ => None,
+        }
+    }
+
+    /// Name of the backend as reported by readiness
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Nats => "nats",
+            Self::Amqp => "amqp",
+            Self::Redis => "redis",
+            Self::Aws => "aws",
+        }
+    }
+}
+
+/// A message to publish as part of a batch
+pub struct OutgoingMessage {
+    pub subject: String,
+    pub headers: Headers,
+    pub payload: Bytes,
+}
+
+/// Messaging backend ingested items are published through.
+///
+/// The pipeline, spool and routes depend on this rather than on [`NatsClient`], so another
+/// backend, such as [`AmqpBus`](crate::amqp::AmqpBus), or an in-memory double, can stand in
+/// for NATS. Stream provisioning, key-value and object stores and other JetStream machinery
+/// stay on the client.
+#[async_trait]
+pub trait MessageBus: Send + Sync {
+    /// Publish one message, giving the stream's acknowledgement when the backend has one
+    async fn publish(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+    ) -> Result<Option<PublishAck>>;
+
+    /// Publish several messages at once, in order, giving one result per message
+    async fn publish_batch(
+        &self,
+        messages: Vec<OutgoingMessage>,
+    ) -> Vec<Result<Option<PublishAck>>>;
+
+    /// Send a request and wait up to `timeout` for the first reply, none when nobody answers
+    async fn request(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+        timeout: Duration,
+    ) -> Result<Option<Bytes>>;
+
+    /// Connection state and statistics for the health check
+    async fn health(&self) -> NatsStats;
+
+    /// Whether the backend is currently connected
+    fn is_connected(&self) -> bool;
+
+    /// Wait until the connection is re-established after an outage
+    async fn reconnected(&self);
+
+    /// Largest message the backend accepts, none when it has no known limit
+    fn max_payload(&self) -> Option<usize>;
+
+    /// Wait until every message published so far has left the service, for shutdown
+    async fn drain(&self) -> Result<()>;
+
+    /// Publish an already serialized message, retrying publish errors with exponential
+    /// backoff and jitter.
+    ///
+    /// Connection errors are not retried, as the backend is already reconnecting and the
+    /// caller can spool the message instead. Retried JetStream publishes carry the same
+    /// message ID, so the stream stores them once.
+    async fn publish_with_retry(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+        policy: &PublishRetryPolicy,
+    ) -> Result<Option<PublishAck>> {
+        let first = self.publish(subject, headers, payload.clone()).await;
+        self.retry_publish(subject, headers, payload, policy, first)
+            .await
+    }
+
+    /// Retry a publish whose first attempt, made elsewhere, gave `first`, following the same
+    /// rules as [`MessageBus::publish_with_retry`]
+    async fn retry_publish(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+        policy: &PublishRetryPolicy,
+        first: Result<Option<PublishAck>>,
+    ) -> Result<Option<PublishAck>> {
+        let mut attempt = 1;
+        let mut result = first;
+        loop {
+            match result {
+                Err(AppError::NatsPublishError(e)) if attempt < policy.max_attempts => {
+                    let delay = policy.delay(attempt);
+                    throttled!(warn!(
+                        "Publish to {} failed on attempt {} of {}, retrying in {:?}: {}",
+                        subject, attempt, policy.max_attempts, delay, e
+                    ));
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    result = self.publish(subject, headers, payload.clone()).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MessageBus for NatsClient {
+    async fn publish(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+    ) -> Result<Option<PublishAck>> {
+        self.publish_bytes(subject, headers, payload).await
+    }
+
+    /// Messages are sent without awaiting acknowledgements in between, up to a window of
+    /// outstanding ones, so the batch costs about one round trip per window
+    async fn publish_batch(
+        &self,
+        messages: Vec<OutgoingMessage>,
+    ) -> Vec<Result<Option<PublishAck>>> {
+        self.publish_pipelined(messages).await
+    }
+
+    async fn request(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+        timeout: Duration,
+    ) -> Result<Option<Bytes>> {
+        NatsClient::request(self, subject, headers, payload, timeout).await
+    }
+
+    async fn health(&self) -> NatsStats {
+        self.stats().await
+    }
+
+    fn is_connected(&self) -> bool {
+        NatsClient::is_connected(self)
+    }
+
+    async fn reconnected(&self) {
+        NatsClient::reconnected(self).await
+    }
+
+    fn max_payload(&self) -> Option<usize> {
+        NatsClient::max_payload(self)
+    }
+
+    async fn drain(&self) -> Result<()> {
+        NatsClient::drain(self).await
+    }
+}
+
+/// Counts bytes of a publish as pending until dropped, for the health check
+pub struct InFlight<'a> {
+    pending_bytes: &'a AtomicU64,
+    bytes: u64,
+}
+
+impl<'a> InFlight<'a> {
+    pub fn new(pending_bytes: &'a AtomicU64, bytes: usize) -> Self {
+        let bytes = bytes as u64;
+        pending_bytes.fetch_add(bytes, Ordering::Relaxed);
+        Self {
+            pending_bytes,
+            bytes,
+        }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.pending_bytes.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
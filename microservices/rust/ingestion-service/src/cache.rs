@@ -0,0 +1,55 @@
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Short-lived cache for read-only endpoint responses.
+///
+/// Concurrent callers that find the value stale wait for a single refresh instead of
+/// each recomputing it.
+pub struct ResponseCache<T> {
+    ttl: Duration,
+    value: RwLock<Option<(Instant, T)>>,
+    refresh: tokio::sync::Mutex<()>,
+}
+
+impl<T: Clone> ResponseCache<T> {
+    /// Create an empty cache
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            value: RwLock::new(None),
+            refresh: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Return the cached value, refreshing it once if it has expired
+    pub async fn get_or_refresh<F, Fut>(&self, refresh: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if let Some(value) = self.fresh() {
+            return value;
+        }
+
+        let _guard = self.refresh.lock().await;
+
+        // Another caller may have refreshed the value while we waited
+        if let Some(value) = self.fresh() {
+            return value;
+        }
+
+        let value = refresh().await;
+        *self.value.write().unwrap_or_else(|e| e.into_inner()) =
+            Some((Instant::now(), value.clone()));
+        value
+    }
+
+    fn fresh(&self) -> Option<T> {
+        let value = self.value.read().unwrap_or_else(|e| e.into_inner());
+        value
+            .as_ref()
+            .filter(|(cached_at, _)| cached_at.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+}
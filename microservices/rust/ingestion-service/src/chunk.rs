@@ -0,0 +1,162 @@
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::models::RawData;
+
+/// Settings for splitting long text payloads into overlapping chunks
+#[derive(Debug, Clone)]
+pub struct ChunkConfig {
+    /// Content types that are chunked
+    pub content_types: Vec<String>,
+
+    /// Payload field holding the text to split
+    pub text_field: String,
+
+    /// Maximum number of words per chunk
+    pub size: usize,
+
+    /// Number of words shared between consecutive chunks
+    pub overlap: usize,
+}
+
+impl ChunkConfig {
+    /// Whether items of the given content type should be chunked
+    pub fn applies_to(&self, content_type: &str) -> bool {
+        self.content_types.iter().any(|t| t == content_type)
+    }
+}
+
+/// Split an item into chunk messages if its text exceeds the configured chunk size.
+///
+/// Each chunk is a copy of the item with the text field replaced by the chunk and
+/// `metadata.chunk` describing its position. Chunk IDs are derived from the parent ID
+/// so that re-ingesting the same document produces the same chunk IDs.
+pub fn chunk_item(item: &RawData, config: &ChunkConfig) -> Option<Vec<RawData>> {
+    if !config.applies_to(&item.content_type) {
+        return None;
+    }
+
+    let text = item.payload.get(&config.text_field)?.as_str()?;
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    if words.len() <= config.size {
+        return None;
+    }
+
+    let step = config.size - config.overlap;
+    let starts: Vec<usize> = (0..words.len())
+        .step_by(step)
+        .take_while(|&start| start == 0 || start + config.overlap < words.len())
+        .collect();
+    let count = starts.len();
+
+    let chunks = starts
+        .into_iter()
+        .enumerate()
+        .map(|(index, start)| {
+            let end = (start + config.size).min(words.len());
+
+            let mut chunk = item.clone();
+            chunk.id = Uuid::new_v5(&item.id, &(index as u64).to_be_bytes());
+            chunk.payload[config.text_field.as_str()] = Value::String(words[start..end].join(" "));
+
+            if !chunk.metadata.is_object() {
+                chunk.metadata = json!({});
+            }
+            chunk.metadata["chunk"] = json!({
+                "parent_id": item.id,
+                "index": index,
+                "count": count,
+                "word_start": start,
+                "word_end": end,
+            });
+
+            chunk
+        })
+        .collect();
+
+    Some(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(size: usize, overlap: usize) -> ChunkConfig {
+        ChunkConfig {
+            content_types: vec!["research_paper".to_string()],
+            text_field: "text".to_string(),
+            size,
+            overlap,
+        }
+    }
+
+    fn item(content_type: &str, words: usize) -> RawData {
+        let text: Vec<String> = (0..words).map(|n| format!("w{}", n)).collect();
+        RawData::builder()
+            .source("arxiv")
+            .content_type(content_type)
+            .payload(json!({ "text": text.join(" "), "title": "Paper" }))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn leaves_short_and_other_items_whole() {
+        assert!(chunk_item(&item("research_paper", 10), &config(10, 2)).is_none());
+        assert!(chunk_item(&item("news_article", 100), &config(10, 2)).is_none());
+
+        let mut no_text = item("research_paper", 100);
+        no_text.payload = json!({ "title": "Paper" });
+        assert!(chunk_item(&no_text, &config(10, 2)).is_none());
+    }
+
+    #[test]
+    fn splits_into_overlapping_chunks() {
+        let parent = item("research_paper", 25);
+        let chunks = chunk_item(&parent, &config(10, 2)).unwrap();
+
+        let ranges: Vec<(u64, u64)> = chunks
+            .iter()
+            .map(|c| {
+                (
+                    c.metadata["chunk"]["word_start"].as_u64().unwrap(),
+                    c.metadata["chunk"]["word_end"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(ranges, vec![(0, 10), (8, 18), (16, 25)]);
+
+        assert_eq!(
+            chunks[1].payload["text"],
+            "w8 w9 w10 w11 w12 w13 w14 w15 w16 w17"
+        );
+        assert_eq!(chunks[1].payload["title"], "Paper");
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.metadata["chunk"]["index"], index);
+            assert_eq!(chunk.metadata["chunk"]["count"], 3);
+            assert_eq!(chunk.metadata["chunk"]["parent_id"], json!(parent.id));
+        }
+    }
+
+    #[test]
+    fn does_not_emit_a_chunk_of_only_overlap() {
+        // A fourth chunk starting at word 24 would only repeat the end of the third
+        let chunks = chunk_item(&item("research_paper", 26), &config(10, 2)).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2].metadata["chunk"]["word_end"], 26);
+    }
+
+    #[test]
+    fn derives_chunk_ids_from_the_parent() {
+        let parent = item("research_paper", 30);
+        let first = chunk_item(&parent, &config(10, 0)).unwrap();
+        let again = chunk_item(&parent, &config(10, 0)).unwrap();
+        let other = chunk_item(&item("research_paper", 30), &config(10, 0)).unwrap();
+
+        let ids = |chunks: &[RawData]| chunks.iter().map(|c| c.id).collect::<Vec<_>>();
+        assert_eq!(ids(&first), ids(&again));
+        assert_ne!(ids(&first), ids(&other));
+        assert_eq!(first.len(), 3);
+    }
+}
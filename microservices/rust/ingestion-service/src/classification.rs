@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+use crate::sources::SourceRegistry;
+
+/// Sensitivity label of the data a source or content type carries, from least to most
+/// sensitive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Classification {
+    Public,
+    Internal,
+    Confidential,
+    Restricted,
+}
+
+impl Classification {
+    /// Parse a classification name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "public" => Some(Self::Public),
+            "internal" => Some(Self::Internal),
+            "confidential" => Some(Self::Confidential),
+            "restricted" => Some(Self::Restricted),
+            _ => None,
+        }
+    }
+
+    /// Name of the classification as used in configuration and manifests
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Public => "public",
+            Self::Internal => "internal",
+            Self::Confidential => "confidential",
+            Self::Restricted => "restricted",
+        }
+    }
+}
+
+/// Settings for labelling and enforcing data classifications
+#[derive(Debug, Clone, Default)]
+pub struct ClassificationConfig {
+    /// Classifications of specific content types
+    pub content_types: Vec<(String, Classification)>,
+
+    /// Sources allowed to send restricted data
+    pub restricted_sources: Vec<String>,
+}
+
+/// Enforces the handling rules of classified data at ingest.
+///
+/// An item is classified as the stricter of its content type's and its source's labels;
+/// unlabelled items are not checked. Confidential and restricted data may not be fetched
+/// from URLs. Restricted data is only accepted from allowlisted sources, and only while
+/// the spool and history are encrypted at rest.
+pub struct ClassificationPolicy {
+    config: ClassificationConfig,
+    encrypted: bool,
+}
+
+impl ClassificationPolicy {
+    /// Create the policy; `encrypted` tells whether local stores are encrypted at rest
+    pub fn new(config: ClassificationConfig, encrypted: bool) -> Self {
+        if !config.content_types.is_empty() {
+            info!(
+                "Enforcing classifications of {} content types",
+                config.content_types.len()
+            );
+        }
+        if !encrypted
+            && config
+                .content_types
+                .iter()
+                .any(|(_, c)| *c == Classification::Restricted)
+        {
+            warn!("ENCRYPTION_KEYS is not set, items of restricted content types will be rejected");
+        }
+
+        Self { config, encrypted }
+    }
+
+    /// Classification of items of a source and content type, if either is labelled
+    pub fn classify(
+        &self,
+        sources: &SourceRegistry,
+        source: &str,
+        content_type: &str,
+    ) -> Option<Classification> {
+        let content_type = self
+            .config
+            .content_types
+            .iter()
+            .find(|(configured, _)| configured == content_type)
+            .map(|(_, classification)| *classification);
+
+        content_type.max(sources.classification(source))
+    }
+
+    /// Check that an item may be ingested under its classification
+    pub fn check(&self, sources: &SourceRegistry, item: &RawData) -> Result<()> {
+        if self.classify(sources, &item.source, &item.content_type)
+            != Some(Classification::Restricted)
+        {
+            return Ok(());
+        }
+
+        if !self.config.restricted_sources.contains(&item.source) {
+            return Err(AppError::PolicyViolation(format!(
+                "Source {} may not send restricted data",
+                item.source
+            )));
+        }
+
+        if !self.encrypted {
+            return Err(AppError::PolicyViolation(
+                "Restricted data is only accepted while encryption at rest is enabled".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check that content of a source and content type may be fetched from a URL
+    pub fn check_fetch(
+        &self,
+        sources: &SourceRegistry,
+        source: &str,
+        content_type: &str,
+    ) -> Result<()> {
+        match self.classify(sources, source, content_type) {
+            Some(classification) if classification >= Classification::Confidential => {
+                Err(AppError::PolicyViolation(format!(
+                    "Items classified {} may not be fetched from URLs",
+                    classification.as_str()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use bytes::Bytes;
+use chrono::SecondsFormat;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::buffers::BufferPool;
+use crate::encoding::WireFormat;
+use crate::error::Result;
+use crate::models::RawData;
+use crate::nats::Headers;
+use crate::registry::RegisteredSchema;
+
+/// CloudEvents version events are published as
+pub const SPEC_VERSION: &str = "1.0";
+
+/// Prefix of the headers carrying event attributes in binary mode
+pub const HEADER_PREFIX: &str = "ce-";
+
+/// Header carrying `datacontenttype` in binary mode and the event format in structured mode
+pub const CONTENT_TYPE_HEADER: &str = "content-type";
+
+/// Content type of structured events
+pub const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json";
+
+/// Extension attribute carrying the item's metadata as JSON
+pub const METADATA_EXTENSION: &str = "ingestmetadata";
+
+/// Partitioning extension attribute carrying the item's partition key
+pub const PARTITION_KEY_EXTENSION: &str = "partitionkey";
+
+/// How events are laid out in a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloudEventsMode {
+    /// The whole event as JSON in the body
+    Structured,
+
+    /// The data alone in the body, with the attributes in `ce-` headers
+    Binary,
+}
+
+impl CloudEventsMode {
+    /// Parse a mode name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "structured" => Some(Self::Structured),
+            "binary" => Some(Self::Binary),
+            _ => None,
+        }
+    }
+
+    /// Name of the mode as used in configuration
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Structured => "structured",
+            Self::Binary => "binary",
+        }
+    }
+}
+
+/// Settings for publishing items as CloudEvents
+#[derive(Debug, Clone)]
+pub struct CloudEventsConfig {
+    pub mode: CloudEventsMode,
+
+    /// Prepended to the content type to form the event `type`, e.g. `ingest.`
+    pub type_prefix: String,
+
+    /// Prepended to the item source to form the event `source`, e.g. `/ingestion/`
+    pub source_prefix: String,
+}
+
+/// A structured event; `data` holds JSON payloads and `data_base64` Avro ones
+#[derive(Serialize)]
+struct StructuredEvent<'a> {
+    specversion: &'static str,
+    id: String,
+    source: String,
+    #[serde(rename = "type")]
+    event_type: String,
+    time: String,
+    datacontenttype: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ingestmetadata: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partitionkey: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<&'a Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_base64: Option<String>,
+}
+
+impl CloudEventsConfig {
+    /// Encoding of event data for a content type's configured format.
+    ///
+    /// The event attributes replace the envelope, so the data is the payload alone: Avro
+    /// when the content type is Avro and JSON otherwise, as MessagePack and Protobuf only
+    /// describe the whole envelope.
+    pub fn data_format(format: WireFormat) -> WireFormat {
+        match format {
+            WireFormat::Avro => WireFormat::Avro,
+            _ => WireFormat::Json,
+        }
+    }
+
+    /// Headers and body of the event for an item
+    pub fn encode(
+        &self,
+        item: &RawData,
+        format: WireFormat,
+        buffers: &BufferPool,
+        schema: Option<&RegisteredSchema>,
+    ) -> Result<(Headers, Bytes)> {
+        let avro = Self::data_format(format) == WireFormat::Avro;
+        let datacontenttype = if avro {
+            "application/avro"
+        } else {
+            "application/json"
+        };
+        let metadata = (!item.metadata.is_null()).then(|| item.metadata.to_string());
+
+        match self.mode {
+            CloudEventsMode::Binary => {
+                let mut headers = vec![
+                    (
+                        format!("{}specversion", HEADER_PREFIX),
+                        SPEC_VERSION.to_string(),
+                    ),
+                    (format!("{}id", HEADER_PREFIX), item.id.to_string()),
+                    (format!("{}source", HEADER_PREFIX), self.source(item)),
+                    (format!("{}type", HEADER_PREFIX), self.event_type(item)),
+                    (format!("{}time", HEADER_PREFIX), time(item)),
+                    (CONTENT_TYPE_HEADER.to_string(), datacontenttype.to_string()),
+                ];
+                if let Some(metadata) = metadata {
+                    headers.push((format!("{}{}", HEADER_PREFIX, METADATA_EXTENSION), metadata));
+                }
+                if let Some(partition_key) = &item.partition_key {
+                    headers.push((
+                        format!("{}{}", HEADER_PREFIX, PARTITION_KEY_EXTENSION),
+                        partition_key.clone(),
+                    ));
+                }
+                let data = if avro {
+                    format.encode(item, buffers, schema)?
+                } else {
+                    buffers.serialize(&item.payload)?
+                };
+                Ok((headers, data))
+            }
+            CloudEventsMode::Structured => {
+                let event = StructuredEvent {
+                    specversion: SPEC_VERSION,
+                    id: item.id.to_string(),
+                    source: self.source(item),
+                    event_type: self.event_type(item),
+                    time: time(item),
+                    datacontenttype,
+                    ingestmetadata: metadata,
+                    partitionkey: item.partition_key.as_deref(),
+                    data: (!avro).then_some(&item.payload),
+                    data_base64: if avro {
+                        Some(STANDARD.encode(format.encode(item, buffers, schema)?))
+                    } else {
+                        None
+                    },
+                };
+                let headers = vec![(
+                    CONTENT_TYPE_HEADER.to_string(),
+                    STRUCTURED_CONTENT_TYPE.to_string(),
+                )];
+                Ok((headers, buffers.serialize(&event)?))
+            }
+        }
+    }
+
+    fn source(&self, item: &RawData) -> String {
+        format!("{}{}", self.source_prefix, item.source)
+    }
+
+    fn event_type(&self, item: &RawData) -> String {
+        format!("{}{}", self.type_prefix, item.content_type)
+    }
+}
+
+fn time(item: &RawData) -> String {
+    item.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
@@ -0,0 +1,63 @@
+use bytes::Bytes;
+use tracing::info;
+
+use crate::error::{AppError, Result};
+
+/// Header naming the compression applied to a message body, set to `zstd`
+pub const CONTENT_ENCODING_HEADER: &str = "content-encoding";
+
+/// Value of `content-encoding` for zstd-compressed bodies
+pub const ZSTD_ENCODING: &str = "zstd";
+
+/// Settings for compressing large message bodies
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    /// Encoded payloads larger than this many bytes are compressed
+    pub threshold: usize,
+
+    /// zstd level, from 1 (fastest) to 22 (smallest)
+    pub level: i32,
+}
+
+/// Compresses encoded payloads above the threshold with zstd, to save bus bandwidth on large
+/// bodies such as scraped HTML.
+///
+/// Compressed messages carry `content-encoding: zstd` and keep their other headers,
+/// including `Ingest-Format` describing the payload once decompressed. Payloads that do
+/// not shrink are sent as they are.
+pub struct Compressor {
+    threshold: usize,
+    level: i32,
+}
+
+impl Compressor {
+    /// Create a compressor
+    pub fn new(config: &CompressionConfig) -> Self {
+        info!(
+            "Compressing payloads over {} bytes with zstd level {}",
+            config.threshold, config.level
+        );
+        Self {
+            threshold: config.threshold,
+            level: config.level,
+        }
+    }
+
+    /// Whether an encoded payload is compressed
+    pub fn applies(&self, payload: &Bytes) -> bool {
+        payload.len() > self.threshold
+    }
+
+    /// Compress a payload, or `None` when compressing it saves nothing
+    pub async fn compress(&self, payload: &Bytes) -> Result<Option<Bytes>> {
+        // Compression is CPU-bound, keep it off the async workers
+        let input = payload.clone();
+        let level = self.level;
+        let compressed = tokio::task::spawn_blocking(move || zstd::bulk::compress(&input, level))
+            .await
+            .map_err(|e| AppError::InternalError(format!("Compression task failed: {}", e)))?
+            .map_err(|e| AppError::InternalError(format!("Failed to compress payload: {}", e)))?;
+
+        Ok((compressed.len() < payload.len()).then(|| compressed.into()))
+    }
+}
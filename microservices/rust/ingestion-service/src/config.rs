@@ -1,17 +1,352 @@
+use std::collections::HashMap;
 use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
 use tracing::warn;
 
+use crate::amqp::AmqpConfig;
+use crate::analytics::AnalyticsConfig;
+use crate::audit::AuditConfig;
+use crate::audit_mirror::{AuditMirrorConfig, DEFAULT_AUDIT_SUBJECT, DEFAULT_REQUESTER_HEADER};
+use crate::aws::{AwsConfig, AwsTarget};
+use crate::backlog::BacklogConfig;
+use crate::buffers::BufferPoolConfig;
+use crate::bus::BusBackend;
+use crate::chunk::ChunkConfig;
+use crate::classification::{Classification, ClassificationConfig};
+use crate::cloudevents::{CloudEventsConfig, CloudEventsMode};
+use crate::compression::CompressionConfig;
+use crate::dead_letter::{DeadLetterConfig, DEFAULT_DEAD_LETTER_SUBJECT};
+use crate::document::{DocumentConfig, DEFAULT_MANIFEST_SUBJECT};
+use crate::email::EmailConfig;
+use crate::embedding::EmbeddingConfig;
+use crate::encoding::{WireFormat, WireFormatConfig};
+use crate::encryption::EncryptionConfig;
+use crate::eventgrid::EventGridConfig;
+use crate::expiry::ExpiryConfig;
+use crate::fetch::FetchConfig;
+use crate::heartbeat::{HeartbeatConfig, DEFAULT_HEARTBEAT_SUBJECT};
+use crate::history::HistoryConfig;
+use crate::http::ProxyConfig;
+use crate::idempotency::IdempotencyConfig;
+use crate::keys::{KeyRingConfig, KeyVersion};
+use crate::local_outbox::LocalOutboxConfig;
+use crate::logging::RepeatLimit;
+use crate::minhash::NearDuplicateConfig;
+use crate::nats::{
+    parse_retention, NatsAuth, NatsTlsConfig, PublishRetryPolicy, SimulationMode, StreamConfig,
+    StreamRetention,
+};
+use crate::offload::OffloadConfig;
+use crate::outbox::OutboxConfig;
+use crate::profiling::ProfilingConfig;
+use crate::pubsub::PubSubConfig;
+use crate::rate::{RateCapConfig, RatePolicy};
+use crate::redis_streams::RedisStreamsConfig;
+use crate::registry::SchemaRegistryConfig;
+use crate::retention::{RetentionConfig, RetentionPolicy};
+use crate::retraction::{RetractionConfig, DEFAULT_RETRACTION_SUBJECT};
+use crate::routing::RoutingConfig;
+use crate::sanitize::SanitizeMode;
+use crate::schedule::ScheduleConfig;
+use crate::script::ScriptConfig;
+use crate::shard::{ShardConfig, ShardKey};
+use crate::spool::SpoolConfig;
+use crate::ssrf::SsrfConfig;
+use crate::stomp::StompConfig;
+use crate::subject::{TokenPolicy, DEFAULT_SUBJECT_TEMPLATE};
+use crate::tcp::TcpIngestConfig;
+use crate::telemetry::{SamplingRule, TraceSampling};
+use crate::udp::UdpIngestConfig;
+use crate::upload::UploadConfig;
+use crate::usage::{BillingPeriod, UsageConfig};
+use crate::webhook::WebhookConfig;
+
+/// Credential that is redacted when the configuration is logged
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    /// The secret value
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Wrap a secret value
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// Compare against a presented value in constant time
+    pub fn matches(&self, candidate: &str) -> bool {
+        let (a, b) = (self.0.as_bytes(), candidate.as_bytes());
+        a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("\"[redacted]\"")
+    }
+}
+
 /// Application configuration loaded from environment variables
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     /// Port to listen on
     pub port: u16,
-    
-    /// NATS server URL
-    pub nats_url: String,
-    
+
+    /// NATS server URLs, tried in turn on connect and on failover
+    pub nats_urls: Vec<String>,
+
     /// Environment name (development, staging, production)
     pub environment: String,
+
+    /// Content types whose payloads have embedded HTML sanitized before publishing
+    pub sanitize_content_types: Vec<String>,
+
+    /// How embedded HTML is sanitized
+    pub sanitize_mode: SanitizeMode,
+
+    /// Whether text is extracted from inline documents attached to payloads
+    pub extract_document_text: bool,
+
+    /// Chunking of long text payloads for embedding consumers
+    pub chunking: ChunkConfig,
+
+    /// Inline embedding provider, disabled when no endpoint is configured
+    pub embedding: Option<EmbeddingConfig>,
+
+    /// Near-duplicate detection, disabled when no content types are configured
+    pub near_duplicate: Option<NearDuplicateConfig>,
+
+    /// Content types scanned for license identifiers
+    pub license_content_types: Vec<String>,
+
+    /// URL fetching for `/ingest/url`, disabled unless `FETCH_ENABLED` is set
+    pub fetch: Option<FetchConfig>,
+
+    /// Proxy used by all outbound HTTP clients
+    pub proxy: ProxyConfig,
+
+    /// Protection against user-supplied URLs reaching internal hosts
+    pub ssrf: SsrfConfig,
+
+    /// How long `/health` and `/stats` responses are cached
+    pub status_cache_ttl: Duration,
+
+    /// Disk spool for messages that fail to publish, disabled unless `SPOOL_DIR` is set
+    pub spool: Option<SpoolConfig>,
+
+    /// Bearer token for `/admin` routes, which are disabled when unset
+    pub admin_token: Option<Secret>,
+
+    /// Source manifest loaded at startup
+    pub sources_manifest: Option<PathBuf>,
+
+    /// Rehearsal mode that keeps publishes out of the real streams
+    pub simulation: Option<SimulationMode>,
+
+    /// Whether subjects are prefixed with the environment name
+    pub namespace_subjects: bool,
+
+    /// Sharding of hot content types across streams, disabled unless content types are listed
+    pub sharding: Option<ShardConfig>,
+
+    /// Backlog-aware admission control, disabled unless streams are listed
+    pub backlog: Option<BacklogConfig>,
+
+    /// Webhook receivers, disabled unless a provider secret is configured
+    pub webhook: Option<WebhookConfig>,
+
+    /// IMAP mailbox polling, disabled unless `IMAP_HOST` is set
+    pub email: Option<EmailConfig>,
+
+    /// Google Pub/Sub push endpoint, disabled unless a verification token is set
+    pub pubsub: Option<PubSubConfig>,
+
+    /// Azure Event Grid endpoint, disabled unless an access key is set
+    pub eventgrid: Option<EventGridConfig>,
+
+    /// Newline-delimited JSON TCP listener, disabled unless `TCP_LISTEN_ADDR` is set
+    pub tcp: Option<TcpIngestConfig>,
+
+    /// UDP telemetry listener, disabled unless `UDP_LISTEN_ADDR` is set
+    pub udp: Option<UdpIngestConfig>,
+
+    /// STOMP-over-WebSocket endpoint, disabled unless `STOMP_ENABLED` is set
+    pub stomp: Option<StompConfig>,
+
+    /// Postgres outbox relay, disabled unless `OUTBOX_DATABASE_URL` is set
+    pub outbox: Option<OutboxConfig>,
+
+    /// ClickHouse analytics sink, disabled unless `CLICKHOUSE_URL` is set
+    pub analytics: Option<AnalyticsConfig>,
+
+    /// Local item history, disabled unless `HISTORY_DB_PATH` is set
+    pub history: Option<HistoryConfig>,
+
+    /// Retention purging, disabled unless a store has an age or size limit
+    pub retention: Option<RetentionConfig>,
+
+    /// Encryption of the spool and history at rest, disabled unless `ENCRYPTION_KEYS` is set
+    pub encryption: Option<EncryptionConfig>,
+
+    /// How long publishes wait for a JetStream acknowledgement; publishes are fire-and-forget
+    /// unless `JETSTREAM_PUBLISH` is set
+    pub jetstream_ack_timeout: Option<Duration>,
+
+    /// Ingest stream created or updated at startup, disabled unless `JETSTREAM_PROVISION` is set
+    pub stream: Option<StreamConfig>,
+
+    /// Pool of buffers reused for request bodies and NATS payloads
+    pub buffer_pool: BufferPoolConfig,
+
+    /// Messages held in memory while NATS is unreachable, when there is no disk spool;
+    /// zero turns buffering off
+    pub republish_buffer: usize,
+
+    /// Content types whose buffered messages are republished ahead of others
+    pub republish_priority_content_types: Vec<String>,
+
+    /// Priority messages republished in a row before a waiting normal message gets a turn
+    pub republish_priority_burst: usize,
+
+    /// Bytes each source may republish per turn, so one source's backlog can't hold up others
+    pub republish_quantum: usize,
+
+    /// Encoding of published messages, per content type
+    pub wire_format: WireFormatConfig,
+
+    /// How long a processor probe waits for a reply
+    pub probe_timeout: Duration,
+
+    /// TLS for the NATS connection, disabled unless `NATS_TLS_ENABLED` is set
+    pub nats_tls: Option<NatsTlsConfig>,
+
+    /// Confluent-compatible schema registry payloads are validated against
+    pub schema_registry: Option<SchemaRegistryConfig>,
+
+    /// Credentials for NATS deployments that require authentication
+    pub nats_auth: Option<NatsAuth>,
+
+    /// Template for the subject of each message, e.g. `ingest.{environment}.{source}.{content_type}`
+    pub subject_template: String,
+
+    /// Per-tenant usage accounting, disabled unless `USAGE_DB_PATH` is set
+    pub usage: Option<UsageConfig>,
+
+    /// Retries of publishes that fail with a transient error
+    pub publish_retry: PublishRetryPolicy,
+
+    /// Signed retractions of ingested items, disabled unless `RETRACTION_SIGNING_KEY` is set
+    pub retraction: Option<RetractionConfig>,
+
+    /// Audit log of operator actions, only written to the service log unless `AUDIT_LOG_PATH` is set
+    pub audit: Option<AuditConfig>,
+
+    /// JSON pointers, e.g. `/metadata/user_id`, searched for data subjects on erasure;
+    /// every string value is searched when empty
+    pub erasure_fields: Vec<String>,
+
+    /// Recognising repeated submissions across replicas, disabled unless `IDEMPOTENCY_KV_BUCKET` is set
+    pub idempotency: Option<IdempotencyConfig>,
+
+    /// Classifications of content types and the sources allowed to send restricted data
+    pub classification: ClassificationConfig,
+
+    /// Offloading of oversized payloads to a NATS object store, disabled unless `OFFLOAD_BUCKET` is set
+    pub offload: Option<OffloadConfig>,
+
+    /// Handling of sources and content types that cannot form a subject token
+    pub subject_tokens: TokenPolicy,
+
+    /// How long pending publishes may take to reach NATS at shutdown
+    pub shutdown_drain_timeout: Duration,
+
+    /// Session-based bulk uploads, disabled unless `UPLOAD_SESSION_DIR` is set
+    pub upload: Option<UploadConfig>,
+
+    /// Per-content-type routing table, disabled unless `ROUTING_TABLE` is set
+    pub routing: Option<RoutingConfig>,
+
+    /// Stream that must capture each content type's messages, by content type. Such
+    /// messages are published through JetStream and fail rather than go unstored.
+    pub expected_streams: HashMap<String, String>,
+
+    /// Per-source validator scripts
+    pub scripts: ScriptConfig,
+
+    /// JSON or YAML file of per-content-type field defaults, if any
+    pub field_defaults: Option<PathBuf>,
+
+    /// RabbitMQ broker items are published to instead of NATS, if `AMQP_URL` is set
+    pub amqp: Option<AmqpConfig>,
+
+    /// Limit on repeats of one log message
+    pub log_repeats: RepeatLimit,
+
+    /// Backend ingested items are published to
+    pub message_bus: BusBackend,
+
+    /// Redis Streams settings, if `REDIS_URL` is set
+    pub redis: Option<RedisStreamsConfig>,
+
+    /// Which traces are marked as sampled for consumers
+    pub trace_sampling: TraceSampling,
+
+    /// SNS topic or SQS queue settings, if `AWS_SNS_TOPIC_ARN` or `AWS_SQS_QUEUE_URL` is set
+    pub aws: Option<AwsConfig>,
+
+    /// On-demand CPU and heap profiles
+    pub profiling: ProfilingConfig,
+
+    /// Dependencies `/ready` requires, replacing the defaults when `READINESS_REQUIRED` is set
+    pub readiness_required: Option<Vec<String>>,
+
+    /// Publish items as CloudEvents, if `CLOUDEVENTS_MODE` is set
+    pub cloudevents: Option<CloudEventsConfig>,
+
+    /// Origins browsers may call the API from, any when empty
+    pub cors_allowed_origins: Vec<String>,
+
+    /// Publish rate caps per content type, if `CONTENT_TYPE_RATE_LIMITS` lists any
+    pub rate_caps: Option<RateCapConfig>,
+
+    /// Publishing of items that fail to publish to a dead-letter subject, disabled unless
+    /// `DEAD_LETTER_ENABLED` is set
+    pub dead_letter: Option<DeadLetterConfig>,
+
+    /// Delivery of items at a requested time, disabled unless `SCHEDULE_DB_PATH` is set
+    pub schedule: Option<ScheduleConfig>,
+
+    /// Expiry of messages waiting in the spool or republish buffer, unless neither
+    /// `MESSAGE_TTL_SECS` nor `MESSAGE_TTL_CONTENT_TYPES` is set
+    pub expiry: Option<ExpiryConfig>,
+
+    /// Persistence of accepted items until they are published, disabled unless
+    /// `LOCAL_OUTBOX_PATH` is set
+    pub local_outbox: Option<LocalOutboxConfig>,
+
+    /// zstd compression of large message bodies, disabled unless `COMPRESSION_THRESHOLD_BYTES`
+    /// is set
+    pub compression: Option<CompressionConfig>,
+
+    /// Assembly manifests for documents sent as several parts, disabled unless
+    /// `DOCUMENT_ASSEMBLY_ENABLED` is set
+    pub documents: Option<DocumentConfig>,
+
+    /// Most JetStream acknowledgements a pipelined batch leaves outstanding before waiting
+    /// for one
+    pub jetstream_max_pending_acks: usize,
+
+    /// Heartbeats announcing the instance on NATS, disabled unless `HEARTBEAT_INTERVAL_SECS`
+    /// is set
+    pub heartbeat: Option<HeartbeatConfig>,
+
+    /// Records of accepted items published to an audit subject, disabled unless
+    /// `AUDIT_MIRROR_ENABLED` is set
+    pub audit_mirror: Option<AuditMirrorConfig>,
 }
 
 impl AppConfig {
@@ -24,23 +359,994 @@ impl AppConfig {
                 warn!("PORT environment variable not set or invalid, using default port 3000");
                 3000
             });
-            
-        let nats_url = env::var("NATS_URL")
-            .unwrap_or_else(|_| {
-                warn!("NATS_URL environment variable not set, using default nats://localhost:4222");
-                "nats://localhost:4222".to_string()
+
+        let mut nats_urls = env_list("NATS_URL");
+        if nats_urls.is_empty() {
+            warn!("NATS_URL environment variable not set, using default nats://localhost:4222");
+            nats_urls.push("nats://localhost:4222".to_string());
+        }
+
+        let environment = env::var("ENVIRONMENT").unwrap_or_else(|_| {
+            warn!("ENVIRONMENT environment variable not set, using default development");
+            "development".to_string()
+        });
+
+        let sanitize_content_types = env_list("SANITIZE_HTML_CONTENT_TYPES");
+
+        let sanitize_mode = env::var("SANITIZE_HTML_MODE")
+            .ok()
+            .and_then(|s| SanitizeMode::parse(&s))
+            .unwrap_or(SanitizeMode::Text);
+
+        let extract_document_text = env_bool("EXTRACT_DOCUMENT_TEXT", false);
+
+        let chunk_size = env_parse("CHUNK_SIZE", 512usize).max(1);
+        let mut chunk_overlap = env_parse("CHUNK_OVERLAP", 64usize);
+        if chunk_overlap >= chunk_size {
+            warn!(
+                "CHUNK_OVERLAP must be smaller than CHUNK_SIZE, using {}",
+                chunk_size / 4
+            );
+            chunk_overlap = chunk_size / 4;
+        }
+
+        let chunking = ChunkConfig {
+            content_types: env_list("CHUNK_CONTENT_TYPES"),
+            text_field: env::var("CHUNK_TEXT_FIELD").unwrap_or_else(|_| "text".to_string()),
+            size: chunk_size,
+            overlap: chunk_overlap,
+        };
+
+        let embedding = env::var("EMBEDDING_ENDPOINT")
+            .ok()
+            .map(|endpoint| EmbeddingConfig {
+                endpoint,
+                model: env::var("EMBEDDING_MODEL").unwrap_or_else(|_| "default".to_string()),
+                api_key: env::var("EMBEDDING_API_KEY").ok().map(Secret),
+                content_types: env_list("EMBEDDING_CONTENT_TYPES"),
+                text_field: env::var("EMBEDDING_TEXT_FIELD").unwrap_or_else(|_| "text".to_string()),
+                timeout: Duration::from_millis(env_parse("EMBEDDING_TIMEOUT_MS", 5000)),
+            });
+
+        let near_duplicate_types = env_list("NEAR_DUP_CONTENT_TYPES");
+        let near_duplicate = (!near_duplicate_types.is_empty()).then(|| NearDuplicateConfig {
+            content_types: near_duplicate_types,
+            text_field: env::var("NEAR_DUP_TEXT_FIELD").unwrap_or_else(|_| "text".to_string()),
+            threshold: env_parse("NEAR_DUP_THRESHOLD", 0.8),
+            window: env_parse("NEAR_DUP_WINDOW", 10_000usize),
+        });
+
+        let license_content_types = match env::var("LICENSE_DETECTION_CONTENT_TYPES") {
+            Ok(_) => env_list("LICENSE_DETECTION_CONTENT_TYPES"),
+            Err(_) => vec!["code_repository".to_string(), "research_paper".to_string()],
+        };
+
+        let fetch = env_bool("FETCH_ENABLED", false).then(|| FetchConfig {
+            user_agent: env::var("FETCH_USER_AGENT")
+                .unwrap_or_else(|_| format!("chimera-ingestion/{}", env!("CARGO_PKG_VERSION"))),
+            max_concurrency_per_domain: env_parse("FETCH_MAX_CONCURRENCY_PER_DOMAIN", 2),
+            min_interval: Duration::from_millis(env_parse("FETCH_MIN_INTERVAL_MS", 1000)),
+            timeout: Duration::from_millis(env_parse("FETCH_TIMEOUT_MS", 10_000)),
+            max_bytes: env_parse("FETCH_MAX_BYTES", 10 * 1024 * 1024),
+            robots_ttl: Duration::from_secs(env_parse("ROBOTS_CACHE_TTL_SECS", 3600)),
+        });
+
+        let proxy = ProxyConfig {
+            url: env::var("OUTBOUND_PROXY_URL")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(Secret),
+            no_proxy: env::var("OUTBOUND_NO_PROXY").ok(),
+        };
+
+        let ssrf = SsrfConfig {
+            enabled: env_bool("SSRF_PROTECTION", true),
+            allowlist: env_list("SSRF_ALLOWLIST"),
+        };
+
+        let status_cache_ttl = Duration::from_millis(env_parse("STATUS_CACHE_TTL_MS", 1000));
+
+        let spool = env::var("SPOOL_DIR").ok().map(|dir| SpoolConfig {
+            dir: PathBuf::from(dir),
+            fsync: env_bool("SPOOL_FSYNC", true),
+            drain_interval: Duration::from_millis(env_parse("SPOOL_DRAIN_INTERVAL_MS", 1000)),
+            ready_threshold: env::var("SPOOL_READY_THRESHOLD")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            io_uring: env_bool("SPOOL_IO_URING", false),
+        });
+
+        let admin_token = env::var("ADMIN_TOKEN")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(Secret);
+        let sources_manifest = env::var("SOURCES_MANIFEST").ok().map(PathBuf::from);
+
+        // A content type's own count, as `content_type=count`, overrides SHARD_COUNT
+        let shard_count = env_parse("SHARD_COUNT", 4u32).max(1);
+        let shard_content_types: Vec<(String, u32)> = env_list("SHARD_CONTENT_TYPES")
+            .into_iter()
+            .filter_map(|entry| match entry.split_once('=') {
+                None => Some((entry, shard_count)),
+                Some((content_type, count)) => match count.trim().parse::<u32>() {
+                    Ok(count) if count > 0 => Some((content_type.trim().to_string(), count)),
+                    _ => {
+                        warn!("Ignoring SHARD_CONTENT_TYPES entry {} without a positive shard count", entry);
+                        None
+                    }
+                },
+            })
+            .collect();
+        let sharding = (!shard_content_types.is_empty()).then(|| ShardConfig {
+            content_types: shard_content_types,
+            key: env::var("SHARD_KEY")
+                .ok()
+                .and_then(|s| ShardKey::parse(&s))
+                .unwrap_or(ShardKey::Source),
+        });
+
+        let backlog_streams: Vec<(String, String)> = env_list("BACKLOG_STREAMS")
+            .into_iter()
+            .filter_map(|entry| match entry.split_once('=') {
+                Some((content_type, stream)) => {
+                    Some((content_type.trim().to_string(), stream.trim().to_string()))
+                }
+                None => {
+                    warn!(
+                        "Ignoring BACKLOG_STREAMS entry {} without content_type=stream",
+                        entry
+                    );
+                    None
+                }
+            })
+            .collect();
+        let backlog = (!backlog_streams.is_empty()).then(|| BacklogConfig {
+            streams: backlog_streams,
+            max_messages: env::var("BACKLOG_MAX_MESSAGES")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            max_pending: env::var("BACKLOG_MAX_PENDING")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            poll_interval: Duration::from_millis(env_parse("BACKLOG_POLL_INTERVAL_MS", 5000u64)),
+        });
+
+        // Key files are re-read this often, so rotated secrets are picked up without a restart
+        let key_refresh = Duration::from_secs(env_parse("KEY_REFRESH_INTERVAL_SECS", 60u64).max(1));
+
+        let webhook = env_key_ring(
+            "GITHUB_WEBHOOK_SECRETS",
+            Some("GITHUB_WEBHOOK_SECRET"),
+            None,
+            key_refresh,
+        )
+        .map(|github_secrets| WebhookConfig {
+            github_secrets,
+            github_content_type: env::var("GITHUB_WEBHOOK_CONTENT_TYPE")
+                .unwrap_or_else(|_| "github_event".to_string()),
+            replay_ttl: Duration::from_secs(
+                env_parse("WEBHOOK_REPLAY_TTL_SECS", 259_200u64).max(1),
+            ),
+            replay_kv_bucket: env::var("WEBHOOK_REPLAY_KV_BUCKET")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "WEBHOOK_DELIVERIES".to_string()),
+        });
+
+        let email = env::var("IMAP_HOST")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|host| {
+                let mailboxes = env_list("IMAP_MAILBOXES");
+                EmailConfig {
+                    host,
+                    port: env_parse("IMAP_PORT", 993u16),
+                    username: env::var("IMAP_USERNAME").unwrap_or_default(),
+                    password: Secret(env::var("IMAP_PASSWORD").unwrap_or_default()),
+                    mailboxes: if mailboxes.is_empty() {
+                        vec!["INBOX".to_string()]
+                    } else {
+                        mailboxes
+                    },
+                    poll_interval: Duration::from_secs(env_parse("IMAP_POLL_INTERVAL_SECS", 60u64)),
+                    source: env::var("IMAP_SOURCE").unwrap_or_else(|_| "email".to_string()),
+                    content_type: "email".to_string(),
+                    attachment_bucket: env::var("EMAIL_ATTACHMENT_BUCKET")
+                        .ok()
+                        .filter(|s| !s.is_empty()),
+                }
+            });
+
+        let pubsub = env::var("PUBSUB_VERIFICATION_TOKEN")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|token| PubSubConfig {
+                verification_token: Secret(token),
+                source: env::var("PUBSUB_SOURCE").ok().filter(|s| !s.is_empty()),
+                content_type: env::var("PUBSUB_CONTENT_TYPE")
+                    .ok()
+                    .filter(|s| !s.is_empty()),
+            });
+
+        let eventgrid = env::var("EVENTGRID_ACCESS_KEY")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|key| EventGridConfig {
+                access_key: Secret(key),
+                source: env::var("EVENTGRID_SOURCE")
+                    .unwrap_or_else(|_| "azure-event-grid".to_string()),
+                content_type: env::var("EVENTGRID_CONTENT_TYPE")
+                    .unwrap_or_else(|_| "azure_event".to_string()),
+            });
+
+        let tcp = env::var("TCP_LISTEN_ADDR")
+            .ok()
+            .and_then(|addr| match addr.parse() {
+                Ok(addr) => Some(TcpIngestConfig {
+                    addr,
+                    auth_token: env::var("TCP_AUTH_TOKEN")
+                        .ok()
+                        .filter(|s| !s.is_empty())
+                        .map(Secret),
+                    max_line_bytes: env_parse("TCP_MAX_LINE_BYTES", 1_048_576usize),
+                }),
+                Err(_) => {
+                    warn!("Ignoring invalid TCP_LISTEN_ADDR {}", addr);
+                    None
+                }
+            });
+
+        let udp = env::var("UDP_LISTEN_ADDR")
+            .ok()
+            .and_then(|addr| match addr.parse() {
+                Ok(addr) => Some(UdpIngestConfig {
+                    addr,
+                    source: env::var("UDP_SOURCE").unwrap_or_else(|_| "udp".to_string()),
+                    content_type: env::var("UDP_CONTENT_TYPE")
+                        .unwrap_or_else(|_| "telemetry".to_string()),
+                    batch_size: env_parse("UDP_BATCH_SIZE", 500usize).max(1),
+                    flush_interval: Duration::from_millis(env_parse(
+                        "UDP_FLUSH_INTERVAL_MS",
+                        1000u64,
+                    )),
+                    queue_capacity: env_parse("UDP_QUEUE_CAPACITY", 10_000usize),
+                }),
+                Err(_) => {
+                    warn!("Ignoring invalid UDP_LISTEN_ADDR {}", addr);
+                    None
+                }
+            });
+
+        let stomp = env_bool("STOMP_ENABLED", false).then(|| StompConfig {
+            passcode: env::var("STOMP_PASSCODE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(Secret),
+        });
+
+        let outbox = env::var("OUTBOX_DATABASE_URL")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|url| OutboxConfig {
+                database_url: Secret(url),
+                table: env::var("OUTBOX_TABLE").unwrap_or_else(|_| "ingest_outbox".to_string()),
+                poll_interval: Duration::from_millis(env_parse("OUTBOX_POLL_INTERVAL_MS", 1000u64)),
+                batch_size: env_parse("OUTBOX_BATCH_SIZE", 100i64).max(1),
+            });
+
+        let analytics = env::var("CLICKHOUSE_URL")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|url| AnalyticsConfig {
+                url,
+                table: env::var("CLICKHOUSE_TABLE").unwrap_or_else(|_| "ingest_events".to_string()),
+                user: env::var("CLICKHOUSE_USER").ok().filter(|s| !s.is_empty()),
+                password: env::var("CLICKHOUSE_PASSWORD")
+                    .ok()
+                    .filter(|s| !s.is_empty())
+                    .map(Secret),
+                batch_size: env_parse("CLICKHOUSE_BATCH_SIZE", 1000usize).max(1),
+                flush_interval: Duration::from_millis(env_parse(
+                    "CLICKHOUSE_FLUSH_INTERVAL_MS",
+                    5000u64,
+                )),
+                queue_capacity: env_parse("CLICKHOUSE_QUEUE_CAPACITY", 100_000usize),
+            });
+
+        let history = env::var("HISTORY_DB_PATH")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|path| HistoryConfig {
+                path: PathBuf::from(path),
+            });
+
+        let retention_policy = |prefix: &str| RetentionPolicy {
+            max_age: env::var(format!("{}_MAX_AGE_SECS", prefix))
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs),
+            max_bytes: env::var(format!("{}_MAX_BYTES", prefix))
+                .ok()
+                .and_then(|s| s.parse().ok()),
+        };
+        let retention = Some(RetentionConfig {
+            interval: Duration::from_secs(env_parse("RETENTION_INTERVAL_SECS", 3600u64).max(1)),
+            spool: retention_policy("SPOOL"),
+            history: retention_policy("HISTORY"),
+        })
+        .filter(|r| r.spool.is_set() || r.history.is_set());
+
+        let encryption = env_key_ring(
+            "ENCRYPTION_KEYS",
+            None,
+            Some("ENCRYPTION_ACTIVE_KEY"),
+            key_refresh,
+        )
+        .map(|keys| EncryptionConfig { keys });
+
+        let jetstream_ack_timeout = env_bool("JETSTREAM_PUBLISH", false)
+            .then(|| Duration::from_millis(env_parse("JETSTREAM_ACK_TIMEOUT_MS", 5000)));
+
+        let stream = env_bool("JETSTREAM_PROVISION", false).then(|| StreamConfig {
+            name: env::var("JETSTREAM_STREAM_NAME")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "INGEST_RAW".to_string()),
+            retention: env::var("JETSTREAM_RETENTION")
+                .ok()
+                .and_then(|s| {
+                    let retention = parse_retention(&s);
+                    if retention.is_none() {
+                        warn!("Ignoring unknown JETSTREAM_RETENTION {}", s);
+                    }
+                    retention
+                })
+                .unwrap_or(StreamRetention::Limits),
+            max_bytes: env::var("JETSTREAM_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            max_age: env::var("JETSTREAM_MAX_AGE_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs),
+            replicas: env_parse("JETSTREAM_REPLICAS", 1usize).clamp(1, 5),
+            duplicate_window: Duration::from_secs(env_parse(
+                "JETSTREAM_DUPLICATE_WINDOW_SECS",
+                120u64,
+            )),
+        });
+
+        let buffer_pool = BufferPoolConfig {
+            buffers: env_parse("BUFFER_POOL_SIZE", 256),
+            max_capacity: env_parse("BUFFER_POOL_MAX_CAPACITY", 1024 * 1024),
+        };
+
+        let wire_format = WireFormatConfig {
+            default: env::var("NATS_FORMAT")
+                .ok()
+                .and_then(|s| {
+                    let format = WireFormat::parse(&s);
+                    if format.is_none() {
+                        warn!("Ignoring unknown NATS_FORMAT {}", s);
+                    }
+                    format
+                })
+                .unwrap_or(WireFormat::Json),
+            content_types: env_list("NATS_FORMAT_CONTENT_TYPES")
+                .into_iter()
+                .filter_map(|entry| match entry.split_once('=').map(|(t, f)| (t.trim(), WireFormat::parse(f))) {
+                    Some((content_type, Some(format))) => Some((content_type.to_string(), format)),
+                    _ => {
+                        warn!("Ignoring NATS_FORMAT_CONTENT_TYPES entry {} without content_type=format", entry);
+                        None
+                    }
+                })
+                .collect(),
+        };
+
+        let schema_registry = env::var("SCHEMA_REGISTRY_URL")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|url| SchemaRegistryConfig {
+                url,
+                username: env::var("SCHEMA_REGISTRY_USERNAME")
+                    .ok()
+                    .filter(|s| !s.is_empty()),
+                password: env::var("SCHEMA_REGISTRY_PASSWORD")
+                    .ok()
+                    .filter(|s| !s.is_empty())
+                    .map(Secret),
+                content_types: env_list("SCHEMA_REGISTRY_CONTENT_TYPES"),
+                subject_suffix: env::var("SCHEMA_REGISTRY_SUBJECT_SUFFIX")
+                    .unwrap_or_else(|_| "-value".to_string()),
+                cache_ttl: Duration::from_secs(env_parse("SCHEMA_REGISTRY_CACHE_TTL_SECS", 300u64)),
+                timeout: Duration::from_millis(env_parse("SCHEMA_REGISTRY_TIMEOUT_MS", 5000u64)),
+            });
+
+        let nats_creds_file = env::var("NATS_CREDS_FILE").ok().filter(|s| !s.is_empty());
+        let nats_nkey_seed = env::var("NATS_NKEY_SEED").ok().filter(|s| !s.is_empty());
+        if nats_creds_file.is_some() && nats_nkey_seed.is_some() {
+            warn!("Both NATS_CREDS_FILE and NATS_NKEY_SEED are set, using the credentials file");
+        }
+        let nats_auth = nats_creds_file
+            .map(|path| NatsAuth::CredsFile(PathBuf::from(path)))
+            .or_else(|| nats_nkey_seed.map(|seed| NatsAuth::NKey(Secret(seed))));
+
+        let usage = env::var("USAGE_DB_PATH")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|path| UsageConfig {
+                path: PathBuf::from(path),
+                period: env::var("USAGE_PERIOD")
+                    .ok()
+                    .and_then(|s| {
+                        let period = BillingPeriod::parse(&s);
+                        if period.is_none() {
+                            warn!("Ignoring unknown USAGE_PERIOD {}", s);
+                        }
+                        period
+                    })
+                    .unwrap_or(BillingPeriod::Month),
+                flush_interval: Duration::from_secs(
+                    env_parse("USAGE_FLUSH_INTERVAL_SECS", 60u64).max(1),
+                ),
+            });
+
+        let retraction = env_key_ring(
+            "RETRACTION_SIGNING_KEYS",
+            Some("RETRACTION_SIGNING_KEY"),
+            Some("RETRACTION_ACTIVE_KEY"),
+            key_refresh,
+        )
+        .map(|signing_keys| RetractionConfig {
+            signing_keys,
+            subject: env::var("RETRACTION_SUBJECT")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_RETRACTION_SUBJECT.to_string()),
+        });
+
+        let audit = env::var("AUDIT_LOG_PATH")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|path| AuditConfig {
+                path: PathBuf::from(path),
+            });
+
+        let idempotency = env::var("IDEMPOTENCY_KV_BUCKET")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|kv_bucket| IdempotencyConfig {
+                kv_bucket,
+                ttl: Duration::from_secs(env_parse("IDEMPOTENCY_TTL_SECS", 86_400u64).max(1)),
+            });
+
+        let classification = ClassificationConfig {
+            content_types: env_list("CONTENT_TYPE_CLASSIFICATIONS")
+                .into_iter()
+                .filter_map(|entry| match entry.split_once('=').map(|(t, c)| (t.trim(), Classification::parse(c))) {
+                    Some((content_type, Some(classification))) => Some((content_type.to_string(), classification)),
+                    _ => {
+                        warn!("Ignoring CONTENT_TYPE_CLASSIFICATIONS entry {} without content_type=classification", entry);
+                        None
+                    }
+                })
+                .collect(),
+            restricted_sources: env_list("CLASSIFICATION_RESTRICTED_SOURCES"),
+        };
+
+        let offload = env::var("OFFLOAD_BUCKET")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|bucket| OffloadConfig {
+                bucket,
+                threshold: env_parse("OFFLOAD_THRESHOLD_BYTES", 786_432usize),
+                max_age: Duration::from_secs(env_parse("OFFLOAD_MAX_AGE_SECS", 0u64)),
+            });
+
+        let upload = env::var("UPLOAD_SESSION_DIR").ok().map(|dir| UploadConfig {
+            dir: PathBuf::from(dir),
+            ttl: Duration::from_secs(env_parse("UPLOAD_SESSION_TTL_SECS", 3600)),
+            max_chunk_bytes: env_parse("UPLOAD_MAX_CHUNK_BYTES", 8 * 1024 * 1024),
+            max_items: env_parse("UPLOAD_MAX_SESSION_ITEMS", 100_000),
+        });
+
+        let routing = env::var("ROUTING_TABLE").ok().map(|file| RoutingConfig {
+            file: PathBuf::from(file),
+            refresh_interval: Duration::from_secs(
+                env_parse("ROUTING_TABLE_REFRESH_SECS", 30u64).max(1),
+            ),
+        });
+
+        let expected_streams = env_list("NATS_EXPECTED_STREAMS")
+            .into_iter()
+            .filter_map(|entry| match entry.split_once('=') {
+                Some((content_type, stream)) => {
+                    Some((content_type.trim().to_string(), stream.trim().to_string()))
+                }
+                None => {
+                    warn!(
+                        "Ignoring NATS_EXPECTED_STREAMS entry {} without content_type=stream",
+                        entry
+                    );
+                    None
+                }
+            })
+            .collect();
+
+        let amqp = env::var("AMQP_URL").ok().filter(|s| !s.is_empty()).map(|url| AmqpConfig {
+            url: Secret(url),
+            exchange: env::var("AMQP_EXCHANGE").unwrap_or_else(|_| "ingest".to_string()),
+            content_type_exchanges: env_list("AMQP_CONTENT_TYPE_EXCHANGES")
+                .into_iter()
+                .filter_map(|entry| match entry.split_once('=') {
+                    Some((content_type, exchange)) => Some((content_type.trim().to_string(), exchange.trim().to_string())),
+                    None => {
+                        warn!("Ignoring AMQP_CONTENT_TYPE_EXCHANGES entry {} without content_type=exchange", entry);
+                        None
+                    }
+                })
+                .collect(),
+            exchange_type: env::var("AMQP_EXCHANGE_TYPE").unwrap_or_else(|_| "topic".to_string()),
+            declare_exchanges: env_bool("AMQP_DECLARE_EXCHANGES", true),
+            routing_key: env::var("AMQP_ROUTING_KEY").unwrap_or_else(|_| "{content_type}.{source}".to_string()),
+            confirm_timeout: Duration::from_millis(env_parse("AMQP_CONFIRM_TIMEOUT_MS", 5000u64).max(1)),
+            max_reconnect_delay: Duration::from_secs(env_parse("AMQP_MAX_RECONNECT_DELAY_SECS", 30u64).max(1)),
+        });
+
+        let sns_topic = env::var("AWS_SNS_TOPIC_ARN").ok().filter(|s| !s.is_empty());
+        let sqs_queue = env::var("AWS_SQS_QUEUE_URL").ok().filter(|s| !s.is_empty());
+        let aws_target = match (sns_topic, sqs_queue) {
+            (Some(topic), Some(_)) => {
+                warn!("Both AWS_SNS_TOPIC_ARN and AWS_SQS_QUEUE_URL are set, publishing to the SNS topic");
+                Some(AwsTarget::Sns(topic))
+            }
+            (Some(topic), None) => Some(AwsTarget::Sns(topic)),
+            (None, Some(queue)) => Some(AwsTarget::Sqs(queue)),
+            (None, None) => None,
+        };
+        let aws = aws_target.map(|target| AwsConfig {
+            target,
+            publish_timeout: Duration::from_millis(
+                env_parse("AWS_PUBLISH_TIMEOUT_MS", 5000u64).max(1),
+            ),
+        });
+
+        let cloudevents = env::var("CLOUDEVENTS_MODE")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .and_then(|s| {
+                let mode = CloudEventsMode::parse(&s);
+                if mode.is_none() {
+                    warn!("Ignoring unknown CLOUDEVENTS_MODE {}", s);
+                }
+                mode
+            })
+            .map(|mode| CloudEventsConfig {
+                mode,
+                type_prefix: env::var("CLOUDEVENTS_TYPE_PREFIX")
+                    .unwrap_or_else(|_| "ingest.".to_string()),
+                source_prefix: env::var("CLOUDEVENTS_SOURCE_PREFIX").unwrap_or_default(),
+            });
+        if cloudevents.is_some() {
+            let mut formats = std::iter::once(wire_format.default)
+                .chain(wire_format.content_types.iter().map(|(_, f)| *f));
+            if formats.any(|f| CloudEventsConfig::data_format(f) != f) {
+                warn!("CloudEvents carry the payload as JSON or Avro, msgpack and protobuf in NATS_FORMAT are not used");
+            }
+        }
+
+        let rate_caps = env_list("CONTENT_TYPE_RATE_LIMITS")
+            .into_iter()
+            .filter_map(|entry| match entry.split_once('=').map(|(t, r)| (t.trim(), r.trim().parse::<f64>())) {
+                Some((content_type, Ok(rate))) if rate > 0.0 && rate.is_finite() => Some((content_type.to_string(), rate)),
+                _ => {
+                    warn!("Ignoring CONTENT_TYPE_RATE_LIMITS entry {} without content_type=messages_per_second", entry);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let rate_caps = (!rate_caps.is_empty()).then(|| RateCapConfig {
+            content_types: rate_caps,
+            policy: env::var("CONTENT_TYPE_RATE_POLICY")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .and_then(|s| {
+                    let policy = RatePolicy::parse(&s);
+                    if policy.is_none() {
+                        warn!("Ignoring unknown CONTENT_TYPE_RATE_POLICY {}", s);
+                    }
+                    policy
+                })
+                .unwrap_or(RatePolicy::Reject),
+            max_delay: Duration::from_millis(env_parse("CONTENT_TYPE_RATE_MAX_DELAY_MS", 1000u64)),
+        });
+
+        let ttl_content_types = env_list("MESSAGE_TTL_CONTENT_TYPES")
+            .into_iter()
+            .filter_map(|entry| match entry.split_once('=').map(|(t, s)| (t.trim(), s.trim().parse::<u64>())) {
+                Some((content_type, Ok(secs))) if secs > 0 => Some((content_type.to_string(), Duration::from_secs(secs))),
+                _ => {
+                    warn!("Ignoring MESSAGE_TTL_CONTENT_TYPES entry {} without content_type=seconds", entry);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let default_ttl = env::var("MESSAGE_TTL_SECS")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .and_then(|s| {
+                let ttl = s
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|&secs| secs > 0)
+                    .map(Duration::from_secs);
+                if ttl.is_none() {
+                    warn!(
+                        "Ignoring MESSAGE_TTL_SECS {}, it must be a positive number of seconds",
+                        s
+                    );
+                }
+                ttl
             });
-            
-        let environment = env::var("ENVIRONMENT")
-            .unwrap_or_else(|_| {
-                warn!("ENVIRONMENT environment variable not set, using default development");
-                "development".to_string()
+        let expiry =
+            (default_ttl.is_some() || !ttl_content_types.is_empty()).then_some(ExpiryConfig {
+                default: default_ttl,
+                content_types: ttl_content_types,
             });
-            
+
+        // RabbitMQ or AWS is used whenever one is configured, unless another backend is chosen
+        let message_bus = match env::var("MESSAGE_BUS")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+        {
+            Some(name) => BusBackend::parse(&name).unwrap_or_else(|| {
+                warn!("Ignoring unknown MESSAGE_BUS {}", name);
+                BusBackend::Nats
+            }),
+            None if amqp.is_some() => BusBackend::Amqp,
+            None if aws.is_some() => BusBackend::Aws,
+            None => BusBackend::Nats,
+        };
+
+        let redis = env::var("REDIS_URL")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|url| RedisStreamsConfig {
+                url: Secret(url),
+                stream_key: env::var("REDIS_STREAM_KEY")
+                    .unwrap_or_else(|_| "{subject}".to_string()),
+                max_len: Some(env_parse("REDIS_STREAM_MAXLEN", 1_000_000u64))
+                    .filter(|max_len| *max_len > 0),
+                max_age: env::var("REDIS_STREAM_MAX_AGE_SECS")
+                    .ok()
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .filter(|secs| *secs > 0)
+                    .map(Duration::from_secs),
+                exact_trim: env_bool("REDIS_STREAM_EXACT_TRIM", false),
+                response_timeout: Duration::from_millis(
+                    env_parse("REDIS_RESPONSE_TIMEOUT_MS", 5000u64).max(1),
+                ),
+                max_reconnect_delay: Duration::from_secs(
+                    env_parse("REDIS_MAX_RECONNECT_DELAY_SECS", 30u64).max(1),
+                ),
+            });
+
+        let simulation = env::var("INGEST_SIMULATION").ok().and_then(|s| {
+            let mode = SimulationMode::parse(&s);
+            if mode.is_none() && !s.trim().is_empty() {
+                warn!("Ignoring unknown INGEST_SIMULATION mode {}", s);
+            }
+            mode
+        });
+
         Self {
             port,
-            nats_url,
+            nats_urls,
             environment,
+            sanitize_content_types,
+            sanitize_mode,
+            extract_document_text,
+            chunking,
+            embedding,
+            near_duplicate,
+            license_content_types,
+            fetch,
+            proxy,
+            ssrf,
+            status_cache_ttl,
+            spool,
+            admin_token,
+            sources_manifest,
+            simulation,
+            namespace_subjects: env_bool("NATS_SUBJECT_NAMESPACE", false),
+            sharding,
+            backlog,
+            webhook,
+            email,
+            pubsub,
+            eventgrid,
+            tcp,
+            udp,
+            stomp,
+            outbox,
+            analytics,
+            history,
+            retention,
+            encryption,
+            jetstream_ack_timeout,
+            stream,
+            buffer_pool,
+            republish_buffer: env_parse("NATS_REPUBLISH_BUFFER", 10_000),
+            republish_priority_content_types: env_list("NATS_REPUBLISH_PRIORITY_CONTENT_TYPES"),
+            republish_priority_burst: env_parse("NATS_REPUBLISH_PRIORITY_BURST", 8),
+            republish_quantum: env_parse("NATS_REPUBLISH_QUANTUM_BYTES", 64 * 1024),
+            wire_format,
+            probe_timeout: Duration::from_millis(env_parse("PROBE_TIMEOUT_MS", 2000)),
+            nats_tls: env_bool("NATS_TLS_ENABLED", false).then(|| NatsTlsConfig {
+                ca_cert: env::var("NATS_TLS_CA_CERT").ok().filter(|s| !s.is_empty()).map(PathBuf::from),
+                client_cert: env::var("NATS_TLS_CLIENT_CERT").ok().filter(|s| !s.is_empty()).map(PathBuf::from),
+                client_key: env::var("NATS_TLS_CLIENT_KEY").ok().filter(|s| !s.is_empty()).map(PathBuf::from),
+            }),
+            schema_registry,
+            nats_auth,
+            subject_template: env::var("NATS_SUBJECT_TEMPLATE")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_SUBJECT_TEMPLATE.to_string()),
+            usage,
+            publish_retry: PublishRetryPolicy {
+                max_attempts: env_parse("NATS_PUBLISH_MAX_ATTEMPTS", 3u32).max(1),
+                base_delay: Duration::from_millis(env_parse("NATS_PUBLISH_RETRY_BASE_MS", 100u64)),
+                max_delay: Duration::from_millis(env_parse("NATS_PUBLISH_RETRY_MAX_MS", 2000u64)),
+                jitter: Some(env_parse("NATS_PUBLISH_RETRY_JITTER", 0.5f64))
+                    .filter(|jitter| !jitter.is_nan())
+                    .map_or(0.5, |jitter| jitter.clamp(0.0, 1.0)),
+            },
+            retraction,
+            audit,
+            erasure_fields: env_list("ERASURE_SUBJECT_FIELDS"),
+            idempotency,
+            classification,
+            offload,
+            subject_tokens: env::var("SUBJECT_TOKEN_POLICY")
+                .ok()
+                .and_then(|s| {
+                    let policy = TokenPolicy::parse(&s);
+                    if policy.is_none() {
+                        warn!("Ignoring unknown SUBJECT_TOKEN_POLICY {}", s);
+                    }
+                    policy
+                })
+                .unwrap_or(TokenPolicy::Reject),
+            shutdown_drain_timeout: Duration::from_secs(env_parse("SHUTDOWN_DRAIN_TIMEOUT_SECS", 10u64)),
+            upload,
+            routing,
+            expected_streams,
+            scripts: ScriptConfig {
+                dir: env::var("VALIDATOR_SCRIPT_DIR").ok().map(PathBuf::from),
+                max_operations: env_parse("VALIDATOR_SCRIPT_MAX_OPERATIONS", 100_000u64).max(1),
+                timeout: Duration::from_millis(env_parse("VALIDATOR_SCRIPT_TIMEOUT_MS", 20u64).max(1)),
+                max_script_bytes: env_parse("VALIDATOR_SCRIPT_MAX_BYTES", 64 * 1024),
+            },
+            field_defaults: env::var("FIELD_DEFAULTS_FILE").ok().map(PathBuf::from),
+            amqp,
+            log_repeats: RepeatLimit {
+                burst: env_parse("LOG_REPEAT_LIMIT", 10u32),
+                window: Duration::from_secs(env_parse("LOG_REPEAT_WINDOW_SECS", 10u64).max(1)),
+            },
+            message_bus,
+            redis,
+            trace_sampling: TraceSampling {
+                rules: env_list("TRACE_SAMPLING_RULES")
+                    .into_iter()
+                    .filter_map(|entry| {
+                        let rule = SamplingRule::parse(&entry);
+                        if rule.is_none() {
+                            warn!("Ignoring TRACE_SAMPLING_RULES entry {}, expected route:/path=rate, source:name=rate or error=rate", entry);
+                        }
+                        rule
+                    })
+                    .collect(),
+                rate: env_parse("TRACE_SAMPLING_RATE", 1.0f64).clamp(0.0, 1.0),
+            },
+            aws,
+            profiling: ProfilingConfig {
+                enabled: env_bool("PROFILING_ENABLED", false),
+                max_duration: Duration::from_secs(env_parse("PROFILING_MAX_SECONDS", 60u64).max(1)),
+                frequency: env_parse("PROFILING_FREQUENCY_HZ", 99i32).clamp(1, 1000),
+            },
+            // Set but empty means nothing is required
+            readiness_required: env::var("READINESS_REQUIRED").ok().map(|_| env_list("READINESS_REQUIRED")),
+            cloudevents,
+            cors_allowed_origins: env_list("CORS_ALLOWED_ORIGINS")
+                .into_iter()
+                .filter(|origin| {
+                    let valid = axum::http::HeaderValue::from_str(origin).is_ok();
+                    if !valid {
+                        warn!("Ignoring invalid CORS_ALLOWED_ORIGINS entry {}", origin);
+                    }
+                    valid
+                })
+                .collect(),
+            rate_caps,
+            dead_letter: env_bool("DEAD_LETTER_ENABLED", false).then(|| DeadLetterConfig {
+                subject: env::var("DEAD_LETTER_SUBJECT")
+                    .ok()
+                    .filter(|s| !s.trim().is_empty())
+                    .unwrap_or_else(|| DEFAULT_DEAD_LETTER_SUBJECT.to_string()),
+            }),
+            schedule: env::var("SCHEDULE_DB_PATH")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|path| ScheduleConfig {
+                    path: PathBuf::from(path),
+                    max_delay: Duration::from_secs(env_parse("SCHEDULE_MAX_DELAY_SECS", 30 * 24 * 3600u64)),
+                    poll_interval: Duration::from_millis(env_parse("SCHEDULE_POLL_INTERVAL_MS", 1000u64).max(10)),
+                }),
+            expiry,
+            local_outbox: env::var("LOCAL_OUTBOX_PATH")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|path| LocalOutboxConfig {
+                    path: PathBuf::from(path),
+                    retry_interval: Duration::from_millis(env_parse("LOCAL_OUTBOX_RETRY_INTERVAL_MS", 1000u64).max(10)),
+                    batch_size: env_parse("LOCAL_OUTBOX_BATCH_SIZE", 100i64).max(1),
+                }),
+            compression: env::var("COMPRESSION_THRESHOLD_BYTES")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .and_then(|s| {
+                    let threshold = s.trim().parse::<usize>().ok();
+                    if threshold.is_none() {
+                        warn!("Ignoring COMPRESSION_THRESHOLD_BYTES {}, it must be a number of bytes", s);
+                    }
+                    threshold
+                })
+                .map(|threshold| CompressionConfig {
+                    threshold,
+                    level: env_parse("COMPRESSION_LEVEL", 3i32).clamp(1, 22),
+                }),
+            documents: env_bool("DOCUMENT_ASSEMBLY_ENABLED", false).then(|| DocumentConfig {
+                subject: env::var("DOCUMENT_MANIFEST_SUBJECT")
+                    .ok()
+                    .filter(|s| !s.trim().is_empty())
+                    .unwrap_or_else(|| DEFAULT_MANIFEST_SUBJECT.to_string()),
+                timeout: Duration::from_secs(env_parse("DOCUMENT_ASSEMBLY_TIMEOUT_SECS", 3600u64).max(1)),
+            }),
+            jetstream_max_pending_acks: env_parse("JETSTREAM_MAX_PENDING_ACKS", 256usize).max(1),
+            heartbeat: env::var("HEARTBEAT_INTERVAL_SECS")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .and_then(|s| {
+                    let interval = s.trim().parse::<u64>().ok().filter(|secs| *secs > 0);
+                    if interval.is_none() {
+                        warn!("Ignoring HEARTBEAT_INTERVAL_SECS {}, it must be a positive number of seconds", s);
+                    }
+                    interval
+                })
+                .map(|secs| HeartbeatConfig {
+                    subject: env::var("HEARTBEAT_SUBJECT")
+                        .ok()
+                        .filter(|s| !s.trim().is_empty())
+                        .unwrap_or_else(|| DEFAULT_HEARTBEAT_SUBJECT.to_string()),
+                    interval: Duration::from_secs(secs),
+                    instance_id: env::var("HEARTBEAT_INSTANCE_ID")
+                        .or_else(|_| env::var("HOSTNAME"))
+                        .ok()
+                        .filter(|s| !s.trim().is_empty()),
+                }),
+            audit_mirror: env_bool("AUDIT_MIRROR_ENABLED", false).then(|| AuditMirrorConfig {
+                subject: env::var("AUDIT_MIRROR_SUBJECT")
+                    .ok()
+                    .filter(|s| !s.trim().is_empty())
+                    .unwrap_or_else(|| DEFAULT_AUDIT_SUBJECT.to_string()),
+                requester_header: env::var("AUDIT_MIRROR_REQUESTER_HEADER")
+                    .ok()
+                    .map(|s| s.trim().to_ascii_lowercase())
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| DEFAULT_REQUESTER_HEADER.to_string()),
+                stream: env::var("AUDIT_MIRROR_STREAM").ok().filter(|s| !s.trim().is_empty()),
+            }),
         }
     }
+
+    /// Whether payloads of the given content type should have HTML sanitized
+    pub fn should_sanitize(&self, content_type: &str) -> bool {
+        self.sanitize_content_types
+            .iter()
+            .any(|t| t == content_type)
+    }
+
+    /// Whether this is a production deployment, where risky settings fail `check-config`
+    pub fn is_production(&self) -> bool {
+        self.environment.eq_ignore_ascii_case("production")
+    }
+
+    /// Subject namespace derived from the environment, when namespacing is enabled
+    pub fn subject_namespace(&self) -> Option<&str> {
+        self.namespace_subjects.then_some(self.environment.as_str())
+    }
+}
+
+/// Read a key ring from `name`, a comma-separated list of `id=secret` or `id@expiry=secret`
+/// entries, or from the file named by `{name}_FILE`. A single secret in `single` is used as
+/// key `default` when the list is not set. `active` names the variable selecting the active key.
+fn env_key_ring(
+    name: &str,
+    single: Option<&str>,
+    active: Option<&str>,
+    refresh_interval: Duration,
+) -> Option<KeyRingConfig> {
+    let mut keys: Vec<KeyVersion> = env_list(name)
+        .into_iter()
+        .filter_map(|entry| match KeyVersion::parse(&entry) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                warn!("Ignoring {} entry: {}", name, e);
+                None
+            }
+        })
+        .collect();
+    if let Some(secret) = single
+        .and_then(|single| env::var(single).ok())
+        .filter(|s| !s.is_empty() && keys.is_empty())
+    {
+        keys.push(KeyVersion {
+            id: "default".to_string(),
+            secret: Secret(secret),
+            expires_at: None,
+        });
+    }
+
+    let file = env::var(format!("{}_FILE", name))
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from);
+    if file.is_some() && !keys.is_empty() {
+        warn!("Both {} and {}_FILE are set, using the file", name, name);
+    }
+
+    (!keys.is_empty() || file.is_some()).then(|| KeyRingConfig {
+        keys,
+        file,
+        active: active
+            .and_then(|active| env::var(active).ok())
+            .filter(|s| !s.is_empty()),
+        refresh_interval,
+    })
+}
+
+/// Read a comma-separated list from an environment variable, ignoring empty entries
+fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|s| {
+            s.split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Read a boolean flag from an environment variable
+fn env_bool(name: &str, default: bool) -> bool {
+    env::var(name)
+        .ok()
+        .map(|s| {
+            matches!(
+                s.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        })
+        .unwrap_or(default)
+}
+
+/// Parse a value from an environment variable, falling back to a default when unset or invalid
+fn env_parse<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(default)
 }
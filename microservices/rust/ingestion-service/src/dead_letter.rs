@@ -0,0 +1,266 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::bus::MessageBus;
+use crate::encoding::{WireFormat, FORMAT_HEADER};
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::RawData;
+use crate::nats::{Headers, PublishRetryPolicy, MSG_ID_HEADER};
+use crate::subject::{SubjectTemplate, TokenPolicy};
+
+/// Subject template dead letters are published to unless `DEAD_LETTER_SUBJECT` is set
+pub const DEFAULT_DEAD_LETTER_SUBJECT: &str = "ingest.dlq.{content_type}";
+
+/// Settings for dead-lettering items that could not be published
+#[derive(Debug, Clone)]
+pub struct DeadLetterConfig {
+    /// Template of the subject dead letters are published to, with the placeholders of
+    /// `NATS_SUBJECT_TEMPLATE`
+    pub subject: String,
+}
+
+/// An item that could not be published once retries were exhausted, with why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The item as it was to be published, after pre-processing
+    pub item: RawData,
+
+    /// Subject the item failed to publish to
+    pub subject: String,
+
+    /// Last publish error
+    pub error: String,
+
+    /// When the item was given up on
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Publishes items that failed to publish to a dead-letter subject, so they can be inspected
+/// and replayed rather than only logged
+pub struct DeadLetters {
+    subject: SubjectTemplate,
+    bus: Arc<dyn MessageBus>,
+    publish_retry: PublishRetryPolicy,
+}
+
+impl DeadLetters {
+    /// Create the publisher, rejecting an invalid subject template
+    pub fn new(
+        config: &DeadLetterConfig,
+        environment: &str,
+        bus: Arc<dyn MessageBus>,
+        publish_retry: PublishRetryPolicy,
+    ) -> Result<Self> {
+        // Items whose content type cannot form a token already failed to publish, so escape it
+        let subject = SubjectTemplate::parse(&config.subject, environment, TokenPolicy::Escape)?;
+        info!(
+            "Publishing items that fail to publish to {}",
+            config.subject
+        );
+
+        Ok(Self {
+            subject,
+            bus,
+            publish_retry,
+        })
+    }
+
+    /// Subject filter matching every dead-letter subject
+    pub fn stream_subject(&self) -> String {
+        self.subject.stream_subject()
+    }
+
+    /// Publish a dead letter for an item that failed to publish to `subject`, returning
+    /// whether it was published. Failures are logged, as the item has failed either way.
+    pub async fn publish(&self, item: &RawData, subject: &str, cause: &AppError) -> bool {
+        match self.try_publish(item, subject, cause).await {
+            Ok(dead_letter_subject) => {
+                throttled!(warn!(
+                    "Dead-lettered item {} to {}: {}",
+                    item.id, dead_letter_subject, cause
+                ));
+                true
+            }
+            Err(e) => {
+                throttled!(error!(
+                    "Failed to dead-letter item {}, it is dropped: {}",
+                    item.id, e
+                ));
+                false
+            }
+        }
+    }
+
+    async fn try_publish(&self, item: &RawData, subject: &str, cause: &AppError) -> Result<String> {
+        let dead_letter_subject = self.subject.render(item)?;
+        let dead_letter = DeadLetter {
+            item: item.clone(),
+            subject: subject.to_string(),
+            error: cause.to_string(),
+            failed_at: Utc::now(),
+        };
+
+        let body = serde_json::to_vec(&dead_letter)
+            .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))?;
+        // Derived from the item's ID, so JetStream stores repeated failures of a retried item
+        // once, but distinct from it, as the dead letter may share the item's stream and would
+        // otherwise make a later retry of the item look like a duplicate
+        let headers: Headers = vec![
+            (MSG_ID_HEADER.to_string(), dead_letter_msg_id(item)),
+            (
+                FORMAT_HEADER.to_string(),
+                WireFormat::Json.as_str().to_string(),
+            ),
+        ];
+
+        self.bus
+            .publish_with_retry(
+                &dead_letter_subject,
+                &headers,
+                body.into(),
+                &self.publish_retry,
+            )
+            .await?;
+        Ok(dead_letter_subject)
+    }
+}
+
+/// Message ID of an item's dead letter
+fn dead_letter_msg_id(item: &RawData) -> String {
+    format!("{}:dlq", item.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use axum::async_trait;
+    use bytes::Bytes;
+    use serde_json::json;
+
+    use super::*;
+    use crate::bus::OutgoingMessage;
+    use crate::models::NatsStats;
+    use crate::nats::PublishAck;
+
+    /// One JetStream stream holding every subject, deduplicating by message ID
+    #[derive(Default)]
+    struct Stream {
+        ids: Mutex<HashSet<String>>,
+    }
+
+    #[async_trait]
+    impl MessageBus for Stream {
+        async fn publish(
+            &self,
+            _subject: &str,
+            headers: &Headers,
+            _payload: Bytes,
+        ) -> Result<Option<PublishAck>> {
+            let id = headers
+                .iter()
+                .find(|(name, _)| name == MSG_ID_HEADER)
+                .map(|(_, id)| id.clone())
+                .unwrap();
+            let mut ids = self.ids.lock().unwrap();
+            let duplicate = !ids.insert(id);
+            Ok(Some(PublishAck {
+                stream: "INGEST".to_string(),
+                sequence: ids.len() as u64,
+                domain: String::new(),
+                duplicate,
+            }))
+        }
+
+        async fn publish_batch(
+            &self,
+            _messages: Vec<OutgoingMessage>,
+        ) -> Vec<Result<Option<PublishAck>>> {
+            unimplemented!()
+        }
+
+        async fn request(
+            &self,
+            _: &str,
+            _: &Headers,
+            _: Bytes,
+            _: Duration,
+        ) -> Result<Option<Bytes>> {
+            unimplemented!()
+        }
+
+        async fn health(&self) -> NatsStats {
+            unimplemented!()
+        }
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+
+        async fn reconnected(&self) {}
+
+        fn max_payload(&self) -> Option<usize> {
+            None
+        }
+
+        async fn drain(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn dead_letters(stream: Arc<Stream>) -> DeadLetters {
+        let config = DeadLetterConfig {
+            subject: DEFAULT_DEAD_LETTER_SUBJECT.to_string(),
+        };
+        let retry = PublishRetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            jitter: 0.0,
+        };
+        DeadLetters::new(&config, "test", stream, retry).unwrap()
+    }
+
+    #[tokio::test]
+    async fn retries_after_dead_lettering_are_not_duplicates() {
+        let stream = Arc::new(Stream::default());
+        let dead_letters = dead_letters(stream.clone());
+        let item = RawData::builder()
+            .source("news-api")
+            .content_type("news_article")
+            .payload(json!({ "title": "Hello" }))
+            .build()
+            .unwrap();
+        let cause = AppError::NatsPublishError("timed out".to_string());
+
+        assert!(
+            dead_letters
+                .publish(&item, "ingest.news_article", &cause)
+                .await
+        );
+
+        // The client retries the item, which lands in the same stream as its dead letter
+        let headers = vec![(MSG_ID_HEADER.to_string(), item.id.to_string())];
+        let ack = stream
+            .publish("ingest.news_article", &headers, Bytes::new())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!ack.duplicate);
+
+        // Repeated failures of the item are still stored once
+        let headers = vec![(MSG_ID_HEADER.to_string(), dead_letter_msg_id(&item))];
+        let ack = stream
+            .publish("ingest.dlq.news_article", &headers, Bytes::new())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(ack.duplicate);
+    }
+}
@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use axum::{
+    extract::Request,
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::error::AppError;
+
+/// Absolute deadline header, either RFC 3339 or Unix epoch milliseconds
+pub const DEADLINE_HEADER: &str = "x-request-deadline";
+
+/// gRPC-style relative timeout header, e.g. `500m` or `2S`
+pub const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+/// Enforce the client's deadline on the whole request.
+///
+/// Requests whose deadline has already passed are rejected before any work is done, and
+/// requests that run past their deadline are aborted with `504 Gateway Timeout`.
+pub async fn enforce_deadline(request: Request, next: Next) -> Response {
+    let remaining = match remaining_budget(request.headers()) {
+        Ok(Some(remaining)) => remaining,
+        Ok(None) => return next.run(request).await,
+        Err(e) => return e.into_response(),
+    };
+
+    if remaining.is_zero() {
+        warn!("Request arrived after its deadline");
+        return AppError::DeadlineExceeded("Request deadline already passed".to_string())
+            .into_response();
+    }
+
+    match tokio::time::timeout_at(Instant::now() + remaining, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!(
+                "Request aborted after exceeding its {:?} deadline",
+                remaining
+            );
+            AppError::DeadlineExceeded("Request did not complete within its deadline".to_string())
+                .into_response()
+        }
+    }
+}
+
+/// Time left before the client's deadline, taking the tighter of both headers
+fn remaining_budget(headers: &HeaderMap) -> Result<Option<Duration>, AppError> {
+    let absolute = headers
+        .get(DEADLINE_HEADER)
+        .map(|value| {
+            let value = value.to_str().unwrap_or_default().trim();
+            parse_deadline(value).ok_or_else(|| {
+                AppError::ValidationError(format!("Invalid {} header: {}", DEADLINE_HEADER, value))
+            })
+        })
+        .transpose()?
+        .map(|deadline| (deadline - Utc::now()).to_std().unwrap_or(Duration::ZERO));
+
+    let relative = headers
+        .get(GRPC_TIMEOUT_HEADER)
+        .map(|value| {
+            let value = value.to_str().unwrap_or_default().trim();
+            parse_grpc_timeout(value).ok_or_else(|| {
+                AppError::ValidationError(format!(
+                    "Invalid {} header: {}",
+                    GRPC_TIMEOUT_HEADER, value
+                ))
+            })
+        })
+        .transpose()?;
+
+    Ok(match (absolute, relative) {
+        (Some(a), Some(r)) => Some(a.min(r)),
+        (a, r) => a.or(r),
+    })
+}
+
+fn parse_deadline(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(millis) = value.parse::<i64>() {
+        return DateTime::from_timestamp_millis(millis);
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|deadline| deadline.with_timezone(&Utc))
+}
+
+/// Parse a gRPC timeout: up to 8 digits followed by a unit (H, M, S, m, u, n)
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount = amount.parse::<u64>().ok()?;
+
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 3600)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_absolute_deadlines() {
+        let expected = DateTime::parse_from_rfc3339("2026-10-15T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(parse_deadline("2026-10-15T12:00:00Z"), Some(expected));
+        assert_eq!(parse_deadline("2026-10-15T14:00:00+02:00"), Some(expected));
+        assert_eq!(
+            parse_deadline(&expected.timestamp_millis().to_string()),
+            Some(expected)
+        );
+        assert_eq!(parse_deadline("tomorrow"), None);
+        assert_eq!(parse_deadline(""), None);
+    }
+
+    #[test]
+    fn parses_grpc_timeouts() {
+        assert_eq!(parse_grpc_timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_grpc_timeout("3M"), Some(Duration::from_secs(180)));
+        assert_eq!(parse_grpc_timeout("2S"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_grpc_timeout("500m"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_grpc_timeout("10u"), Some(Duration::from_micros(10)));
+        assert_eq!(
+            parse_grpc_timeout("99999999n"),
+            Some(Duration::from_nanos(99_999_999))
+        );
+
+        // At most 8 digits, a known unit, and no sign
+        assert_eq!(parse_grpc_timeout("123456789S"), None);
+        assert_eq!(parse_grpc_timeout("5s"), None);
+        assert_eq!(parse_grpc_timeout("S"), None);
+        assert_eq!(parse_grpc_timeout("-5S"), None);
+    }
+
+    #[test]
+    fn takes_the_tighter_deadline() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(remaining_budget(&headers).unwrap(), None);
+
+        let in_a_minute = (Utc::now() + chrono::Duration::seconds(60)).to_rfc3339();
+        headers.insert(DEADLINE_HEADER, in_a_minute.parse().unwrap());
+        let remaining = remaining_budget(&headers).unwrap().unwrap();
+        assert!(remaining > Duration::from_secs(50) && remaining <= Duration::from_secs(60));
+
+        headers.insert(GRPC_TIMEOUT_HEADER, "500m".parse().unwrap());
+        assert_eq!(
+            remaining_budget(&headers).unwrap(),
+            Some(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn passed_deadlines_leave_no_budget() {
+        let mut headers = HeaderMap::new();
+        headers.insert(DEADLINE_HEADER, "1000".parse().unwrap());
+        assert_eq!(remaining_budget(&headers).unwrap(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(GRPC_TIMEOUT_HEADER, "soon".parse().unwrap());
+        assert!(matches!(
+            remaining_budget(&headers),
+            Err(AppError::ValidationError(_))
+        ));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(DEADLINE_HEADER, "not a date".parse().unwrap());
+        assert!(matches!(
+            remaining_budget(&headers),
+            Err(AppError::ValidationError(_))
+        ));
+    }
+}
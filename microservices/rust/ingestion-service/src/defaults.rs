@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::{debug, info};
+
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+
+/// Defaults file: per content type, the value of each field an item may leave out
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DefaultsFile {
+    #[serde(default)]
+    content_types: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+/// A default value and where it goes
+#[derive(Debug)]
+struct FieldDefault {
+    /// Whether the field is in the payload rather than the metadata
+    payload: bool,
+
+    /// Unescaped tokens of the JSON pointer below the payload or metadata
+    path: Vec<String>,
+
+    value: Value,
+}
+
+/// Default values filled into fields that producers leave out, per content type, so gaps
+/// are papered over centrally rather than by every consumer.
+///
+/// Fields are JSON pointers into the payload or metadata, e.g. `/payload/visibility`. A
+/// default only applies when the field is missing; a field sent as `null` is kept. Missing
+/// objects on the way to a field are created, but a field below a value that is not an
+/// object is left alone.
+#[derive(Debug, Default)]
+pub struct FieldDefaults {
+    content_types: HashMap<String, Vec<FieldDefault>>,
+}
+
+impl FieldDefaults {
+    /// Load defaults from a JSON or YAML file, by file extension
+    pub fn open(file: &Path) -> Result<Self> {
+        let body = std::fs::read(file).map_err(|e| {
+            AppError::ValidationError(format!(
+                "Failed to read field defaults {}: {}",
+                file.display(),
+                e
+            ))
+        })?;
+        let parsed: DefaultsFile = match file.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_slice(&body).map_err(|e| {
+                AppError::ValidationError(format!("Invalid YAML field defaults: {}", e))
+            })?,
+            _ => serde_json::from_slice(&body).map_err(|e| {
+                AppError::ValidationError(format!("Invalid JSON field defaults: {}", e))
+            })?,
+        };
+
+        let mut content_types = HashMap::new();
+        for (content_type, fields) in parsed.content_types {
+            let mut defaults = Vec::with_capacity(fields.len());
+            for (field, value) in fields {
+                let (payload, pointer) = match (field.strip_prefix("/payload/"), field.strip_prefix("/metadata/")) {
+                    (Some(pointer), _) => (true, pointer),
+                    (_, Some(pointer)) => (false, pointer),
+                    _ => {
+                        return Err(AppError::ValidationError(format!(
+                            "Invalid default field {} of content type {}: fields are JSON pointers below /payload or /metadata",
+                            field, content_type
+                        )))
+                    }
+                };
+                let path = pointer
+                    .split('/')
+                    .map(|token| token.replace("~1", "/").replace("~0", "~"))
+                    .collect();
+                defaults.push(FieldDefault {
+                    payload,
+                    path,
+                    value,
+                });
+            }
+            content_types.insert(content_type, defaults);
+        }
+
+        info!(
+            "Loaded field defaults for {} content types from {}",
+            content_types.len(),
+            file.display()
+        );
+
+        Ok(Self { content_types })
+    }
+
+    /// Fill in the defaults of an item's content type for the fields it leaves out
+    pub fn apply(&self, item: &mut RawData) {
+        let Some(defaults) = self.content_types.get(&item.content_type) else {
+            return;
+        };
+
+        for default in defaults {
+            let root = if default.payload {
+                &mut item.payload
+            } else {
+                &mut item.metadata
+            };
+            if root.is_null() {
+                *root = Value::Object(Map::new());
+            }
+            if !insert_missing(root, &default.path, &default.value) {
+                debug!(
+                    "Skipped default of /{}/{} for item {}, a parent is not an object",
+                    if default.payload {
+                        "payload"
+                    } else {
+                        "metadata"
+                    },
+                    default.path.join("/"),
+                    item.id
+                );
+            }
+        }
+    }
+}
+
+/// Set the field at `path` to `value` unless it is present, creating missing objects on the
+/// way. Returns false when a value on the way is not an object.
+fn insert_missing(root: &mut Value, path: &[String], value: &Value) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+
+    let mut current = root;
+    for token in parents {
+        let Some(object) = current.as_object_mut() else {
+            return false;
+        };
+        current = object
+            .entry(token.as_str())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+
+    match current.as_object_mut() {
+        Some(object) => {
+            object.entry(last.as_str()).or_insert_with(|| value.clone());
+            true
+        }
+        None => false,
+    }
+}
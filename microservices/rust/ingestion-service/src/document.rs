@@ -0,0 +1,473 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::bus::MessageBus;
+use crate::encoding::{WireFormat, FORMAT_HEADER};
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::RawData;
+use crate::nats::{Headers, PublishRetryPolicy, MSG_ID_HEADER};
+use crate::subject::{SubjectTemplate, TokenPolicy};
+
+/// Subject template assembly manifests are published to unless `DOCUMENT_MANIFEST_SUBJECT`
+/// is set
+pub const DEFAULT_MANIFEST_SUBJECT: &str = "ingest.document.{content_type}";
+
+/// Most parts a document may have
+const MAX_PARTS: u64 = 10_000;
+
+/// Incomplete documents tracked at once; the longest waiting are given up on first
+const MAX_TRACKED_DOCUMENTS: usize = 10_000;
+
+/// Settings for assembling documents sent as several parts
+#[derive(Debug, Clone)]
+pub struct DocumentConfig {
+    /// Template of the subject manifests are published to, with the placeholders of
+    /// `NATS_SUBJECT_TEMPLATE`
+    pub subject: String,
+
+    /// How long to wait for the rest of a document after its first part
+    pub timeout: Duration,
+}
+
+/// Where an item belongs in a logical document, read from `metadata.document`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    /// Document ID shared by all of its parts
+    pub document_id: String,
+
+    /// Position of the part, from 0
+    pub index: u32,
+
+    /// Number of parts in the document
+    pub count: u32,
+}
+
+/// Part of a document an item is, if it carries `metadata.document`
+pub fn part(item: &RawData) -> Result<Option<Part>> {
+    let Some(document) = item
+        .metadata
+        .get("document")
+        .filter(|document| !document.is_null())
+    else {
+        return Ok(None);
+    };
+
+    let invalid = |reason: &str| AppError::ValidationError(format!("metadata.document {}", reason));
+    let document_id = document
+        .get("id")
+        .and_then(Value::as_str)
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| invalid("needs a non-empty id"))?;
+    let count = document
+        .get("part_count")
+        .and_then(Value::as_u64)
+        .filter(|count| (1..=MAX_PARTS).contains(count))
+        .ok_or_else(|| invalid(&format!("needs a part_count from 1 to {}", MAX_PARTS)))?;
+    let index = document
+        .get("part_index")
+        .and_then(Value::as_u64)
+        .filter(|index| *index < count)
+        .ok_or_else(|| invalid("needs a part_index below part_count"))?;
+
+    Ok(Some(Part {
+        document_id: document_id.to_string(),
+        index: index as u32,
+        count: count as u32,
+    }))
+}
+
+/// Published once every part of a document has been published, listing its parts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentManifest {
+    /// Document ID shared by the parts
+    pub document_id: String,
+
+    /// Source of the parts
+    pub source: String,
+
+    /// Content type of the parts
+    pub content_type: String,
+
+    /// Number of parts
+    pub part_count: u32,
+
+    /// Item IDs of the parts, in part order
+    pub parts: Vec<Uuid>,
+
+    /// When the first part to arrive was published
+    pub first_seen: DateTime<Utc>,
+
+    /// When the last part to arrive was published
+    pub completed_at: DateTime<Utc>,
+}
+
+/// Parts of a document received so far
+struct Assembly {
+    content_type: String,
+    parts: Vec<Option<Uuid>>,
+    received: usize,
+    first_seen: DateTime<Utc>,
+    started: Instant,
+}
+
+#[derive(Default)]
+struct AssemblyState {
+    /// Incomplete documents by source and document ID
+    assemblies: HashMap<(String, String), Assembly>,
+
+    /// Documents by when their first part arrived, oldest first. Entries of documents that
+    /// completed since are skipped.
+    arrivals: VecDeque<(Instant, (String, String))>,
+}
+
+/// Tracks the parts of documents sent as several items, and publishes an assembly manifest
+/// once all have been published, so consumers know when a document can be put together.
+///
+/// Parts are tracked in memory per replica, so all parts of a document must reach the same
+/// one. A document still missing parts after the timeout is logged and forgotten.
+pub struct Documents {
+    subject: SubjectTemplate,
+    timeout: Duration,
+    bus: Arc<dyn MessageBus>,
+    publish_retry: PublishRetryPolicy,
+    state: Mutex<AssemblyState>,
+}
+
+impl Documents {
+    /// Create the tracker, rejecting an invalid subject template
+    pub fn new(
+        config: &DocumentConfig,
+        environment: &str,
+        bus: Arc<dyn MessageBus>,
+        publish_retry: PublishRetryPolicy,
+    ) -> Result<Self> {
+        let subject = SubjectTemplate::parse(&config.subject, environment, TokenPolicy::Escape)?;
+        info!(
+            "Publishing document assembly manifests to {}",
+            config.subject
+        );
+
+        Ok(Self {
+            subject,
+            timeout: config.timeout,
+            bus,
+            publish_retry,
+            state: Mutex::new(AssemblyState::default()),
+        })
+    }
+
+    /// Subject filter matching every manifest subject
+    pub fn stream_subject(&self) -> String {
+        self.subject.stream_subject()
+    }
+
+    /// Check an item's `metadata.document` before it is published, and that it agrees with the
+    /// parts of its document received so far
+    pub fn check(&self, item: &RawData) -> Result<()> {
+        let Some(part) = part(item)? else {
+            return Ok(());
+        };
+
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(assembly) = state
+            .assemblies
+            .get(&(item.source.clone(), part.document_id.clone()))
+        else {
+            return Ok(());
+        };
+        if assembly.parts.len() != part.count as usize {
+            return Err(AppError::ValidationError(format!(
+                "Document {} has {} parts, not {}",
+                part.document_id,
+                assembly.parts.len(),
+                part.count
+            )));
+        }
+        if assembly.content_type != item.content_type {
+            return Err(AppError::ValidationError(format!(
+                "Parts of document {} are {}, not {}",
+                part.document_id, assembly.content_type, item.content_type
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Record a published part, publishing the document's manifest if it was the last one
+    /// missing. A part sent again replaces the earlier one.
+    pub async fn record(&self, item: &RawData) {
+        let Ok(Some(part)) = part(item) else {
+            return;
+        };
+
+        let Some(manifest) = self.add(item, &part) else {
+            return;
+        };
+        match self.publish(item, &manifest).await {
+            Ok(subject) => info!(
+                "Document {} of {} is complete with {} parts, published its manifest to {}",
+                manifest.document_id, manifest.source, manifest.part_count, subject
+            ),
+            Err(e) => throttled!(error!(
+                "Failed to publish the manifest of document {} of {}: {}",
+                manifest.document_id, manifest.source, e
+            )),
+        }
+    }
+
+    /// Add a part, giving the document's manifest once it is complete
+    fn add(&self, item: &RawData, part: &Part) -> Option<DocumentManifest> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.forget_stale(&mut state);
+
+        let key = (item.source.clone(), part.document_id.clone());
+        if !state.assemblies.contains_key(&key) {
+            if state.assemblies.len() >= MAX_TRACKED_DOCUMENTS {
+                forget_oldest(&mut state);
+            }
+            let started = Instant::now();
+            state.assemblies.insert(
+                key.clone(),
+                Assembly {
+                    content_type: item.content_type.clone(),
+                    parts: vec![None; part.count as usize],
+                    received: 0,
+                    first_seen: Utc::now(),
+                    started,
+                },
+            );
+            state.arrivals.push_back((started, key.clone()));
+        }
+
+        let assembly = state.assemblies.get_mut(&key)?;
+        let slot = assembly.parts.get_mut(part.index as usize)?;
+        if slot.replace(item.id).is_none() {
+            assembly.received += 1;
+        }
+        if assembly.received < assembly.parts.len() {
+            return None;
+        }
+
+        let assembly = state.assemblies.remove(&key)?;
+        Some(DocumentManifest {
+            document_id: part.document_id.clone(),
+            source: item.source.clone(),
+            content_type: assembly.content_type,
+            part_count: part.count,
+            parts: assembly.parts.into_iter().flatten().collect(),
+            first_seen: assembly.first_seen,
+            completed_at: Utc::now(),
+        })
+    }
+
+    /// Forget documents still incomplete after the timeout
+    fn forget_stale(&self, state: &mut AssemblyState) {
+        while state
+            .arrivals
+            .front()
+            .is_some_and(|(started, _)| started.elapsed() > self.timeout)
+        {
+            if let Some((started, key)) = state.arrivals.pop_front() {
+                give_up(state, started, &key);
+            }
+        }
+    }
+
+    async fn publish(&self, item: &RawData, manifest: &DocumentManifest) -> Result<String> {
+        let subject = self.subject.render(item)?;
+        let body = serde_json::to_vec(manifest)
+            .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))?;
+        // Source and document ID, so JetStream stores a manifest published twice once
+        let headers: Headers = vec![
+            (
+                MSG_ID_HEADER.to_string(),
+                format!("{}/{}", manifest.source, manifest.document_id),
+            ),
+            (
+                FORMAT_HEADER.to_string(),
+                WireFormat::Json.as_str().to_string(),
+            ),
+        ];
+
+        self.bus
+            .publish_with_retry(&subject, &headers, body.into(), &self.publish_retry)
+            .await?;
+        Ok(subject)
+    }
+}
+
+/// Give up on the document that has waited longest for its parts
+fn forget_oldest(state: &mut AssemblyState) {
+    while let Some((started, key)) = state.arrivals.pop_front() {
+        if give_up(state, started, &key) {
+            return;
+        }
+    }
+}
+
+/// Forget an incomplete document, unless it completed since or the key now belongs to a
+/// later document with the same ID, giving whether it was forgotten
+fn give_up(state: &mut AssemblyState, started: Instant, key: &(String, String)) -> bool {
+    if state
+        .assemblies
+        .get(key)
+        .is_none_or(|assembly| assembly.started != started)
+    {
+        return false;
+    }
+    if let Some(assembly) = state.assemblies.remove(key) {
+        throttled!(warn!(
+            "Document {} of {} is incomplete with {}/{} parts, giving up on it",
+            key.1,
+            key.0,
+            assembly.received,
+            assembly.parts.len()
+        ));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::nats::{NatsClient, SimulationMode};
+
+    async fn documents() -> Documents {
+        let bus = NatsClient::new(
+            Vec::new(),
+            Some(SimulationMode::Null),
+            None,
+            None,
+            1,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let config = DocumentConfig {
+            subject: DEFAULT_MANIFEST_SUBJECT.to_string(),
+            timeout: Duration::from_secs(60),
+        };
+        let retry = PublishRetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            jitter: 0.0,
+        };
+        Documents::new(&config, "test", Arc::new(bus), retry).unwrap()
+    }
+
+    fn item(content_type: &str, document: Value) -> RawData {
+        RawData::builder()
+            .source("crm")
+            .content_type(content_type)
+            .payload(json!({}))
+            .metadata(json!({"document": document}))
+            .build()
+            .unwrap()
+    }
+
+    fn document(index: u64, count: u64) -> Value {
+        json!({"id": "contract-7", "part_index": index, "part_count": count})
+    }
+
+    #[test]
+    fn items_without_a_document_are_not_parts() {
+        assert_eq!(part(&item("contract", Value::Null)).unwrap(), None);
+    }
+
+    #[test]
+    fn reads_a_part() {
+        assert_eq!(
+            part(&item("contract", document(1, 3))).unwrap(),
+            Some(Part {
+                document_id: "contract-7".to_string(),
+                index: 1,
+                count: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        for document in [
+            json!("contract-7"),
+            json!({"part_index": 0, "part_count": 1}),
+            json!({"id": "", "part_index": 0, "part_count": 1}),
+            json!({"id": 7, "part_index": 0, "part_count": 1}),
+            json!({"id": "contract-7", "part_index": 0}),
+            json!({"id": "contract-7", "part_count": 1}),
+            json!({"id": "contract-7", "part_index": 0, "part_count": 0}),
+            json!({"id": "contract-7", "part_index": -1, "part_count": 2}),
+            json!({"id": "contract-7", "part_index": 0.5, "part_count": 2}),
+            json!({"id": "contract-7", "part_index": "0", "part_count": 2}),
+            json!({"id": "contract-7", "part_index": 2, "part_count": 2}),
+        ] {
+            assert!(
+                matches!(
+                    part(&item("contract", document.clone())),
+                    Err(AppError::ValidationError(_))
+                ),
+                "{} was accepted",
+                document
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_documents_with_too_many_parts() {
+        assert!(part(&item("contract", document(0, MAX_PARTS))).is_ok());
+        assert!(part(&item("contract", document(0, MAX_PARTS + 1))).is_err());
+        // Too large for a u32, so it must not wrap around to a small count
+        assert!(part(&item("contract", document(0, u64::from(u32::MAX) + 2))).is_err());
+        assert!(part(&item("contract", document(u64::MAX, 2))).is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_parts_that_disagree_with_earlier_ones() {
+        let documents = documents().await;
+        let first = item("contract", document(0, 3));
+        documents.record(&first).await;
+
+        assert!(documents.check(&item("contract", document(1, 3))).is_ok());
+        assert!(documents.check(&item("contract", document(1, 4))).is_err());
+        assert!(documents.check(&item("invoice", document(1, 3))).is_err());
+        assert!(documents
+            .check(&item("contract", json!({"id": ""})))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn gives_the_manifest_once_every_part_is_recorded() {
+        let documents = documents().await;
+        let parts: Vec<RawData> = (0..3)
+            .map(|index| item("contract", document(index, 3)))
+            .collect();
+        let add = |item: &RawData| documents.add(item, &part(item).unwrap().unwrap());
+
+        assert!(add(&parts[2]).is_none());
+        assert!(add(&parts[0]).is_none());
+        // A part sent again replaces the earlier one rather than completing the document
+        let resent = item("contract", document(0, 3));
+        assert!(add(&resent).is_none());
+
+        let manifest = add(&parts[1]).unwrap();
+        assert_eq!(manifest.document_id, "contract-7");
+        assert_eq!(manifest.source, "crm");
+        assert_eq!(manifest.part_count, 3);
+        assert_eq!(manifest.parts, vec![resent.id, parts[1].id, parts[2].id]);
+
+        // The document is forgotten once complete, so its ID may be used again
+        assert!(documents.check(&item("contract", document(0, 5))).is_ok());
+    }
+}
@@ -0,0 +1,319 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use mail_parser::{Address, MessageParser, MimeHeaders};
+use object_store::aws::{AmazonS3Builder, AmazonS3ConfigKey};
+use object_store::path::Path;
+use object_store::{ObjectStore, ObjectStoreExt, PutPayload};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{self, pki_types::ServerName};
+use tokio_rustls::TlsConnector;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::Secret;
+use crate::error::{AppError, Result};
+use crate::http::{self, ProxyConfig};
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+use crate::readiness::{Dependency, Readiness};
+
+/// Settings for polling IMAP mailboxes
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    /// IMAP server host, connected to over TLS
+    pub host: String,
+
+    /// IMAP server port
+    pub port: u16,
+
+    /// Login user
+    pub username: String,
+
+    /// Login password
+    pub password: Secret,
+
+    /// Mailboxes checked for unseen messages
+    pub mailboxes: Vec<String>,
+
+    /// Pause between polls
+    pub poll_interval: Duration,
+
+    /// Source assigned to ingested messages
+    pub source: String,
+
+    /// Content type assigned to ingested messages
+    pub content_type: String,
+
+    /// S3 bucket attachments are uploaded to; attachments are only described when unset
+    pub attachment_bucket: Option<String>,
+}
+
+/// Polls IMAP mailboxes and ingests unseen messages.
+///
+/// Messages are marked seen once ingested or rejected, so failed publishes are retried on
+/// the next poll.
+pub struct EmailPoller {
+    config: EmailConfig,
+    pipeline: Arc<Pipeline>,
+    attachments: Option<Arc<dyn ObjectStore>>,
+    imap: Arc<Dependency>,
+    bucket: Option<Arc<Dependency>>,
+}
+
+impl EmailPoller {
+    /// Create a new poller, connecting to the attachment bucket if one is configured
+    pub fn new(
+        config: EmailConfig,
+        proxy: &ProxyConfig,
+        pipeline: Arc<Pipeline>,
+        readiness: &Readiness,
+    ) -> Result<Self> {
+        let attachments = config
+            .attachment_bucket
+            .as_ref()
+            .map(|bucket| {
+                // Credentials, region and endpoint come from the standard AWS_* variables
+                let builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+                http::object_store_options(proxy)
+                    .into_iter()
+                    .fold(builder, |builder, (key, value)| {
+                        builder.with_config(AmazonS3ConfigKey::Client(key), value)
+                    })
+                    .build()
+                    .map(|store| Arc::new(store) as Arc<dyn ObjectStore>)
+                    .map_err(|e| {
+                        AppError::InternalError(format!(
+                            "Failed to configure attachment bucket: {}",
+                            e
+                        ))
+                    })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            imap: readiness.register("imap", false),
+            bucket: attachments
+                .as_ref()
+                .map(|_| readiness.register("s3", false)),
+            config,
+            pipeline,
+            attachments,
+        })
+    }
+
+    /// Spawn the background polling task
+    pub fn spawn(self) {
+        tokio::spawn(async move {
+            loop {
+                let result = self.poll().await;
+                self.imap.record(&result);
+                match result {
+                    Ok(0) => {}
+                    Ok(count) => info!("Ingested {} email messages", count),
+                    Err(e) => warn!("Email poll of {} failed: {}", self.config.host, e),
+                }
+                tokio::time::sleep(self.config.poll_interval).await;
+            }
+        });
+    }
+
+    /// Check every mailbox once, returning the number of messages ingested
+    async fn poll(&self) -> Result<usize> {
+        let tcp = TcpStream::connect((self.config.host.as_str(), self.config.port))
+            .await
+            .map_err(imap_error)?;
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .map_err(imap_error)?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+        let server_name = ServerName::try_from(self.config.host.clone()).map_err(imap_error)?;
+        let tls = TlsConnector::from(Arc::new(tls_config))
+            .connect(server_name, tcp)
+            .await
+            .map_err(imap_error)?;
+
+        let mut client = async_imap::Client::new(tls);
+        client.read_response().await.map_err(imap_error)?;
+        let mut session = client
+            .login(&self.config.username, self.config.password.expose())
+            .await
+            .map_err(|(e, _)| imap_error(e))?;
+
+        let mut ingested = 0;
+
+        for mailbox in &self.config.mailboxes {
+            session.select(mailbox).await.map_err(imap_error)?;
+
+            let mut uids: Vec<u32> = session
+                .uid_search("UNSEEN")
+                .await
+                .map_err(imap_error)?
+                .into_iter()
+                .collect();
+            uids.sort_unstable();
+
+            for uid in uids {
+                let fetches: Vec<_> = session
+                    .uid_fetch(uid.to_string(), "BODY.PEEK[]")
+                    .await
+                    .map_err(imap_error)?
+                    .try_collect()
+                    .await
+                    .map_err(imap_error)?;
+
+                let Some(raw) = fetches.iter().find_map(|fetch| fetch.body()) else {
+                    continue;
+                };
+
+                let seen = match self.ingest(mailbox, uid, raw).await {
+                    Ok(()) => {
+                        ingested += 1;
+                        true
+                    }
+                    Err(e @ AppError::ValidationError(_))
+                    | Err(e @ AppError::PolicyViolation(_)) => {
+                        warn!("Rejected message {} in {}: {}", uid, mailbox, e);
+                        true
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to ingest message {} in {}, will retry: {}",
+                            uid, mailbox, e
+                        );
+                        false
+                    }
+                };
+
+                if seen {
+                    session
+                        .uid_store(uid.to_string(), "+FLAGS (\\Seen)")
+                        .await
+                        .map_err(imap_error)?
+                        .try_collect::<Vec<_>>()
+                        .await
+                        .map_err(imap_error)?;
+                }
+            }
+        }
+
+        session.logout().await.map_err(imap_error)?;
+
+        Ok(ingested)
+    }
+
+    /// Parse a raw message, upload its attachments and ingest it
+    async fn ingest(&self, mailbox: &str, uid: u32, raw: &[u8]) -> Result<()> {
+        let message = MessageParser::default()
+            .parse(raw)
+            .ok_or_else(|| AppError::ValidationError("Unparseable email message".to_string()))?;
+
+        // Derive the ID from the Message-ID so a message re-read after a failure keeps its ID
+        let message_id = message
+            .message_id()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}:{}:{}", self.config.host, mailbox, uid));
+        let id = Uuid::new_v5(
+            &Uuid::NAMESPACE_OID,
+            format!("email:{}", message_id).as_bytes(),
+        );
+
+        let mut attachments = Vec::new();
+        for (index, part) in message.attachments().enumerate() {
+            let filename = part.attachment_name().unwrap_or("attachment").to_string();
+            let mime_type = part
+                .content_type()
+                .map(|ct| match ct.subtype() {
+                    Some(subtype) => format!("{}/{}", ct.ctype(), subtype),
+                    None => ct.ctype().to_string(),
+                })
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            let contents = part.contents();
+
+            let mut attachment = json!({
+                "filename": filename,
+                "mime_type": mime_type,
+                "size": contents.len(),
+            });
+
+            if let (Some(store), Some(bucket)) = (&self.attachments, &self.config.attachment_bucket)
+            {
+                let key = Path::from(format!(
+                    "email/{}/{}-{}",
+                    id,
+                    index,
+                    sanitize_filename(&filename)
+                ));
+                let result = store.put(&key, PutPayload::from(contents.to_vec())).await;
+                if let Some(bucket) = &self.bucket {
+                    bucket.record(&result);
+                }
+                result.map_err(|e| {
+                    AppError::InternalError(format!(
+                        "Failed to upload attachment {}: {}",
+                        filename, e
+                    ))
+                })?;
+                attachment["location"] = json!(format!("s3://{}/{}", bucket, key));
+            }
+
+            attachments.push(attachment);
+        }
+
+        let mut item = RawData::builder()
+            .id(id)
+            .source(self.config.source.clone())
+            .content_type(self.config.content_type.clone())
+            .payload(json!({
+                "message_id": message_id,
+                "mailbox": mailbox,
+                "subject": message.subject(),
+                "from": addresses(message.from()),
+                "to": addresses(message.to()),
+                "date": message.date().map(|d| d.to_rfc3339()),
+                "text": message.body_text(0),
+                "attachments": attachments,
+            }))
+            .build()?;
+
+        self.pipeline.process(&mut item).await.map(|_| ())
+    }
+}
+
+fn addresses(address: Option<&Address<'_>>) -> Value {
+    address
+        .map(|address| {
+            address
+                .iter()
+                .map(|addr| json!({ "name": addr.name(), "address": addr.address() }))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default()
+        .into()
+}
+
+/// Keep object keys free of path separators and unusual characters
+fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn imap_error(e: impl std::fmt::Display) -> AppError {
+    AppError::FetchError(format!("IMAP error: {}", e))
+}
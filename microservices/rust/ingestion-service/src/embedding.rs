@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::config::Secret;
+use crate::error::{AppError, Result};
+use crate::http::{self, ProxyConfig};
+use crate::logging::throttled;
+use crate::models::RawData;
+
+/// Settings for the inline embedding provider
+#[derive(Debug, Clone)]
+pub struct EmbeddingConfig {
+    /// OpenAI-compatible embeddings endpoint
+    pub endpoint: String,
+
+    /// Model name sent with each request
+    pub model: String,
+
+    /// Optional bearer token for the provider
+    pub api_key: Option<Secret>,
+
+    /// Content types that get embeddings computed at ingest
+    pub content_types: Vec<String>,
+
+    /// Payload field holding the text to embed
+    pub text_field: String,
+
+    /// Request timeout for the provider
+    pub timeout: Duration,
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a str,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+/// Client for computing embeddings through an HTTP provider
+pub struct EmbeddingClient {
+    http: reqwest::Client,
+    config: EmbeddingConfig,
+}
+
+impl EmbeddingClient {
+    /// Create a new embedding client
+    pub fn new(config: EmbeddingConfig, proxy: &ProxyConfig) -> Result<Self> {
+        let http = http::client_builder(proxy)?
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| AppError::InternalError(format!("Failed to build HTTP client: {}", e)))?;
+
+        Ok(Self { http, config })
+    }
+
+    /// Compute and attach an embedding to `metadata.embedding` when the item qualifies.
+    ///
+    /// Provider failures are logged and the item is left without an embedding so that
+    /// ingestion never fails because of the enrichment.
+    pub async fn embed(&self, item: &mut RawData) {
+        if !self
+            .config
+            .content_types
+            .iter()
+            .any(|t| t == &item.content_type)
+        {
+            return;
+        }
+
+        let Some(text) = item
+            .payload
+            .get(&self.config.text_field)
+            .and_then(Value::as_str)
+        else {
+            debug!("Item {} has no text to embed", item.id);
+            return;
+        };
+
+        match self.request(text).await {
+            Ok(vector) => {
+                if !item.metadata.is_object() {
+                    item.metadata = json!({});
+                }
+                item.metadata["embedding"] = json!({
+                    "model": self.config.model,
+                    "dimensions": vector.len(),
+                    "vector": vector,
+                });
+            }
+            Err(e) => throttled!(warn!("Skipping embedding for item {}: {}", item.id, e)),
+        }
+    }
+
+    async fn request(&self, text: &str) -> Result<Vec<f32>> {
+        let mut request = self
+            .http
+            .post(&self.config.endpoint)
+            .json(&EmbeddingRequest {
+                model: &self.config.model,
+                input: text,
+            });
+
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key.expose());
+        }
+
+        let response = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                throttled!(error!("Embedding request failed: {}", e));
+                AppError::InternalError(format!("Embedding request failed: {}", e))
+            })?;
+
+        let body: EmbeddingResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::InternalError(format!("Invalid embedding response: {}", e)))?;
+
+        body.data
+            .into_iter()
+            .next()
+            .map(|d| d.embedding)
+            .ok_or_else(|| {
+                AppError::InternalError("Embedding response contained no data".to_string())
+            })
+    }
+}
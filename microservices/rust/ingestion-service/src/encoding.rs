@@ -0,0 +1,248 @@
+use bytes::{BufMut, Bytes};
+use chrono::SecondsFormat;
+use prost::Message;
+use serde::Serialize;
+
+use crate::buffers::BufferPool;
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+use crate::nats::Headers;
+use crate::registry::RegisteredSchema;
+
+/// Header declaring how a message body is encoded
+pub const FORMAT_HEADER: &str = "Ingest-Format";
+
+/// Version of the message envelope, `major.minor`. The minor version grows with additive
+/// changes consumers can ignore; the major version with changes they cannot.
+pub const ENVELOPE_VERSION: &str = "1.0";
+
+/// Headers stamped on every item message, so consumers can tell the envelope version and
+/// content type without decoding the body
+pub const SCHEMA_VERSION_HEADER: &str = "chimera-schema-version";
+pub const CHIMERA_CONTENT_TYPE_HEADER: &str = "chimera-content-type";
+
+/// Headers carrying the item's fields when only the payload is in the body
+pub const SOURCE_HEADER: &str = "Ingest-Source";
+pub const CONTENT_TYPE_HEADER: &str = "Ingest-Content-Type";
+pub const TIMESTAMP_HEADER: &str = "Ingest-Timestamp";
+pub const METADATA_HEADER: &str = "Ingest-Metadata";
+pub const PARTITION_KEY_HEADER: &str = "Ingest-Partition-Key";
+
+/// An item as published, carrying the envelope version alongside its fields
+#[derive(Serialize)]
+struct Envelope<'a> {
+    schema_version: &'static str,
+    #[serde(flatten)]
+    item: &'a RawData,
+}
+
+impl<'a> Envelope<'a> {
+    fn new(item: &'a RawData) -> Self {
+        Self {
+            schema_version: ENVELOPE_VERSION,
+            item,
+        }
+    }
+}
+
+/// Whether a consumer of the given envelope version can read published messages, i.e. the
+/// major versions match
+pub fn is_compatible(version: &str) -> Result<bool> {
+    let major = |version: &str| {
+        let mut parts = version.trim().splitn(2, '.');
+        let major = parts.next().and_then(|major| major.parse::<u32>().ok());
+        let minor_valid = parts
+            .next()
+            .is_none_or(|minor| minor.parse::<u32>().is_ok());
+        major.filter(|_| minor_valid)
+    };
+    let requested = major(version).ok_or_else(|| {
+        AppError::ValidationError(format!(
+            "Invalid schema version {}, expected major.minor",
+            version
+        ))
+    })?;
+    Ok(major(ENVELOPE_VERSION) == Some(requested))
+}
+
+/// Encoding of message bodies on the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    /// The JSON documented in `WIRE_FORMAT.md`
+    Json,
+
+    /// MessagePack with the same fields and values as the JSON
+    MessagePack,
+
+    /// Protocol Buffers, see `proto/raw_data.proto`
+    Protobuf,
+
+    /// The payload alone as Avro in the Confluent wire format, using the content type's
+    /// schema registry subject; the other fields travel in headers
+    Avro,
+}
+
+impl WireFormat {
+    /// Parse a format name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "msgpack" | "messagepack" => Some(Self::MessagePack),
+            "protobuf" | "proto" => Some(Self::Protobuf),
+            "avro" => Some(Self::Avro),
+            _ => None,
+        }
+    }
+
+    /// Name sent in the format header
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::MessagePack => "msgpack",
+            Self::Protobuf => "protobuf",
+            Self::Avro => "avro",
+        }
+    }
+
+    /// Headers carrying the fields of an item that the body leaves out
+    pub fn envelope_headers(self, item: &RawData) -> Headers {
+        if self != Self::Avro {
+            return Headers::new();
+        }
+
+        let mut headers = vec![
+            (SOURCE_HEADER.to_string(), item.source.clone()),
+            (CONTENT_TYPE_HEADER.to_string(), item.content_type.clone()),
+            (
+                TIMESTAMP_HEADER.to_string(),
+                item.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            ),
+        ];
+        if !item.metadata.is_null() {
+            headers.push((METADATA_HEADER.to_string(), item.metadata.to_string()));
+        }
+        if let Some(partition_key) = &item.partition_key {
+            headers.push((PARTITION_KEY_HEADER.to_string(), partition_key.clone()));
+        }
+        headers
+    }
+
+    /// Encode a message into a pooled buffer.
+    ///
+    /// Avro needs the registry schema the item was validated against.
+    pub fn encode(
+        self,
+        item: &RawData,
+        buffers: &BufferPool,
+        schema: Option<&RegisteredSchema>,
+    ) -> Result<Bytes> {
+        match self {
+            Self::Json => buffers.serialize(&Envelope::new(item)),
+            Self::MessagePack => {
+                let mut buffer = buffers.take();
+                let mut serializer = rmp_serde::Serializer::new((&mut *buffer).writer())
+                    .with_struct_map()
+                    .with_human_readable();
+                Envelope::new(item)
+                    .serialize(&mut serializer)
+                    .map_err(|e| {
+                        AppError::InternalError(format!("MessagePack serialization error: {}", e))
+                    })?;
+                Ok(buffer.split().freeze())
+            }
+            Self::Protobuf => {
+                let message = RawDataProto::from_item(item)?;
+                let mut buffer = buffers.take();
+                message.encode(&mut *buffer).map_err(|e| {
+                    AppError::InternalError(format!("Protobuf serialization error: {}", e))
+                })?;
+                Ok(buffer.split().freeze())
+            }
+            Self::Avro => {
+                let schema = schema.ok_or_else(|| {
+                    AppError::InternalError(format!(
+                        "Avro encoding of {} requires its content type to be validated against the schema registry",
+                        item.content_type
+                    ))
+                })?;
+                Ok(schema.encode_avro(&item.payload)?.into())
+            }
+        }
+    }
+}
+
+/// Settings for choosing the encoding of published messages
+#[derive(Debug, Clone)]
+pub struct WireFormatConfig {
+    /// Encoding used for content types without their own
+    pub default: WireFormat,
+
+    /// Encodings for specific content types
+    pub content_types: Vec<(String, WireFormat)>,
+}
+
+impl WireFormatConfig {
+    /// Encoding for messages of a content type
+    pub fn format_for(&self, content_type: &str) -> WireFormat {
+        self.content_types
+            .iter()
+            .find(|(configured, _)| configured == content_type)
+            .map(|(_, format)| *format)
+            .unwrap_or(self.default)
+    }
+}
+
+/// Protobuf form of a message, mirroring `proto/raw_data.proto`.
+///
+/// `payload` and `metadata` are arbitrary JSON, so they are carried as JSON bytes rather
+/// than mapped onto protobuf types.
+#[derive(Clone, PartialEq, Message)]
+pub struct RawDataProto {
+    #[prost(string, tag = "1")]
+    pub id: String,
+
+    #[prost(string, tag = "2")]
+    pub source: String,
+
+    #[prost(string, tag = "3")]
+    pub content_type: String,
+
+    #[prost(bytes = "vec", tag = "4")]
+    pub payload: Vec<u8>,
+
+    /// RFC 3339, as in the JSON format
+    #[prost(string, tag = "5")]
+    pub timestamp: String,
+
+    #[prost(bytes = "vec", tag = "6")]
+    pub metadata: Vec<u8>,
+
+    /// Envelope version, as `schema_version` in the JSON format
+    #[prost(string, tag = "7")]
+    pub schema_version: String,
+
+    /// Empty when the item has none
+    #[prost(string, tag = "8")]
+    pub partition_key: String,
+}
+
+impl RawDataProto {
+    /// Convert a message to its protobuf form
+    pub fn from_item(item: &RawData) -> Result<Self> {
+        let json = |value: &serde_json::Value| {
+            serde_json::to_vec(value)
+                .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))
+        };
+
+        Ok(Self {
+            id: item.id.to_string(),
+            source: item.source.clone(),
+            content_type: item.content_type.clone(),
+            payload: json(&item.payload)?,
+            timestamp: item.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            metadata: json(&item.metadata)?,
+            schema_version: ENVELOPE_VERSION.to_string(),
+            partition_key: item.partition_key.clone().unwrap_or_default(),
+        })
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::error::{AppError, Result};
+use crate::keys::{KeyRing, KeyRingConfig, KeyVersion};
+
+/// Prefix marking an encrypted value, followed by `{key_id}:{base64(nonce || ciphertext)}`
+const PREFIX: &str = "enc:v1:";
+
+/// Length of an AES-GCM nonce
+const NONCE_LEN: usize = 12;
+
+/// Settings for encrypting local stores at rest
+#[derive(Debug, Clone)]
+pub struct EncryptionConfig {
+    /// Base64-encoded 256-bit keys
+    pub keys: KeyRingConfig,
+}
+
+/// AES-256-GCM encryption for values written to the spool and history store.
+///
+/// Values are tagged with the ID of the key that encrypted them. Rotating means adding a
+/// new key, making it active and keeping the old one until nothing encrypted with it is
+/// left. An expired key no longer encrypts but still decrypts, so nothing becomes
+/// unreadable before it is removed. Values written before encryption was enabled are read
+/// as plaintext.
+pub struct Cipher {
+    ring: Arc<KeyRing>,
+}
+
+impl Cipher {
+    /// Load the configured keys
+    pub fn new(config: &EncryptionConfig) -> Result<Self> {
+        Ok(Self {
+            ring: KeyRing::open("encryption", config.keys.clone(), |key| {
+                aes_key(key).map(|_| ())
+            })?,
+        })
+    }
+
+    /// Encrypt a value with the active key
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<String> {
+        let key = self.ring.current()?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = aes_key(&key)?
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| AppError::InternalError("Encryption failed".to_string()))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(format!("{}{}:{}", PREFIX, key.id, STANDARD.encode(sealed)))
+    }
+
+    /// Decrypt a value produced by `encrypt`
+    pub fn decrypt(&self, value: &str) -> Result<Vec<u8>> {
+        let (id, sealed) = value
+            .strip_prefix(PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(|| decrypt_error("not an encrypted value"))?;
+
+        let key = self
+            .ring
+            .get(id)
+            .ok_or_else(|| decrypt_error(&format!("key {} is not configured", id)))?;
+        let cipher = aes_key(&key)?;
+        let sealed = STANDARD
+            .decode(sealed)
+            .map_err(|_| decrypt_error("malformed value"))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(decrypt_error("malformed value"));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| decrypt_error(&format!("authentication failed with key {}", id)))
+    }
+}
+
+/// Whether a stored value was written encrypted
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// AES-256-GCM cipher for a base64-encoded 32-byte key
+fn aes_key(key: &KeyVersion) -> Result<Aes256Gcm> {
+    let bytes = STANDARD.decode(key.secret.expose()).map_err(|_| {
+        AppError::ValidationError(format!("Encryption key {} is not base64", key.id))
+    })?;
+    Aes256Gcm::new_from_slice(&bytes).map_err(|_| {
+        AppError::ValidationError(format!("Encryption key {} must be 32 bytes", key.id))
+    })
+}
+
+fn decrypt_error(reason: &str) -> AppError {
+    AppError::InternalError(format!("Decryption failed: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::keys::KeyVersion;
+
+    fn cipher(entries: &[&str]) -> Cipher {
+        let keys = entries
+            .iter()
+            .map(|entry| KeyVersion::parse(entry).unwrap())
+            .collect();
+        Cipher::new(&EncryptionConfig {
+            keys: KeyRingConfig {
+                keys,
+                file: None,
+                active: None,
+                refresh_interval: Duration::from_secs(60),
+            },
+        })
+        .unwrap()
+    }
+
+    fn key(id: &str, byte: u8) -> String {
+        format!("{}={}", id, STANDARD.encode([byte; 32]))
+    }
+
+    #[test]
+    fn round_trips_values() {
+        let cipher = cipher(&[&key("k1", 1)]);
+        let sealed = cipher.encrypt(b"spooled payload").unwrap();
+
+        assert!(is_encrypted(&sealed));
+        assert!(sealed.starts_with("enc:v1:k1:"));
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), b"spooled payload");
+        // A fresh nonce every time
+        assert_ne!(cipher.encrypt(b"spooled payload").unwrap(), sealed);
+    }
+
+    #[test]
+    fn rejects_tampered_and_foreign_values() {
+        let cipher = cipher(&[&key("k1", 1)]);
+        let sealed = cipher.encrypt(b"payload").unwrap();
+
+        let (head, body) = sealed.rsplit_once(':').unwrap();
+        let mut bytes = STANDARD.decode(body).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(cipher
+            .decrypt(&format!("{}:{}", head, STANDARD.encode(bytes)))
+            .is_err());
+
+        assert!(!is_encrypted("cGxhaW4="));
+        assert!(cipher.decrypt("cGxhaW4=").is_err());
+        assert!(cipher.decrypt("enc:v1:k1:AAAA").is_err());
+    }
+
+    #[test]
+    fn decrypts_with_older_keys_after_rotation() {
+        let before = cipher(&[&key("k1", 1)]);
+        let old = before.encrypt(b"old").unwrap();
+
+        let rotated = cipher(&[&key("k2", 2), &key("k1", 1)]);
+        let new = rotated.encrypt(b"new").unwrap();
+        assert!(new.starts_with("enc:v1:k2:"));
+        assert_eq!(rotated.decrypt(&old).unwrap(), b"old");
+        assert_eq!(rotated.decrypt(&new).unwrap(), b"new");
+
+        // Once the old key is removed its values are unreadable
+        let retired = cipher(&[&key("k2", 2)]);
+        assert!(retired.decrypt(&old).is_err());
+        assert_eq!(retired.decrypt(&new).unwrap(), b"new");
+    }
+
+    #[test]
+    fn expired_keys_decrypt_but_no_longer_encrypt() {
+        let old = cipher(&[&key("k1", 1)]).encrypt(b"old").unwrap();
+
+        let rotated = cipher(&[&key("k1@2020-01-01T00:00:00Z", 1), &key("k2", 2)]);
+        assert!(rotated.encrypt(b"new").unwrap().starts_with("enc:v1:k2:"));
+        assert_eq!(rotated.decrypt(&old).unwrap(), b"old");
+
+        let expired = cipher(&[&key("k1@2020-01-01T00:00:00Z", 1)]);
+        assert!(expired.encrypt(b"new").is_err());
+    }
+
+    #[test]
+    fn rejects_keys_of_the_wrong_length() {
+        let keys =
+            vec![KeyVersion::parse(&format!("short={}", STANDARD.encode([1u8; 16]))).unwrap()];
+        let config = EncryptionConfig {
+            keys: KeyRingConfig {
+                keys,
+                file: None,
+                active: None,
+                refresh_interval: Duration::from_secs(60),
+            },
+        };
+        assert!(matches!(
+            Cipher::new(&config),
+            Err(AppError::ValidationError(_))
+        ));
+    }
+}
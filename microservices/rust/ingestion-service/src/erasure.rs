@@ -0,0 +1,251 @@
+use std::sync::Arc;
+
+use axum::{extract::Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::audit::AuditEntry;
+use crate::config::AppConfig;
+use crate::error::{AppError, Result};
+use crate::history::HistoryQuery;
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+use crate::retraction::{RetractRequest, Retractor};
+
+/// Check that erasure fields are JSON pointers into an item's payload or metadata, so a typo
+/// is reported rather than silently matching nothing
+pub fn check_fields(fields: &[String]) -> Result<()> {
+    for field in fields {
+        if field_root(field).is_none() {
+            return Err(AppError::ValidationError(format!(
+                "Invalid erasure field {}: fields are JSON pointers starting with /payload or /metadata",
+                field
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Body of an erasure request
+#[derive(Debug, Deserialize)]
+pub struct ErasureRequest {
+    /// Identifier of the data subject, e.g. an email address or user ID
+    pub subject_id: String,
+
+    /// Why the subject's data is erased, e.g. a ticket reference
+    pub reason: String,
+
+    /// Who asked for the erasure, recorded in the audit log
+    #[serde(default)]
+    pub requested_by: Option<String>,
+
+    /// Only search items from this source
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+/// An item retracted by an erasure
+#[derive(Debug, Clone, Serialize)]
+pub struct ErasedItem {
+    pub item_id: Uuid,
+    pub retraction_id: Uuid,
+
+    /// Whether the retraction was spooled to be published once NATS is back
+    pub spooled: bool,
+}
+
+/// An item whose retraction failed
+#[derive(Debug, Clone, Serialize)]
+pub struct ErasureFailure {
+    pub item_id: Uuid,
+    pub error: String,
+}
+
+/// Outcome of an erasure request, returned to the caller and recorded in the audit log
+#[derive(Debug, Clone, Serialize)]
+pub struct ErasureReport {
+    pub id: Uuid,
+
+    /// `completed`, or `partial` when some retractions failed and the request should be repeated
+    pub status: String,
+
+    /// HMAC-SHA256 of the subject identifier under the retraction signing key, so the report
+    /// can be matched to a request without the audit log holding the identifier
+    pub subject_hash: String,
+
+    /// ID of the signing key `subject_hash` was computed with
+    pub subject_hash_key: String,
+
+    pub reason: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requested_by: Option<String>,
+
+    /// Items in the history that mention the subject
+    pub matched: usize,
+
+    pub retracted: Vec<ErasedItem>,
+    pub failed: Vec<ErasureFailure>,
+
+    pub started_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+}
+
+/// Erase a data subject: retract every item in the history that mentions them and record an
+/// erasure report in the audit log
+#[instrument(skip_all)]
+pub async fn erase(
+    Extension(retractor): Extension<Arc<Retractor>>,
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    Json(request): Json<ErasureRequest>,
+) -> Result<Json<ErasureReport>> {
+    let subject_id = request.subject_id.trim().to_string();
+    if subject_id.is_empty() {
+        return Err(AppError::ValidationError(
+            "An erasure needs a subject identifier".to_string(),
+        ));
+    }
+    if request.reason.trim().is_empty() {
+        return Err(AppError::ValidationError(
+            "An erasure needs a reason".to_string(),
+        ));
+    }
+
+    let started_at = Utc::now();
+    let id = Uuid::new_v4();
+    let fields = config.erasure_fields.clone();
+
+    // Scanning decrypts every item, so keep it off the async workers
+    let query = HistoryQuery {
+        source: request.source.clone(),
+        ..Default::default()
+    };
+    let matches = tokio::task::spawn_blocking({
+        let pipeline = pipeline.clone();
+        let subject_id = subject_id.clone();
+        move || -> Result<Vec<Uuid>> {
+            let history = pipeline.history().ok_or_else(|| {
+                AppError::InternalError("History store is not enabled".to_string())
+            })?;
+            let mut matches = Vec::new();
+            history.scan(&query, |item| {
+                if mentions(&item, &subject_id, &fields) {
+                    matches.push(item.id);
+                }
+                true
+            })?;
+            Ok(matches)
+        }
+    })
+    .await
+    .map_err(|e| AppError::InternalError(format!("Erasure task failed: {}", e)))??;
+
+    info!("Erasure {} matched {} items", id, matches.len());
+
+    let mut retracted = Vec::with_capacity(matches.len());
+    let mut failed = Vec::new();
+    for item_id in &matches {
+        let retraction = RetractRequest {
+            reason: format!("Erasure {}: {}", id, request.reason),
+            requested_by: request.requested_by.clone(),
+            source: None,
+            content_type: None,
+        };
+        match retractor.retract(&pipeline, *item_id, retraction).await {
+            Ok(response) => retracted.push(ErasedItem {
+                item_id: *item_id,
+                retraction_id: response.id,
+                spooled: response.spooled,
+            }),
+            Err(e) => {
+                warn!("Erasure {} failed to retract item {}: {}", id, item_id, e);
+                failed.push(ErasureFailure {
+                    item_id: *item_id,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    let (subject_hash_key, subject_hash) = retractor.sign(subject_id.as_bytes())?;
+    let report = ErasureReport {
+        id,
+        status: if failed.is_empty() {
+            "completed"
+        } else {
+            "partial"
+        }
+        .to_string(),
+        subject_hash,
+        subject_hash_key,
+        reason: request.reason,
+        requested_by: request.requested_by,
+        matched: matches.len(),
+        retracted,
+        failed,
+        started_at,
+        completed_at: Utc::now(),
+    };
+
+    retractor
+        .audit()
+        .record(&AuditEntry {
+            timestamp: report.completed_at,
+            action: "erase".to_string(),
+            actor: report.requested_by.clone(),
+            details: serde_json::to_value(&report)
+                .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))?,
+        })
+        .await?;
+
+    info!(
+        "Erasure {} {}: {} retracted, {} failed",
+        id,
+        report.status,
+        report.retracted.len(),
+        report.failed.len()
+    );
+
+    Ok(Json(report))
+}
+
+/// Whether an item mentions a subject, as a string value anywhere in its payload or metadata,
+/// or only in `fields` when given. Identifiers are compared ignoring ASCII case, as email
+/// addresses usually are.
+fn mentions(item: &RawData, subject_id: &str, fields: &[String]) -> bool {
+    if fields.is_empty() {
+        return contains(&item.payload, subject_id) || contains(&item.metadata, subject_id);
+    }
+
+    fields.iter().any(|field| {
+        let value = match field_root(field) {
+            Some(("/payload", pointer)) => item.payload.pointer(pointer),
+            Some((_, pointer)) => item.metadata.pointer(pointer),
+            None => None,
+        };
+        value.is_some_and(|value| contains(value, subject_id))
+    })
+}
+
+/// Whether a value is, or holds at any depth, a string equal to the subject identifier
+fn contains(value: &Value, subject_id: &str) -> bool {
+    match value {
+        Value::String(s) => s.trim().eq_ignore_ascii_case(subject_id),
+        Value::Array(values) => values.iter().any(|v| contains(v, subject_id)),
+        Value::Object(map) => map.values().any(|v| contains(v, subject_id)),
+        _ => false,
+    }
+}
+
+/// Split an erasure field into its root and the pointer within it
+fn field_root(field: &str) -> Option<(&'static str, &str)> {
+    ["/payload", "/metadata"].into_iter().find_map(|root| {
+        let pointer = field.strip_prefix(root)?;
+        (pointer.is_empty() || pointer.starts_with('/')).then_some((root, pointer))
+    })
+}
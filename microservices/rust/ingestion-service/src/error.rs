@@ -3,53 +3,99 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use serde::Serialize;
 use thiserror::Error;
+use utoipa::ToSchema;
 
 /// Custom error types for the ingestion service
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum AppError {
     #[error("Failed to connect to NATS: {0}")]
     NatsConnectionError(String),
-    
+
     #[error("Failed to publish message to NATS: {0}")]
     NatsPublishError(String),
-    
+
     #[error("Invalid input data: {0}")]
     ValidationError(String),
-    
+
     #[error("Internal server error: {0}")]
     InternalError(String),
+
+    #[error("Request violates policy: {0}")]
+    PolicyViolation(String),
+
+    #[error("Failed to fetch remote content: {0}")]
+    FetchError(String),
+
+    #[error("Request deadline exceeded: {0}")]
+    DeadlineExceeded(String),
+
+    #[error("Rate limit exceeded: {0}")]
+    RateLimited(String),
+
+    #[error("Ingestion paused: {0}")]
+    Paused(String),
+
+    #[error("Duplicate delivery: {0}")]
+    Duplicate(String),
+
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+
+    #[error("Stream unavailable: {0}")]
+    StreamUnavailable(String),
+}
+
+/// Error response body
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+/// Details of an error
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorDetail {
+    /// Human-readable description
+    pub message: String,
+
+    /// HTTP status code
+    pub code: u16,
 }
 
 /// Convert application errors into appropriate HTTP responses
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, error_message) = match self {
-            AppError::NatsConnectionError(msg) => (
-                StatusCode::SERVICE_UNAVAILABLE,
-                msg,
-            ),
-            AppError::NatsPublishError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                msg,
-            ),
-            AppError::ValidationError(msg) => (
-                StatusCode::BAD_REQUEST,
-                msg,
-            ),
-            AppError::InternalError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                msg,
-            ),
+            AppError::NatsConnectionError(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
+            AppError::NatsPublishError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            AppError::ValidationError(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::InternalError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            AppError::PolicyViolation(msg) => (StatusCode::FORBIDDEN, msg),
+            AppError::FetchError(msg) => (StatusCode::BAD_GATEWAY, msg),
+            AppError::DeadlineExceeded(msg) => (StatusCode::GATEWAY_TIMEOUT, msg),
+            AppError::RateLimited(msg) => (StatusCode::TOO_MANY_REQUESTS, msg),
+            AppError::Paused(msg) => (StatusCode::LOCKED, msg),
+            AppError::Duplicate(msg) => (StatusCode::CONFLICT, msg),
+            AppError::PayloadTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, msg),
+            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            AppError::PreconditionFailed(msg) => (StatusCode::PRECONDITION_FAILED, msg),
+            AppError::StreamUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
         };
 
-        let body = Json(json!({
-            "error": {
-                "message": error_message,
-                "code": status.as_u16()
-            }
-        }));
+        let body = Json(ErrorResponse {
+            error: ErrorDetail {
+                message: error_message,
+                code: status.as_u16(),
+            },
+        });
 
         (status, body).into_response()
     }
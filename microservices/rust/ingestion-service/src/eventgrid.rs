@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Json, Query},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::config::{AppConfig, Secret};
+use crate::error::{AppError, Result};
+use crate::models::{BatchIngestResponse, RawData};
+use crate::pipeline::Pipeline;
+
+/// Event type of the handshake Event Grid sends when a subscription is created
+const VALIDATION_EVENT: &str = "Microsoft.EventGrid.SubscriptionValidationEvent";
+
+/// Settings for the Azure Event Grid endpoint
+#[derive(Debug, Clone)]
+pub struct EventGridConfig {
+    /// Key the subscription appends to the endpoint URL as `?key=`
+    pub access_key: Secret,
+
+    /// Source assigned to ingested events
+    pub source: String,
+
+    /// Content type assigned to ingested events
+    pub content_type: String,
+}
+
+/// Event in the Event Grid schema
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventGridEvent {
+    pub id: String,
+
+    #[serde(default)]
+    pub topic: Option<String>,
+
+    #[serde(default)]
+    pub subject: Option<String>,
+
+    pub event_type: String,
+
+    #[serde(default)]
+    pub event_time: Option<String>,
+
+    #[serde(default)]
+    pub data_version: Option<String>,
+
+    #[serde(default)]
+    pub data: Value,
+}
+
+/// Query parameters of an Event Grid delivery
+#[derive(Debug, Deserialize)]
+pub struct EventGridParams {
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+/// Receive events delivered by an Event Grid subscription.
+///
+/// Answers the subscription validation handshake, and otherwise ingests every event in the
+/// batch. Any failure fails the whole delivery so Event Grid retries it; item IDs derive from
+/// event IDs, so events published before the failure keep their IDs on retry.
+#[instrument(skip_all)]
+pub async fn eventgrid_events(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    Query(params): Query<EventGridParams>,
+    Json(events): Json<Vec<EventGridEvent>>,
+) -> Result<Response> {
+    let eventgrid = authorize(&config, &params)?;
+
+    if let Some(validation) = events
+        .iter()
+        .find(|event| event.event_type == VALIDATION_EVENT)
+    {
+        let code = validation
+            .data
+            .get("validationCode")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                AppError::ValidationError("Validation event has no validationCode".to_string())
+            })?;
+
+        info!(
+            "Completed Event Grid subscription validation for {:?}",
+            validation.topic
+        );
+        return Ok(Json(json!({ "validationResponse": code })).into_response());
+    }
+
+    let mut ids = Vec::with_capacity(events.len());
+
+    for event in events {
+        let mut item = RawData::builder()
+            .id(Uuid::new_v5(
+                &Uuid::NAMESPACE_OID,
+                format!("eventgrid:{}", event.id).as_bytes(),
+            ))
+            .source(eventgrid.source.clone())
+            .content_type(eventgrid.content_type.clone())
+            .payload(json!({
+                "event_id": event.id,
+                "event_type": event.event_type,
+                "topic": event.topic,
+                "subject": event.subject,
+                "event_time": event.event_time,
+                "data_version": event.data_version,
+                "data": event.data,
+            }))
+            .build()?;
+
+        pipeline.process(&mut item).await?;
+        ids.push(item.id);
+    }
+
+    debug!("Ingested {} Event Grid events", ids.len());
+
+    let response = BatchIngestResponse {
+        status: "success".to_string(),
+        count: ids.len(),
+        ids,
+        timestamp: Utc::now(),
+        deliver_at: None,
+        sequence_gaps: Vec::new(),
+    };
+
+    Ok((StatusCode::CREATED, Json(response)).into_response())
+}
+
+/// Answer the CloudEvents webhook validation handshake Event Grid uses for CloudEvents subscriptions
+pub async fn eventgrid_handshake(
+    Extension(config): Extension<Arc<AppConfig>>,
+    Query(params): Query<EventGridParams>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    authorize(&config, &params)?;
+
+    let origin = headers
+        .get("webhook-request-origin")
+        .cloned()
+        .ok_or_else(|| {
+            AppError::ValidationError("Missing WebHook-Request-Origin header".to_string())
+        })?;
+
+    Ok((
+        StatusCode::OK,
+        [
+            ("webhook-allowed-origin", origin),
+            ("webhook-allowed-rate", HeaderValue::from_static("*")),
+        ],
+    )
+        .into_response())
+}
+
+fn authorize<'a>(config: &'a AppConfig, params: &EventGridParams) -> Result<&'a EventGridConfig> {
+    let eventgrid = config.eventgrid.as_ref().ok_or_else(|| {
+        AppError::InternalError("Event Grid delivery is not configured".to_string())
+    })?;
+
+    if !params
+        .key
+        .as_deref()
+        .is_some_and(|key| eventgrid.access_key.matches(key))
+    {
+        warn!("Rejected Event Grid delivery with an invalid key");
+        return Err(AppError::PolicyViolation(
+            "Invalid Event Grid access key".to_string(),
+        ));
+    }
+
+    Ok(eventgrid)
+}
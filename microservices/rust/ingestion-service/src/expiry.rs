@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::nats::Headers;
+
+/// Header holding the RFC 3339 time after which a message is stale and no longer delivered
+pub const EXPIRES_AT_HEADER: &str = "Ingest-Expires-At";
+
+/// Settings for expiring messages that wait too long to be delivered
+#[derive(Debug, Clone)]
+pub struct ExpiryConfig {
+    /// Time to live of messages of content types without their own
+    pub default: Option<Duration>,
+
+    /// Time to live of messages of specific content types
+    pub content_types: Vec<(String, Duration)>,
+}
+
+impl ExpiryConfig {
+    /// Time to live of messages of a content type, if they expire
+    pub fn ttl_for(&self, content_type: &str) -> Option<Duration> {
+        self.content_types
+            .iter()
+            .find(|(t, _)| t == content_type)
+            .map(|(_, ttl)| *ttl)
+            .or(self.default)
+    }
+}
+
+/// Value of `Ingest-Expires-At` for a message published now with a time to live
+pub fn expires_at(ttl: Duration) -> String {
+    let ttl = chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX);
+    Utc::now()
+        .checked_add_signed(ttl)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Whether a message's `Ingest-Expires-At` has passed.
+///
+/// Messages without the header, or with one that cannot be read, never expire, so a
+/// damaged header delivers stale data rather than losing it.
+pub fn is_expired(headers: &Headers, now: DateTime<Utc>) -> bool {
+    headers
+        .iter()
+        .find(|(name, _)| name == EXPIRES_AT_HEADER)
+        .and_then(|(_, value)| DateTime::parse_from_rfc3339(value).ok())
+        .is_some_and(|at| at < now)
+}
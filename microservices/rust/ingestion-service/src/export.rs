@@ -0,0 +1,280 @@
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use arrow_array::builder::{StringBuilder, TimestampMicrosecondBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Query},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::{info, instrument, warn};
+
+use crate::error::{AppError, Result};
+use crate::history::{HistoryQuery, HistoryStore};
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+
+/// Rows per Parquet row group; each group is buffered in memory before it is streamed
+const ROW_GROUP_SIZE: usize = 10_000;
+
+/// Bytes gathered before a chunk is sent to the client
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Query parameters for exports
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    /// Only items from this source
+    pub source: Option<String>,
+
+    /// Only items timestamped at or after this instant
+    pub from: Option<DateTime<Utc>>,
+
+    /// Only items timestamped before this instant
+    pub to: Option<DateTime<Utc>>,
+
+    /// `parquet` (default) or `ndjson`
+    pub format: Option<String>,
+
+    /// Most items returned
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ExportFormat {
+    Parquet,
+    Ndjson,
+}
+
+/// Stream items from the history store as Parquet or newline-delimited JSON
+#[instrument(skip(pipeline))]
+pub async fn export(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response> {
+    if pipeline.history().is_none() {
+        return Err(AppError::InternalError(
+            "History store is not enabled".to_string(),
+        ));
+    }
+
+    let format = match params.format.as_deref().unwrap_or("parquet") {
+        "parquet" => ExportFormat::Parquet,
+        "ndjson" => ExportFormat::Ndjson,
+        other => {
+            return Err(AppError::ValidationError(format!(
+                "Unknown export format {}",
+                other
+            )))
+        }
+    };
+
+    let query = HistoryQuery {
+        source: params.source,
+        from: params.from,
+        to: params.to,
+        limit: params.limit,
+    };
+
+    info!("Exporting history as {:?}", format);
+
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(4);
+    tokio::task::spawn_blocking(move || {
+        let Some(history) = pipeline.history() else {
+            return;
+        };
+        let mut writer = ChunkWriter::new(tx.clone());
+        let result = match format {
+            ExportFormat::Parquet => write_parquet(history, &query, writer),
+            ExportFormat::Ndjson => {
+                write_ndjson(history, &query, &mut writer).and_then(|_| writer.finish())
+            }
+        };
+
+        // The response has started, so errors can only cut the body short
+        if let Err(e) = result {
+            warn!("History export failed: {}", e);
+            let _ = tx.blocking_send(Err(io::Error::other(e.to_string())));
+        }
+    });
+
+    let body = Body::from_stream(futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }));
+
+    let (content_type, extension) = match format {
+        ExportFormat::Parquet => ("application/vnd.apache.parquet", "parquet"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"export.{}\"", extension),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn write_ndjson(
+    history: &HistoryStore,
+    query: &HistoryQuery,
+    writer: &mut ChunkWriter,
+) -> Result<()> {
+    let mut failure = None;
+    history.scan(query, |item| {
+        let written = serde_json::to_writer(&mut *writer, &item)
+            .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))
+            .and_then(|_| writer.write_all(b"\n").map_err(export_error));
+        match written {
+            Ok(()) => true,
+            Err(e) => {
+                failure = Some(e);
+                false
+            }
+        }
+    })?;
+
+    failure.map_or(Ok(()), Err)
+}
+
+fn write_parquet(history: &HistoryStore, query: &HistoryQuery, writer: ChunkWriter) -> Result<()> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("source", DataType::Utf8, false),
+        Field::new("content_type", DataType::Utf8, false),
+        Field::new("payload", DataType::Utf8, false),
+        Field::new("metadata", DataType::Utf8, false),
+    ]));
+
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_row_count(Some(ROW_GROUP_SIZE))
+        .build();
+    let mut parquet =
+        ArrowWriter::try_new(writer, schema.clone(), Some(properties)).map_err(export_error)?;
+
+    let mut rows = Vec::with_capacity(ROW_GROUP_SIZE);
+    let mut failure = None;
+    history.scan(query, |item| {
+        rows.push(item);
+        if rows.len() < ROW_GROUP_SIZE {
+            return true;
+        }
+        match record_batch(&schema, &std::mem::take(&mut rows))
+            .and_then(|batch| parquet.write(&batch).map_err(export_error))
+        {
+            Ok(()) => true,
+            Err(e) => {
+                failure = Some(e);
+                false
+            }
+        }
+    })?;
+
+    if let Some(e) = failure {
+        return Err(e);
+    }
+
+    if !rows.is_empty() {
+        parquet
+            .write(&record_batch(&schema, &rows)?)
+            .map_err(export_error)?;
+    }
+
+    parquet.into_inner().map_err(export_error)?.finish()
+}
+
+fn record_batch(schema: &Arc<Schema>, items: &[RawData]) -> Result<RecordBatch> {
+    let mut id = StringBuilder::new();
+    let mut timestamp = TimestampMicrosecondBuilder::new().with_timezone("UTC");
+    let mut source = StringBuilder::new();
+    let mut content_type = StringBuilder::new();
+    let mut payload = StringBuilder::new();
+    let mut metadata = StringBuilder::new();
+
+    for item in items {
+        id.append_value(item.id.to_string());
+        timestamp.append_value(item.timestamp.timestamp_micros());
+        source.append_value(&item.source);
+        content_type.append_value(&item.content_type);
+        payload.append_value(item.payload.to_string());
+        metadata.append_value(item.metadata.to_string());
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(id.finish()),
+        Arc::new(timestamp.finish()),
+        Arc::new(source.finish()),
+        Arc::new(content_type.finish()),
+        Arc::new(payload.finish()),
+        Arc::new(metadata.finish()),
+    ];
+
+    RecordBatch::try_new(schema.clone(), columns).map_err(export_error)
+}
+
+/// Buffers output from a blocking writer and sends it to the response body in chunks
+struct ChunkWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    buffer: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn new(tx: mpsc::Sender<io::Result<Bytes>>) -> Self {
+        Self {
+            tx,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    /// Send whatever is still buffered
+    fn finish(mut self) -> Result<()> {
+        self.flush().map_err(export_error)
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(CHUNK_SIZE),
+        ));
+        // Fails once the client has gone away, which stops the export
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "export client disconnected"))
+    }
+}
+
+fn export_error(e: impl std::fmt::Display) -> AppError {
+    AppError::InternalError(format!("Export failed: {}", e))
+}
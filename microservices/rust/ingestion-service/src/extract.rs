@@ -0,0 +1,134 @@
+use std::io::{Cursor, Read};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::error::{AppError, Result};
+use crate::sanitize::{self, SanitizeMode};
+
+/// Largest uncompressed docx main part read, so a small archive cannot inflate without bound
+const MAX_DOCX_XML_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Document formats the extractor understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DocumentKind {
+    Pdf,
+    Html,
+    Docx,
+    PlainText,
+}
+
+impl DocumentKind {
+    /// Determine the document kind from a MIME type or, failing that, a file name
+    fn detect(mime_type: Option<&str>, filename: Option<&str>) -> Option<Self> {
+        let by_mime =
+            mime_type.and_then(
+                |mime| match mime.split(';').next().unwrap_or_default().trim() {
+                    "application/pdf" => Some(Self::Pdf),
+                    "text/html" | "application/xhtml+xml" => Some(Self::Html),
+                    "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
+                        Some(Self::Docx)
+                    }
+                    "text/plain" | "text/markdown" => Some(Self::PlainText),
+                    _ => None,
+                },
+            );
+
+        by_mime.or_else(|| {
+            let extension = filename?.rsplit_once('.')?.1.to_ascii_lowercase();
+            match extension.as_str() {
+                "pdf" => Some(Self::Pdf),
+                "html" | "htm" => Some(Self::Html),
+                "docx" => Some(Self::Docx),
+                "txt" | "md" => Some(Self::PlainText),
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Extract text from an inline `payload.document` and attach it as `payload.extracted_text`.
+///
+/// The document is expected as `{ "data": "<base64>", "mime_type": "...", "filename": "..." }`.
+/// Payloads without a document are left untouched.
+pub fn extract_document_text(payload: &mut Value) -> Result<()> {
+    let Some(document) = payload.get("document").and_then(Value::as_object) else {
+        return Ok(());
+    };
+
+    let Some(data) = document.get("data").and_then(Value::as_str) else {
+        return Ok(());
+    };
+
+    let mime_type = document.get("mime_type").and_then(Value::as_str);
+    let filename = document.get("filename").and_then(Value::as_str);
+
+    let Some(kind) = DocumentKind::detect(mime_type, filename) else {
+        warn!(
+            "Unsupported document type for extraction: {:?}",
+            mime_type.or(filename)
+        );
+        return Ok(());
+    };
+
+    let bytes = STANDARD.decode(data).map_err(|e| {
+        AppError::ValidationError(format!("Document data is not valid base64: {}", e))
+    })?;
+
+    let text = match kind {
+        DocumentKind::Pdf => pdf_extract::extract_text_from_mem(&bytes).map_err(|e| {
+            AppError::ValidationError(format!("Failed to extract text from PDF: {}", e))
+        })?,
+        DocumentKind::Html => {
+            sanitize::sanitize_html(&String::from_utf8_lossy(&bytes), SanitizeMode::Text)
+        }
+        DocumentKind::Docx => extract_docx(&bytes)?,
+        DocumentKind::PlainText => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+
+    debug!(
+        "Extracted {} characters from {:?} document",
+        text.len(),
+        kind
+    );
+
+    if let Some(map) = payload.as_object_mut() {
+        map.insert(
+            "extracted_text".to_string(),
+            Value::String(text.trim().to_string()),
+        );
+    }
+
+    Ok(())
+}
+
+/// Extract paragraph text from the main part of a docx archive
+fn extract_docx(bytes: &[u8]) -> Result<String> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        AppError::ValidationError(format!("Failed to read docx document: {}", e))
+    };
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| invalid(&e))?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(|e| invalid(&e))?
+        .take(MAX_DOCX_XML_BYTES + 1)
+        .read_to_string(&mut xml)
+        .map_err(|e| invalid(&e))?;
+    if xml.len() as u64 > MAX_DOCX_XML_BYTES {
+        return Err(AppError::ValidationError(format!(
+            "Docx document text exceeds {} bytes",
+            MAX_DOCX_XML_BYTES
+        )));
+    }
+
+    let paragraphs: Vec<String> = xml
+        .split("</w:p>")
+        .map(|paragraph| sanitize::sanitize_html(paragraph, SanitizeMode::Text))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect();
+
+    Ok(paragraphs.join("\n"))
+}
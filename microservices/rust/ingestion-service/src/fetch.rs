@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{header, Url};
+use serde_json::{json, Value};
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+use crate::error::{AppError, Result};
+use crate::http::{self, ProxyConfig};
+use crate::ssrf::{SsrfConfig, SsrfGuard};
+
+/// Hosts tracked for robots.txt and politeness before idle entries are evicted
+const MAX_TRACKED_HOSTS: usize = 10_000;
+
+/// Largest robots.txt read; the rest is ignored, as major crawlers do
+const MAX_ROBOTS_BYTES: usize = 512 * 1024;
+
+/// Settings for fetching user-supplied URLs
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// User agent sent with every request and matched against robots.txt groups
+    pub user_agent: String,
+
+    /// Maximum concurrent requests to a single host
+    pub max_concurrency_per_domain: usize,
+
+    /// Minimum spacing between requests to a single host
+    pub min_interval: Duration,
+
+    /// Per-request timeout
+    pub timeout: Duration,
+
+    /// Largest response body accepted
+    pub max_bytes: usize,
+
+    /// How long a fetched robots.txt is trusted
+    pub robots_ttl: Duration,
+}
+
+/// Politeness state kept per host
+struct DomainState {
+    permits: Semaphore,
+    next_allowed: tokio::sync::Mutex<Instant>,
+}
+
+/// Fetches URLs on behalf of producers while respecting robots.txt and per-host limits
+pub struct UrlFetcher {
+    http: reqwest::Client,
+    config: FetchConfig,
+    guard: SsrfGuard,
+    robots: Mutex<HashMap<String, (Instant, Arc<RobotsRules>)>>,
+    domains: Mutex<HashMap<String, Arc<DomainState>>>,
+}
+
+impl UrlFetcher {
+    /// Create a new fetcher
+    pub fn new(config: FetchConfig, proxy: &ProxyConfig, ssrf: &SsrfConfig) -> Result<Self> {
+        let guard = SsrfGuard::new(ssrf);
+        let http = guard
+            .clone()
+            .apply(http::client_builder(proxy)?)
+            .user_agent(config.user_agent.clone())
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| AppError::InternalError(format!("Failed to build HTTP client: {}", e)))?;
+
+        Ok(Self {
+            http,
+            config,
+            guard,
+            robots: Mutex::new(HashMap::new()),
+            domains: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Fetch a URL and return it as a payload carrying the body as an inline document
+    pub async fn fetch(&self, raw_url: &str) -> Result<Value> {
+        let url = Url::parse(raw_url)
+            .map_err(|e| AppError::ValidationError(format!("Invalid URL: {}", e)))?;
+
+        self.guard.check_url(&url).await?;
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| AppError::ValidationError("URL has no host".to_string()))?
+            .to_ascii_lowercase();
+
+        let robots = self.robots_for(&url, &host).await;
+        if !robots.is_allowed(url.path()) {
+            warn!("Fetch of {} disallowed by robots.txt", url);
+            return Err(AppError::PolicyViolation(format!(
+                "Fetching {} is disallowed by robots.txt",
+                url
+            )));
+        }
+
+        let delay = robots.crawl_delay.map_or(self.config.min_interval, |d| {
+            d.max(self.config.min_interval)
+        });
+        let response = self.polite_get(&host, url.clone(), delay).await?;
+
+        if !response.status().is_success() {
+            return Err(AppError::FetchError(format!(
+                "{} returned {}",
+                url,
+                response.status()
+            )));
+        }
+
+        if response
+            .content_length()
+            .is_some_and(|len| len > self.config.max_bytes as u64)
+        {
+            return Err(AppError::ValidationError(format!(
+                "Response from {} is too large",
+                url
+            )));
+        }
+
+        let mime_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+
+        let body = response.bytes().await.map_err(|e| {
+            AppError::FetchError(format!("Failed to read response from {}: {}", url, e))
+        })?;
+
+        if body.len() > self.config.max_bytes {
+            return Err(AppError::ValidationError(format!(
+                "Response from {} is too large",
+                url
+            )));
+        }
+
+        debug!("Fetched {} bytes from {}", body.len(), url);
+
+        let filename = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("index");
+
+        Ok(json!({
+            "url": url.as_str(),
+            "fetched_at": chrono::Utc::now(),
+            "document": {
+                "filename": filename,
+                "mime_type": mime_type,
+                "data": STANDARD.encode(&body),
+            },
+        }))
+    }
+
+    /// Issue a GET while holding the host's concurrency permit and honoring its spacing
+    async fn polite_get(&self, host: &str, url: Url, delay: Duration) -> Result<reqwest::Response> {
+        let domain = self.domain(host);
+        let _permit = domain
+            .permits
+            .acquire()
+            .await
+            .map_err(|e| AppError::InternalError(e.to_string()))?;
+
+        {
+            let mut next_allowed = domain.next_allowed.lock().await;
+            let now = Instant::now();
+            if *next_allowed > now {
+                debug!(
+                    "Waiting {:?} before fetching from {}",
+                    *next_allowed - now,
+                    host
+                );
+                tokio::time::sleep_until((*next_allowed).into()).await;
+            }
+            *next_allowed = Instant::now() + delay;
+        }
+
+        self.guard.get(&self.http, url).await
+    }
+
+    fn domain(&self, host: &str) -> Arc<DomainState> {
+        let mut domains = self.domains.lock().unwrap_or_else(|e| e.into_inner());
+        if domains.len() >= MAX_TRACKED_HOSTS && !domains.contains_key(host) {
+            // Hosts with no request in flight and no spacing left to wait out hold no state
+            let now = Instant::now();
+            domains.retain(|_, state| {
+                Arc::strong_count(state) > 1
+                    || state
+                        .next_allowed
+                        .try_lock()
+                        .map_or(true, |next| *next > now)
+            });
+            debug!("Evicted idle hosts, {} still tracked", domains.len());
+        }
+        domains
+            .entry(host.to_string())
+            .or_insert_with(|| {
+                Arc::new(DomainState {
+                    permits: Semaphore::new(self.config.max_concurrency_per_domain.max(1)),
+                    next_allowed: tokio::sync::Mutex::new(Instant::now()),
+                })
+            })
+            .clone()
+    }
+
+    /// Return cached robots.txt rules for a host, fetching them when missing or stale
+    async fn robots_for(&self, url: &Url, host: &str) -> Arc<RobotsRules> {
+        let cache_key = format!("{}://{}", url.scheme(), url.authority());
+
+        {
+            let cache = self.robots.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some((fetched_at, rules)) = cache.get(&cache_key) {
+                if fetched_at.elapsed() < self.config.robots_ttl {
+                    return rules.clone();
+                }
+            }
+        }
+
+        let mut robots_url = url.clone();
+        robots_url.set_path("/robots.txt");
+        robots_url.set_query(None);
+        robots_url.set_fragment(None);
+
+        let rules = match self
+            .polite_get(host, robots_url, self.config.min_interval)
+            .await
+        {
+            Ok(response) if response.status().is_success() => {
+                let body = read_capped(response, MAX_ROBOTS_BYTES).await;
+                RobotsRules::parse(&String::from_utf8_lossy(&body), &self.config.user_agent)
+            }
+            // A missing robots.txt means everything is allowed
+            Ok(response) if response.status().is_client_error() => RobotsRules::allow_all(),
+            Ok(response) => {
+                warn!(
+                    "robots.txt for {} returned {}, treating as disallowed",
+                    host,
+                    response.status()
+                );
+                RobotsRules::disallow_all()
+            }
+            Err(e) => {
+                warn!("Failed to fetch robots.txt for {}: {}", host, e);
+                RobotsRules::disallow_all()
+            }
+        };
+
+        let rules = Arc::new(rules);
+        let mut cache = self.robots.lock().unwrap_or_else(|e| e.into_inner());
+        if cache.len() >= MAX_TRACKED_HOSTS {
+            // Drop stale rules, then the older half if too many are still fresh
+            cache.retain(|_, (fetched_at, _)| fetched_at.elapsed() < self.config.robots_ttl);
+            if cache.len() >= MAX_TRACKED_HOSTS / 2 {
+                let mut fetched: Vec<Instant> =
+                    cache.values().map(|(fetched_at, _)| *fetched_at).collect();
+                fetched.sort_unstable();
+                let cutoff = fetched[fetched.len() / 2];
+                cache.retain(|_, (fetched_at, _)| *fetched_at > cutoff);
+            }
+            debug!(
+                "Evicted cached robots.txt rules, {} still cached",
+                cache.len()
+            );
+        }
+        cache.insert(cache_key, (Instant::now(), rules.clone()));
+
+        rules
+    }
+}
+
+/// Read up to `limit` bytes of a response body, ignoring the rest and any read error
+async fn read_capped(mut response: reqwest::Response, limit: usize) -> Vec<u8> {
+    let mut body = Vec::new();
+    while body.len() < limit {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                body.extend_from_slice(&chunk[..chunk.len().min(limit - body.len())])
+            }
+            Ok(None) | Err(_) => break,
+        }
+    }
+    body
+}
+
+/// Allow and disallow rules from the robots.txt group that applies to us
+#[derive(Debug, Default)]
+struct RobotsRules {
+    /// Path patterns with whether they allow access
+    rules: Vec<(bool, String)>,
+
+    crawl_delay: Option<Duration>,
+}
+
+impl RobotsRules {
+    fn allow_all() -> Self {
+        Self::default()
+    }
+
+    fn disallow_all() -> Self {
+        Self {
+            rules: vec![(false, "/".to_string())],
+            crawl_delay: None,
+        }
+    }
+
+    /// Parse robots.txt, keeping the group for our user agent or the wildcard group
+    fn parse(body: &str, user_agent: &str) -> Self {
+        let product = user_agent
+            .split('/')
+            .next()
+            .unwrap_or(user_agent)
+            .trim()
+            .to_ascii_lowercase();
+
+        let mut specific: Option<RobotsRules> = None;
+        let mut wildcard: Option<RobotsRules> = None;
+
+        let mut agents: Vec<String> = Vec::new();
+        let mut group = RobotsRules::default();
+        let mut in_rules = false;
+
+        let mut finish = |agents: &[String], group: RobotsRules| {
+            if agents
+                .iter()
+                .any(|a| a != "*" && product.contains(a.as_str()))
+            {
+                specific.get_or_insert(group);
+            } else if agents.iter().any(|a| a == "*") {
+                wildcard.get_or_insert(group);
+            }
+        };
+
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim();
+
+            match key.as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        finish(&agents, std::mem::take(&mut group));
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    agents.push(value.to_ascii_lowercase());
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+                    // An empty disallow allows everything and adds no rule
+                    if !value.is_empty() {
+                        group.rules.push((key == "allow", value.to_string()));
+                    }
+                }
+                "crawl-delay" => {
+                    in_rules = true;
+                    group.crawl_delay = value.parse::<f64>().ok().map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+        finish(&agents, group);
+
+        specific.or(wildcard).unwrap_or_default()
+    }
+
+    /// Longest matching rule wins, with allow winning ties
+    fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| pattern_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+}
+
+/// Match a robots.txt path pattern supporting `*` wildcards and a trailing `$` anchor
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty() || pattern.ends_with('*')
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+
+/// What a pause applies to
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseScope {
+    /// Every item from one source
+    Source(String),
+
+    /// Every item of one content type
+    ContentType(String),
+}
+
+impl std::fmt::Display for PauseScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Source(source) => write!(f, "source {}", source),
+            Self::ContentType(content_type) => write!(f, "content type {}", content_type),
+        }
+    }
+}
+
+/// An active pause
+#[derive(Debug, Clone, Serialize)]
+pub struct Pause {
+    /// What is paused
+    pub scope: PauseScope,
+
+    /// Explanation returned to affected producers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+
+    /// When the pause started
+    pub paused_at: DateTime<Utc>,
+
+    /// Whether the pause was set by backlog admission control rather than an operator
+    pub automatic: bool,
+}
+
+/// Operator-controlled pauses of ingestion per source or content type.
+///
+/// Pauses are held in memory and cleared on restart.
+#[derive(Default)]
+pub struct FlowControl {
+    pauses: RwLock<BTreeMap<PauseScope, Pause>>,
+}
+
+impl FlowControl {
+    /// Pause ingestion for a scope, replacing any existing pause for it
+    pub fn pause(&self, scope: PauseScope, reason: Option<String>) -> Pause {
+        self.insert(scope, reason, false)
+    }
+
+    /// Pause ingestion on behalf of admission control, leaving operator pauses untouched
+    pub fn pause_automatic(&self, scope: PauseScope, reason: String) {
+        if !self.is_paused(&scope) {
+            self.insert(scope, Some(reason), true);
+        }
+    }
+
+    /// Lift a pause set by admission control, returning whether one was lifted
+    pub fn resume_automatic(&self, scope: &PauseScope) -> bool {
+        let mut pauses = self.pauses.write().unwrap_or_else(|e| e.into_inner());
+        if pauses.get(scope).is_some_and(|pause| pause.automatic) {
+            pauses.remove(scope);
+            return true;
+        }
+        false
+    }
+
+    /// Whether a scope is currently paused
+    pub fn is_paused(&self, scope: &PauseScope) -> bool {
+        self.pauses
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(scope)
+    }
+
+    fn insert(&self, scope: PauseScope, reason: Option<String>, automatic: bool) -> Pause {
+        let pause = Pause {
+            scope: scope.clone(),
+            reason,
+            paused_at: Utc::now(),
+            automatic,
+        };
+        info!("Paused ingestion for {}", scope);
+        self.pauses
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(scope, pause.clone());
+        pause
+    }
+
+    /// Resume ingestion for a scope, returning whether it was paused
+    pub fn resume(&self, scope: &PauseScope) -> bool {
+        let resumed = self
+            .pauses
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(scope)
+            .is_some();
+        if resumed {
+            info!("Resumed ingestion for {}", scope);
+        }
+        resumed
+    }
+
+    /// All active pauses
+    pub fn pauses(&self) -> Vec<Pause> {
+        self.pauses
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Refuse an item whose source or content type is paused
+    pub fn check(&self, item: &RawData) -> Result<()> {
+        let pauses = self.pauses.read().unwrap_or_else(|e| e.into_inner());
+
+        let pause = pauses
+            .get(&PauseScope::Source(item.source.clone()))
+            .or_else(|| pauses.get(&PauseScope::ContentType(item.content_type.clone())));
+
+        match pause {
+            None => Ok(()),
+            Some(pause) => Err(AppError::Paused(match &pause.reason {
+                Some(reason) => format!("Ingestion for {} is paused: {}", pause.scope, reason),
+                None => format!("Ingestion for {} is paused", pause.scope),
+            })),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::encoding::{WireFormat, FORMAT_HEADER};
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::nats::{Headers, NatsClient};
+use crate::pipeline::Pipeline;
+
+/// Subject heartbeats are published to unless `HEARTBEAT_SUBJECT` is set
+pub const DEFAULT_HEARTBEAT_SUBJECT: &str = "ingest.heartbeat";
+
+/// Settings for announcing the instance on NATS
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    /// Subject heartbeats are published to, namespaced like item subjects
+    pub subject: String,
+
+    /// Pause between heartbeats
+    pub interval: Duration,
+
+    /// ID the instance announces itself with; the host name, or a random UUID without one
+    pub instance_id: Option<String>,
+}
+
+/// Work waiting inside the instance when a heartbeat was sent
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueueDepths {
+    /// Records in the disk spool waiting to be republished
+    pub spool_pending: u64,
+
+    /// Messages in the republish buffer waiting for NATS to come back
+    pub republish_pending: u64,
+}
+
+/// Published every interval so orchestrators can discover live instances over NATS
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// ID of the instance, stable while it runs
+    pub instance_id: String,
+
+    /// Name of the service
+    pub service: String,
+
+    /// Version of the service
+    pub version: String,
+
+    /// Seconds since the instance started
+    pub uptime_seconds: u64,
+
+    /// Seconds until the next heartbeat, so a consumer can tell when one was missed
+    pub interval_seconds: u64,
+
+    /// Work waiting inside the instance
+    pub queues: QueueDepths,
+
+    /// When the heartbeat was sent
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Publishes a heartbeat with the instance's version, uptime and queue depths every interval.
+///
+/// Heartbeats go out over core NATS even when JetStream is enabled, as they are only of use
+/// while fresh; a consumer that has not heard from an instance for a few intervals can take
+/// it as gone. None are sent while NATS is disconnected.
+pub struct HeartbeatPublisher {
+    subject: String,
+    interval: Duration,
+    instance_id: String,
+    nats_client: Arc<NatsClient>,
+    pipeline: Arc<Pipeline>,
+}
+
+impl HeartbeatPublisher {
+    /// Create the publisher
+    pub fn new(
+        config: &HeartbeatConfig,
+        nats_client: Arc<NatsClient>,
+        pipeline: Arc<Pipeline>,
+    ) -> Self {
+        let instance_id = config
+            .instance_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        info!(
+            "Publishing heartbeats of instance {} to {} every {:?}",
+            instance_id, config.subject, config.interval
+        );
+
+        Self {
+            subject: config.subject.clone(),
+            interval: config.interval,
+            instance_id,
+            nats_client,
+            pipeline,
+        }
+    }
+
+    /// Spawn the background task, which sends the first heartbeat straight away
+    pub fn spawn(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if !self.nats_client.is_connected() {
+                    debug!("Skipping heartbeat while NATS is disconnected");
+                    continue;
+                }
+                if let Err(e) = self.publish().await {
+                    throttled!(warn!(
+                        "Failed to publish heartbeat to {}: {}",
+                        self.subject, e
+                    ));
+                }
+            }
+        });
+    }
+
+    /// The instance's heartbeat as of now
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            instance_id: self.instance_id.clone(),
+            service: "ingestion-service".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_seconds: self.pipeline.stats().uptime().as_secs(),
+            interval_seconds: self.interval.as_secs(),
+            queues: QueueDepths {
+                spool_pending: self.pipeline.spool().map_or(0, |spool| spool.pending()),
+                republish_pending: self
+                    .pipeline
+                    .republish()
+                    .map_or(0, |buffer| buffer.len() as u64),
+            },
+            timestamp: Utc::now(),
+        }
+    }
+
+    async fn publish(&self) -> Result<()> {
+        let body = serde_json::to_vec(&self.heartbeat())
+            .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))?;
+        let headers: Headers = vec![(
+            FORMAT_HEADER.to_string(),
+            WireFormat::Json.as_str().to_string(),
+        )];
+
+        self.nats_client
+            .publish_core(&self.subject, &headers, body.into())
+            .await
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::encryption::{self, Cipher};
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+use crate::retention::{PurgeReport, RetentionPolicy};
+
+/// Most rows deleted per step when trimming the history to its size limit
+const PURGE_BATCH: u64 = 1000;
+
+/// Settings for the local history of ingested items
+#[derive(Debug, Clone)]
+pub struct HistoryConfig {
+    /// SQLite database file
+    pub path: PathBuf,
+}
+
+/// Filter for reading items back out of the history
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    /// Only items from this source
+    pub source: Option<String>,
+
+    /// Only items timestamped at or after this instant
+    pub from: Option<DateTime<Utc>>,
+
+    /// Only items timestamped before this instant
+    pub to: Option<DateTime<Utc>>,
+
+    /// Most items returned
+    pub limit: Option<u64>,
+}
+
+/// SQLite record of every item accepted for publishing.
+///
+/// Items are stored whole, keyed by ID, so exports can serve samples without reading
+/// NATS or object storage. Writes go through one connection; readers open their own so
+/// long exports don't block ingestion.
+pub struct HistoryStore {
+    path: PathBuf,
+    conn: Arc<Mutex<Connection>>,
+    cipher: Option<Arc<Cipher>>,
+}
+
+impl HistoryStore {
+    /// Open the database, creating the schema if needed.
+    ///
+    /// Payloads and metadata are encrypted at rest when a cipher is given.
+    pub fn open(config: HistoryConfig, cipher: Option<Arc<Cipher>>) -> Result<Self> {
+        if let Some(dir) = config
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(dir).map_err(history_error)?;
+        }
+
+        let conn = Connection::open(&config.path).map_err(history_error)?;
+
+        // Incremental vacuuming lets purges hand space back to the filesystem; databases
+        // created without it are converted once
+        let auto_vacuum: i64 = conn
+            .query_row("PRAGMA auto_vacuum", [], |row| row.get(0))
+            .map_err(history_error)?;
+        if auto_vacuum != 2 {
+            conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")
+                .map_err(history_error)?;
+        }
+
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS history (
+                 id TEXT PRIMARY KEY,
+                 timestamp_us INTEGER NOT NULL,
+                 source TEXT NOT NULL,
+                 content_type TEXT NOT NULL,
+                 payload TEXT NOT NULL,
+                 metadata TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS history_timestamp ON history (timestamp_us);
+             CREATE INDEX IF NOT EXISTS history_source ON history (source, timestamp_us);",
+        )
+        .map_err(history_error)?;
+
+        info!("Recording item history in {}", config.path.display());
+
+        Ok(Self {
+            path: config.path,
+            conn: Arc::new(Mutex::new(conn)),
+            cipher,
+        })
+    }
+
+    /// Record an accepted item
+    pub async fn record(&self, item: &RawData) -> Result<()> {
+        let id = item.id.to_string();
+        let timestamp_us = item.timestamp.timestamp_micros();
+        let source = item.source.clone();
+        let content_type = item.content_type.clone();
+        let payload = self.seal(&item.payload)?;
+        let metadata = self.seal(&item.metadata)?;
+
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            conn.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .prepare_cached(
+                    "INSERT OR REPLACE INTO history (id, timestamp_us, source, content_type, payload, metadata)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?
+                .execute(params![id, timestamp_us, source, content_type, payload, metadata])
+                .map(|_| ())
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("History task failed: {}", e)))?
+        .map_err(history_error)
+    }
+
+    /// Source and content type of a recorded item
+    pub async fn describe(&self, id: Uuid) -> Result<Option<(String, String)>> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            conn.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .query_row(
+                    "SELECT source, content_type FROM history WHERE id = ?1",
+                    params![id.to_string()],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("History task failed: {}", e)))?
+        .map_err(history_error)
+    }
+
+    /// Delete a recorded item, returning whether it was recorded
+    pub async fn remove(&self, id: Uuid) -> Result<bool> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            conn.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .execute("DELETE FROM history WHERE id = ?1", params![id.to_string()])
+                .map(|deleted| deleted > 0)
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("History task failed: {}", e)))?
+        .map_err(history_error)
+    }
+
+    /// Delete items outside the retention policy, oldest first, and shrink the database
+    pub async fn purge(&self, policy: &RetentionPolicy) -> Result<PurgeReport> {
+        let cutoff = policy
+            .max_age
+            .and_then(|age| chrono::Duration::from_std(age).ok())
+            .map(|age| (Utc::now() - age).timestamp_micros());
+        let max_bytes = policy.max_bytes;
+
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || -> rusqlite::Result<PurgeReport> {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            let pages = |conn: &Connection| -> rusqlite::Result<(u64, u64, u64)> {
+                let pragma = |name: &str| {
+                    conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get::<_, i64>(0))
+                };
+                Ok((
+                    pragma("page_size")? as u64,
+                    pragma("page_count")? as u64,
+                    pragma("freelist_count")? as u64,
+                ))
+            };
+
+            let (page_size, pages_before, _) = pages(&conn)?;
+            let mut purged = 0u64;
+
+            if let Some(cutoff) = cutoff {
+                purged += conn.execute(
+                    "DELETE FROM history WHERE timestamp_us < ?1",
+                    params![cutoff],
+                )? as u64;
+            }
+
+            if let Some(max_bytes) = max_bytes {
+                loop {
+                    let (_, page_count, free) = pages(&conn)?;
+                    let used = (page_count - free) * page_size;
+                    if used <= max_bytes {
+                        break;
+                    }
+
+                    // Delete about as many rows as the excess holds on average
+                    let rows: u64 = conn.query_row("SELECT count(*) FROM history", [], |row| {
+                        row.get::<_, i64>(0)
+                    })? as u64;
+                    let excess_rows = ((used - max_bytes) * rows)
+                        .div_ceil(used)
+                        .clamp(1, PURGE_BATCH);
+                    let deleted = conn.execute(
+                        "DELETE FROM history WHERE id IN
+                         (SELECT id FROM history ORDER BY timestamp_us LIMIT ?1)",
+                        params![excess_rows as i64],
+                    )?;
+                    if deleted == 0 {
+                        break;
+                    }
+                    purged += deleted as u64;
+                }
+            }
+
+            // Every step of incremental_vacuum frees one page, so run it to completion
+            let mut vacuum = conn.prepare("PRAGMA incremental_vacuum")?;
+            let mut steps = vacuum.query([])?;
+            while steps.next()?.is_some() {}
+            drop(steps);
+            drop(vacuum);
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+
+            let (_, pages_after, _) = pages(&conn)?;
+            let reclaimed = pages_before.saturating_sub(pages_after) * page_size;
+
+            Ok(PurgeReport::new("history", purged, reclaimed))
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("History task failed: {}", e)))?
+        .map_err(history_error)
+    }
+
+    /// Read matching items in timestamp order, handing each to `visit` until it returns false.
+    ///
+    /// This blocks, so call it from a blocking task.
+    pub fn scan(&self, query: &HistoryQuery, mut visit: impl FnMut(RawData) -> bool) -> Result<()> {
+        let conn = Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(history_error)?;
+
+        let mut statement = conn
+            .prepare(
+                "SELECT id, timestamp_us, source, content_type, payload, metadata FROM history
+                 WHERE (?1 IS NULL OR source = ?1)
+                   AND (?2 IS NULL OR timestamp_us >= ?2)
+                   AND (?3 IS NULL OR timestamp_us < ?3)
+                 ORDER BY timestamp_us
+                 LIMIT ?4",
+            )
+            .map_err(history_error)?;
+
+        let limit = query
+            .limit
+            .map_or(-1, |limit| limit.min(i64::MAX as u64) as i64);
+        let mut rows = statement
+            .query(params![
+                query.source,
+                query.from.map(|t| t.timestamp_micros()),
+                query.to.map(|t| t.timestamp_micros()),
+                limit,
+            ])
+            .map_err(history_error)?;
+
+        while let Some(row) = rows.next().map_err(history_error)? {
+            let id: String = row.get(0).map_err(history_error)?;
+            let timestamp_us: i64 = row.get(1).map_err(history_error)?;
+            let payload: String = row.get(4).map_err(history_error)?;
+            let metadata: String = row.get(5).map_err(history_error)?;
+
+            let item = RawData {
+                id: id.parse().map_err(history_error)?,
+                source: row.get(2).map_err(history_error)?,
+                content_type: row.get(3).map_err(history_error)?,
+                payload: self.open_value(&payload)?,
+                timestamp: DateTime::from_timestamp_micros(timestamp_us).unwrap_or_default(),
+                metadata: self.open_value(&metadata)?,
+                partition_key: None,
+            };
+
+            if !visit(item) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl HistoryStore {
+    /// Serialize a value for storage, encrypting it when a cipher is configured
+    fn seal(&self, value: &serde_json::Value) -> Result<String> {
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(value.to_string().as_bytes()),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    /// Read a stored value, which is plain JSON if it was written before encryption was enabled
+    fn open_value(&self, stored: &str) -> Result<serde_json::Value> {
+        if !encryption::is_encrypted(stored) {
+            return serde_json::from_str(stored).map_err(history_error);
+        }
+
+        let cipher = self.cipher.as_ref().ok_or_else(|| {
+            AppError::InternalError(
+                "History item is encrypted but no encryption keys are configured".to_string(),
+            )
+        })?;
+        serde_json::from_slice(&cipher.decrypt(stored)?).map_err(history_error)
+    }
+}
+
+fn history_error(e: impl std::fmt::Display) -> AppError {
+    AppError::InternalError(format!("History store error: {}", e))
+}
@@ -0,0 +1,98 @@
+use aws_sdk_sns::config::SharedHttpClient;
+use aws_smithy_http_client::tls::{self, rustls_provider::CryptoMode};
+use aws_smithy_http_client::{proxy, Builder, ConnectorBuilder};
+use object_store::ClientConfigKey;
+use reqwest::{ClientBuilder, NoProxy, Proxy};
+use tracing::info;
+
+use crate::config::Secret;
+use crate::error::{AppError, Result};
+
+/// Proxy settings applied to every outbound HTTP client
+#[derive(Debug, Clone, Default)]
+pub struct ProxyConfig {
+    /// Explicit proxy URL for all outbound traffic; falls back to `HTTP_PROXY`/`HTTPS_PROXY`.
+    /// Redacted when logged, as it may carry credentials.
+    pub url: Option<Secret>,
+
+    /// Comma-separated hosts, domains and CIDRs that bypass the proxy; falls back to `NO_PROXY`
+    pub no_proxy: Option<String>,
+}
+
+/// Create a client builder with the service's proxy settings applied.
+///
+/// All outbound clients must be built from this so proxy configuration is honored
+/// consistently.
+pub fn client_builder(proxy: &ProxyConfig) -> Result<ClientBuilder> {
+    let builder = reqwest::Client::builder();
+
+    let Some(url) = &proxy.url else {
+        // reqwest reads HTTP_PROXY, HTTPS_PROXY and NO_PROXY from the environment by default
+        return Ok(builder);
+    };
+
+    let no_proxy = match &proxy.no_proxy {
+        Some(list) => NoProxy::from_string(list),
+        None => NoProxy::from_env(),
+    };
+
+    let proxy = Proxy::all(url.expose())
+        .map_err(|e| AppError::InternalError(format!("Invalid outbound proxy URL: {}", e)))?
+        .no_proxy(no_proxy);
+
+    info!("Routing outbound HTTP through configured proxy");
+
+    Ok(builder.proxy(proxy))
+}
+
+/// Proxy settings as object store client options, as object stores build their own HTTP
+/// clients instead of using [`client_builder`]
+pub fn object_store_options(proxy: &ProxyConfig) -> Vec<(ClientConfigKey, String)> {
+    let Some(url) = &proxy.url else {
+        // The store's client reads HTTP_PROXY, HTTPS_PROXY and NO_PROXY like reqwest's
+        return Vec::new();
+    };
+
+    let mut options = vec![(ClientConfigKey::ProxyUrl, url.expose().to_string())];
+    if let Some(no_proxy) = proxy
+        .no_proxy
+        .clone()
+        .or_else(|| std::env::var("NO_PROXY").ok())
+    {
+        options.push((ClientConfigKey::ProxyExcludes, no_proxy));
+    }
+    options
+}
+
+/// HTTPS client for the AWS SDK routed through the configured proxy, as the SDK builds its
+/// own client instead of using [`client_builder`]. None without a proxy URL, leaving the
+/// SDK's default client, which reads HTTP_PROXY, HTTPS_PROXY and NO_PROXY.
+pub fn aws_http_client(proxy: &ProxyConfig) -> Result<Option<SharedHttpClient>> {
+    let Some(url) = &proxy.url else {
+        return Ok(None);
+    };
+
+    let mut proxy_config = proxy::ProxyConfig::all(url.expose())
+        .map_err(|e| AppError::InternalError(format!("Invalid outbound proxy URL: {}", e)))?;
+    if let Some(no_proxy) = proxy
+        .no_proxy
+        .clone()
+        .or_else(|| std::env::var("NO_PROXY").ok())
+    {
+        proxy_config = proxy_config.no_proxy(no_proxy);
+    }
+
+    // The SDK's default client, with the proxy in place of the one read from the environment
+    let client = Builder::new().build_with_connector_fn(move |settings, runtime_components| {
+        let mut connector = ConnectorBuilder::default()
+            .tls_provider(tls::Provider::Rustls(CryptoMode::AwsLc))
+            .proxy_config(proxy_config.clone());
+        connector.set_connector_settings(settings.cloned());
+        if let Some(components) = runtime_components {
+            connector.set_sleep_impl(components.sleep_impl());
+        }
+        connector.build()
+    });
+
+    Ok(Some(client))
+}
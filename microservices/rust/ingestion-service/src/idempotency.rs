@@ -0,0 +1,251 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use async_nats::jetstream::kv::{Operation, Store};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::IngestResponse;
+use crate::nats::NatsClient;
+
+/// How long an unfinished claim blocks repeats before another request may take it over,
+/// covering replicas that crashed or requests aborted mid-ingestion
+const PENDING_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Attempts at claiming an item when other requests keep changing its entry
+const CLAIM_ATTEMPTS: usize = 3;
+
+/// Settings for recognising repeated submissions across replicas
+#[derive(Debug, Clone)]
+pub struct IdempotencyConfig {
+    /// JetStream key-value bucket item IDs are recorded in, namespaced like stream names
+    pub kv_bucket: String,
+
+    /// How long an item ID is remembered
+    pub ttl: Duration,
+}
+
+/// State of an item ID in the bucket
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum Record {
+    /// A request is ingesting the item
+    Pending { since: DateTime<Utc> },
+
+    /// The item was ingested with this response
+    Done { response: IngestResponse },
+
+    /// The request that claimed the item gave up, so the next one may claim it
+    Released,
+}
+
+/// Outcome of claiming an item ID
+#[derive(Debug)]
+pub enum Claim {
+    /// The item is new, or its earlier ingestion failed; the claim is held until completed
+    /// or released
+    New(Box<HeldClaim>),
+
+    /// The item was already ingested with this response
+    Done(IngestResponse),
+
+    /// Another request is ingesting the item
+    InFlight,
+
+    /// The bucket could not be reached, so the item is ingested without the check
+    Unchecked,
+}
+
+/// A claim on an item ID held by this request.
+///
+/// Dropped without being completed or released, as when the request is cancelled by its
+/// deadline, it is released in the background, so the client's retry is not refused until
+/// the claim goes stale.
+#[derive(Debug)]
+pub struct HeldClaim {
+    kv: Store,
+    id: Uuid,
+    revision: u64,
+    settled: AtomicBool,
+}
+
+impl Drop for HeldClaim {
+    fn drop(&mut self) {
+        if self.settled.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        debug!(
+            "Releasing the idempotency claim on item {} of an abandoned request",
+            self.id
+        );
+        let (kv, id, revision) = (self.kv.clone(), self.id, self.revision);
+        runtime.spawn(async move { release(&kv, id, revision).await });
+    }
+}
+
+/// Record of recently ingested item IDs in a JetStream key-value bucket.
+///
+/// Shared by every replica, so a client retrying a submission gets the original response
+/// rather than the item being published twice, whichever replica the retry reaches.
+pub struct IdempotencyStore {
+    kv: Store,
+}
+
+impl IdempotencyStore {
+    /// Open the bucket, creating it if needed; `None` without a NATS connection
+    pub async fn open(
+        config: &IdempotencyConfig,
+        nats_client: &NatsClient,
+    ) -> Result<Option<Self>> {
+        let Some(kv) = nats_client.key_value(&config.kv_bucket, config.ttl).await? else {
+            warn!("Idempotency checks are disabled without a NATS connection");
+            return Ok(None);
+        };
+
+        info!(
+            "Recording item IDs for {:?} in key-value bucket {}",
+            config.ttl, config.kv_bucket
+        );
+
+        Ok(Some(Self { kv }))
+    }
+
+    /// Claim an item ID before ingesting the item.
+    ///
+    /// Claims are made with compare-and-set, so of several requests for one item only one
+    /// gets [`Claim::New`].
+    pub async fn claim(&self, id: Uuid) -> Claim {
+        match self.try_claim(id).await {
+            Ok(claim) => claim,
+            Err(e) => {
+                throttled!(warn!(
+                    "Ingesting item {} without an idempotency check: {}",
+                    id, e
+                ));
+                Claim::Unchecked
+            }
+        }
+    }
+
+    async fn try_claim(&self, id: Uuid) -> Result<Claim> {
+        let key = id.to_string();
+        let pending = encode(&Record::Pending { since: Utc::now() })?;
+
+        for _ in 0..CLAIM_ATTEMPTS {
+            let entry = self.kv.entry(key.as_str()).await.map_err(kv_error)?;
+
+            // Revision the entry must still be at for the claim to succeed; zero when absent
+            let revision = match entry {
+                None => 0,
+                Some(entry) if entry.operation != Operation::Put => entry.revision,
+                Some(entry) => match serde_json::from_slice(&entry.value) {
+                    Ok(Record::Done { response }) => return Ok(Claim::Done(response)),
+                    Ok(Record::Released) => entry.revision,
+                    Ok(Record::Pending { since })
+                        if (Utc::now() - since).to_std().unwrap_or_default() < PENDING_TIMEOUT =>
+                    {
+                        return Ok(Claim::InFlight)
+                    }
+                    Ok(Record::Pending { .. }) => {
+                        warn!("Taking over stale idempotency claim on item {}", id);
+                        entry.revision
+                    }
+                    Err(e) => return Err(kv_error(e)),
+                },
+            };
+
+            match self
+                .kv
+                .update(key.as_str(), pending.clone(), revision)
+                .await
+            {
+                Ok(revision) => {
+                    return Ok(Claim::New(Box::new(HeldClaim {
+                        kv: self.kv.clone(),
+                        id,
+                        revision,
+                        settled: AtomicBool::new(false),
+                    })))
+                }
+                // Another request changed the entry first, look again
+                Err(e) => debug!("Idempotency claim on item {} raced: {}", id, e),
+            }
+        }
+
+        // Requests for the item keep racing, so one of them is ingesting it
+        Ok(Claim::InFlight)
+    }
+
+    /// Record the response of an ingested item, so repeats are answered with it
+    pub async fn complete(&self, id: Uuid, claim: &Claim, response: &IngestResponse) {
+        let Claim::New(held) = claim else {
+            return;
+        };
+        // Left pending if recording fails, as releasing would let a retry publish it again
+        held.settled.store(true, Ordering::Relaxed);
+
+        let stored = encode(&Record::Done {
+            response: response.clone(),
+        });
+        let result = match stored {
+            Ok(stored) => self
+                .kv
+                .update(id.to_string(), stored, held.revision)
+                .await
+                .map_err(kv_error),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            throttled!(warn!(
+                "Failed to record ingested item {} for idempotency: {}",
+                id, e
+            ));
+        }
+    }
+
+    /// Give up a claim after ingestion failed, so a retry is ingested
+    pub async fn release(&self, id: Uuid, claim: &Claim) {
+        let Claim::New(held) = claim else {
+            return;
+        };
+        held.settled.store(true, Ordering::Relaxed);
+
+        release(&self.kv, id, held.revision).await;
+    }
+}
+
+/// Release a claim held at `revision`. Compared against the revision, so a claim another
+/// request has taken over since is left alone.
+async fn release(kv: &Store, id: Uuid, revision: u64) {
+    let result = match encode(&Record::Released) {
+        Ok(released) => kv
+            .update(id.to_string(), released, revision)
+            .await
+            .map_err(kv_error),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        throttled!(warn!(
+            "Failed to release idempotency claim on item {}: {}",
+            id, e
+        ));
+    }
+}
+
+fn encode(record: &Record) -> Result<bytes::Bytes> {
+    serde_json::to_vec(record)
+        .map(Into::into)
+        .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))
+}
+
+fn kv_error(e: impl std::fmt::Display) -> AppError {
+    AppError::NatsConnectionError(format!("Idempotency bucket error: {}", e))
+}
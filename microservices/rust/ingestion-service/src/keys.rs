@@ -0,0 +1,377 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+use crate::config::Secret;
+use crate::error::{AppError, Result};
+
+/// One version of a key, identified so signatures and ciphertexts can name the key they used
+#[derive(Debug, Clone)]
+pub struct KeyVersion {
+    pub id: String,
+    pub secret: Secret,
+
+    /// After this the key no longer signs, encrypts or verifies
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl KeyVersion {
+    /// Parse a key entry, `id=secret` or `id@expiry=secret` with an RFC 3339 expiry
+    pub fn parse(entry: &str) -> Result<Self> {
+        let (name, secret) = entry.split_once('=').ok_or_else(|| {
+            AppError::ValidationError("Key entries are id=secret or id@expiry=secret".to_string())
+        })?;
+
+        let (id, expires_at) = match name.split_once('@') {
+            Some((id, expiry)) => {
+                let expires_at = DateTime::parse_from_rfc3339(expiry.trim()).map_err(|e| {
+                    AppError::ValidationError(format!("Invalid expiry of key {}: {}", id.trim(), e))
+                })?;
+                (id.trim(), Some(expires_at.with_timezone(&Utc)))
+            }
+            None => (name.trim(), None),
+        };
+
+        if id.is_empty() || id.contains(|c: char| c == ':' || c == ',' || c.is_whitespace()) {
+            return Err(AppError::ValidationError(format!(
+                "Invalid key ID {:?}",
+                id
+            )));
+        }
+
+        Ok(Self {
+            id: id.to_string(),
+            secret: Secret::new(secret.trim()),
+            expires_at,
+        })
+    }
+
+    /// Whether the key has expired at `now`
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Where a key ring's keys come from
+#[derive(Debug, Clone)]
+pub struct KeyRingConfig {
+    /// Keys given in configuration, in order of preference
+    pub keys: Vec<KeyVersion>,
+
+    /// File holding one key entry per line, re-read every `refresh_interval` so mounted
+    /// secrets can be rotated without a restart; used instead of `keys` when set
+    pub file: Option<PathBuf>,
+
+    /// ID of the key new signatures and ciphertexts use; defaults to the first unexpired key
+    pub active: Option<String>,
+
+    /// How often `file` is re-read
+    pub refresh_interval: Duration,
+}
+
+/// Versioned keys of one feature, rotated by adding a new key ahead of the old one and
+/// removing the old one once nothing signed or encrypted with it is in use.
+///
+/// The newest unexpired key, or the configured active one, signs and encrypts. Any
+/// unexpired key verifies, so signatures stay valid across a rotation until their key
+/// expires. Keys read from a file are reloaded periodically; a reload that fails to parse
+/// keeps the previous keys.
+pub struct KeyRing {
+    name: &'static str,
+    config: KeyRingConfig,
+    check: fn(&KeyVersion) -> Result<()>,
+    keys: RwLock<Arc<Vec<KeyVersion>>>,
+}
+
+impl KeyRing {
+    /// Load a key ring, checking every key with `check`, and start reloading its file
+    pub fn open(
+        name: &'static str,
+        config: KeyRingConfig,
+        check: fn(&KeyVersion) -> Result<()>,
+    ) -> Result<Arc<Self>> {
+        let keys = load(name, &config, check)?;
+        info!("Loaded {} {} keys", keys.len(), name);
+
+        let ring = Arc::new(Self {
+            name,
+            config,
+            check,
+            keys: RwLock::new(Arc::new(keys)),
+        });
+
+        if ring.config.file.is_some() {
+            ring.clone().spawn_refresher();
+        }
+
+        Ok(ring)
+    }
+
+    /// Key new signatures and ciphertexts are made with
+    pub fn current(&self) -> Result<KeyVersion> {
+        let keys = self.keys();
+        let now = Utc::now();
+        let active = match &self.config.active {
+            Some(active) => keys
+                .iter()
+                .find(|key| &key.id == active && !key.is_expired(now)),
+            None => None,
+        };
+
+        active
+            .or_else(|| keys.iter().find(|key| !key.is_expired(now)))
+            .cloned()
+            .ok_or_else(|| AppError::InternalError(format!("Every {} key has expired", self.name)))
+    }
+
+    /// Keys signatures are verified against: every unexpired key
+    pub fn verifying(&self) -> Vec<KeyVersion> {
+        let now = Utc::now();
+        self.keys()
+            .iter()
+            .filter(|key| !key.is_expired(now))
+            .cloned()
+            .collect()
+    }
+
+    /// A key by ID, expired or not
+    pub fn get(&self, id: &str) -> Option<KeyVersion> {
+        self.keys().iter().find(|key| key.id == id).cloned()
+    }
+
+    fn keys(&self) -> Arc<Vec<KeyVersion>> {
+        self.keys.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn spawn_refresher(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.refresh_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match load(self.name, &self.config, self.check) {
+                    Ok(keys) => {
+                        let ids = |keys: &[KeyVersion]| {
+                            keys.iter()
+                                .map(|k| (k.id.clone(), k.expires_at))
+                                .collect::<Vec<_>>()
+                        };
+                        let mut current = self.keys.write().unwrap_or_else(|e| e.into_inner());
+                        if ids(&current) != ids(&keys) {
+                            info!("Reloaded {} {} keys", keys.len(), self.name);
+                        }
+                        *current = Arc::new(keys);
+                    }
+                    Err(e) => warn!(
+                        "Keeping current {} keys, reloading failed: {}",
+                        self.name, e
+                    ),
+                }
+            }
+        });
+    }
+}
+
+/// Read and check a key ring's keys
+fn load(
+    name: &str,
+    config: &KeyRingConfig,
+    check: fn(&KeyVersion) -> Result<()>,
+) -> Result<Vec<KeyVersion>> {
+    let keys = match &config.file {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| {
+                AppError::ValidationError(format!(
+                    "Failed to read {} keys from {}: {}",
+                    name,
+                    path.display(),
+                    e
+                ))
+            })?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(KeyVersion::parse)
+            .collect::<Result<Vec<_>>>()?,
+        None => config.keys.clone(),
+    };
+
+    if keys.is_empty() {
+        return Err(AppError::ValidationError(format!(
+            "No {} keys are configured",
+            name
+        )));
+    }
+    for (index, key) in keys.iter().enumerate() {
+        if keys[..index].iter().any(|other| other.id == key.id) {
+            return Err(AppError::ValidationError(format!(
+                "Duplicate {} key {}",
+                name, key.id
+            )));
+        }
+        check(key)?;
+    }
+    if let Some(active) = config
+        .active
+        .as_ref()
+        .filter(|active| !keys.iter().any(|key| &key.id == *active))
+    {
+        return Err(AppError::ValidationError(format!(
+            "Active {} key {} is not configured",
+            name, active
+        )));
+    }
+
+    Ok(keys)
+}
+
+/// Accept any key
+pub fn any_key(_: &KeyVersion) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::encryption::{Cipher, EncryptionConfig};
+
+    /// Key file removed when the test ends
+    struct KeyFile(PathBuf);
+
+    impl KeyFile {
+        fn new(entries: &[String]) -> Self {
+            let file =
+                Self(std::env::temp_dir().join(format!("ingest-keys-test-{}", Uuid::new_v4())));
+            file.write(entries);
+            file
+        }
+
+        fn write(&self, entries: &[String]) {
+            std::fs::write(&self.0, entries.join("\n")).unwrap();
+        }
+    }
+
+    impl Drop for KeyFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn key(id: &str, byte: u8) -> String {
+        format!("{}={}", id, STANDARD.encode([byte; 32]))
+    }
+
+    fn config(entries: &[&str], active: Option<&str>) -> KeyRingConfig {
+        KeyRingConfig {
+            keys: entries
+                .iter()
+                .map(|entry| KeyVersion::parse(entry).unwrap())
+                .collect(),
+            file: None,
+            active: active.map(str::to_string),
+            refresh_interval: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn parses_key_entries() {
+        let key = KeyVersion::parse(" k1 = secret ").unwrap();
+        assert_eq!(key.id, "k1");
+        assert_eq!(key.secret.expose(), "secret");
+        assert!(key.expires_at.is_none());
+
+        let key = KeyVersion::parse("k2@2020-01-01T00:00:00Z=secret").unwrap();
+        assert_eq!(key.id, "k2");
+        assert!(key.is_expired(Utc::now()));
+
+        for entry in [
+            "no-secret",
+            "=secret",
+            "k:1=secret",
+            "k 1=secret",
+            "k1@soon=secret",
+        ] {
+            assert!(KeyVersion::parse(entry).is_err(), "{} was accepted", entry);
+        }
+    }
+
+    #[test]
+    fn the_first_unexpired_or_the_active_key_is_current() {
+        let ring = KeyRing::open(
+            "test",
+            config(&["old@2020-01-01T00:00:00Z=a", "new=b", "next=c"], None),
+            any_key,
+        )
+        .unwrap();
+        assert_eq!(ring.current().unwrap().id, "new");
+        let verifying: Vec<String> = ring.verifying().into_iter().map(|key| key.id).collect();
+        assert_eq!(verifying, vec!["new", "next"]);
+        // Expired keys are still found by ID
+        assert!(ring.get("old").is_some());
+
+        let ring =
+            KeyRing::open("test", config(&["new=b", "next=c"], Some("next")), any_key).unwrap();
+        assert_eq!(ring.current().unwrap().id, "next");
+    }
+
+    #[test]
+    fn rejects_unknown_duplicate_and_missing_keys() {
+        assert!(KeyRing::open("test", config(&["k1=a"], Some("k2")), any_key).is_err());
+        assert!(KeyRing::open("test", config(&["k1=a", "k1=b"], None), any_key).is_err());
+        assert!(KeyRing::open("test", config(&[], None), any_key).is_err());
+
+        let ring = KeyRing::open("test", config(&["k1=a"], None), any_key).unwrap();
+        assert!(ring.get("k2").is_none());
+    }
+
+    #[test]
+    fn values_of_an_unknown_key_are_not_decrypted() {
+        let mut keys = config(&[&key("k1", 1)], None);
+        let cipher = Cipher::new(&EncryptionConfig { keys: keys.clone() }).unwrap();
+        let sealed = cipher.encrypt(b"payload").unwrap();
+
+        keys.keys = vec![KeyVersion::parse(&key("k2", 1)).unwrap()];
+        let other = Cipher::new(&EncryptionConfig { keys }).unwrap();
+        let error = other.decrypt(&sealed).unwrap_err();
+        assert!(error.to_string().contains("key k1 is not configured"));
+    }
+
+    #[tokio::test]
+    async fn values_decrypt_after_the_key_file_is_rotated() {
+        let file = KeyFile::new(&[key("k1", 1)]);
+        let keys = KeyRingConfig {
+            keys: Vec::new(),
+            file: Some(file.0.clone()),
+            active: None,
+            refresh_interval: Duration::from_millis(10),
+        };
+        let cipher = Cipher::new(&EncryptionConfig { keys }).unwrap();
+        let old = cipher.encrypt(b"old").unwrap();
+        assert!(old.starts_with("enc:v1:k1:"));
+
+        // The new key goes ahead of the old one, which stays until nothing uses it
+        file.write(&[key("k2", 2), key("k1", 1)]);
+        let mut new = cipher.encrypt(b"new").unwrap();
+        for _ in 0..100 {
+            if new.starts_with("enc:v1:k2:") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            new = cipher.encrypt(b"new").unwrap();
+        }
+        assert!(new.starts_with("enc:v1:k2:"));
+        assert_eq!(cipher.decrypt(&old).unwrap(), b"old");
+        assert_eq!(cipher.decrypt(&new).unwrap(), b"new");
+
+        // A reload that fails to parse keeps the previous keys
+        file.write(&["not a key entry".to_string()]);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(cipher.decrypt(&old).unwrap(), b"old");
+    }
+}
@@ -0,0 +1,115 @@
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::models::RawData;
+
+/// Marker used by SPDX short-form license identifiers
+const SPDX_TAG: &str = "spdx-license-identifier:";
+
+/// Well-known license phrases mapped to SPDX identifiers, most specific first
+const LICENSE_PHRASES: &[(&str, &str)] = &[
+    ("gnu affero general public license", "AGPL-3.0"),
+    ("gnu lesser general public license", "LGPL-3.0"),
+    ("gnu general public license, version 2", "GPL-2.0"),
+    ("gnu general public license version 2", "GPL-2.0"),
+    ("gnu general public license", "GPL-3.0"),
+    ("apache license, version 2.0", "Apache-2.0"),
+    ("apache license 2.0", "Apache-2.0"),
+    ("mozilla public license, v. 2.0", "MPL-2.0"),
+    ("mozilla public license version 2.0", "MPL-2.0"),
+    (
+        "creative commons attribution-sharealike 4.0",
+        "CC-BY-SA-4.0",
+    ),
+    ("cc by-sa 4.0", "CC-BY-SA-4.0"),
+    (
+        "creative commons attribution-noncommercial 4.0",
+        "CC-BY-NC-4.0",
+    ),
+    ("cc by-nc 4.0", "CC-BY-NC-4.0"),
+    ("creative commons attribution 4.0", "CC-BY-4.0"),
+    ("cc by 4.0", "CC-BY-4.0"),
+    ("cc0 1.0", "CC0-1.0"),
+    ("this is free and unencumbered software", "Unlicense"),
+    ("the unlicense", "Unlicense"),
+    ("isc license", "ISC"),
+    ("neither the name of", "BSD-3-Clause"),
+    (
+        "redistribution and use in source and binary forms",
+        "BSD-2-Clause",
+    ),
+    ("permission is hereby granted, free of charge", "MIT"),
+    ("mit license", "MIT"),
+];
+
+/// Detect a license in the item payload and stamp it as `metadata.license`.
+///
+/// Explicit SPDX identifiers take precedence over license text matches. A license
+/// already supplied by the producer is never overwritten.
+pub fn detect_license(item: &mut RawData) {
+    if item.metadata.get("license").is_some() {
+        return;
+    }
+
+    let mut texts = Vec::new();
+    collect_strings(&item.payload, &mut texts);
+
+    let detected = texts
+        .iter()
+        .find_map(|text| spdx_identifier(text).map(|id| (id, "spdx_identifier")))
+        .or_else(|| {
+            texts
+                .iter()
+                .find_map(|text| license_phrase(text).map(|id| (id.to_string(), "license_text")))
+        });
+
+    if let Some((license, method)) = detected {
+        debug!(
+            "Detected license {} for item {} via {}",
+            license, item.id, method
+        );
+        if !item.metadata.is_object() {
+            item.metadata = json!({});
+        }
+        item.metadata["license"] = Value::String(license);
+        item.metadata["license_detection"] = Value::String(method.to_string());
+    }
+}
+
+/// Extract the expression following an `SPDX-License-Identifier:` tag
+fn spdx_identifier(text: &str) -> Option<String> {
+    let start = text.to_ascii_lowercase().find(SPDX_TAG)? + SPDX_TAG.len();
+    let expression = text[start..]
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_end_matches("*/")
+        .trim_end_matches("-->")
+        .trim();
+
+    let valid = !expression.is_empty()
+        && expression
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || " .-+()".contains(c));
+
+    valid.then(|| expression.to_string())
+}
+
+/// Match well-known license wording
+fn license_phrase(text: &str) -> Option<&'static str> {
+    let lower = text.to_lowercase();
+    LICENSE_PHRASES
+        .iter()
+        .find(|(phrase, _)| lower.contains(phrase))
+        .map(|(_, id)| *id)
+}
+
+fn collect_strings<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+    match value {
+        Value::String(s) => out.push(s),
+        Value::Array(items) => items.iter().for_each(|v| collect_strings(v, out)),
+        Value::Object(map) => map.values().for_each(|v| collect_strings(v, out)),
+        _ => {}
+    }
+}
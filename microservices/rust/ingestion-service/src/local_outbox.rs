@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rusqlite::{params, Connection};
+use tokio::sync::Notify;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::encryption::{self, Cipher};
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+use crate::schedule;
+
+/// Settings for persisting accepted items locally until they are published
+#[derive(Debug, Clone)]
+pub struct LocalOutboxConfig {
+    /// SQLite database file holding items until they are published
+    pub path: PathBuf,
+
+    /// Pause before retrying items that failed to publish
+    pub retry_interval: Duration,
+
+    /// Most items published per pass
+    pub batch_size: i64,
+}
+
+/// Accepted items persisted before the request is answered, and published from there.
+///
+/// Items are written to a local SQLite database, encrypted at rest when a cipher is given,
+/// and a background task publishes them through the pipeline in the order they were
+/// accepted. An item is deleted only once its publish is confirmed, or written to the disk
+/// spool, so items survive a crash or restart at any point and are published at least
+/// once. Items that can never be published are dead-lettered rather than only dropped.
+/// Only the replica that accepted an item publishes it.
+pub struct LocalOutbox {
+    config: LocalOutboxConfig,
+    conn: Arc<Mutex<Connection>>,
+    cipher: Option<Arc<Cipher>>,
+    added: Notify,
+}
+
+impl LocalOutbox {
+    /// Open the database, creating the schema if needed
+    pub fn open(config: LocalOutboxConfig, cipher: Option<Arc<Cipher>>) -> Result<Self> {
+        if let Some(dir) = config
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(dir).map_err(outbox_error)?;
+        }
+
+        let conn = Connection::open(&config.path).map_err(outbox_error)?;
+        // A full sync per commit, as the item is acknowledged to its producer once written
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = FULL;
+             CREATE TABLE IF NOT EXISTS outbox (
+                 seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                 id TEXT NOT NULL UNIQUE,
+                 item TEXT NOT NULL
+             );",
+        )
+        .map_err(outbox_error)?;
+
+        let pending: i64 = conn
+            .query_row("SELECT count(*) FROM outbox", [], |row| row.get(0))
+            .map_err(outbox_error)?;
+        info!(
+            "Persisting accepted items to {}, {} pending",
+            config.path.display(),
+            pending
+        );
+
+        Ok(Self {
+            config,
+            conn: Arc::new(Mutex::new(conn)),
+            cipher,
+            added: Notify::new(),
+        })
+    }
+
+    /// Persist items in one transaction, replacing pending items with the same IDs
+    pub async fn add(&self, items: &[&RawData]) -> Result<()> {
+        let rows = items
+            .iter()
+            .map(|item| Ok((item.id.to_string(), self.seal(item)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.blocking(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            {
+                let mut statement =
+                    tx.prepare_cached("INSERT OR REPLACE INTO outbox (id, item) VALUES (?1, ?2)")?;
+                for (id, item) in &rows {
+                    statement.execute(params![id, item])?;
+                }
+            }
+            tx.commit()
+        })
+        .await?;
+
+        self.added.notify_one();
+        Ok(())
+    }
+
+    /// Publish items as they are added, retrying failed ones every retry interval
+    pub fn spawn(self: Arc<Self>, pipeline: Arc<Pipeline>) {
+        tokio::spawn(async move {
+            loop {
+                match self.publish_pending(&pipeline).await {
+                    Ok(0) => {}
+                    Ok(count) => info!("Published {} items from the local outbox", count),
+                    Err(e) => throttled!(warn!("Failed to publish from the local outbox: {}", e)),
+                }
+
+                tokio::select! {
+                    _ = self.added.notified() => {}
+                    _ = tokio::time::sleep(self.config.retry_interval) => {}
+                }
+            }
+        });
+    }
+
+    /// Publish pending items until none are left or one fails for a reason that may pass,
+    /// giving the number published
+    async fn publish_pending(&self, pipeline: &Pipeline) -> Result<usize> {
+        let mut published = 0;
+
+        loop {
+            let limit = self.config.batch_size;
+            let pending: Vec<(String, String)> = self
+                .blocking(move |conn| {
+                    let mut statement =
+                        conn.prepare_cached("SELECT id, item FROM outbox ORDER BY seq LIMIT ?1")?;
+                    let rows = statement
+                        .query_map(params![limit], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                    rows.collect()
+                })
+                .await?;
+            if pending.is_empty() {
+                return Ok(published);
+            }
+            let count = pending.len();
+
+            let mut items = Vec::with_capacity(count);
+            for (id, stored) in pending {
+                match self.open_item(&stored) {
+                    Ok(item) => items.push(item),
+                    Err(e) => {
+                        error!("Dropping outbox item {}, it cannot be read: {}", id, e);
+                        self.blocking(move |conn| {
+                            conn.execute("DELETE FROM outbox WHERE id = ?1", params![id])
+                        })
+                        .await?;
+                    }
+                }
+            }
+            let mut refs: Vec<&mut RawData> = items.iter_mut().collect();
+            let results = pipeline.process_batch_durable(&mut refs).await;
+
+            let mut retrying = 0;
+            for (item, result) in items.iter().zip(results) {
+                match result {
+                    Ok(_) => published += 1,
+                    Err(e) if schedule::retryable(&e) => {
+                        throttled!(warn!(
+                            "Outbox item {} failed to publish, retrying: {}",
+                            item.id, e
+                        ));
+                        retrying += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        throttled!(error!(
+                            "Giving up on outbox item {}, it cannot be published: {}",
+                            item.id, e
+                        ));
+                        pipeline.dead_letter(item, &e).await;
+                    }
+                }
+                self.remove(item.id).await?;
+            }
+
+            // Retried after the retry interval, rather than straight away
+            if retrying > 0 || count < limit as usize {
+                return Ok(published);
+            }
+        }
+    }
+
+    async fn remove(&self, id: Uuid) -> Result<()> {
+        let id = id.to_string();
+        self.blocking(move |conn| {
+            conn.prepare_cached("DELETE FROM outbox WHERE id = ?1")?
+                .execute(params![id])
+                .map(|_| ())
+        })
+        .await
+    }
+
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&conn.lock().unwrap_or_else(|e| e.into_inner())))
+            .await
+            .map_err(|e| AppError::InternalError(format!("Local outbox task failed: {}", e)))?
+            .map_err(outbox_error)
+    }
+
+    /// Serialize an item for storage, encrypting it when a cipher is configured
+    fn seal(&self, item: &RawData) -> Result<String> {
+        let json = serde_json::to_string(item).map_err(outbox_error)?;
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(json.as_bytes()),
+            None => Ok(json),
+        }
+    }
+
+    /// Read a stored item, which is plain JSON if it was written before encryption was enabled
+    fn open_item(&self, stored: &str) -> Result<RawData> {
+        if !encryption::is_encrypted(stored) {
+            return serde_json::from_str(stored).map_err(outbox_error);
+        }
+
+        let cipher = self.cipher.as_ref().ok_or_else(|| {
+            AppError::InternalError(
+                "Outbox item is encrypted but no encryption keys are configured".to_string(),
+            )
+        })?;
+        serde_json::from_slice(&cipher.decrypt(stored)?).map_err(outbox_error)
+    }
+}
+
+fn outbox_error(e: impl std::fmt::Display) -> AppError {
+    AppError::InternalError(format!("Local outbox error: {}", e))
+}
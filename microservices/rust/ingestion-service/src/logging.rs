@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use axum::{extract::Extension, Json};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+use tracing_subscriber::{filter::LevelFilter, reload, EnvFilter, Registry};
+
+use crate::error::{AppError, Result};
+
+/// Limit on repeats of one log message, to keep a flood of identical warnings under load
+/// from drowning the log pipeline
+#[derive(Debug, Clone)]
+pub struct RepeatLimit {
+    /// Messages logged from one call site per window; `0` disables the limit
+    pub burst: u32,
+
+    /// Window the burst is counted over
+    pub window: Duration,
+}
+
+/// Log filter as a default level and levels per target
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LogFilter {
+    /// Level of targets without their own, e.g. `info`
+    pub level: String,
+
+    /// Level per target, e.g. `ingestion_service::nats` or `request_summary`
+    #[serde(default)]
+    pub targets: BTreeMap<String, String>,
+}
+
+impl LogFilter {
+    /// Parse `RUST_LOG` style directives, e.g. `info,ingestion_service::nats=debug`
+    pub fn parse(directives: &str) -> Self {
+        let mut filter = Self {
+            level: "info".to_string(),
+            targets: BTreeMap::new(),
+        };
+        for directive in directives
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+        {
+            match directive.rsplit_once('=') {
+                Some((target, level)) => {
+                    filter.targets.insert(target.to_string(), level.to_string());
+                }
+                // A bare target enables every level of it, as in `EnvFilter`
+                None if directive.parse::<LevelFilter>().is_err() => {
+                    filter
+                        .targets
+                        .insert(directive.to_string(), "trace".to_string());
+                }
+                None => filter.level = directive.to_string(),
+            }
+        }
+        filter
+    }
+
+    /// Render as `RUST_LOG` style directives
+    pub fn directives(&self) -> String {
+        std::iter::once(self.level.clone())
+            .chain(
+                self.targets
+                    .iter()
+                    .map(|(target, level)| format!("{}={}", target, level)),
+            )
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn env_filter(&self) -> Result<EnvFilter> {
+        // `EnvFilter` takes a misspelt level for a target, which would silently change nothing
+        for level in std::iter::once(&self.level).chain(self.targets.values()) {
+            level
+                .parse::<LevelFilter>()
+                .map_err(|_| AppError::ValidationError(format!("Invalid log level {}", level)))?;
+        }
+        EnvFilter::try_new(self.directives()).map_err(|e| {
+            AppError::ValidationError(format!("Invalid log filter {}: {}", self.directives(), e))
+        })
+    }
+}
+
+/// Change to the log filter; a target set to `null` falls back to the default level
+#[derive(Debug, Deserialize)]
+pub struct LogFilterUpdate {
+    #[serde(default)]
+    pub level: Option<String>,
+
+    #[serde(default)]
+    pub targets: BTreeMap<String, Option<String>>,
+}
+
+/// Filter of the log output, adjustable at runtime through the admin API so one module can
+/// be turned up while investigating without a restart. Trace export is not affected.
+pub struct LogLevels {
+    handle: reload::Handle<EnvFilter, Registry>,
+    startup: LogFilter,
+    current: Mutex<LogFilter>,
+}
+
+impl LogLevels {
+    /// Build the reloadable filter for the log output from `RUST_LOG` style directives
+    pub fn new(directives: &str) -> (reload::Layer<EnvFilter, Registry>, Arc<Self>) {
+        let startup = LogFilter::parse(directives);
+        let (layer, handle) = reload::Layer::new(EnvFilter::new(directives));
+        let levels = Arc::new(Self {
+            handle,
+            current: Mutex::new(startup.clone()),
+            startup,
+        });
+        (layer, levels)
+    }
+
+    /// The filter in effect
+    pub fn current(&self) -> LogFilter {
+        self.current
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Apply a change to the filter, giving the filter now in effect
+    pub fn update(&self, update: LogFilterUpdate) -> Result<LogFilter> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        let mut filter = current.clone();
+        if let Some(level) = update.level {
+            filter.level = level;
+        }
+        for (target, level) in update.targets {
+            match level {
+                Some(level) => filter.targets.insert(target, level),
+                None => filter.targets.remove(&target),
+            };
+        }
+        self.apply(&mut current, filter)
+    }
+
+    /// Go back to the filter the service started with
+    pub fn reset(&self) -> Result<LogFilter> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        self.apply(&mut current, self.startup.clone())
+    }
+
+    fn apply(&self, current: &mut LogFilter, filter: LogFilter) -> Result<LogFilter> {
+        self.handle
+            .reload(filter.env_filter()?)
+            .map_err(|e| AppError::InternalError(format!("Failed to change log filter: {}", e)))?;
+        info!(
+            "Log filter changed from {} to {}",
+            current.directives(),
+            filter.directives()
+        );
+        *current = filter;
+        Ok(current.clone())
+    }
+}
+
+/// Show the log filter in effect
+pub async fn get_log_levels(Extension(levels): Extension<Arc<LogLevels>>) -> Json<LogFilter> {
+    Json(levels.current())
+}
+
+/// Change the default level or the levels of targets
+#[instrument(skip(levels))]
+pub async fn put_log_levels(
+    Extension(levels): Extension<Arc<LogLevels>>,
+    Json(update): Json<LogFilterUpdate>,
+) -> Result<Json<LogFilter>> {
+    Ok(Json(levels.update(update)?))
+}
+
+/// Go back to the log filter of `RUST_LOG`
+pub async fn reset_log_levels(
+    Extension(levels): Extension<Arc<LogLevels>>,
+) -> Result<Json<LogFilter>> {
+    Ok(Json(levels.reset()?))
+}
+
+/// Repeats of one call site's message within the current window
+struct Repeats {
+    window_start: Instant,
+    logged: u32,
+    suppressed: u64,
+}
+
+static REPEAT_LIMIT: OnceLock<RepeatLimit> = OnceLock::new();
+static REPEATS: OnceLock<Mutex<HashMap<&'static str, Repeats>>> = OnceLock::new();
+
+/// Set the limit on repeated messages; until then repeats are not limited
+pub fn limit_repeats(limit: RepeatLimit) {
+    if limit.burst > 0 {
+        info!(
+            "Logging at most {} repeats of a message per {:?}",
+            limit.burst, limit.window
+        );
+    }
+    let _ = REPEAT_LIMIT.set(limit);
+}
+
+/// Whether a message from `site` may be logged, giving how many repeats were suppressed
+/// since the last one that was
+pub fn admit(site: &'static str) -> Option<u64> {
+    let Some(limit) = REPEAT_LIMIT.get().filter(|limit| limit.burst > 0) else {
+        return Some(0);
+    };
+
+    let now = Instant::now();
+    let mut repeats = REPEATS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let repeats = repeats.entry(site).or_insert(Repeats {
+        window_start: now,
+        logged: 0,
+        suppressed: 0,
+    });
+    if now.duration_since(repeats.window_start) >= limit.window {
+        repeats.window_start = now;
+        repeats.logged = 0;
+    }
+
+    if repeats.logged < limit.burst {
+        repeats.logged += 1;
+        Some(std::mem::take(&mut repeats.suppressed))
+    } else {
+        repeats.suppressed += 1;
+        None
+    }
+}
+
+/// Log a message that can repeat once per item, e.g. `throttled!(warn!("...", id))`, at most
+/// [`RepeatLimit::burst`] times per window from one call site. The next message logged after
+/// repeats were dropped carries their count in a `suppressed` field.
+macro_rules! throttled {
+    ($level:ident!($($arg:tt)+)) => {
+        match $crate::logging::admit(concat!(file!(), ":", line!())) {
+            Some(0) => tracing::$level!($($arg)+),
+            Some(suppressed) => tracing::$level!(suppressed, $($arg)+),
+            None => {}
+        }
+    };
+}
+
+pub(crate) use throttled;
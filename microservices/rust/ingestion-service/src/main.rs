@@ -1,67 +1,780 @@
-mod models;
+mod admin;
+mod amqp;
+mod analytics;
+mod audit;
+mod audit_mirror;
+mod aws;
+mod backlog;
+mod batch;
+mod buffers;
+mod bus;
+mod cache;
+mod chunk;
+mod classification;
+mod cloudevents;
+mod compression;
+mod config;
+mod dead_letter;
+mod deadline;
+mod defaults;
+mod document;
+mod email;
+mod embedding;
+mod encoding;
+mod encryption;
+mod erasure;
 mod error;
+mod eventgrid;
+mod expiry;
+mod export;
+mod extract;
+mod fetch;
+mod flow;
+mod heartbeat;
+mod history;
+mod http;
+mod idempotency;
+mod keys;
+mod license;
+mod lint;
+mod local_outbox;
+mod logging;
+mod minhash;
+mod models;
 mod nats;
+mod negotiate;
+mod offload;
+mod openapi;
+mod ordering;
+mod outbox;
+mod pipeline;
+mod platform;
+mod profiling;
+mod pubsub;
+mod rate;
+mod readiness;
+mod redis_streams;
+mod registry;
+mod republish;
+mod retention;
+mod retraction;
 mod routes;
-mod config;
+mod routing;
+mod sanitize;
+mod schedule;
+mod script;
+mod sequence;
+mod shard;
+mod sources;
+mod spool;
+mod ssrf;
+mod startup;
+mod stats;
+mod stomp;
+mod subject;
+mod summary;
+mod tcp;
+mod telemetry;
+mod udp;
+mod upload;
+mod usage;
+mod webhook;
+
+#[cfg(test)]
+mod wire_format;
 
-use std::sync::Arc;
 use axum::{
-    routing::{post, get},
-    Router,
-    extract::Extension,
+    extract::{DefaultBodyLimit, Extension},
     http::{HeaderValue, Method},
+    middleware,
+    routing::{delete, get, post, put},
+    Router,
 };
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Instant;
 use tower_http::{
+    cors::{AllowOrigin, Any, CorsLayer},
     trace::TraceLayer,
-    cors::{CorsLayer, Any},
 };
-use tracing::{info, Level};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing::{error, info, warn};
+use tracing_subscriber::{
+    filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, Layer,
+};
 
+use crate::amqp::AmqpBus;
+use crate::audit::AuditLog;
+use crate::aws::AwsBus;
+use crate::backlog::BacklogMonitor;
+use crate::buffers::BufferPool;
+use crate::bus::{BusBackend, MessageBus};
+use crate::cache::ResponseCache;
 use crate::config::AppConfig;
-use crate::nats::NatsClient;
+use crate::email::EmailPoller;
+use crate::encryption::Cipher;
+use crate::error::AppError;
+use crate::fetch::UrlFetcher;
+use crate::flow::FlowControl;
+use crate::heartbeat::HeartbeatPublisher;
+use crate::history::HistoryStore;
+use crate::idempotency::IdempotencyStore;
+use crate::local_outbox::LocalOutbox;
+use crate::nats::{NatsClient, SimulationMode};
+use crate::offload::Offloader;
+use crate::outbox::OutboxRelay;
+use crate::pipeline::Pipeline;
+use crate::readiness::Readiness;
+use crate::redis_streams::RedisStreamsBus;
+use crate::retention::Retention;
+use crate::retraction::Retractor;
+use crate::schedule::Schedule;
+use crate::sources::{SourceManifest, SourceRegistry};
+use crate::spool::Spool;
+use crate::startup::{Classify, FailureClass, StartupError};
+use crate::upload::UploadSessions;
+use crate::webhook::{ReplayGuard, WebhookReceiver};
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // Logged as one structured line so log pipelines can alert on the failure class
+            error!(
+                failure = e.class.as_str(),
+                exit_code = e.class.exit_code(),
+                error = %e,
+                "Ingestion service stopped"
+            );
+            e.exit_code()
+        }
+    }
+}
+
+async fn run() -> Result<(), StartupError> {
+    let started = Instant::now();
+
+    // `ingestion-service openapi` prints the API contract for client generation and exits
+    if std::env::args().nth(1).as_deref() == Some("openapi") {
+        println!("{}", openapi::spec_json()?);
+        return Ok(());
+    }
+
+    // `ingestion-service check-config` reports risky settings before a rollout and exits
+    if std::env::args().nth(1).as_deref() == Some("check-config") {
+        return lint::check_config().await;
+    }
+
+    // Initialize tracing; RUST_LOG only filters logs, so quieter logging doesn't break trace propagation
+    let (filter, log_levels) =
+        logging::LogLevels::new(&std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()));
+    let fmt = match std::env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => tracing_subscriber::fmt::layer().json().boxed(),
+        _ => tracing_subscriber::fmt::layer().boxed(),
+    };
     tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "info,tower_http=debug".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
+        .with(fmt.with_filter(filter))
+        .with(telemetry::layer().with_filter(LevelFilter::INFO))
         .init();
 
     info!("Initializing Chimera Ingestion Service");
-    
+
     // Load configuration
     let config = AppConfig::from_env();
     info!("Loaded configuration: {:#?}", config);
+    info!("Running in {} environment", config.environment);
+    logging::limit_repeats(config.log_repeats.clone());
+    telemetry::set_sampling(config.trace_sampling.clone());
+    profiling::start(&config.profiling).await;
+
+    // Request bodies and NATS payloads share one pool of reusable buffers
+    let buffers = Arc::new(BufferPool::new(config.buffer_pool.clone()));
 
-    // Initialize NATS connection
-    let nats_client = NatsClient::new(&config.nats_url).await?;
+    // Initialize NATS connection, unless Redis Streams carry every message
+    let nats_client = if config.message_bus == BusBackend::Redis {
+        info!("Publishing to Redis Streams without connecting to NATS");
+        NatsClient::detached(config.subject_namespace())
+    } else {
+        if let Some(tls) = &config.nats_tls {
+            tls.check().classify(FailureClass::Config)?;
+        }
+        if let Some(auth) = &config.nats_auth {
+            auth.check().await.classify(FailureClass::Config)?;
+        }
+        let nats_servers = nats::server_addrs(&config.nats_urls).classify(FailureClass::Config)?;
+        NatsClient::new(
+            nats_servers,
+            config.simulation,
+            config.subject_namespace(),
+            config.jetstream_ack_timeout,
+            config.jetstream_max_pending_acks,
+            config.nats_tls.as_ref(),
+            config.nats_auth.as_ref(),
+        )
+        .await
+        .classify(FailureClass::BusUnreachable)?
+    };
     let nats_client = Arc::new(nats_client);
 
+    // Ingested items go to RabbitMQ, Redis or AWS instead when chosen; with RabbitMQ or AWS,
+    // NATS stays connected for the JetStream stores and streams. Their keys are prefixed
+    // for simulation and namespacing like NATS subjects.
+    let subject_prefix = nats::subject_prefix(config.simulation, config.subject_namespace());
+    let bus: Arc<dyn MessageBus> = match config.message_bus {
+        BusBackend::Nats => nats_client.clone(),
+        // The unconnected client discards every message, whichever backend was chosen
+        _ if config.simulation == Some(SimulationMode::Null) => {
+            warn!(
+                "Simulation mode is null, messages will be discarded instead of sent to {}",
+                config.message_bus.name()
+            );
+            nats_client.clone()
+        }
+        BusBackend::Amqp => {
+            let amqp_config = config
+                .amqp
+                .clone()
+                .ok_or_else(|| {
+                    AppError::ValidationError(
+                        "MESSAGE_BUS is amqp but AMQP_URL is not set".to_string(),
+                    )
+                })
+                .classify(FailureClass::Config)?;
+            let amqp =
+                Arc::new(AmqpBus::new(amqp_config, subject_prefix).classify(FailureClass::Config)?);
+            amqp.connect()
+                .await
+                .classify(FailureClass::BusUnreachable)?;
+            amqp
+        }
+        BusBackend::Redis => {
+            let redis_config = config
+                .redis
+                .clone()
+                .ok_or_else(|| {
+                    AppError::ValidationError(
+                        "MESSAGE_BUS is redis but REDIS_URL is not set".to_string(),
+                    )
+                })
+                .classify(FailureClass::Config)?;
+            redis_config.check().classify(FailureClass::Config)?;
+            RedisStreamsBus::connect(redis_config, subject_prefix)
+                .await
+                .classify(FailureClass::BusUnreachable)?
+        }
+        BusBackend::Aws => {
+            let aws_config = config
+                .aws
+                .clone()
+                .ok_or_else(|| {
+                    AppError::ValidationError("MESSAGE_BUS is aws but neither AWS_SNS_TOPIC_ARN nor AWS_SQS_QUEUE_URL is set".to_string())
+                })
+                .classify(FailureClass::Config)?;
+            aws_config.check().classify(FailureClass::Config)?;
+            // A prefix cannot keep rehearsals off the topic or queue production reads
+            if config.simulation == Some(SimulationMode::Prefix) {
+                return Err(AppError::ValidationError(
+                    "INGEST_SIMULATION=prefix is not supported with MESSAGE_BUS aws, use null"
+                        .to_string(),
+                ))
+                .classify(FailureClass::Config);
+            }
+            AwsBus::connect(aws_config, &config.proxy, subject_prefix)
+                .await
+                .classify(FailureClass::BusUnreachable)?
+        }
+    };
+
+    // Track the backends readiness depends on. The bus is only required when neither the
+    // spool, the republish buffer nor the local outbox can hold messages while it is away.
+    let readiness = Arc::new(Readiness::new(config.readiness_required.clone()));
+    let bus_required =
+        config.spool.is_none() && config.republish_buffer == 0 && config.local_outbox.is_none();
+    let watched_bus = bus.clone();
+    readiness.watch(config.message_bus.name(), bus_required, move || {
+        watched_bus.is_connected()
+    });
+    if matches!(config.message_bus, BusBackend::Amqp | BusBackend::Aws) {
+        let watched_nats = nats_client.clone();
+        readiness.watch("nats", false, move || watched_nats.is_connected());
+    }
+
+    let port = config.port;
+    let config = Arc::new(config);
+
+    // Encrypt payloads written to local disk when keys are configured
+    let cipher = config
+        .encryption
+        .as_ref()
+        .map(|encryption_config| Cipher::new(encryption_config).map(Arc::new))
+        .transpose()
+        .classify(FailureClass::Config)?;
+
+    // Recover the disk spool; the pipeline drains any backlog
+    let spool = match config.spool.clone() {
+        Some(spool_config) => {
+            let spool = Spool::open(spool_config, cipher.clone())
+                .await
+                .classify(FailureClass::SpoolCorruption)?;
+            Some(Arc::new(spool))
+        }
+        None => None,
+    };
+
+    // Open the local history of accepted items
+    let history = config
+        .history
+        .clone()
+        .map(|history_config| HistoryStore::open(history_config, cipher.clone()))
+        .transpose()?;
+
+    // Open the store of items held until their requested delivery time
+    let schedule = config
+        .schedule
+        .clone()
+        .map(|schedule_config| Schedule::open(schedule_config, cipher.clone()).map(Arc::new))
+        .transpose()?;
+
+    // Open the store accepted items are persisted to until they are published
+    let local_outbox = config
+        .local_outbox
+        .clone()
+        .map(|outbox_config| LocalOutbox::open(outbox_config, cipher.clone()).map(Arc::new))
+        .transpose()?;
+
+    // Recognise repeated submissions across replicas
+    let idempotency = match &config.idempotency {
+        Some(idempotency_config) => IdempotencyStore::open(idempotency_config, &nats_client)
+            .await
+            .classify(FailureClass::BusUnreachable)?,
+        None => None,
+    };
+
+    // Payloads too large for a NATS message are published through the object store
+    let offloader = match &config.offload {
+        Some(offload_config) => Offloader::open(offload_config, &nats_client)
+            .await
+            .classify(FailureClass::BusUnreachable)?,
+        None => None,
+    };
+
+    // Load registered sources from the startup manifest
+    let sources = Arc::new(SourceRegistry::default());
+    let manifest = match &config.sources_manifest {
+        Some(path) => Some(
+            SourceManifest::read(path)
+                .await
+                .classify(FailureClass::Config)?,
+        ),
+        None => None,
+    };
+
+    // Risky settings only fail `check-config`; a running service reports them and carries on
+    for finding in lint::lint(&config, manifest.as_ref()) {
+        warn!("Risky configuration, {}", finding);
+    }
+
+    if let Some(manifest) = manifest {
+        sources
+            .import(manifest, true)
+            .classify(FailureClass::Config)?;
+    }
+
+    // Build the shared ingestion pipeline
+    let flow = Arc::new(FlowControl::default());
+    let pipeline = Arc::new(
+        Pipeline::new(
+            config.clone(),
+            &nats_client,
+            bus.clone(),
+            spool,
+            history,
+            schedule,
+            local_outbox,
+            idempotency,
+            offloader,
+            sources.clone(),
+            flow.clone(),
+            buffers.clone(),
+            &readiness,
+        )
+        .classify(FailureClass::Config)?,
+    );
+
+    // Local source schemas must stay compatible with the registry subjects they name
+    if let Some(registry) = pipeline.schema_registry() {
+        registry
+            .check_manifest(&sources.export())
+            .await
+            .classify(FailureClass::Config)?;
+    }
+
+    // Pause content types whose downstream streams fall too far behind
+    if let Some(backlog_config) = config.backlog.clone() {
+        BacklogMonitor::new(backlog_config, nats_client.clone(), flow.clone()).spawn();
+    }
+
+    // Record operator actions such as retractions
+    let audit = Arc::new(AuditLog::open(config.audit.clone()).await?);
+
+    // Publish signed retractions of ingested items
+    erasure::check_fields(&config.erasure_fields).classify(FailureClass::Config)?;
+    let retractor = config
+        .retraction
+        .clone()
+        .map(|retraction_config| {
+            Retractor::new(
+                retraction_config,
+                bus.clone(),
+                audit.clone(),
+                config.publish_retry.clone(),
+            )
+            .map(Arc::new)
+        })
+        .transpose()
+        .classify(FailureClass::Config)?;
+
+    // Create or update the stream capturing ingest subjects, and retractions, dead letters and
+    // document manifests unless those already match. Shard subjects have an extra token with the default
+    // template, so they stay with their own streams.
+    if let Some(stream_config) = &config.stream {
+        let mut subjects = vec![pipeline.subject_template().stream_subject()];
+        if let Some(retractor) = retractor
+            .as_ref()
+            .filter(|r| !subject::matches(&subjects[0], r.subject()))
+        {
+            subjects.push(retractor.subject().to_string());
+        }
+        if let Some(dead_letters) = pipeline.dead_letters() {
+            let dead_letter_subject = dead_letters.stream_subject();
+            if !subject::matches(&subjects[0], &dead_letter_subject) {
+                subjects.push(dead_letter_subject);
+            }
+        }
+        if let Some(documents) = pipeline.documents() {
+            let manifest_subject = documents.stream_subject();
+            if !subject::matches(&subjects[0], &manifest_subject) {
+                subjects.push(manifest_subject);
+            }
+        }
+        nats_client
+            .provision_stream(stream_config, &subjects)
+            .await
+            .classify(FailureClass::BusUnreachable)?;
+    }
+
+    // Create the stream keeping audit records apart from the ingest stream
+    if let Some(audit_mirror) = pipeline.audit_mirror() {
+        audit_mirror
+            .provision(&nats_client)
+            .await
+            .classify(FailureClass::BusUnreachable)?;
+    }
+
+    // Create the streams for sharded content types
+    if let Some(sharder) = pipeline.sharder() {
+        sharder
+            .provision(&nats_client)
+            .await
+            .classify(FailureClass::BusUnreachable)?;
+    }
+
+    // Create the streams named in the routing table
+    if let Some(router) = pipeline.content_router() {
+        router
+            .provision(&nats_client)
+            .await
+            .classify(FailureClass::BusUnreachable)?;
+        router.clone().spawn_refresher(nats_client.clone());
+    }
+
+    // Poll IMAP mailboxes for partner feeds that arrive by email
+    if let Some(email_config) = config.email.clone() {
+        EmailPoller::new(email_config, &config.proxy, pipeline.clone(), &readiness)
+            .classify(FailureClass::Config)?
+            .spawn();
+    }
+
+    // Relay transactional outbox rows written by other services
+    if let Some(outbox_config) = config.outbox.clone() {
+        OutboxRelay::new(outbox_config, pipeline.clone(), &readiness)
+            .classify(FailureClass::Config)?
+            .spawn();
+    }
+    readiness.check_configured();
+
+    // Accept newline-delimited JSON from emitters that cannot speak HTTP
+    if let Some(tcp_config) = config.tcp.clone() {
+        tcp::spawn(tcp_config, pipeline.clone())
+            .await
+            .classify(FailureClass::Bind)?;
+    }
+
+    // Accept loss-tolerant telemetry datagrams
+    if let Some(udp_config) = config.udp.clone() {
+        udp::spawn(udp_config, pipeline.clone())
+            .await
+            .classify(FailureClass::Bind)?;
+    }
+
+    // Publish scheduled items as they fall due
+    if let Some(schedule) = pipeline.schedule() {
+        schedule.clone().spawn(pipeline.clone());
+    }
+
+    // Publish accepted items persisted to the local outbox
+    if let Some(local_outbox) = pipeline.local_outbox() {
+        local_outbox.clone().spawn(pipeline.clone());
+    }
+
+    // Announce the instance to orchestrators over NATS
+    if let Some(heartbeat_config) = &config.heartbeat {
+        HeartbeatPublisher::new(heartbeat_config, nats_client.clone(), pipeline.clone()).spawn();
+    }
+
+    // Keep the spool and history within their retention limits
+    let retention = config
+        .retention
+        .clone()
+        .map(|retention_config| Arc::new(Retention::new(retention_config, pipeline.clone())));
+    if let Some(retention) = &retention {
+        retention.clone().spawn();
+    }
+
+    // Cache monitoring responses so frequent polling stays off the ingest path
+    let health_cache = Arc::new(ResponseCache::<models::HealthResponse>::new(
+        config.status_cache_ttl,
+    ));
+    let stats_cache = Arc::new(ResponseCache::<models::StatsResponse>::new(
+        config.status_cache_ttl,
+    ));
+
     // Build our application with a route
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/health", get(routes::health_check))
+        .route("/ready", get(routes::readiness_check))
+        .route("/stats", get(routes::stats))
+        .route("/stats/batches/:id", get(routes::batch_stats))
+        .route("/schema", get(routes::schema))
+        .route("/openapi.json", get(routes::openapi_spec))
         .route("/ingest", post(routes::ingest_data))
         .route("/ingest/batch", post(routes::ingest_batch))
+        .route("/ingest/probe/:content_type", get(routes::probe_processors));
+
+    // URL ingestion is only exposed when fetching is enabled
+    if let Some(fetch_config) = config.fetch.clone() {
+        let fetcher = Arc::new(
+            UrlFetcher::new(fetch_config, &config.proxy, &config.ssrf)
+                .classify(FailureClass::Config)?,
+        );
+        app = app
+            .route("/ingest/url", post(routes::ingest_url))
+            .layer(Extension(fetcher));
+    }
+
+    // Scheduled items can only be cancelled when scheduling is enabled
+    if config.schedule.is_some() {
+        app = app.route("/ingest/scheduled/:id", delete(routes::cancel_scheduled));
+    }
+
+    // Bulk upload sessions are only exposed when they have somewhere to be stored
+    if let Some(upload_config) = config.upload.clone() {
+        let sessions = Arc::new(UploadSessions::open(upload_config, cipher.clone()).await?);
+        sessions.clone().spawn_sweeper();
+        app = app
+            .route("/ingest/sessions", post(upload::open_session))
+            .route(
+                "/ingest/sessions/:id",
+                get(upload::get_session).delete(upload::abort_session),
+            )
+            .route(
+                "/ingest/sessions/:id/chunks/:seq",
+                put(upload::put_chunk).layer(DefaultBodyLimit::max(sessions.max_chunk_bytes())),
+            )
+            .route("/ingest/sessions/:id/commit", post(upload::commit_session))
+            .layer(Extension(sessions));
+    }
+
+    // Webhook receivers are only exposed when a provider secret is configured
+    if let Some(webhook_config) = config.webhook.clone() {
+        let replays = ReplayGuard::open(&webhook_config, nats_client.clone());
+        let receiver =
+            Arc::new(WebhookReceiver::new(webhook_config, replays).classify(FailureClass::Config)?);
+        app = app
+            .route("/webhooks/github", post(webhook::github_webhook))
+            .layer(Extension(receiver));
+    }
+
+    // Pub/Sub push delivery is only exposed when a verification token is configured
+    if config.pubsub.is_some() {
+        app = app.route("/ingest/pubsub", post(pubsub::pubsub_push));
+    }
+
+    // Event Grid delivery is only exposed when an access key is configured
+    if config.eventgrid.is_some() {
+        app = app.route(
+            "/ingest/eventgrid",
+            post(eventgrid::eventgrid_events).options(eventgrid::eventgrid_handshake),
+        );
+    }
+
+    // STOMP over WebSocket for vendor systems that only emit STOMP
+    if config.stomp.is_some() {
+        app = app.route("/stomp", get(stomp::stomp_socket));
+    }
+
+    // Admin routes are only exposed when an admin token is configured
+    if config.admin_token.is_some() {
+        let mut admin_routes = Router::new()
+            .route("/admin/sources/import", post(admin::import_sources))
+            .route("/admin/sources/export", get(admin::export_sources))
+            .route("/admin/pauses", get(admin::list_pauses))
+            .route("/admin/pause", post(admin::pause_ingestion))
+            .route("/admin/resume", post(admin::resume_ingestion))
+            .route(
+                "/admin/logging",
+                get(logging::get_log_levels)
+                    .put(logging::put_log_levels)
+                    .delete(logging::reset_log_levels),
+            )
+            .route("/admin/validators", get(script::list_validators))
+            .route(
+                "/admin/validators/:source",
+                get(script::get_validator)
+                    .put(script::put_validator)
+                    .delete(script::delete_validator),
+            );
+
+        // Exports hand out whole payloads, so they sit behind the admin token too
+        if config.history.is_some() {
+            admin_routes = admin_routes.route("/export", get(export::export));
+        }
+
+        if config.usage.is_some() {
+            admin_routes = admin_routes.route("/admin/usage", get(usage::usage_report));
+        }
+
+        // Retractions purge items downstream, so they sit behind the admin token too
+        if let Some(retractor) = retractor {
+            admin_routes = admin_routes.route("/retract/:id", post(retraction::retract));
+
+            // Erasures find the subject's items in the history
+            if config.history.is_some() {
+                admin_routes = admin_routes.route("/admin/erasure", post(erasure::erase));
+            }
+
+            admin_routes = admin_routes.layer(Extension(retractor));
+        }
+
+        if let Some(retention) = retention {
+            admin_routes = admin_routes
+                .route("/admin/purge", post(retention::purge_stores))
+                .layer(Extension(retention));
+        }
+
+        // Profiles reveal code paths and stall the process while taken
+        #[cfg(all(target_os = "linux", feature = "profiling"))]
+        if config.profiling.enabled {
+            admin_routes = admin_routes
+                .route("/debug/pprof/profile", get(profiling::cpu_profile))
+                .route("/debug/pprof/heap", get(profiling::heap_profile));
+        }
+
+        admin_routes = admin_routes.layer(Extension(log_levels));
+
+        app = app.merge(admin_routes.route_layer(middleware::from_fn(admin::require_admin)));
+    } else {
+        info!("ADMIN_TOKEN not set, admin routes are disabled");
+    }
+
+    let summary_config = config.clone();
+    let spool_pending = pipeline.spool().map(|spool| spool.pending());
+    let usage = pipeline.usage().cloned();
+    let draining = pipeline.clone();
+
+    // Browsers may call from any origin unless the allowed ones are listed
+    let cors_origins = if config.cors_allowed_origins.is_empty() {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(
+            config
+                .cors_allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+
+    let app = app
         // Add middleware
+        .layer(middleware::from_fn(deadline::enforce_deadline))
+        .layer(middleware::from_fn(negotiate::negotiate_format))
         .layer(
             CorsLayer::new()
-                .allow_origin(Any)
+                .allow_origin(cors_origins)
                 .allow_methods([Method::GET, Method::POST])
-                .allow_headers(Any)
+                .allow_headers(Any),
         )
-        .layer(TraceLayer::new_for_http())
-        .layer(Extension(nats_client));
+        .layer(middleware::from_fn(audit_mirror::capture_request))
+        .layer(middleware::from_fn(summary::log_summary))
+        .layer(TraceLayer::new_for_http().make_span_with(telemetry::request_span))
+        .layer(Extension(bus))
+        .layer(Extension(buffers))
+        .layer(Extension(pipeline))
+        .layer(Extension(sources))
+        .layer(Extension(flow))
+        .layer(Extension(health_cache))
+        .layer(Extension(stats_cache))
+        .layer(Extension(readiness))
+        .layer(Extension(config));
 
     // Run our app
-    let addr = format!("0.0.0.0:{}", config.port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let addr = format!("0.0.0.0:{}", port);
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .classify(FailureClass::Bind)?;
     info!("Ingestion service listening on {}", addr);
-    
-    axum::serve(listener, app).await?;
-    
+
+    // One structured line describing what came up, for orchestration and runbooks
+    info!(
+        version = env!("CARGO_PKG_VERSION"),
+        environment = %summary_config.environment,
+        addr = %addr,
+        simulation = ?summary_config.simulation,
+        jetstream = summary_config.jetstream_ack_timeout.is_some(),
+        spool_pending = ?spool_pending,
+        history = summary_config.history.is_some(),
+        admin = summary_config.admin_token.is_some(),
+        tcp = ?summary_config.tcp.as_ref().map(|tcp| tcp.addr),
+        udp = ?summary_config.udp.as_ref().map(|udp| udp.addr),
+        stomp = summary_config.stomp.is_some(),
+        startup_ms = started.elapsed().as_millis() as u64,
+        "Startup complete"
+    );
+
+    // Let in-flight requests finish when asked to stop
+    axum::serve(listener, app)
+        .with_graceful_shutdown(platform::shutdown_signal())
+        .await?;
+
+    // Messages accepted before the signal must reach NATS before the process exits
+    let drain_timeout = summary_config.shutdown_drain_timeout;
+    match tokio::time::timeout(drain_timeout, draining.drain()).await {
+        Ok(Ok(())) => info!("Flushed pending publishes to NATS"),
+        Ok(Err(e)) => error!("Failed to flush pending publishes at shutdown: {}", e),
+        Err(_) => error!(
+            "Pending publishes did not reach NATS within {:?} of shutdown",
+            drain_timeout
+        ),
+    }
+
+    // Usage counted since the last flush would otherwise go unbilled
+    if let Some(usage) = usage {
+        if let Err(e) = usage.flush().await {
+            error!("Failed to write usage at shutdown: {}", e);
+        }
+    }
+
+    info!("Ingestion service shut down");
+
     Ok(())
 }
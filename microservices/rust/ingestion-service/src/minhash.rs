@@ -0,0 +1,318 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::models::RawData;
+
+/// Number of hash functions in a signature
+const NUM_HASHES: usize = 64;
+
+/// Number of LSH bands, each covering `NUM_HASHES / NUM_BANDS` rows
+const NUM_BANDS: usize = 16;
+
+/// Number of words per shingle
+const SHINGLE_SIZE: usize = 3;
+
+/// Settings for near-duplicate detection
+#[derive(Debug, Clone)]
+pub struct NearDuplicateConfig {
+    /// Content types checked for near duplicates
+    pub content_types: Vec<String>,
+
+    /// Payload field holding the text to compare
+    pub text_field: String,
+
+    /// Estimated Jaccard similarity at which items are tagged as duplicates
+    pub threshold: f64,
+
+    /// Number of recent signatures kept for comparison
+    pub window: usize,
+}
+
+type Signature = [u64; NUM_HASHES];
+
+/// Sliding window of recent signatures indexed by LSH band
+#[derive(Default)]
+struct Window {
+    order: VecDeque<Uuid>,
+    signatures: HashMap<Uuid, Signature>,
+    buckets: HashMap<(usize, u64), Vec<Uuid>>,
+}
+
+/// Detects likely near-duplicate text using MinHash signatures
+pub struct NearDuplicateDetector {
+    config: NearDuplicateConfig,
+    window: Mutex<Window>,
+}
+
+impl NearDuplicateDetector {
+    /// Create a new detector with an empty window
+    pub fn new(config: NearDuplicateConfig) -> Self {
+        Self {
+            config,
+            window: Mutex::new(Window::default()),
+        }
+    }
+
+    /// Tag the item in `metadata.near_duplicate` if it closely matches a recent item,
+    /// then add it to the window.
+    pub fn check(&self, item: &mut RawData) {
+        if !self
+            .config
+            .content_types
+            .iter()
+            .any(|t| t == &item.content_type)
+        {
+            return;
+        }
+
+        let Some(text) = item
+            .payload
+            .get(&self.config.text_field)
+            .and_then(Value::as_str)
+        else {
+            return;
+        };
+
+        let Some(signature) = signature(text) else {
+            return;
+        };
+
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+
+        let best = band_keys(&signature)
+            .filter_map(|key| window.buckets.get(&key))
+            .flatten()
+            .filter(|&&id| id != item.id)
+            .filter_map(|&id| {
+                window
+                    .signatures
+                    .get(&id)
+                    .map(|other| (id, similarity(&signature, other)))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((id, score)) = best.filter(|(_, score)| *score >= self.config.threshold) {
+            debug!(
+                "Item {} is a near duplicate of {} ({:.2})",
+                item.id, id, score
+            );
+            if !item.metadata.is_object() {
+                item.metadata = json!({});
+            }
+            item.metadata["near_duplicate"] = json!({
+                "of": id,
+                "similarity": score,
+            });
+        }
+
+        window.insert(item.id, signature, self.config.window);
+    }
+}
+
+impl Window {
+    fn insert(&mut self, id: Uuid, signature: Signature, capacity: usize) {
+        // A resubmitted item replaces its signature, keeping its place in the window
+        match self.signatures.insert(id, signature) {
+            Some(old_signature) => self.unindex(id, &old_signature),
+            None => self.order.push_back(id),
+        }
+        for key in band_keys(&signature) {
+            self.buckets.entry(key).or_default().push(id);
+        }
+
+        while self.order.len() > capacity {
+            let Some(old_id) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(old_signature) = self.signatures.remove(&old_id) {
+                self.unindex(old_id, &old_signature);
+            }
+        }
+    }
+
+    /// Remove an item from the band buckets of its signature
+    fn unindex(&mut self, old_id: Uuid, old_signature: &Signature) {
+        for key in band_keys(old_signature) {
+            if let Some(ids) = self.buckets.get_mut(&key) {
+                ids.retain(|&id| id != old_id);
+                if ids.is_empty() {
+                    self.buckets.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+/// Compute the MinHash signature of the word shingles in a text
+fn signature(text: &str) -> Option<Signature> {
+    let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        return None;
+    }
+
+    let mut signature = [u64::MAX; NUM_HASHES];
+    for shingle in words.windows(SHINGLE_SIZE.min(words.len())) {
+        let base = fnv1a(shingle);
+        for (i, slot) in signature.iter_mut().enumerate() {
+            *slot = (*slot).min(splitmix64(
+                base ^ (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15),
+            ));
+        }
+    }
+
+    Some(signature)
+}
+
+/// Estimated Jaccard similarity of two signatures
+fn similarity(a: &Signature, b: &Signature) -> f64 {
+    let equal = a.iter().zip(b.iter()).filter(|(x, y)| x == y).count();
+    equal as f64 / NUM_HASHES as f64
+}
+
+/// Bucket keys for each LSH band of a signature
+fn band_keys(signature: &Signature) -> impl Iterator<Item = (usize, u64)> + '_ {
+    signature
+        .chunks(NUM_HASHES / NUM_BANDS)
+        .enumerate()
+        .map(|(band, rows)| (band, rows.iter().fold(0u64, |acc, &h| splitmix64(acc ^ h))))
+}
+
+fn fnv1a(words: &[String]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for word in words {
+        for byte in word.bytes().chain(std::iter::once(b' ')) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> NearDuplicateDetector {
+        NearDuplicateDetector::new(NearDuplicateConfig {
+            content_types: vec!["news_article".to_string()],
+            text_field: "text".to_string(),
+            threshold: 0.5,
+            window: 10,
+        })
+    }
+
+    fn article(content_type: &str, text: &str) -> RawData {
+        RawData::builder()
+            .source("news-api")
+            .content_type(content_type)
+            .payload(json!({ "text": text }))
+            .build()
+            .unwrap()
+    }
+
+    const STORY: &str = "The city council voted on Tuesday to expand the bike lane network across \
+        the downtown core, adding twelve miles of protected lanes over the next three years \
+        and funding the work from the existing transportation budget";
+
+    #[test]
+    fn tags_near_duplicates_of_recent_items() {
+        let detector = detector();
+        let mut original = article("news_article", STORY);
+        detector.check(&mut original);
+        assert!(original.metadata.get("near_duplicate").is_none());
+
+        let mut reworded = article("news_article", &STORY.replace("Tuesday", "Wednesday"));
+        detector.check(&mut reworded);
+        assert_eq!(
+            reworded.metadata["near_duplicate"]["of"],
+            json!(original.id)
+        );
+        assert!(
+            reworded.metadata["near_duplicate"]["similarity"]
+                .as_f64()
+                .unwrap()
+                >= 0.5
+        );
+
+        let mut unrelated = article(
+            "news_article",
+            "Local bakery wins a regional award for its sourdough bread and pastries",
+        );
+        detector.check(&mut unrelated);
+        assert!(unrelated.metadata.get("near_duplicate").is_none());
+    }
+
+    #[test]
+    fn ignores_other_content_types_and_the_item_itself() {
+        let detector = detector();
+        let mut first = article("research_paper", STORY);
+        detector.check(&mut first);
+        let mut second = article("research_paper", STORY);
+        detector.check(&mut second);
+        assert!(second.metadata.get("near_duplicate").is_none());
+
+        // A resubmission of the same item is not its own duplicate
+        let mut item = article("news_article", STORY);
+        detector.check(&mut item);
+        detector.check(&mut item);
+        assert!(item.metadata.get("near_duplicate").is_none());
+    }
+
+    #[test]
+    fn identical_texts_have_identical_signatures() {
+        let a = signature("Some Words  here and there").unwrap();
+        let b = signature("some words here and THERE").unwrap();
+        assert_eq!(similarity(&a, &b), 1.0);
+        assert!(signature("   ").is_none());
+    }
+
+    #[test]
+    fn reinserting_an_item_replaces_its_buckets() {
+        let id = Uuid::new_v4();
+        let first = signature("the quick brown fox jumps over the lazy dog").unwrap();
+        let second = signature("an entirely different sentence about something else").unwrap();
+
+        let mut window = Window::default();
+        window.insert(id, first, 10);
+        window.insert(id, first, 10);
+        window.insert(id, second, 10);
+
+        assert_eq!(window.order.len(), 1);
+        assert!(band_keys(&first).all(|key| !window
+            .buckets
+            .get(&key)
+            .is_some_and(|ids| ids.contains(&id))));
+        assert!(band_keys(&second).all(|key| window.buckets[&key] == vec![id]));
+    }
+
+    #[test]
+    fn evicts_the_oldest_items_and_their_buckets() {
+        let mut window = Window::default();
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        for (n, id) in ids.iter().enumerate() {
+            window.insert(
+                *id,
+                signature(&format!("document number {} with some words", n)).unwrap(),
+                2,
+            );
+        }
+
+        assert_eq!(window.order, VecDeque::from(ids[1..].to_vec()));
+        assert!(!window.signatures.contains_key(&ids[0]));
+        assert!(window
+            .buckets
+            .values()
+            .all(|bucket| !bucket.contains(&ids[0])));
+    }
+}
@@ -1,80 +1,574 @@
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::error::{AppError, Result};
+use crate::nats::PublishAck;
+
 /// Represents raw data ingested into the system from various sources
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct RawData {
     /// Unique identifier for the data item
     #[serde(default = "Uuid::new_v4")]
     pub id: Uuid,
-    
+
     /// Source of the data (e.g., "arxiv", "github", "news-api")
     pub source: String,
-    
+
     /// Type of content (e.g., "research_paper", "code_repository", "news_article")
     pub content_type: String,
-    
+
     /// The actual data payload, represented as arbitrary JSON
     pub payload: serde_json::Value,
-    
+
     /// Timestamp when the data was ingested, defaults to current time
     #[serde(default = "Utc::now")]
     pub timestamp: DateTime<Utc>,
-    
+
     /// Optional metadata about the data
     #[serde(default)]
     pub metadata: serde_json::Value,
+
+    /// Key items are sharded on in preference to their source, so items sharing it, such as
+    /// the events of one customer, land on one shard and keep their order there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_key: Option<String>,
+}
+
+impl RawData {
+    /// Start building a data item; `source` and `content_type` must be set before `build()`
+    pub fn builder() -> RawDataBuilder<Missing, Missing> {
+        RawDataBuilder {
+            id: None,
+            source: Missing,
+            content_type: Missing,
+            payload: serde_json::Value::Null,
+            metadata: serde_json::Value::Null,
+        }
+    }
+}
+
+/// Marker for a required builder field that has not been set yet
+pub struct Missing;
+
+/// Marker for a required builder field that has been set
+pub struct Set(String);
+
+/// Builder for [`RawData`] that only offers `build()` once the required fields are set
+pub struct RawDataBuilder<S, C> {
+    id: Option<Uuid>,
+    source: S,
+    content_type: C,
+    payload: serde_json::Value,
+    metadata: serde_json::Value,
+}
+
+impl<C> RawDataBuilder<Missing, C> {
+    /// Set the source of the data
+    pub fn source(self, source: impl Into<String>) -> RawDataBuilder<Set, C> {
+        RawDataBuilder {
+            id: self.id,
+            source: Set(source.into()),
+            content_type: self.content_type,
+            payload: self.payload,
+            metadata: self.metadata,
+        }
+    }
+}
+
+impl<S> RawDataBuilder<S, Missing> {
+    /// Set the type of content
+    pub fn content_type(self, content_type: impl Into<String>) -> RawDataBuilder<S, Set> {
+        RawDataBuilder {
+            id: self.id,
+            source: self.source,
+            content_type: Set(content_type.into()),
+            payload: self.payload,
+            metadata: self.metadata,
+        }
+    }
+}
+
+impl<S, C> RawDataBuilder<S, C> {
+    /// Use a specific ID instead of a generated one
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Set the data payload
+    pub fn payload(mut self, payload: serde_json::Value) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Set metadata about the data
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+impl RawDataBuilder<Set, Set> {
+    /// Validate the fields and build the data item
+    pub fn build(self) -> Result<RawData> {
+        let Set(source) = self.source;
+        let Set(content_type) = self.content_type;
+
+        if source.trim().is_empty() {
+            return Err(AppError::ValidationError(
+                "Source field cannot be empty".to_string(),
+            ));
+        }
+
+        if content_type.trim().is_empty() {
+            return Err(AppError::ValidationError(
+                "Content type field cannot be empty".to_string(),
+            ));
+        }
+
+        if self.payload.is_null() {
+            return Err(AppError::ValidationError(
+                "Payload cannot be null".to_string(),
+            ));
+        }
+
+        Ok(RawData {
+            id: self.id.unwrap_or_else(Uuid::new_v4),
+            source,
+            content_type,
+            payload: self.payload,
+            timestamp: Utc::now(),
+            metadata: self.metadata,
+            partition_key: None,
+        })
+    }
 }
 
 /// Batch of raw data items to be ingested
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct BatchRawData {
     /// Collection of data items to ingest
     pub items: Vec<RawData>,
 }
 
+/// Request to ingest the content behind a URL
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UrlIngestRequest {
+    /// Unique identifier for the resulting data item
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+
+    /// Source of the data (e.g., "arxiv", "github", "news-api")
+    pub source: String,
+
+    /// Type of content (e.g., "research_paper", "code_repository", "news_article")
+    pub content_type: String,
+
+    /// URL to fetch
+    pub url: String,
+
+    /// Optional metadata about the data
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+}
+
 /// Response for successful ingestion
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct IngestResponse {
-    /// Status of the operation
+    /// Status of the operation: `success`, `duplicate` when JetStream already stored
+    /// every message of the item, or `scheduled` when it is held until `deliver_at`
     pub status: String,
-    
+
     /// ID of the ingested data item
     pub id: Uuid,
-    
+
     /// Timestamp when the data was ingested
     pub timestamp: DateTime<Utc>,
+
+    /// JetStream stream that stored the item, when publishing through JetStream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+
+    /// Stream sequence numbers of the item's messages, one per chunk
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sequences: Vec<u64>,
+
+    /// When a scheduled item will be published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deliver_at: Option<DateTime<Utc>>,
+
+    /// Gap or regression the item's `metadata.sequence` revealed in its source's sequence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence_gap: Option<SequenceGap>,
+}
+
+impl IngestResponse {
+    /// Response for a published item and its JetStream acknowledgements
+    pub fn published(id: Uuid, acks: &[PublishAck]) -> Self {
+        let duplicate = !acks.is_empty() && acks.iter().all(|ack| ack.duplicate);
+
+        Self {
+            status: if duplicate { "duplicate" } else { "success" }.to_string(),
+            id,
+            timestamp: Utc::now(),
+            stream: acks.first().map(|ack| ack.stream.clone()),
+            sequences: acks.iter().map(|ack| ack.sequence).collect(),
+            deliver_at: None,
+            sequence_gap: None,
+        }
+    }
+
+    /// Response for an item held until its delivery time
+    pub fn scheduled(id: Uuid, deliver_at: DateTime<Utc>) -> Self {
+        Self {
+            status: "scheduled".to_string(),
+            id,
+            timestamp: Utc::now(),
+            stream: None,
+            sequences: Vec::new(),
+            deliver_at: Some(deliver_at),
+            sequence_gap: None,
+        }
+    }
 }
 
 /// Response for batch ingestion
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct BatchIngestResponse {
     /// Status of the operation
     pub status: String,
-    
+
     /// Number of items successfully ingested
     pub count: usize,
-    
+
     /// IDs of the ingested data items
     pub ids: Vec<Uuid>,
-    
+
     /// Timestamp when the batch was processed
     pub timestamp: DateTime<Utc>,
+
+    /// When the items of a scheduled batch will be published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deliver_at: Option<DateTime<Utc>>,
+
+    /// Gaps and regressions the items' `metadata.sequence` revealed, in item order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sequence_gaps: Vec<SequenceGap>,
+}
+
+/// A sequence number out of step with the last one received from its source
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SequenceGap {
+    /// ID of the item carrying the sequence number
+    pub id: Uuid,
+
+    /// Source whose sequence it belongs to
+    pub source: String,
+
+    /// `gap` when numbers were skipped, so items may have been lost, or `regression` when
+    /// the number went backwards, so items may have been resent or the producer restarted
+    pub kind: String,
+
+    /// Number that was expected, one after the last received
+    pub expected: u64,
+
+    /// Number that was received
+    pub received: u64,
+}
+
+/// State of an upload session
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UploadSessionResponse {
+    /// Session ID, also the batch ID its items are published with
+    pub id: Uuid,
+
+    /// `open`, or `committed` once its items were published
+    pub status: String,
+
+    /// Numbers of the chunks received so far
+    pub chunks: Vec<u32>,
+
+    /// Number of items in the received chunks
+    pub items: usize,
+
+    /// When the session is discarded unless changed again
+    pub expires_at: DateTime<Utc>,
 }
 
 /// Health check response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct HealthResponse {
     /// Service name
     pub service: String,
-    
+
     /// Service status
     pub status: String,
-    
+
     /// Service version
     pub version: String,
-    
+
     /// Timestamp of the health check
     pub timestamp: DateTime<Utc>,
+
+    /// Name of the NATS server currently connected to, absent while disconnected or when
+    /// simulating without NATS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nats_server: Option<String>,
+
+    /// State of the services the ingestion service depends on
+    pub dependencies: HealthDependencies,
+}
+
+/// Dependencies reported by the health check
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HealthDependencies {
+    pub nats: NatsStats,
+}
+
+/// Connection state and statistics of the NATS client
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NatsStats {
+    /// `connected`, `disconnected`, `connecting`, or `simulated` when running without NATS
+    pub state: String,
+
+    /// Name of the server connected to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+
+    /// Version of the server connected to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_version: Option<String>,
+
+    /// Host and port of the server connected to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_address: Option<String>,
+
+    /// Round trip time of a message through the server, absent while disconnected or when
+    /// the server did not answer in time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<f64>,
+
+    /// Times the connection was re-established since the service started
+    pub reconnects: u64,
+
+    /// Bytes of publishes handed to the client and not yet written out or acknowledged
+    pub pending_bytes: u64,
+}
+
+/// Ingestion counters for a group of items
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct IngestCounters {
+    /// Items published successfully
+    pub published: u64,
+
+    /// Items rejected by validation or pre-processing
+    pub rejected: u64,
+
+    /// Valid items that could not be published
+    pub failed: u64,
+
+    /// Items spooled to disk, or buffered in memory, because publishing failed
+    pub spooled: u64,
+
+    /// Failed items published to the dead-letter subject, also counted as failed
+    #[serde(default)]
+    pub dead_lettered: u64,
+
+    /// Spooled or buffered messages dropped once past their `Ingest-Expires-At`, counted
+    /// per message rather than per item
+    #[serde(default)]
+    pub expired: u64,
+
+    /// Items whose `metadata.sequence` skipped numbers of their source's sequence
+    #[serde(default)]
+    pub sequence_gaps: u64,
+
+    /// Sequence numbers skipped by those items, each possibly a lost item
+    #[serde(default)]
+    pub missed_sequences: u64,
+
+    /// Items whose `metadata.sequence` went backwards
+    #[serde(default)]
+    pub sequence_regressions: u64,
+}
+
+/// Counters for records received over the UDP listener
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct DatagramCounters {
+    /// Records received
+    pub received: u64,
+
+    /// Records forwarded to the pipeline in a batch
+    pub forwarded: u64,
+
+    /// Records dropped because the batching queue was full
+    pub dropped_overflow: u64,
+
+    /// Records dropped because their batch could not be ingested
+    pub dropped_failed: u64,
+}
+
+/// Counters for space reclaimed from one local store by retention purges
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct RetentionCounters {
+    /// Purges run
+    pub purges: u64,
+
+    /// Entries removed
+    pub purged_records: u64,
+
+    /// Disk space handed back to the filesystem
+    pub reclaimed_bytes: u64,
+}
+
+/// Availability counters of one dependency
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct DependencyCounters {
+    /// Whether the dependency is up, or not yet reached
+    pub up: bool,
+
+    /// Times the dependency went down since the service started
+    pub outages: u64,
+
+    /// Time spent down since the service started, the current outage included
+    pub downtime_seconds: f64,
+}
+
+/// Ingestion statistics response
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StatsResponse {
+    /// Service name
+    pub service: String,
+
+    /// Seconds since the service started
+    pub uptime_seconds: u64,
+
+    /// Counters across all items
+    pub totals: IngestCounters,
+
+    /// Counters per content type
+    pub by_content_type: BTreeMap<String, IngestCounters>,
+
+    /// Counters per source
+    pub by_source: BTreeMap<String, IngestCounters>,
+
+    /// UDP listener counters, present when the listener is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datagrams: Option<DatagramCounters>,
+
+    /// Retention purge counters per store, present once a purge has run
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub retention: BTreeMap<String, RetentionCounters>,
+
+    /// Availability counters per dependency, such as the message bus or the outbox database
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, DependencyCounters>,
+
+    /// Timestamp of the snapshot
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Ingestion counters of one producer-supplied batch
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchStatsResponse {
+    /// Batch ID sent in `Ingest-Batch-Id`
+    pub batch_id: String,
+
+    /// Counters across the batch's items
+    pub totals: IngestCounters,
+
+    /// When the first item of the batch was processed
+    pub first_seen: DateTime<Utc>,
+
+    /// When the latest item of the batch was processed
+    pub last_seen: DateTime<Utc>,
+
+    /// Timestamp of the snapshot
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Envelope version and encodings of published messages, for consumers to check against
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SchemaResponse {
+    /// Envelope version as `major.minor`, also sent in the `chimera-schema-version` header
+    pub version: String,
+
+    /// Encoding of content types without their own
+    pub default_format: String,
+
+    /// Encodings of specific content types
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub formats: BTreeMap<String, String>,
+
+    /// `structured` or `binary` when items are published as CloudEvents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloudevents: Option<String>,
+
+    /// Whether the version the consumer asked about can read published messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compatible: Option<bool>,
+
+    /// Timestamp of the response
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Result of probing the processors of a content type
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProbeResponse {
+    /// Content type that was probed
+    pub content_type: String,
+
+    /// Subject the probe was sent to
+    pub subject: String,
+
+    /// Whether any processor replied before the timeout
+    pub responded: bool,
+
+    /// Round trip time of the reply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+
+    /// Timestamp of the probe
+    pub timestamp: DateTime<Utc>,
+}
+
+/// State of one dependency in the readiness check
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DependencyStatus {
+    /// `up`, `down`, or `unknown` before the dependency was first used
+    pub status: String,
+
+    /// Whether the service is not ready while the dependency is down or unknown
+    pub required: bool,
+
+    /// Latest error while the dependency is down
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// When the dependency entered its current status
+    pub since: DateTime<Utc>,
+}
+
+/// Readiness check response
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReadyResponse {
+    /// Whether the service should receive traffic
+    pub ready: bool,
+
+    /// `ready`, `degraded` while an optional dependency is down, or `unavailable`
+    pub status: String,
+
+    /// Messages waiting in the disk spool
+    pub spool_pending: u64,
+
+    /// State of each dependency in use
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, DependencyStatus>,
+
+    /// Timestamp of the readiness check
+    pub timestamp: DateTime<Utc>,
 }
@@ -1,49 +1,1098 @@
-use async_nats::Client;
-use serde::Serialize;
-use tracing::{info, error, instrument};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::bus::OutgoingMessage;
+use crate::config::Secret;
 use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::NatsStats;
+use crate::telemetry;
+use async_nats::connection::State;
+use async_nats::jetstream;
+use async_nats::{Client, ConnectOptions, Event, HeaderMap, Request, RequestErrorKind, ServerAddr};
+use bytes::Bytes;
+use futures::stream::FuturesUnordered;
+use futures::{StreamExt, TryStreamExt};
+use tokio::sync::Notify;
+use tracing::{debug, error, info, instrument, warn};
+
+/// Subject prefix used when rehearsing in prefix simulation mode
+const SIMULATION_PREFIX: &str = "simulate.";
+
+/// How long the health check waits for its round trip through the server
+const RTT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where messages go when the service runs as a rehearsal against staging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationMode {
+    /// Publish to the usual subjects under a `simulate.` prefix
+    Prefix,
+
+    /// Discard every message without connecting to NATS
+    Null,
+}
+
+impl SimulationMode {
+    /// Parse a mode name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "prefix" => Some(Self::Prefix),
+            "null" => Some(Self::Null),
+            _ => None,
+        }
+    }
+}
+
+/// Prefix of every subject published in `simulation` mode and under `namespace`, applied by
+/// [`NatsClient`] and likewise to the routing keys, stream keys or subject attributes of the
+/// other backends
+pub fn subject_prefix(simulation: Option<SimulationMode>, namespace: Option<&str>) -> String {
+    let mut prefix = String::new();
+    if simulation == Some(SimulationMode::Prefix) {
+        prefix.push_str(SIMULATION_PREFIX);
+    }
+    if let Some(namespace) = namespace {
+        prefix.push_str(namespace);
+        prefix.push('.');
+    }
+    prefix
+}
+
+/// Message headers as name and value pairs
+pub type Headers = Vec<(String, String)>;
+
+pub use async_nats::jetstream::publish::PublishAck;
+
+pub use async_nats::jetstream::stream::RetentionPolicy as StreamRetention;
+
+/// Settings for the ingest stream created or updated at startup
+#[derive(Debug, Clone)]
+pub struct StreamConfig {
+    /// Stream name, namespaced like subjects
+    pub name: String,
+
+    /// When stored messages may be removed
+    pub retention: StreamRetention,
+
+    /// Largest the stream may grow before old messages are discarded
+    pub max_bytes: Option<i64>,
+
+    /// Oldest message the stream keeps
+    pub max_age: Option<Duration>,
+
+    /// Copies of each message kept across the cluster
+    pub replicas: usize,
+
+    /// How long the stream remembers message IDs to drop duplicates
+    pub duplicate_window: Duration,
+}
+
+/// Parse a retention policy name as used in configuration
+pub fn parse_retention(value: &str) -> Option<StreamRetention> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "limits" => Some(StreamRetention::Limits),
+        "interest" => Some(StreamRetention::Interest),
+        "workqueue" | "work_queue" => Some(StreamRetention::WorkQueue),
+        _ => None,
+    }
+}
+
+/// How publishes that fail with a transient error are retried
+#[derive(Debug, Clone)]
+pub struct PublishRetryPolicy {
+    /// Attempts per message, including the first; one turns retries off
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled for every retry after it
+    pub base_delay: Duration,
+
+    /// Longest delay between attempts
+    pub max_delay: Duration,
+
+    /// Fraction of each delay, between 0 and 1, that is randomized so producers that failed
+    /// together do not retry in lockstep
+    pub jitter: f64,
+}
+
+impl PublishRetryPolicy {
+    /// Delay before retry number `retry`, counting from one
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay);
+        exponential.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * rand::random::<f64>())
+    }
+}
+
+/// TLS settings for the NATS connection
+#[derive(Debug, Clone, Default)]
+pub struct NatsTlsConfig {
+    /// PEM bundle of CAs trusted to sign the server certificate, instead of the system roots
+    pub ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate presented for mutual TLS
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM private key of the client certificate
+    pub client_key: Option<PathBuf>,
+}
+
+impl NatsTlsConfig {
+    /// Check that the configured files exist and that the client certificate and key are
+    /// set together, so a typo is reported as such rather than as an unreachable server
+    pub fn check(&self) -> Result<()> {
+        if self.client_cert.is_some() != self.client_key.is_some() {
+            return Err(AppError::ValidationError(
+                "NATS client certificate and key must be configured together".to_string(),
+            ));
+        }
+
+        for path in [&self.ca_cert, &self.client_cert, &self.client_key]
+            .into_iter()
+            .flatten()
+        {
+            if !path.is_file() {
+                return Err(AppError::ValidationError(format!(
+                    "NATS TLS file {} does not exist",
+                    path.display()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How the service authenticates to NATS
+#[derive(Debug, Clone)]
+pub enum NatsAuth {
+    /// Credentials file holding a user JWT and NKey seed, for decentralized auth
+    CredsFile(PathBuf),
+
+    /// NKey seed, for servers that list the NKey's public key as a user
+    NKey(Secret),
+}
+
+impl NatsAuth {
+    /// Check that a credentials file can be read and parsed, so a bad file is reported as
+    /// such rather than as an unreachable server
+    pub async fn check(&self) -> Result<()> {
+        match self {
+            Self::CredsFile(path) => ConnectOptions::with_credentials_file(path)
+                .await
+                .map(|_| ())
+                .map_err(|e| credentials_error(path, e)),
+            Self::NKey(_) => Ok(()),
+        }
+    }
+}
+
+/// A publish sent by [`NatsClient::send`], holding the JetStream acknowledgement it waits for
+pub struct PendingPublish(
+    Option<jetstream::context::PublishAckFuture>,
+    InFlight,
+    Option<String>,
+);
+
+/// Bytes of a publish counted as pending until dropped
+struct InFlight {
+    bytes: u64,
+    pending: Arc<AtomicU64>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.pending.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// Header JetStream uses to drop duplicate publishes of the same message
+pub const MSG_ID_HEADER: &str = "Nats-Msg-Id";
+
+/// Header naming the stream that must store a message, which JetStream rejects otherwise
+pub const EXPECTED_STREAM_HEADER: &str = "Nats-Expected-Stream";
 
 /// Client wrapper for NATS interactions
 pub struct NatsClient {
-    client: Client,
+    client: Option<Client>,
+    /// Set when publishes go through JetStream and wait for the stream's acknowledgement
+    jetstream: Option<jetstream::Context>,
+    /// Prepended to every subject for environment namespacing and simulation
+    subject_prefix: String,
+    /// Tracked from connection events so publishes fail fast during an outage
+    connected: Arc<AtomicBool>,
+    /// Signalled whenever the connection is re-established
+    reconnected: Arc<Notify>,
+    /// Times the connection was re-established, for the health check
+    reconnects: Arc<AtomicU64>,
+    /// Bytes of publishes in progress, for the health check
+    pending_bytes: Arc<AtomicU64>,
+    /// Most JetStream acknowledgements a pipelined batch leaves outstanding
+    max_pending_acks: usize,
 }
 
 impl NatsClient {
-    /// Create a new NATS client
-    pub async fn new(url: &str) -> Result<Self> {
-        info!("Connecting to NATS server at {}", url);
-        
-        let client = async_nats::connect(url)
+    /// Create a new NATS client.
+    ///
+    /// Subjects are prefixed with `namespace` when given, and with `simulate.` ahead of that
+    /// in prefix simulation mode. With `jetstream_ack_timeout` set, every publish waits up
+    /// to that long for a JetStream acknowledgement. With `tls` set, the connection must use
+    /// TLS, verified against its CA when given. `auth` selects credentials file or NKey
+    /// authentication. The client connects to any of `servers`, and fails over to the others,
+    /// or to servers the cluster advertises, when the connection drops.
+    pub async fn new(
+        servers: Vec<ServerAddr>,
+        simulation: Option<SimulationMode>,
+        namespace: Option<&str>,
+        jetstream_ack_timeout: Option<Duration>,
+        max_pending_acks: usize,
+        tls: Option<&NatsTlsConfig>,
+        auth: Option<&NatsAuth>,
+    ) -> Result<Self> {
+        let subject_prefix = subject_prefix(simulation, namespace);
+
+        if simulation == Some(SimulationMode::Null) {
+            warn!("Simulation mode is null, messages will be discarded without publishing");
+            return Ok(Self::unconnected(subject_prefix));
+        }
+
+        let connected = Arc::new(AtomicBool::new(true));
+        let reconnected = Arc::new(Notify::new());
+        let reconnects = Arc::new(AtomicU64::new(0));
+        let pending_bytes = Arc::new(AtomicU64::new(0));
+
+        // Host and port only, as URLs may carry credentials
+        let addresses: Vec<String> = servers
+            .iter()
+            .map(|server| format!("{}:{}", server.host(), server.port()))
+            .collect();
+        info!("Connecting to NATS server at {}", addresses.join(", "));
+
+        let options = ConnectOptions::new().event_callback({
+            let connected = connected.clone();
+            let reconnected = reconnected.clone();
+            let reconnects = reconnects.clone();
+            move |event| {
+                let connected = connected.clone();
+                let reconnected = reconnected.clone();
+                let reconnects = reconnects.clone();
+                async move {
+                    match event {
+                        Event::Connected => {
+                            if !connected.swap(true, Ordering::Relaxed) {
+                                reconnects.fetch_add(1, Ordering::Relaxed);
+                                info!("Reconnected to NATS");
+                            }
+                            reconnected.notify_one();
+                        }
+                        Event::Disconnected => {
+                            connected.store(false, Ordering::Relaxed);
+                            warn!("Disconnected from NATS, reconnecting");
+                        }
+                        other => warn!("NATS connection event: {}", other),
+                    }
+                }
+            }
+        });
+
+        let options = match tls {
+            Some(tls) => {
+                info!("Connecting to NATS over TLS");
+                let mut options = options.require_tls(true);
+                if let Some(ca_cert) = &tls.ca_cert {
+                    options = options.add_root_certificates(ca_cert.clone());
+                }
+                if let (Some(cert), Some(key)) = (&tls.client_cert, &tls.client_key) {
+                    options = options.add_client_certificate(cert.clone(), key.clone());
+                }
+                options
+            }
+            None => options,
+        };
+
+        let options = match auth {
+            Some(NatsAuth::CredsFile(path)) => {
+                info!(
+                    "Authenticating to NATS with credentials file {}",
+                    path.display()
+                );
+                options
+                    .credentials_file(path)
+                    .await
+                    .map_err(|e| credentials_error(path, e))?
+            }
+            Some(NatsAuth::NKey(seed)) => {
+                info!("Authenticating to NATS with an NKey");
+                options.nkey(seed.expose().to_string())
+            }
+            None => options,
+        };
+
+        let client = options.connect(servers).await.map_err(|e| {
+            error!("Failed to connect to NATS: {}", e);
+            AppError::NatsConnectionError(e.to_string())
+        })?;
+
+        info!(
+            "Successfully connected to NATS server {}",
+            client.server_info().server_name
+        );
+
+        if simulation == Some(SimulationMode::Prefix) {
+            warn!(
+                "Simulation mode is prefix, messages will be published under {}",
+                SIMULATION_PREFIX
+            );
+        }
+        if !subject_prefix.is_empty() {
+            info!("Publishing subjects under {}", subject_prefix);
+        }
+
+        let jetstream = jetstream_ack_timeout.map(|timeout| {
+            info!("Publishing through JetStream with acknowledgements");
+            let mut context = jetstream::new(client.clone());
+            context.set_timeout(timeout);
+            context
+        });
+
+        Ok(Self {
+            client: Some(client),
+            jetstream,
+            subject_prefix,
+            connected,
+            reconnected,
+            reconnects,
+            pending_bytes,
+            max_pending_acks,
+        })
+    }
+
+    /// A client that never connects, for when another backend carries every message.
+    ///
+    /// Subjects are still prefixed with `namespace`, as they name Redis streams, but the
+    /// JetStream stores and streams are unavailable, as when simulating without NATS.
+    pub fn detached(namespace: Option<&str>) -> Self {
+        Self::unconnected(
+            namespace
+                .map(|namespace| format!("{}.", namespace))
+                .unwrap_or_default(),
+        )
+    }
+
+    fn unconnected(subject_prefix: String) -> Self {
+        Self {
+            client: None,
+            jetstream: None,
+            subject_prefix,
+            connected: Arc::new(AtomicBool::new(true)),
+            reconnected: Arc::new(Notify::new()),
+            reconnects: Arc::new(AtomicU64::new(0)),
+            pending_bytes: Arc::new(AtomicU64::new(0)),
+            max_pending_acks: 1,
+        }
+    }
+
+    /// Whether the client is currently connected; always true when simulating without NATS
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Connection state and statistics for the health check.
+    ///
+    /// The round trip time is measured by sending an empty message to a fresh inbox and
+    /// waiting for the server to deliver it back.
+    pub async fn stats(&self) -> NatsStats {
+        let pending_bytes = self.pending_bytes.load(Ordering::Relaxed);
+        let reconnects = self.reconnects.load(Ordering::Relaxed);
+        let Some(client) = &self.client else {
+            return NatsStats {
+                state: "simulated".to_string(),
+                server_name: None,
+                server_version: None,
+                server_address: None,
+                rtt_ms: None,
+                reconnects,
+                pending_bytes,
+            };
+        };
+
+        let state = client.connection_state();
+        if state != State::Connected {
+            return NatsStats {
+                state: match state {
+                    State::Pending => "connecting",
+                    _ => "disconnected",
+                }
+                .to_string(),
+                server_name: None,
+                server_version: None,
+                server_address: None,
+                rtt_ms: None,
+                reconnects,
+                pending_bytes,
+            };
+        }
+
+        let info = client.server_info();
+        let rtt_ms = match tokio::time::timeout(RTT_TIMEOUT, round_trip(client)).await {
+            Ok(Ok(rtt)) => Some(rtt.as_secs_f64() * 1000.0),
+            Ok(Err(e)) => {
+                warn!("Failed to measure NATS round trip time: {}", e);
+                None
+            }
+            Err(_) => {
+                warn!("NATS round trip took longer than {:?}", RTT_TIMEOUT);
+                None
+            }
+        };
+
+        NatsStats {
+            state: "connected".to_string(),
+            server_name: Some(info.server_name),
+            server_version: Some(info.version),
+            server_address: Some(format!("{}:{}", info.host, info.port)),
+            rtt_ms,
+            reconnects,
+            pending_bytes,
+        }
+    }
+
+    /// Count a publish's bytes as pending until the returned guard is dropped
+    fn in_flight(&self, payload: &Bytes) -> InFlight {
+        let bytes = payload.len() as u64;
+        self.pending_bytes.fetch_add(bytes, Ordering::Relaxed);
+        InFlight {
+            bytes,
+            pending: self.pending_bytes.clone(),
+        }
+    }
+
+    /// Wait until every message published so far has been written to the server, for shutdown.
+    ///
+    /// async-nats 0.33 has no client drain. The service holds no long-lived subscriptions,
+    /// so flushing outgoing messages is all a drain would do for it.
+    pub async fn drain(&self) -> Result<()> {
+        let Some(client) = &self.client else {
+            return Ok(());
+        };
+
+        client.flush().await.map_err(|e| {
+            AppError::NatsPublishError(format!("Failed to flush NATS connection: {}", e))
+        })
+    }
+
+    /// Largest message the connected server accepts, none when simulating without NATS
+    pub fn max_payload(&self) -> Option<usize> {
+        let client = self.client.as_ref()?;
+        Some(client.server_info().max_payload).filter(|max| *max > 0)
+    }
+
+    /// Wait until the connection is re-established after an outage
+    pub async fn reconnected(&self) {
+        self.reconnected.notified().await
+    }
+
+    /// Create a JetStream stream capturing `subject` unless it already exists.
+    ///
+    /// The stream name and subject are namespaced like published subjects.
+    pub async fn ensure_stream(&self, name: &str, subject: &str) -> Result<()> {
+        self.create_stream(name, subject, false).await
+    }
+
+    /// Create a JetStream stream capturing `subject` whose messages cannot be deleted or
+    /// purged, unless it already exists.
+    ///
+    /// An existing stream is left as it is, so one created without those protections keeps
+    /// lacking them.
+    pub async fn ensure_sealed_stream(&self, name: &str, subject: &str) -> Result<()> {
+        self.create_stream(name, subject, true).await
+    }
+
+    async fn create_stream(&self, name: &str, subject: &str, sealed: bool) -> Result<()> {
+        let Some(client) = &self.client else {
+            return Ok(());
+        };
+
+        let config = jetstream::stream::Config {
+            name: self.stream_name(name),
+            subjects: vec![format!("{}{}", self.subject_prefix, subject)],
+            deny_delete: sealed,
+            deny_purge: sealed,
+            ..Default::default()
+        };
+
+        jetstream::new(client.clone())
+            .get_or_create_stream(config)
             .await
             .map_err(|e| {
-                error!("Failed to connect to NATS: {}", e);
+                error!("Failed to provision stream {}: {}", name, e);
                 AppError::NatsConnectionError(e.to_string())
             })?;
-        
-        info!("Successfully connected to NATS");
-        
-        Ok(Self { client })
-    }
-
-    /// Publish a message to a NATS subject
-    #[instrument(skip(self, payload), fields(subject = %subject))]
-    pub async fn publish<T: Serialize>(&self, subject: &str, payload: &T) -> Result<()> {
-        let payload = serde_json::to_vec(payload).map_err(|e| {
-            error!("JSON serialization error: {}", e);
-            AppError::InternalError(format!("JSON serialization error: {}", e))
-        })?;
-        
-        info!("Publishing message to subject: {}", subject);
-        
-        self.client.publish(subject, payload.into())
+
+        Ok(())
+    }
+
+    /// Create the ingest stream capturing `subjects`, or bring an existing one in line with `config`.
+    ///
+    /// Only the subjects, retention, limits, replicas and duplicate window are managed; other settings made
+    /// on the stream out of band are kept.
+    pub async fn provision_stream(&self, config: &StreamConfig, subjects: &[String]) -> Result<()> {
+        let Some(client) = &self.client else {
+            return Ok(());
+        };
+
+        let context = jetstream::new(client.clone());
+        let provision_error = |e: &dyn std::fmt::Display| {
+            error!("Failed to provision stream {}: {}", config.name, e);
+            AppError::NatsConnectionError(e.to_string())
+        };
+
+        let desired = |mut stream: jetstream::stream::Config| {
+            stream.subjects = subjects
+                .iter()
+                .map(|subject| format!("{}{}", self.subject_prefix, subject))
+                .collect();
+            stream.retention = config.retention;
+            stream.max_bytes = config.max_bytes.unwrap_or(-1);
+            stream.max_age = config.max_age.unwrap_or_default();
+            stream.num_replicas = config.replicas;
+            stream.duplicate_window = config.duplicate_window;
+            stream
+        };
+
+        let name = self.stream_name(&config.name);
+        let stream = context
+            .get_or_create_stream(desired(jetstream::stream::Config {
+                name: name.clone(),
+                ..Default::default()
+            }))
+            .await
+            .map_err(|e| provision_error(&e))?;
+
+        let current = &stream.cached_info().config;
+        let wanted = desired(current.clone());
+        if *current != wanted {
+            context
+                .update_stream(wanted)
+                .await
+                .map_err(|e| provision_error(&e))?;
+            info!("Updated stream {}", name);
+        } else {
+            info!("Stream {} is provisioned", name);
+        }
+
+        Ok(())
+    }
+
+    /// Open a JetStream key-value bucket whose entries expire after `max_age`, creating it if
+    /// needed.
+    ///
+    /// The bucket name is namespaced like stream names. Returns `None` when simulating without
+    /// NATS.
+    pub async fn key_value(
+        &self,
+        bucket: &str,
+        max_age: Duration,
+    ) -> Result<Option<jetstream::kv::Store>> {
+        let Some(client) = &self.client else {
+            return Ok(None);
+        };
+
+        let context = jetstream::new(client.clone());
+        let bucket = self.stream_name(bucket);
+        if let Ok(store) = context.get_key_value(bucket.as_str()).await {
+            return Ok(Some(store));
+        }
+
+        let store = context
+            .create_key_value(jetstream::kv::Config {
+                bucket: bucket.clone(),
+                history: 1,
+                max_age,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| {
+                error!("Failed to create key-value bucket {}: {}", bucket, e);
+                AppError::NatsConnectionError(e.to_string())
+            })?;
+
+        info!("Created key-value bucket {}", bucket);
+
+        Ok(Some(store))
+    }
+
+    /// Open a JetStream object store bucket whose objects expire after `max_age`, or never
+    /// when zero, creating it if needed.
+    ///
+    /// The bucket name is namespaced like stream names. Returns `None` when simulating without
+    /// NATS.
+    pub async fn object_store(
+        &self,
+        bucket: &str,
+        max_age: Duration,
+    ) -> Result<Option<jetstream::object_store::ObjectStore>> {
+        let Some(client) = &self.client else {
+            return Ok(None);
+        };
+
+        let context = jetstream::new(client.clone());
+        let bucket = self.stream_name(bucket);
+        if let Ok(store) = context.get_object_store(bucket.as_str()).await {
+            return Ok(Some(store));
+        }
+
+        let store = context
+            .create_object_store(jetstream::object_store::Config {
+                bucket: bucket.clone(),
+                max_age,
+                ..Default::default()
+            })
             .await
             .map_err(|e| {
-                error!("Failed to publish to NATS: {}", e);
+                error!("Failed to create object store bucket {}: {}", bucket, e);
+                AppError::NatsConnectionError(e.to_string())
+            })?;
+
+        info!("Created object store bucket {}", bucket);
+
+        Ok(Some(store))
+    }
+
+    /// Message count of a JetStream stream and the largest pending count among its consumers
+    pub async fn stream_backlog(&self, name: &str) -> Result<(u64, u64)> {
+        let Some(client) = &self.client else {
+            return Ok((0, 0));
+        };
+
+        let mut stream = jetstream::new(client.clone())
+            .get_stream(name)
+            .await
+            .map_err(|e| AppError::NatsConnectionError(e.to_string()))?;
+
+        let messages = stream
+            .info()
+            .await
+            .map_err(|e| AppError::NatsConnectionError(e.to_string()))?
+            .state
+            .messages;
+
+        let mut pending = 0;
+        let mut consumers = stream.consumers();
+        while let Some(consumer) = consumers
+            .try_next()
+            .await
+            .map_err(|e| AppError::NatsConnectionError(e.to_string()))?
+        {
+            pending = pending.max(consumer.num_pending);
+        }
+
+        Ok((messages, pending))
+    }
+
+    /// Publish an already serialized message to a NATS subject.
+    ///
+    /// Returns the stream acknowledgement when publishing through JetStream.
+    #[instrument(skip(self, headers, payload), fields(subject = %subject))]
+    pub async fn publish_bytes(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+    ) -> Result<Option<PublishAck>> {
+        // While disconnected, publishes would queue inside the client until they time out
+        if !self.is_connected() {
+            return Err(AppError::NatsConnectionError(
+                "NATS is disconnected".to_string(),
+            ));
+        }
+
+        let _in_flight = self.in_flight(&payload);
+        if self.jetstream.is_some() || expected_stream(headers).is_some() {
+            return self.publish_jetstream(subject, headers, payload).await;
+        }
+
+        self.publish_core(subject, headers, payload).await?;
+        Ok(None)
+    }
+
+    /// Publish a message with core NATS even when JetStream is enabled, for messages no
+    /// stream keeps, such as heartbeats
+    #[instrument(skip(self, headers, payload), fields(subject = %subject))]
+    pub async fn publish_core(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+    ) -> Result<()> {
+        if !self.is_connected() {
+            return Err(AppError::NatsConnectionError(
+                "NATS is disconnected".to_string(),
+            ));
+        }
+
+        let Some(client) = &self.client else {
+            debug!("Simulation discarded message for subject: {}", subject);
+            return Ok(());
+        };
+
+        let subject = format!("{}{}", self.subject_prefix, subject);
+
+        debug!("Publishing message to subject: {}", subject);
+
+        client
+            .publish_with_headers(subject.clone(), header_map(headers), payload)
+            .await
+            .map_err(|e| {
+                throttled!(error!("Failed to publish to NATS: {}", e));
                 AppError::NatsPublishError(e.to_string())
             })?;
-        
-        info!("Successfully published message to {}", subject);
-        
+
+        debug!("Successfully published message to {}", subject);
+
         Ok(())
     }
+
+    /// Send a message without waiting for the server, as part of a pipelined batch.
+    ///
+    /// The publish is confirmed by [`NatsClient::publish_pipelined`].
+    #[instrument(skip(self, headers, payload), fields(subject = %subject))]
+    async fn send(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+    ) -> Result<PendingPublish> {
+        if !self.is_connected() {
+            return Err(AppError::NatsConnectionError(
+                "NATS is disconnected".to_string(),
+            ));
+        }
+
+        let Some(client) = &self.client else {
+            debug!("Simulation discarded message for subject: {}", subject);
+            return Ok(PendingPublish(None, self.in_flight(&Bytes::new()), None));
+        };
+
+        let in_flight = self.in_flight(&payload);
+        let subject = format!("{}{}", self.subject_prefix, subject);
+
+        debug!("Sending pipelined message to subject: {}", subject);
+
+        let expected = expected_stream(headers).map(|_| jetstream::new(client.clone()));
+        match self.jetstream.clone().or(expected) {
+            Some(context) => context
+                .publish_with_headers(subject, header_map(headers), payload)
+                .await
+                .map(|ack| {
+                    PendingPublish(
+                        Some(ack),
+                        in_flight,
+                        expected_stream(headers).map(str::to_string),
+                    )
+                })
+                .map_err(|e| {
+                    throttled!(error!("Failed to publish to JetStream: {}", e));
+                    AppError::NatsPublishError(e.to_string())
+                }),
+            None => client
+                .publish_with_headers(subject, header_map(headers), payload)
+                .await
+                .map(|_| PendingPublish(None, in_flight, None))
+                .map_err(|e| {
+                    throttled!(error!("Failed to publish to NATS: {}", e));
+                    AppError::NatsPublishError(e.to_string())
+                }),
+        }
+    }
+
+    /// Publish a batch of messages, sending each without waiting for the server and keeping
+    /// at most `max_pending_acks` JetStream acknowledgements outstanding.
+    ///
+    /// Once the window is full, the connection is flushed and sending resumes as soon as any
+    /// outstanding acknowledgement arrives, so a large batch neither waits a round trip per
+    /// message nor piles up unbounded in the client. Results are in the order of `messages`;
+    /// a publish that failed to send keeps its error.
+    pub async fn publish_pipelined(
+        &self,
+        messages: Vec<OutgoingMessage>,
+    ) -> Vec<Result<Option<PublishAck>>> {
+        let count = messages.len();
+        let mut results: Vec<Option<Result<Option<PublishAck>>>> =
+            (0..count).map(|_| None).collect();
+        let mut acks = FuturesUnordered::new();
+        // Core publishes have no acknowledgement, they are sent once flushed
+        let mut unflushed = Vec::new();
+        let mut sent = false;
+
+        for (index, message) in messages.into_iter().enumerate() {
+            if acks.len() >= self.max_pending_acks {
+                self.flush_pipelined(&mut sent, &mut unflushed, &mut results)
+                    .await;
+                if let Some((index, result)) = acks.next().await {
+                    results[index] = Some(result);
+                }
+            }
+
+            match self
+                .send(&message.subject, &message.headers, message.payload)
+                .await
+            {
+                Ok(PendingPublish(Some(ack), in_flight, expected)) => {
+                    sent = true;
+                    acks.push(async move {
+                        let result = ack.await.map(Some).map_err(|e| {
+                            throttled!(error!(
+                                "JetStream did not acknowledge pipelined publish: {}",
+                                e
+                            ));
+                            publish_error(e, expected.as_deref())
+                        });
+                        drop(in_flight);
+                        (index, result)
+                    });
+                }
+                Ok(pending) => {
+                    sent = true;
+                    unflushed.push((index, pending));
+                }
+                Err(e) => results[index] = Some(Err(e)),
+            }
+        }
+
+        self.flush_pipelined(&mut sent, &mut unflushed, &mut results)
+            .await;
+        while let Some((index, result)) = acks.next().await {
+            results[index] = Some(result);
+        }
+
+        debug!("Confirmed {} pipelined publishes", count);
+        results.into_iter().flatten().collect()
+    }
+
+    /// Flush the publishes sent since the last flush, confirming the core publishes among them
+    async fn flush_pipelined(
+        &self,
+        sent: &mut bool,
+        unflushed: &mut Vec<(usize, PendingPublish)>,
+        results: &mut [Option<Result<Option<PublishAck>>>],
+    ) {
+        let flushed = match &self.client {
+            Some(client) if *sent => client.flush().await.map_err(|e| {
+                error!("Failed to flush pipelined publishes: {}", e);
+                e.to_string()
+            }),
+            _ => Ok(()),
+        };
+        *sent = false;
+
+        for (index, _pending) in unflushed.drain(..) {
+            results[index] = Some(
+                flushed
+                    .clone()
+                    .map(|_| None)
+                    .map_err(AppError::NatsPublishError),
+            );
+        }
+    }
+
+    /// Publish to a JetStream stream and wait for its acknowledgement.
+    ///
+    /// Unlike a core publish, this fails when no stream captures the subject or the stream
+    /// does not store the message in time, so the caller can retry or spool it.
+    #[instrument(skip(self, headers, payload), fields(subject = %subject))]
+    pub async fn publish_jetstream(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+    ) -> Result<Option<PublishAck>> {
+        let Some(client) = &self.client else {
+            debug!("Simulation discarded message for subject: {}", subject);
+            return Ok(None);
+        };
+
+        let context = self
+            .jetstream
+            .clone()
+            .unwrap_or_else(|| jetstream::new(client.clone()));
+        let subject = format!("{}{}", self.subject_prefix, subject);
+
+        debug!("Publishing message to stream subject: {}", subject);
+
+        let ack = context
+            .publish_with_headers(subject.clone(), header_map(headers), payload)
+            .await
+            .map_err(|e| {
+                throttled!(error!("Failed to publish to JetStream: {}", e));
+                AppError::NatsPublishError(e.to_string())
+            })?
+            .await
+            .map_err(|e| {
+                throttled!(error!(
+                    "JetStream did not acknowledge publish to {}: {}",
+                    subject, e
+                ));
+                publish_error(e, expected_stream(headers))
+            })?;
+
+        debug!(
+            "Stream {} acknowledged message to {} at sequence {}",
+            ack.stream, subject, ack.sequence
+        );
+
+        Ok(Some(ack))
+    }
+
+    /// Send a request and wait up to `timeout` for the first reply.
+    ///
+    /// Returns `None` when nobody is subscribed or no reply arrives in time, and when
+    /// simulating without NATS.
+    #[instrument(skip(self, headers, payload), fields(subject = %subject))]
+    pub async fn request(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+        timeout: Duration,
+    ) -> Result<Option<Bytes>> {
+        if !self.is_connected() {
+            return Err(AppError::NatsConnectionError(
+                "NATS is disconnected".to_string(),
+            ));
+        }
+
+        let Some(client) = &self.client else {
+            debug!("Simulation discarded request for subject: {}", subject);
+            return Ok(None);
+        };
+
+        let subject = format!("{}{}", self.subject_prefix, subject);
+        let request = Request::new()
+            .headers(header_map(headers))
+            .payload(payload)
+            .timeout(Some(timeout));
+
+        match client.send_request(subject.clone(), request).await {
+            Ok(reply) => Ok(Some(reply.payload)),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    RequestErrorKind::NoResponders | RequestErrorKind::TimedOut
+                ) =>
+            {
+                debug!("No reply to request on {}: {}", subject, e);
+                Ok(None)
+            }
+            Err(e) => {
+                error!("Failed to send request to {}: {}", subject, e);
+                Err(AppError::NatsPublishError(e.to_string()))
+            }
+        }
+    }
+}
+
+impl NatsClient {
+    /// Namespace a stream name the way subjects are namespaced
+    pub fn stream_name(&self, name: &str) -> String {
+        let name_prefix: String = self
+            .subject_prefix
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("{}{}", name_prefix, name)
+    }
+}
+
+/// Parse NATS server URLs, accepting the same forms as the client, e.g. `nats://host:4222`,
+/// `tls://host` or `host:4222`
+pub fn server_addrs(urls: &[String]) -> Result<Vec<ServerAddr>> {
+    urls.iter()
+        .map(|url| {
+            url.parse::<ServerAddr>()
+                .map_err(|e| AppError::ValidationError(format!("Invalid NATS URL {}: {}", url, e)))
+        })
+        .collect()
+}
+
+fn credentials_error(path: &std::path::Path, e: std::io::Error) -> AppError {
+    AppError::ValidationError(format!(
+        "Invalid NATS credentials file {}: {}",
+        path.display(),
+        e
+    ))
+}
+
+/// Convert message headers, adding the current trace context so consumers can join the trace
+fn header_map(headers: &Headers) -> HeaderMap {
+    let mut headers = headers.clone();
+    telemetry::inject(&mut headers);
+
+    let mut header_map = HeaderMap::new();
+    for (name, value) in &headers {
+        header_map.insert(name.as_str(), value.as_str());
+    }
+    header_map
+}
+
+/// Time for an empty message to travel through the server and back to the client
+async fn round_trip(client: &Client) -> std::result::Result<Duration, String> {
+    let inbox = client.new_inbox();
+    let mut subscriber = client
+        .subscribe(inbox.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    let started = Instant::now();
+    client
+        .publish(inbox, Bytes::new())
+        .await
+        .map_err(|e| e.to_string())?;
+    client.flush().await.map_err(|e| e.to_string())?;
+    subscriber
+        .next()
+        .await
+        .ok_or_else(|| "subscription closed".to_string())?;
+    let rtt = started.elapsed();
+    let _ = subscriber.unsubscribe().await;
+    Ok(rtt)
+}
+
+/// Stream a message must be stored by, from its headers
+fn expected_stream(headers: &Headers) -> Option<&str> {
+    headers
+        .iter()
+        .find(|(name, _)| name == EXPECTED_STREAM_HEADER)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Error for a failed JetStream acknowledgement. When the message names the stream that must
+/// store it, a missing or different stream fails fast instead of being retried or spooled.
+fn publish_error(e: jetstream::context::PublishError, expected: Option<&str>) -> AppError {
+    let Some(stream) = expected else {
+        return AppError::NatsPublishError(e.to_string());
+    };
+
+    // async-nats 0.33 keeps the server's error code only in the message
+    let mismatch = e.to_string().ends_with("error code 10060)");
+    match e.kind() {
+        jetstream::context::PublishErrorKind::StreamNotFound => {
+            AppError::StreamUnavailable(format!(
+                "Stream {} does not exist or does not capture the subject",
+                stream
+            ))
+        }
+        _ if mismatch => {
+            AppError::StreamUnavailable(format!("The subject is not captured by stream {}", stream))
+        }
+        _ => AppError::NatsPublishError(e.to_string()),
+    }
 }
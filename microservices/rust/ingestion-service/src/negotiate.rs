@@ -0,0 +1,148 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::debug;
+
+use crate::error::AppError;
+
+/// Largest JSON response re-encoded; larger ones, such as exports, stay JSON
+const MAX_NEGOTIATED_BYTES: usize = 4 * 1024 * 1024;
+
+/// Encoding of a response body a client can ask for in `Accept`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// JSON, the default
+    Json,
+
+    /// MessagePack with named fields
+    MessagePack,
+
+    /// CBOR
+    Cbor,
+}
+
+impl ResponseFormat {
+    /// Media type of the format, as sent in `Content-Type`
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::MessagePack => "application/msgpack",
+            Self::Cbor => "application/cbor",
+        }
+    }
+
+    /// Format named by a media type, including the older MessagePack names
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "*/*" | "application/*" => Some(Self::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            "application/cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Format a request's `Accept` header prefers, by quality and then by order, JSON when
+    /// it names none of them
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+            return Self::Json;
+        };
+
+        let mut best = (Self::Json, 0.0);
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let Some(format) = params.next().and_then(|media_type| {
+                Self::from_media_type(&media_type.trim().to_ascii_lowercase())
+            }) else {
+                continue;
+            };
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > best.1 {
+                best = (format, quality);
+            }
+        }
+        best.0
+    }
+}
+
+/// Re-encode JSON responses as MessagePack or CBOR when the request's `Accept` prefers one,
+/// so low-bandwidth clients get compact acknowledgements.
+///
+/// The body keeps the JSON shape: the same field names, with timestamps and IDs as strings.
+/// Responses that are not JSON, and clients that accept JSON or name no format, are left
+/// alone.
+pub async fn negotiate_format(request: Request, next: Next) -> Response {
+    let format = ResponseFormat::from_headers(request.headers());
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(ResponseFormat::Json.content_type()));
+    let small = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|upper| upper <= MAX_NEGOTIATED_BYTES as u64);
+    if format == ResponseFormat::Json || !is_json || !small {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let json = match axum::body::to_bytes(body, MAX_NEGOTIATED_BYTES).await {
+        Ok(json) => json,
+        Err(e) => {
+            return AppError::InternalError(format!(
+                "Failed to read response for {}: {}",
+                format.content_type(),
+                e
+            ))
+            .into_response()
+        }
+    };
+
+    let encoded = serde_json::from_slice::<serde_json::Value>(&json)
+        .map_err(|e| e.to_string())
+        .and_then(|value| encode(&value, format));
+    match encoded {
+        Ok(encoded) => {
+            parts.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(format.content_type()),
+            );
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(encoded))
+        }
+        // The JSON is still a valid answer
+        Err(e) => {
+            debug!("Sending JSON instead of {}: {}", format.content_type(), e);
+            Response::from_parts(parts, Body::from(json))
+        }
+    }
+}
+
+/// Encode a JSON response body in a format
+pub fn encode(value: &serde_json::Value, format: ResponseFormat) -> Result<Vec<u8>, String> {
+    match format {
+        ResponseFormat::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+        ResponseFormat::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        ResponseFormat::Cbor => {
+            let mut encoded = Vec::new();
+            ciborium::into_writer(value, &mut encoded).map_err(|e| e.to_string())?;
+            Ok(encoded)
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use async_nats::jetstream::object_store::ObjectStore;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::error::{AppError, Result};
+use crate::nats::NatsClient;
+
+/// Header marking a message whose body is a pointer to an offloaded payload, set to
+/// `object-store`
+pub const OFFLOAD_HEADER: &str = "Ingest-Offload";
+
+/// Settings for offloading oversized payloads to a NATS object store
+#[derive(Debug, Clone)]
+pub struct OffloadConfig {
+    /// JetStream object store bucket payloads are stored in, namespaced like stream names
+    pub bucket: String,
+
+    /// Encoded payloads larger than this many bytes are offloaded
+    pub threshold: usize,
+
+    /// How long offloaded payloads are kept, forever when zero
+    pub max_age: Duration,
+}
+
+/// Body published in place of an offloaded payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectPointer {
+    /// Object store bucket holding the payload
+    pub bucket: String,
+
+    /// Name of the object, the ID of the message it belongs to
+    pub name: String,
+
+    /// Digest of the object as reported by the object store, e.g. `SHA-256=<base64url>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+
+    /// Size of the object in bytes
+    pub size: usize,
+}
+
+/// Stores encoded payloads above the threshold in a NATS object store, so items larger than
+/// the server's maximum message size can still be published.
+///
+/// The published message keeps its headers, including `Ingest-Format` describing the stored
+/// payload, and carries an [`ObjectPointer`] as its JSON body instead.
+pub struct Offloader {
+    store: ObjectStore,
+    threshold: usize,
+}
+
+impl Offloader {
+    /// Open the bucket, creating it if needed; `None` without a NATS connection
+    pub async fn open(config: &OffloadConfig, nats_client: &NatsClient) -> Result<Option<Self>> {
+        let Some(store) = nats_client
+            .object_store(&config.bucket, config.max_age)
+            .await?
+        else {
+            warn!("Payload offloading is disabled without a NATS connection");
+            return Ok(None);
+        };
+
+        if let Some(max_payload) = nats_client
+            .max_payload()
+            .filter(|max| config.threshold > *max)
+        {
+            warn!(
+                "OFFLOAD_THRESHOLD_BYTES {} is above the server's max payload of {} bytes, payloads in between will fail to publish",
+                config.threshold, max_payload
+            );
+        }
+
+        info!(
+            "Offloading payloads over {} bytes to object store bucket {}",
+            config.threshold, config.bucket
+        );
+
+        Ok(Some(Self {
+            store,
+            threshold: config.threshold,
+        }))
+    }
+
+    /// Whether an encoded payload is offloaded
+    pub fn applies(&self, payload: &Bytes) -> bool {
+        payload.len() > self.threshold
+    }
+
+    /// Store a payload under `name`, giving the pointer body published in its place
+    pub async fn offload(&self, name: &str, payload: &Bytes) -> Result<Bytes> {
+        let info = self
+            .store
+            .put(name, &mut payload.as_ref())
+            .await
+            .map_err(|e| {
+                AppError::NatsPublishError(format!("Failed to offload payload {}: {}", name, e))
+            })?;
+
+        info!(
+            "Offloaded payload {} of {} bytes to {}",
+            name, info.size, info.bucket
+        );
+
+        let pointer = ObjectPointer {
+            bucket: info.bucket,
+            name: info.name,
+            digest: info.digest,
+            size: info.size,
+        };
+        serde_json::to_vec(&pointer)
+            .map(Into::into)
+            .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))
+    }
+}
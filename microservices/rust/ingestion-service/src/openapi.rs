@@ -0,0 +1,73 @@
+use utoipa::OpenApi;
+
+use crate::error::{AppError, ErrorDetail, ErrorResponse, Result};
+use crate::models::{
+    BatchIngestResponse, BatchRawData, BatchStatsResponse, DatagramCounters, DependencyCounters,
+    DependencyStatus, HealthDependencies, HealthResponse, IngestCounters, IngestResponse,
+    NatsStats, RawData, ReadyResponse, SchemaResponse, SequenceGap, StatsResponse,
+    UploadSessionResponse, UrlIngestRequest,
+};
+use crate::routes;
+use crate::upload;
+
+/// OpenAPI description of the producer-facing API.
+///
+/// Admin routes and the processor probe are operator tooling and are left out of the
+/// published contract.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Chimera Ingestion Service",
+        description = "Accepts data from producers, pre-processes it and publishes it to NATS"
+    ),
+    paths(
+        routes::health_check,
+        routes::readiness_check,
+        routes::stats,
+        routes::batch_stats,
+        routes::schema,
+        routes::ingest_data,
+        routes::ingest_batch,
+        routes::ingest_url,
+        routes::cancel_scheduled,
+        upload::open_session,
+        upload::get_session,
+        upload::put_chunk,
+        upload::commit_session,
+        upload::abort_session,
+    ),
+    components(schemas(
+        RawData,
+        BatchRawData,
+        UrlIngestRequest,
+        UploadSessionResponse,
+        IngestResponse,
+        BatchIngestResponse,
+        SequenceGap,
+        HealthResponse,
+        HealthDependencies,
+        NatsStats,
+        ReadyResponse,
+        DependencyStatus,
+        StatsResponse,
+        BatchStatsResponse,
+        SchemaResponse,
+        IngestCounters,
+        DatagramCounters,
+        DependencyCounters,
+        ErrorResponse,
+        ErrorDetail,
+    )),
+    tags(
+        (name = "ingest", description = "Submit data for ingestion"),
+        (name = "status", description = "Health, readiness and statistics"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The OpenAPI document as pretty-printed JSON
+pub fn spec_json() -> Result<String> {
+    ApiDoc::openapi()
+        .to_pretty_json()
+        .map_err(|e| AppError::InternalError(format!("Failed to render OpenAPI document: {}", e)))
+}
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{Mutex as LaneLock, OwnedMutexGuard};
+
+/// One lane per ordered source, which its items pass through one at a time.
+///
+/// Items wait for their lane in the order they arrive, and hold it from admission until
+/// their messages are published, spooled or buffered, so an item cannot overtake an earlier
+/// one of the same source however long that one's publish takes. Lanes are kept per replica.
+#[derive(Default)]
+pub struct OrderedLanes {
+    lanes: Mutex<HashMap<String, Arc<LaneLock<()>>>>,
+}
+
+impl OrderedLanes {
+    /// Wait for a source's lane, holding it until the guard is dropped
+    pub async fn enter(&self, source: &str) -> OwnedMutexGuard<()> {
+        let lane = self
+            .lanes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(source.to_string())
+            .or_default()
+            .clone();
+        lane.lock_owned().await
+    }
+}
@@ -0,0 +1,270 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio_postgres::Client;
+use tokio_postgres_rustls::MakeRustlsConnect;
+use tokio_rustls::rustls;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::Secret;
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+use crate::readiness::{Dependency, Readiness};
+use crate::routes;
+
+/// Settings for relaying a transactional outbox table
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    /// Postgres connection string; `sslmode` is honoured
+    pub database_url: Secret,
+
+    /// Outbox table, optionally schema-qualified
+    pub table: String,
+
+    /// Pause between polls when the outbox is drained
+    pub poll_interval: Duration,
+
+    /// Most rows claimed per transaction
+    pub batch_size: i64,
+}
+
+/// Relays rows from a Postgres outbox table through the pipeline.
+///
+/// Rows are claimed with `FOR UPDATE SKIP LOCKED`, so several replicas can relay the same
+/// table without publishing a row twice, and marked delivered in the same transaction.
+/// A crash between publish and commit leaves the rows to be relayed again; their IDs are
+/// derived from the row ID so consumers can drop the repeats.
+pub struct OutboxRelay {
+    config: OutboxConfig,
+    pipeline: Arc<Pipeline>,
+    table: String,
+    database: Arc<Dependency>,
+}
+
+/// A claimed outbox row
+struct OutboxRow {
+    ctid: String,
+    id: String,
+    source: String,
+    content_type: String,
+    payload: Value,
+    metadata: Option<Value>,
+}
+
+impl OutboxRelay {
+    /// Create a new relay, checking the table name since it is interpolated into queries
+    pub fn new(
+        config: OutboxConfig,
+        pipeline: Arc<Pipeline>,
+        readiness: &Readiness,
+    ) -> Result<Self> {
+        let table = quote_table(&config.table).ok_or_else(|| {
+            AppError::ValidationError(format!("Invalid outbox table name {}", config.table))
+        })?;
+
+        Ok(Self {
+            config,
+            pipeline,
+            table,
+            database: readiness.register("postgres", false),
+        })
+    }
+
+    /// Spawn the background relay task
+    pub fn spawn(self) {
+        tokio::spawn(async move {
+            let mut client: Option<Client> = None;
+
+            loop {
+                let result = match client.as_mut() {
+                    Some(client) if !client.is_closed() => self.relay(client).await,
+                    _ => match self.connect().await {
+                        Ok(connected) => self.relay(client.insert(connected)).await,
+                        Err(e) => Err(e),
+                    },
+                };
+
+                match result {
+                    // A full batch means more rows are probably waiting
+                    Ok(count) if count as i64 >= self.config.batch_size => continue,
+                    Ok(0) => {}
+                    Ok(count) => info!("Relayed {} outbox rows", count),
+                    Err(e) => {
+                        warn!("Outbox relay from {} failed: {}", self.config.table, e);
+                        client = None;
+                    }
+                }
+                tokio::time::sleep(self.config.poll_interval).await;
+            }
+        });
+    }
+
+    async fn connect(&self) -> Result<Client> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| self.database_error(e))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+        let (client, connection) = tokio_postgres::connect(
+            self.config.database_url.expose(),
+            MakeRustlsConnect::new(tls_config),
+        )
+        .await
+        .map_err(|e| self.database_error(e))?;
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("Outbox database connection closed: {}", e);
+            }
+        });
+
+        Ok(client)
+    }
+
+    /// Claim, publish and mark one batch of rows, returning the number of rows handled
+    async fn relay(&self, client: &mut Client) -> Result<usize> {
+        let transaction = client
+            .transaction()
+            .await
+            .map_err(|e| self.database_error(e))?;
+
+        // ctid identifies the locked rows without knowing the type of the table's key
+        let rows = transaction
+            .query(
+                &format!(
+                    "SELECT ctid::text, id::text, source, content_type, payload, metadata FROM {} \
+                     WHERE delivered_at IS NULL ORDER BY id LIMIT $1 FOR UPDATE SKIP LOCKED",
+                    self.table
+                ),
+                &[&self.config.batch_size],
+            )
+            .await
+            .map_err(|e| self.database_error(e))?;
+
+        let rows: Vec<OutboxRow> = rows
+            .iter()
+            .map(|row| {
+                Ok(OutboxRow {
+                    ctid: row.try_get(0)?,
+                    id: row.try_get(1)?,
+                    source: row.try_get(2)?,
+                    content_type: row.try_get(3)?,
+                    payload: row.try_get(4)?,
+                    metadata: row.try_get(5)?,
+                })
+            })
+            .collect::<std::result::Result<_, tokio_postgres::Error>>()
+            .map_err(|e| self.database_error(e))?;
+
+        let mut delivered = Vec::new();
+        let mut rejected = Vec::new();
+        let mut failure = None;
+
+        for row in rows {
+            match self.publish(&row).await {
+                Ok(()) => delivered.push(row.ctid),
+                Err(e @ AppError::ValidationError(_)) | Err(e @ AppError::PolicyViolation(_)) => {
+                    warn!("Rejected outbox row {}: {}", row.id, e);
+                    rejected.push((row.ctid, e.to_string()));
+                }
+                // Stop at the first failure so rows are published in order; it and the rows
+                // after it are retried on the next poll
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+        }
+
+        if !delivered.is_empty() {
+            transaction
+                .execute(
+                    &format!(
+                        "UPDATE {} SET delivered_at = now() WHERE ctid = ANY($1::text[]::tid[])",
+                        self.table
+                    ),
+                    &[&delivered],
+                )
+                .await
+                .map_err(|e| self.database_error(e))?;
+        }
+
+        for (ctid, error) in &rejected {
+            transaction
+                .execute(
+                    &format!(
+                        "UPDATE {} SET delivered_at = now(), error = $2 WHERE ctid = $1::text::tid",
+                        self.table
+                    ),
+                    &[ctid, error],
+                )
+                .await
+                .map_err(|e| self.database_error(e))?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| self.database_error(e))?;
+        self.database.up();
+
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(delivered.len() + rejected.len()),
+        }
+    }
+
+    /// Error of a failed database call, marking the database down
+    fn database_error(&self, e: impl std::fmt::Display) -> AppError {
+        let e = AppError::FetchError(format!("Outbox database error: {}", e));
+        self.database.down(&e);
+        e
+    }
+
+    async fn publish(&self, row: &OutboxRow) -> Result<()> {
+        let mut item = RawData::builder()
+            .id(Uuid::new_v5(
+                &Uuid::NAMESPACE_OID,
+                format!("outbox:{}:{}", self.config.table, row.id).as_bytes(),
+            ))
+            .source(row.source.clone())
+            .content_type(row.content_type.clone())
+            .payload(row.payload.clone())
+            .metadata(row.metadata.clone().unwrap_or(Value::Null))
+            .build()?;
+
+        if let Err(e) = routes::validate(&item) {
+            self.pipeline.reject(&item, &e);
+            return Err(e);
+        }
+
+        self.pipeline.process(&mut item).await.map(|_| ())
+    }
+}
+
+/// Quote a possibly schema-qualified table name, rejecting anything but plain identifiers
+fn quote_table(table: &str) -> Option<String> {
+    let parts: Vec<&str> = table.split('.').collect();
+    let valid = parts.len() <= 2
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && !part.starts_with(|c: char| c.is_ascii_digit())
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+
+    valid.then(|| {
+        parts
+            .iter()
+            .map(|part| format!("\"{}\"", part))
+            .collect::<Vec<_>>()
+            .join(".")
+    })
+}
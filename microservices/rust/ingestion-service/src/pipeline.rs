@@ -0,0 +1,1101 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+use tracing::{debug, info, warn};
+
+use crate::analytics::AnalyticsSink;
+use crate::audit_mirror::AuditMirror;
+use crate::batch;
+use crate::buffers::BufferPool;
+use crate::bus::{BusBackend, MessageBus, OutgoingMessage};
+use crate::chunk;
+use crate::classification::ClassificationPolicy;
+use crate::cloudevents::CloudEventsConfig;
+use crate::compression::{self, Compressor};
+use crate::config::AppConfig;
+use crate::dead_letter::DeadLetters;
+use crate::defaults::FieldDefaults;
+use crate::document::Documents;
+use crate::embedding::EmbeddingClient;
+use crate::encoding::{self, WireFormat};
+use crate::error::{AppError, Result};
+use crate::expiry;
+use crate::extract;
+use crate::flow::FlowControl;
+use crate::history::HistoryStore;
+use crate::idempotency::IdempotencyStore;
+use crate::license;
+use crate::local_outbox::LocalOutbox;
+use crate::logging::throttled;
+use crate::minhash::NearDuplicateDetector;
+use crate::models::{RawData, SequenceGap};
+use crate::nats::{self, Headers, NatsClient, PublishAck};
+use crate::offload::{Offloader, OFFLOAD_HEADER};
+use crate::ordering::OrderedLanes;
+use crate::rate::RateCaps;
+use crate::readiness::Readiness;
+use crate::registry::{RegisteredSchema, SchemaRegistryClient};
+use crate::republish::RepublishBuffer;
+use crate::routing::ContentRouter;
+use crate::sanitize;
+use crate::schedule::{self, Schedule};
+use crate::script::ScriptValidators;
+use crate::sequence::{self, SequenceTracker};
+use crate::shard::Sharder;
+use crate::sources::SourceRegistry;
+use crate::spool::Spool;
+use crate::stats::{IngestStats, Outcome};
+use crate::subject::SubjectTemplate;
+use crate::summary::{self, Stage};
+use crate::telemetry;
+use crate::usage::UsageLedger;
+
+/// Pre-processing and publishing stages shared by every ingestion route
+pub struct Pipeline {
+    config: Arc<AppConfig>,
+    bus: Arc<dyn MessageBus>,
+    expected_streams: HashMap<String, String>,
+    embedding_client: Option<EmbeddingClient>,
+    near_duplicate_detector: Option<NearDuplicateDetector>,
+    spool: Option<Arc<Spool>>,
+    sources: Arc<SourceRegistry>,
+    field_defaults: FieldDefaults,
+    validators: ScriptValidators,
+    flow: Arc<FlowControl>,
+    sharder: Option<Sharder>,
+    content_router: Option<Arc<ContentRouter>>,
+    analytics: Option<AnalyticsSink>,
+    history: Option<HistoryStore>,
+    schedule: Option<Arc<Schedule>>,
+    local_outbox: Option<Arc<LocalOutbox>>,
+    republish: Option<Arc<RepublishBuffer>>,
+    buffers: Arc<BufferPool>,
+    schema_registry: Option<SchemaRegistryClient>,
+    subject_template: SubjectTemplate,
+    usage: Option<Arc<UsageLedger>>,
+    idempotency: Option<IdempotencyStore>,
+    classification: ClassificationPolicy,
+    offloader: Option<Offloader>,
+    compressor: Option<Compressor>,
+    rate_caps: Option<RateCaps>,
+    dead_letters: Option<DeadLetters>,
+    documents: Option<Documents>,
+    audit_mirror: Option<AuditMirror>,
+    lanes: OrderedLanes,
+    sequences: SequenceTracker,
+    stats: Arc<IngestStats>,
+}
+
+impl Pipeline {
+    /// Build the pipeline and its optional stages from configuration
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config: Arc<AppConfig>,
+        nats_client: &NatsClient,
+        bus: Arc<dyn MessageBus>,
+        spool: Option<Arc<Spool>>,
+        history: Option<HistoryStore>,
+        schedule: Option<Arc<Schedule>>,
+        local_outbox: Option<Arc<LocalOutbox>>,
+        idempotency: Option<IdempotencyStore>,
+        offloader: Option<Offloader>,
+        sources: Arc<SourceRegistry>,
+        flow: Arc<FlowControl>,
+        buffers: Arc<BufferPool>,
+        readiness: &Readiness,
+    ) -> Result<Self> {
+        let embedding_client = config
+            .embedding
+            .clone()
+            .map(|c| EmbeddingClient::new(c, &config.proxy))
+            .transpose()?;
+
+        let near_duplicate_detector = config
+            .near_duplicate
+            .clone()
+            .map(NearDuplicateDetector::new);
+
+        let sharder = config.sharding.clone().map(Sharder::new);
+
+        let content_router = config
+            .routing
+            .clone()
+            .map(|c| ContentRouter::open(c, &config.environment, config.subject_tokens))
+            .transpose()?;
+
+        let analytics = config
+            .analytics
+            .clone()
+            .map(|c| {
+                AnalyticsSink::spawn(c, &config.proxy, readiness.register("clickhouse", false))
+            })
+            .transpose()?;
+
+        let schema_registry = config
+            .schema_registry
+            .clone()
+            .map(|c| SchemaRegistryClient::new(c, &config.proxy))
+            .transpose()?;
+
+        // Avro bodies are encoded with the registry schema, so their content types must be governed
+        let governed = config
+            .schema_registry
+            .as_ref()
+            .map(|r| r.content_types.as_slice())
+            .unwrap_or_default();
+        if config.wire_format.default == WireFormat::Avro {
+            warn!("NATS_FORMAT is avro, content types not in SCHEMA_REGISTRY_CONTENT_TYPES will fail to publish");
+        }
+        for (content_type, _) in config
+            .wire_format
+            .content_types
+            .iter()
+            .filter(|(_, f)| *f == WireFormat::Avro)
+        {
+            if !governed.contains(content_type) {
+                warn!("Content type {} is encoded as avro but not validated against the schema registry", content_type);
+            }
+        }
+
+        let subject_template = SubjectTemplate::parse(
+            &config.subject_template,
+            &config.environment,
+            config.subject_tokens,
+        )?;
+
+        let mut expected_streams = HashMap::new();
+        for (content_type, stream) in &config.expected_streams {
+            if stream.is_empty()
+                || stream.contains(|c: char| c == '.' || c == '*' || c == '>' || c.is_whitespace())
+            {
+                return Err(AppError::ValidationError(format!(
+                    "Invalid expected stream {:?} for content type {}",
+                    stream, content_type
+                )));
+            }
+            info!(
+                "Messages of content type {} must be stored by stream {}",
+                content_type, stream
+            );
+            expected_streams.insert(content_type.clone(), nats_client.stream_name(stream));
+        }
+
+        let usage = config
+            .usage
+            .clone()
+            .map(|c| {
+                let usage = Arc::new(UsageLedger::open(c)?);
+                usage.clone().spawn_flusher();
+                Ok::<_, AppError>(usage)
+            })
+            .transpose()?;
+
+        let field_defaults = match &config.field_defaults {
+            Some(file) => FieldDefaults::open(file)?,
+            None => FieldDefaults::default(),
+        };
+
+        let validators = ScriptValidators::open(config.scripts.clone())?;
+
+        let classification =
+            ClassificationPolicy::new(config.classification.clone(), config.encryption.is_some());
+
+        let rate_caps = config.rate_caps.as_ref().map(RateCaps::new);
+
+        let compressor = config.compression.as_ref().map(Compressor::new);
+
+        let dead_letters = config
+            .dead_letter
+            .as_ref()
+            .map(|c| {
+                DeadLetters::new(
+                    c,
+                    &config.environment,
+                    bus.clone(),
+                    config.publish_retry.clone(),
+                )
+            })
+            .transpose()?;
+
+        let documents = config
+            .documents
+            .as_ref()
+            .map(|c| {
+                Documents::new(
+                    c,
+                    &config.environment,
+                    bus.clone(),
+                    config.publish_retry.clone(),
+                )
+            })
+            .transpose()?;
+
+        let audit_mirror = config
+            .audit_mirror
+            .as_ref()
+            .map(|c| {
+                AuditMirror::new(
+                    c,
+                    &config.environment,
+                    bus.clone(),
+                    config.publish_retry.clone(),
+                )
+            })
+            .transpose()?;
+
+        let stats = Arc::new(IngestStats::default());
+
+        // Drain any backlog recovered from the spool, dropping what expired meanwhile
+        if let Some(spool) = &spool {
+            spool.clone().spawn_drainer(bus.clone(), stats.clone());
+        }
+
+        // Without a disk spool, messages that fail during a NATS outage wait in memory
+        let republish = (spool.is_none() && config.republish_buffer > 0).then(|| {
+            let buffer = Arc::new(RepublishBuffer::new(
+                config.republish_buffer,
+                config.republish_priority_burst,
+                config.republish_quantum,
+            ));
+            buffer.clone().spawn_flusher(bus.clone(), stats.clone());
+            buffer
+        });
+
+        Ok(Self {
+            config,
+            bus,
+            expected_streams,
+            embedding_client,
+            near_duplicate_detector,
+            spool,
+            sources,
+            field_defaults,
+            validators,
+            flow,
+            sharder,
+            content_router,
+            analytics,
+            history,
+            schedule,
+            local_outbox,
+            republish,
+            buffers,
+            schema_registry,
+            subject_template,
+            usage,
+            idempotency,
+            classification,
+            offloader,
+            compressor,
+            rate_caps,
+            dead_letters,
+            documents,
+            audit_mirror,
+            lanes: OrderedLanes::default(),
+            sequences: SequenceTracker::default(),
+            stats,
+        })
+    }
+
+    /// Ingestion counters for this pipeline
+    pub fn stats(&self) -> &IngestStats {
+        &self.stats
+    }
+
+    /// Stream sharding for hot content types, if enabled
+    pub fn sharder(&self) -> Option<&Sharder> {
+        self.sharder.as_ref()
+    }
+
+    /// Per-source validator scripts
+    pub fn validators(&self) -> &ScriptValidators {
+        &self.validators
+    }
+
+    /// Per-content-type routing table, if configured
+    pub fn content_router(&self) -> Option<&Arc<ContentRouter>> {
+        self.content_router.as_ref()
+    }
+
+    /// Disk spool for failed publishes, if enabled
+    pub fn spool(&self) -> Option<&Spool> {
+        self.spool.as_deref()
+    }
+
+    /// Buffer of messages waiting for NATS to come back, if enabled
+    pub fn republish(&self) -> Option<&Arc<RepublishBuffer>> {
+        self.republish.as_ref()
+    }
+
+    /// Items held until their requested delivery time, if enabled
+    pub fn schedule(&self) -> Option<&Arc<Schedule>> {
+        self.schedule.as_ref()
+    }
+
+    /// Store accepted items are persisted to until they are published, if enabled
+    pub fn local_outbox(&self) -> Option<&Arc<LocalOutbox>> {
+        self.local_outbox.as_ref()
+    }
+
+    /// Publisher of items that fail to publish, if enabled
+    pub fn dead_letters(&self) -> Option<&DeadLetters> {
+        self.dead_letters.as_ref()
+    }
+
+    /// Assembly of documents sent as several parts, if enabled
+    pub fn documents(&self) -> Option<&Documents> {
+        self.documents.as_ref()
+    }
+
+    /// Mirror of accepted items to the audit subject, if enabled
+    pub fn audit_mirror(&self) -> Option<&AuditMirror> {
+        self.audit_mirror.as_ref()
+    }
+
+    /// Schema registry payloads are validated against, if configured
+    pub fn schema_registry(&self) -> Option<&SchemaRegistryClient> {
+        self.schema_registry.as_ref()
+    }
+
+    /// Template the subjects of messages are rendered from
+    pub fn subject_template(&self) -> &SubjectTemplate {
+        &self.subject_template
+    }
+
+    /// Per-tenant usage accounting, if enabled
+    pub fn usage(&self) -> Option<&Arc<UsageLedger>> {
+        self.usage.as_ref()
+    }
+
+    /// Check that content of a source and content type may be fetched from a URL under its
+    /// classification
+    pub fn check_fetch(&self, source: &str, content_type: &str) -> Result<()> {
+        self.classification
+            .check_fetch(&self.sources, source, content_type)
+    }
+
+    /// Check that an item may be ingested under its classification, before it is stored for
+    /// later ingestion
+    pub fn check_classification(&self, item: &RawData) -> Result<()> {
+        self.classification.check(&self.sources, item)
+    }
+
+    /// Run the checks an item must pass to be admitted, as it would be when published, for
+    /// items answered for now and published later.
+    ///
+    /// The item is not pre-processed, and is not counted against its source's quota until it
+    /// is published.
+    pub async fn check_admission(&self, item: &RawData) -> Result<()> {
+        let mut item = item.clone();
+        self.field_defaults.apply(&mut item);
+
+        self.flow
+            .check(&item)
+            .and_then(|_| self.sources.check_uncounted(&item))
+            .and_then(|_| self.validators.check(&item))
+            .and_then(|_| self.classification.check(&self.sources, &item))?;
+        if let Some(documents) = &self.documents {
+            documents.check(&item)?;
+        }
+        if let Some(registry) = &self.schema_registry {
+            registry.validate(&item).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Dead-letter an item that failed for good, when enabled, returning whether it was.
+    ///
+    /// Items held until published call this once they are given up on, as their producer was
+    /// answered when they were accepted. Their dead letters share a message ID, so JetStream
+    /// stores one per item however often it failed.
+    pub async fn dead_letter(&self, item: &RawData, cause: &AppError) -> bool {
+        let Some(dead_letters) = &self.dead_letters else {
+            return false;
+        };
+
+        // An item refused before it was routed has no subject of its own
+        let subject = self
+            .route(item)
+            .map(|(subject, _)| subject)
+            .unwrap_or_default();
+        let published = dead_letters.publish(item, &subject, cause).await;
+        if published {
+            self.stats.record_dead_letter(item);
+        }
+        published
+    }
+
+    /// Push everything accepted so far out to NATS before shutting down: buffered messages
+    /// get one more republish attempt and the connection is flushed
+    pub async fn drain(&self) -> Result<()> {
+        if let Some(republish) = &self.republish {
+            let left = republish.drain(self.bus.as_ref(), &self.stats).await;
+            if left > 0 {
+                warn!(
+                    "{} messages buffered during the NATS outage are lost at shutdown",
+                    left
+                );
+            }
+        }
+
+        self.bus.drain().await
+    }
+
+    /// Record of recently ingested item IDs shared across replicas, if enabled
+    pub fn idempotency(&self) -> Option<&IdempotencyStore> {
+        self.idempotency.as_ref()
+    }
+
+    /// Local history of accepted items, if enabled
+    pub fn history(&self) -> Option<&HistoryStore> {
+        self.history.as_ref()
+    }
+
+    /// Record an item that failed validation before reaching the pipeline
+    pub fn reject(&self, item: &RawData, error: &AppError) {
+        self.record(item, Outcome::Rejected, Duration::ZERO, Some(error));
+    }
+
+    /// Check a validated item's sequence number against its source's, counting and logging
+    /// the gap or regression it reveals for the response to the producer
+    pub fn check_sequence(&self, item: &RawData) -> Option<SequenceGap> {
+        let sequence = sequence::sequence(item).ok()??;
+        let gap = self.sequences.observe(item, sequence)?;
+        throttled!(warn!(
+            "Sequence {} of source {} is a {}, {} was expected",
+            gap.received, gap.source, gap.kind, gap.expected
+        ));
+        self.stats.record_sequence_gap(item, &gap);
+        Some(gap)
+    }
+
+    /// Pre-process and publish a validated item, recording the outcome.
+    ///
+    /// Returns the JetStream acknowledgements of the item's messages when publishing through
+    /// JetStream; messages that were spooled have none.
+    pub async fn process(&self, item: &mut RawData) -> Result<Vec<PublishAck>> {
+        self.process_one(item, true).await
+    }
+
+    async fn process_one(&self, item: &mut RawData, buffer: bool) -> Result<Vec<PublishAck>> {
+        let started = Instant::now();
+
+        // Held until the item is delivered, so the next item of an ordered source waits
+        let _lane = match self.sources.is_ordered(&item.source) {
+            true => Some(self.lanes.enter(&item.source).await),
+            false => None,
+        };
+
+        let (subject, messages) = self.prepare(item, started).await?;
+        let publishing = Instant::now();
+        let delivered = self
+            .deliver(item, &subject, messages, Vec::new(), buffer)
+            .await;
+        summary::record_stage(Stage::Publish, publishing.elapsed());
+
+        self.finish(item, &subject, started, delivered).await
+    }
+
+    /// Pre-process and publish a batch of validated items, recording each outcome.
+    ///
+    /// Every message of the batch is sent before any acknowledgement is awaited, and the
+    /// connection is flushed once, so the batch costs about one round trip rather than one
+    /// per message. Items whose publishes fail are then retried, spooled or buffered one by
+    /// one, as [`Pipeline::process`] would. Items of ordered sources are not pipelined but
+    /// published one at a time, ahead of the rest; once one fails for a reason that may pass,
+    /// the later ones of its source fail without being tried. Results are in the order of
+    /// `items`.
+    pub async fn process_batch(&self, items: &mut [&mut RawData]) -> Vec<Result<Vec<PublishAck>>> {
+        self.process_items(items, true).await
+    }
+
+    /// Pre-process and publish a batch of items kept in durable storage until published,
+    /// recording each outcome.
+    ///
+    /// As [`Pipeline::process_batch`], except that items whose publishes fail are not held
+    /// in the republish buffer, which would lose them in a crash; they fail instead, and
+    /// stay where they are kept. The disk spool is still used.
+    pub async fn process_batch_durable(
+        &self,
+        items: &mut [&mut RawData],
+    ) -> Vec<Result<Vec<PublishAck>>> {
+        self.process_items(items, false).await
+    }
+
+    async fn process_items(
+        &self,
+        items: &mut [&mut RawData],
+        buffer: bool,
+    ) -> Vec<Result<Vec<PublishAck>>> {
+        // A pipelined item whose publish failed is retried after later ones went out, which
+        // an ordered source cannot allow
+        let ordered: Vec<bool> = items
+            .iter()
+            .map(|item| self.sources.is_ordered(&item.source))
+            .collect();
+        if !ordered.contains(&true) {
+            return self.pipeline_items(items, buffer).await;
+        }
+
+        let mut results: Vec<Option<Result<Vec<PublishAck>>>> =
+            (0..items.len()).map(|_| None).collect();
+        let mut unordered = Vec::with_capacity(items.len());
+        let mut unordered_indices = Vec::with_capacity(items.len());
+        let mut held_back = HashSet::new();
+        for (index, item) in items.iter_mut().enumerate() {
+            if ordered[index] {
+                // Later items of a source whose item may yet go through wait for its retry
+                if held_back.contains(&item.source) {
+                    let e = AppError::NatsPublishError(format!(
+                        "An earlier item of ordered source {} failed to publish",
+                        item.source
+                    ));
+                    self.record(item, Outcome::Failed, Duration::ZERO, Some(&e));
+                    results[index] = Some(Err(e));
+                    continue;
+                }
+                let result = self.process_one(item, buffer).await;
+                if result.as_ref().is_err_and(schedule::retryable) {
+                    held_back.insert(item.source.clone());
+                }
+                results[index] = Some(result);
+            } else {
+                unordered.push(&mut **item);
+                unordered_indices.push(index);
+            }
+        }
+
+        let pipelined = self.pipeline_items(&mut unordered, buffer).await;
+        for (index, result) in unordered_indices.into_iter().zip(pipelined) {
+            results[index] = Some(result);
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    async fn pipeline_items(
+        &self,
+        items: &mut [&mut RawData],
+        buffer: bool,
+    ) -> Vec<Result<Vec<PublishAck>>> {
+        let started = Instant::now();
+
+        let mut prepared = Vec::with_capacity(items.len());
+        for item in items.iter_mut() {
+            prepared.push(self.prepare(item, started).await);
+        }
+
+        let outgoing = prepared
+            .iter()
+            .flatten()
+            .flat_map(|(subject, messages)| {
+                messages.iter().map(|(headers, payload)| OutgoingMessage {
+                    subject: subject.clone(),
+                    headers: headers.clone(),
+                    payload: payload.clone(),
+                })
+            })
+            .collect();
+        let publishing = Instant::now();
+        let mut confirmed = self.bus.publish_batch(outgoing).await.into_iter();
+        summary::record_stage(Stage::Publish, publishing.elapsed());
+
+        let mut results = Vec::with_capacity(items.len());
+        for (item, prepared) in items.iter().zip(prepared) {
+            let (subject, messages) = match prepared {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    results.push(Err(e));
+                    continue;
+                }
+            };
+            let first = confirmed.by_ref().take(messages.len()).collect();
+            let publishing = Instant::now();
+            let delivered = self.deliver(item, &subject, messages, first, buffer).await;
+            summary::record_stage(Stage::Publish, publishing.elapsed());
+            results.push(self.finish(item, &subject, started, delivered).await);
+        }
+
+        results
+    }
+
+    /// Check, pre-process, route and encode an item, giving its subject and the headers and
+    /// body of each message to publish.
+    ///
+    /// Failures are recorded, as rejections unless the item was admitted.
+    async fn prepare(
+        &self,
+        item: &mut RawData,
+        started: Instant,
+    ) -> Result<(String, Vec<(Headers, Bytes)>)> {
+        let admitting = Instant::now();
+        let admitted = self.admit(item).await;
+        summary::record_stage(Stage::Admit, admitting.elapsed());
+        let (subject, headers, schema) = match admitted {
+            Ok(admitted) => admitted,
+            Err(e) => {
+                self.record(item, Outcome::Rejected, started.elapsed(), Some(&e));
+                return Err(e);
+            }
+        };
+
+        let encoding = Instant::now();
+        let encoded = self.encode(item, headers, schema.as_deref()).await;
+        summary::record_stage(Stage::Encode, encoding.elapsed());
+        let messages = match encoded {
+            Ok(messages) => messages,
+            Err(e) => {
+                self.record(item, Outcome::Failed, started.elapsed(), Some(&e));
+                return Err(e);
+            }
+        };
+
+        // Capped by messages, so each chunk of a chunked item counts
+        if let Some(rate_caps) = &self.rate_caps {
+            match rate_caps.acquire(&item.content_type, messages.len()).await {
+                Ok(waited) => summary::record_stage(Stage::Throttle, waited),
+                Err(e) => {
+                    self.record(item, Outcome::Rejected, started.elapsed(), Some(&e));
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok((subject, messages))
+    }
+
+    /// Fill in field defaults, then run the checks, schema validation and pre-processing an
+    /// item must pass, giving its subject, routing headers and registry schema
+    async fn admit(
+        &self,
+        item: &mut RawData,
+    ) -> Result<(String, Headers, Option<Arc<RegisteredSchema>>)> {
+        self.field_defaults.apply(item);
+
+        self.flow
+            .check(item)
+            .and_then(|_| self.sources.check(item))
+            .and_then(|_| self.validators.check(item))
+            .and_then(|_| self.classification.check(&self.sources, item))?;
+        if let Some(documents) = &self.documents {
+            documents.check(item)?;
+        }
+
+        let schema = match &self.schema_registry {
+            Some(registry) => registry.validate(item).await?,
+            None => None,
+        };
+
+        self.preprocess(item).await?;
+        let (subject, headers) = self.route(item)?;
+
+        Ok((subject, headers, schema))
+    }
+
+    /// Record the outcome of delivering an item, and the item in history and the audit
+    /// mirror once it is published or spooled.
+    ///
+    /// Items that failed to publish to `subject` are dead-lettered when enabled; they still
+    /// count and are reported as failed.
+    async fn finish(
+        &self,
+        item: &RawData,
+        subject: &str,
+        started: Instant,
+        delivered: Result<(Outcome, Vec<PublishAck>)>,
+    ) -> Result<Vec<PublishAck>> {
+        match delivered {
+            Ok((outcome, acks)) => {
+                self.record(item, outcome, started.elapsed(), None);
+                // The item is already published, so a history failure only costs the record
+                if let Some(history) = &self.history {
+                    if let Err(e) = history.record(item).await {
+                        throttled!(warn!("Failed to record item {} in history: {}", item.id, e));
+                    }
+                }
+                if let Some(documents) = &self.documents {
+                    documents.record(item).await;
+                }
+                if let Some(audit_mirror) = &self.audit_mirror {
+                    audit_mirror.record(item, subject, outcome).await;
+                }
+                Ok(acks)
+            }
+            Err(e) => {
+                self.record(item, Outcome::Failed, started.elapsed(), Some(&e));
+                if let Some(dead_letters) = &self.dead_letters {
+                    if dead_letters.publish(item, subject, &e).await {
+                        self.stats.record_dead_letter(item);
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn record(
+        &self,
+        item: &RawData,
+        outcome: Outcome,
+        elapsed: Duration,
+        error: Option<&AppError>,
+    ) {
+        self.stats.record(item, outcome);
+        summary::record_item(item, self.sources.tenant(&item.source), outcome);
+        if let Some(analytics) = &self.analytics {
+            analytics.record(item, outcome, elapsed, error);
+        }
+    }
+
+    /// Apply the configured pre-processing stages to a validated item
+    async fn preprocess(&self, item: &mut RawData) -> Result<()> {
+        let config = &self.config;
+
+        if config.extract_document_text {
+            // Extraction is CPU-bound, keep it off the async workers
+            let mut payload = std::mem::take(&mut item.payload);
+            item.payload = tokio::task::spawn_blocking(move || {
+                extract::extract_document_text(&mut payload).map(|_| payload)
+            })
+            .await
+            .map_err(|e| match e.is_panic() {
+                // The PDF parser panics on some malformed documents
+                true => {
+                    AppError::ValidationError("Failed to extract text from document".to_string())
+                }
+                false => AppError::InternalError(format!("Document extraction task failed: {}", e)),
+            })??;
+        }
+
+        if config.should_sanitize(&item.content_type) {
+            sanitize::sanitize_value(&mut item.payload, config.sanitize_mode);
+        }
+
+        if config.license_content_types.contains(&item.content_type) {
+            license::detect_license(item);
+        }
+
+        if let Some(detector) = &self.near_duplicate_detector {
+            detector.check(item);
+        }
+
+        Ok(())
+    }
+
+    /// Subject and routing headers for an item: the source's override, its content type's
+    /// route, its shard, or the subject rendered from the template. A content type route
+    /// adds its consumer hints whichever subject is used.
+    fn route(&self, item: &RawData) -> Result<(String, Headers)> {
+        if let Some(subject) = self.sources.subject_override(&item.source) {
+            return Ok((subject, Headers::new()));
+        }
+
+        let content_route = self
+            .content_router
+            .as_ref()
+            .and_then(|router| router.get(&item.content_type));
+        let (subject, mut headers) =
+            match content_route.as_ref().and_then(|route| route.subject(item)) {
+                Some(subject) => (subject?, Headers::new()),
+                None => {
+                    match self.sharder.as_ref().and_then(|sharder| {
+                        sharder.route(item, self.sources.is_ordered(&item.source))
+                    }) {
+                        Some(route) => route,
+                        None => (self.subject_template.render(item)?, Headers::new()),
+                    }
+                }
+            };
+        if let Some(route) = &content_route {
+            headers.extend(route.headers().iter().cloned());
+        }
+
+        Ok((subject, headers))
+    }
+
+    /// Headers and body of each message of an item, one per chunk when chunking applies
+    async fn encode(
+        &self,
+        item: &RawData,
+        mut headers: Headers,
+        schema: Option<&RegisteredSchema>,
+    ) -> Result<Vec<(Headers, Bytes)>> {
+        // Published through JetStream, so a missing stream fails the publish instead of the
+        // message going unstored
+        if let Some(stream) = self.expected_streams.get(&item.content_type) {
+            headers.push((nats::EXPECTED_STREAM_HEADER.to_string(), stream.clone()));
+        }
+
+        // Consumers reconcile a batch spread over many requests by its ID
+        if let Some(batch_id) = batch::batch_id(item) {
+            headers.push((batch::BATCH_HEADER.to_string(), batch_id.to_string()));
+        }
+
+        // Stale once its time to live has passed, so a spooled or buffered message is dropped
+        // rather than delivered late
+        if let Some(ttl) = self
+            .config
+            .expiry
+            .as_ref()
+            .and_then(|e| e.ttl_for(&item.content_type))
+        {
+            headers.push((
+                expiry::EXPIRES_AT_HEADER.to_string(),
+                expiry::expires_at(ttl),
+            ));
+        }
+
+        // Consumers fetch the exact schema the payload was validated against from the registry
+        headers.extend(self.sources.schema_headers(&item.source));
+        headers.extend(schema.map(RegisteredSchema::headers).unwrap_or_default());
+
+        // Taken here rather than at publish time, so a spooled or buffered message stays
+        // in the trace of the request that ingested it
+        telemetry::inject(&mut headers);
+        telemetry::sample_source(&mut headers, &item.source);
+
+        let messages = match chunk::chunk_item(item, &self.config.chunking) {
+            Some(chunks) => {
+                debug!("Publishing item {} as {} chunks", item.id, chunks.len());
+                chunks
+            }
+            None => vec![item.clone()],
+        };
+
+        // CloudEvents carry the payload alone, so only its encoding applies
+        let format = match &self.config.cloudevents {
+            Some(_) => CloudEventsConfig::data_format(
+                self.config.wire_format.format_for(&item.content_type),
+            ),
+            None => self.config.wire_format.format_for(&item.content_type),
+        };
+        let max_payload_bytes = self
+            .content_router
+            .as_ref()
+            .and_then(|router| router.get(&item.content_type))
+            .and_then(|route| route.max_payload_bytes());
+
+        let mut encoded = Vec::with_capacity(messages.len());
+        for mut message in messages {
+            // Embeddings are computed per published message so each chunk gets its own vector
+            if let Some(embedding_client) = &self.embedding_client {
+                embedding_client.embed(&mut message).await;
+            }
+
+            // JetStream drops a repeated message ID within the stream's duplicate window, so a
+            // replayed, retried or drained publish is stored once; chunk IDs are derived from
+            // the item's, so this holds per chunk too
+            let mut headers = headers.clone();
+            headers.push((nats::MSG_ID_HEADER.to_string(), message.id.to_string()));
+            headers.push((
+                encoding::FORMAT_HEADER.to_string(),
+                format.as_str().to_string(),
+            ));
+            headers.push((
+                encoding::SCHEMA_VERSION_HEADER.to_string(),
+                encoding::ENVELOPE_VERSION.to_string(),
+            ));
+            headers.push((
+                encoding::CHIMERA_CONTENT_TYPE_HEADER.to_string(),
+                message.content_type.clone(),
+            ));
+            headers.extend(format.envelope_headers(&message));
+
+            // The AMQP and Redis buses route by source and content type, which only Avro
+            // messages carry
+            if self.config.message_bus != BusBackend::Nats && format != WireFormat::Avro {
+                headers.push((encoding::SOURCE_HEADER.to_string(), message.source.clone()));
+                headers.push((
+                    encoding::CONTENT_TYPE_HEADER.to_string(),
+                    message.content_type.clone(),
+                ));
+            }
+
+            // Encoded once, so a spooled or buffered message keeps its format when republished
+            let payload = match &self.config.cloudevents {
+                Some(cloudevents) => {
+                    let (event_headers, payload) =
+                        cloudevents.encode(&message, format, &self.buffers, schema)?;
+                    headers.extend(event_headers);
+                    payload
+                }
+                None => format.encode(&message, &self.buffers, schema)?,
+            };
+
+            // Compressed before the size limits, which apply to what is sent
+            let payload = match &self.compressor {
+                Some(compressor) if compressor.applies(&payload) => {
+                    match compressor.compress(&payload).await? {
+                        Some(compressed) => {
+                            headers.push((
+                                compression::CONTENT_ENCODING_HEADER.to_string(),
+                                compression::ZSTD_ENCODING.to_string(),
+                            ));
+                            compressed
+                        }
+                        None => payload,
+                    }
+                }
+                _ => payload,
+            };
+
+            if let Some(max) = max_payload_bytes.filter(|max| payload.len() > *max) {
+                return Err(AppError::PayloadTooLarge(format!(
+                    "Message {} of {} bytes exceeds the limit of {} bytes for content type {}",
+                    message.id,
+                    payload.len(),
+                    max,
+                    item.content_type
+                )));
+            }
+
+            // Oversized payloads go to the object store, with a pointer published in their place
+            let payload = match &self.offloader {
+                Some(offloader) if offloader.applies(&payload) => {
+                    headers.push((OFFLOAD_HEADER.to_string(), "object-store".to_string()));
+                    offloader.offload(&message.id.to_string(), &payload).await?
+                }
+                _ => payload,
+            };
+
+            // Checked here, as the server answers an oversized message by dropping the connection;
+            // the limit covers headers too, each sent as `name: value` plus a line break
+            let size = payload.len()
+                + headers
+                    .iter()
+                    .map(|(name, value)| name.len() + value.len() + 4)
+                    .sum::<usize>();
+            if let Some(max_payload) = self.bus.max_payload().filter(|max| size > *max) {
+                return Err(AppError::PayloadTooLarge(format!(
+                    "Message {} of {} bytes exceeds the NATS max payload of {} bytes",
+                    message.id, size, max_payload
+                )));
+            }
+
+            encoded.push((headers, payload));
+        }
+
+        Ok(encoded)
+    }
+
+    /// Publish the messages of an item, retrying failed publishes.
+    ///
+    /// `first` holds the results of attempts already made in a pipelined batch, counted as
+    /// the first attempt of the leading messages. Messages that fail to publish are spooled
+    /// to disk when the spool is enabled, or, when `buffer` allows, buffered until NATS
+    /// reconnects. Messages of an ordered source join the spool or buffer straight away
+    /// while earlier messages wait there, rather than overtaking them.
+    async fn deliver(
+        &self,
+        item: &RawData,
+        subject: &str,
+        messages: Vec<(Headers, Bytes)>,
+        first: Vec<Result<Option<PublishAck>>>,
+        buffer: bool,
+    ) -> Result<(Outcome, Vec<PublishAck>)> {
+        let republish = self.republish.as_ref().filter(|_| buffer);
+        let policy = &self.config.publish_retry;
+
+        let ordered = self.sources.is_ordered(&item.source);
+        let mut outcome = Outcome::Published;
+        if ordered {
+            // The spool is drained in order but not tracked per source, so any backlog counts
+            let spooled = self.spool.as_ref().is_some_and(|spool| spool.pending() > 0);
+            let buffered = self
+                .republish
+                .as_ref()
+                .is_some_and(|r| r.contains_source(&item.source));
+            if buffered && republish.is_none() {
+                return Err(AppError::NatsConnectionError(format!(
+                    "Earlier messages of ordered source {} are waiting to be republished",
+                    item.source
+                )));
+            }
+            if spooled || buffered {
+                outcome = Outcome::Spooled;
+            }
+        }
+        let mut acks = Vec::new();
+        let mut first = first.into_iter();
+
+        for (headers, payload) in messages {
+            let attempted = first.next();
+
+            // Once one message is spooled the rest follow, keeping the item's messages in order
+            if outcome == Outcome::Published {
+                let published = match attempted {
+                    Some(result) => {
+                        self.bus
+                            .retry_publish(subject, &headers, payload.clone(), policy, result)
+                            .await
+                    }
+                    None => {
+                        self.bus
+                            .publish_with_retry(subject, &headers, payload.clone(), policy)
+                            .await
+                    }
+                };
+                match published {
+                    Ok(ack) => {
+                        acks.extend(ack);
+                        self.count_usage(item, &payload);
+                        continue;
+                    }
+                    // Spooling would only defer the failure until the stream is created
+                    Err(e @ AppError::StreamUnavailable(_)) => return Err(e),
+                    Err(e) if self.spool.is_some() => {
+                        throttled!(warn!(
+                            "Spooling item {} after publish failure: {}",
+                            item.id, e
+                        ));
+                        outcome = Outcome::Spooled;
+                    }
+                    Err(e) if republish.is_some() => {
+                        throttled!(warn!(
+                            "Buffering item {} until NATS reconnects: {}",
+                            item.id, e
+                        ));
+                        outcome = Outcome::Spooled;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            let mut headers = headers;
+            telemetry::sample_failure(&mut headers);
+            if let Some(spool) = &self.spool {
+                spool
+                    .append(Some(&item.source), subject, &headers, &payload)
+                    .await?;
+            } else if let Some(republish) = republish {
+                // Jumping the line would put an ordered source's messages out of order
+                let priority = !ordered
+                    && self
+                        .config
+                        .republish_priority_content_types
+                        .contains(&item.content_type);
+                if !republish.push(&item.source, subject, &headers, payload.clone(), priority) {
+                    return Err(AppError::NatsConnectionError(format!(
+                        "NATS is unavailable and the republish buffer is full ({} messages)",
+                        republish.len()
+                    )));
+                }
+            }
+            self.count_usage(item, &payload);
+        }
+
+        Ok((outcome, acks))
+    }
+
+    /// Charge a message accepted for delivery to its source's tenant
+    fn count_usage(&self, item: &RawData, payload: &[u8]) {
+        if let Some(usage) = &self.usage {
+            let tenant = self.sources.tenant(&item.source);
+            usage.record(tenant.as_deref(), &item.source, 1, payload.len() as u64);
+        }
+        summary::record_bytes(payload.len());
+    }
+}
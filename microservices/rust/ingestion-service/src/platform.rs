@@ -0,0 +1,42 @@
+//! Operating system specifics, kept here so the rest of the service builds unchanged on
+//! Unix, Windows and static musl targets.
+
+use tracing::{info, warn};
+
+/// Resolve once the process is asked to stop.
+///
+/// Ctrl-C stops the service everywhere. On Unix, so does SIGTERM, which is what container
+/// runtimes and service managers send.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received Ctrl-C, shutting down"),
+        _ = terminate() => info!("Received SIGTERM, shutting down"),
+    }
+}
+
+#[cfg(unix)]
+async fn terminate() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            sigterm.recv().await;
+        }
+        Err(e) => {
+            warn!("Failed to listen for SIGTERM: {}", e);
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminate() {
+    std::future::pending::<()>().await;
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+#[cfg(all(target_os = "linux", feature = "profiling"))]
+mod profiler;
+
+#[cfg(all(target_os = "linux", feature = "profiling"))]
+pub use profiler::{cpu_profile, heap_profile};
+
+/// Settings for CPU and heap profiles taken on demand through the admin API
+#[derive(Debug, Clone)]
+pub struct ProfilingConfig {
+    /// Whether the profile endpoints are exposed and allocations are sampled
+    pub enabled: bool,
+
+    /// Longest CPU profile that can be requested
+    #[cfg_attr(not(all(target_os = "linux", feature = "profiling")), allow(dead_code))]
+    pub max_duration: Duration,
+
+    /// CPU samples taken per second
+    #[cfg_attr(not(all(target_os = "linux", feature = "profiling")), allow(dead_code))]
+    pub frequency: i32,
+}
+
+/// Start sampling allocations when profiling is enabled, so heap profiles cover what was
+/// allocated since startup
+pub async fn start(config: &ProfilingConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    #[cfg(all(target_os = "linux", feature = "profiling"))]
+    {
+        profiler::activate_heap_sampling().await;
+        tracing::info!(
+            "Profiling enabled, CPU profiles of up to {:?} at {} Hz",
+            config.max_duration,
+            config.frequency
+        );
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "profiling")))]
+    tracing::warn!("PROFILING_ENABLED needs a Linux build with the profiling feature, profiles are unavailable");
+}
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Extension, Query},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use pprof::protos::Message;
+use pprof::ProfilerGuardBuilder;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::{info, instrument};
+
+use crate::config::AppConfig;
+use crate::error::{AppError, Result};
+
+/// Allocations go through jemalloc, whose sampling the heap profiles are built from
+#[global_allocator]
+static ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+/// Sampling is compiled in but left inactive until profiling is enabled; one allocation is
+/// sampled per 512 KiB on average
+#[allow(non_upper_case_globals)]
+#[export_name = "malloc_conf"]
+pub static malloc_conf: &[u8] = b"prof:true,prof_active:false,lg_prof_sample:19\0";
+
+/// Seconds a CPU profile lasts when the request does not say
+const DEFAULT_CPU_SECONDS: u64 = 30;
+
+/// Frames of the signal handler and runtime that would otherwise top every sample
+const BLOCKLIST: &[&str] = &["libc", "libgcc", "pthread", "vdso"];
+
+/// Held while a CPU profile is taken, as the profiler samples the whole process
+static CPU_PROFILE: Mutex<()> = Mutex::const_new(());
+
+/// Query parameters for CPU profiles
+#[derive(Debug, Deserialize)]
+pub struct CpuProfileParams {
+    /// Seconds to sample for
+    pub seconds: Option<u64>,
+
+    /// `pprof` (default) or `flamegraph`
+    pub format: Option<String>,
+}
+
+/// Query parameters for heap profiles
+#[derive(Debug, Deserialize)]
+pub struct HeapProfileParams {
+    /// `pprof` (default) or `flamegraph`
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ProfileFormat {
+    Pprof,
+    Flamegraph,
+}
+
+impl ProfileFormat {
+    fn parse(format: Option<&str>) -> Result<Self> {
+        match format.unwrap_or("pprof") {
+            "pprof" => Ok(Self::Pprof),
+            "flamegraph" => Ok(Self::Flamegraph),
+            other => Err(AppError::ValidationError(format!(
+                "Unknown profile format {}",
+                other
+            ))),
+        }
+    }
+}
+
+pub(super) async fn activate_heap_sampling() {
+    jemalloc_pprof::activate_jemalloc_profiling().await;
+}
+
+/// Sample the CPU for a while, giving a pprof profile or an SVG flame graph
+#[instrument(skip(config))]
+pub async fn cpu_profile(
+    Extension(config): Extension<Arc<AppConfig>>,
+    Query(params): Query<CpuProfileParams>,
+) -> Result<Response> {
+    let format = ProfileFormat::parse(params.format.as_deref())?;
+    let duration = Duration::from_secs(params.seconds.unwrap_or(DEFAULT_CPU_SECONDS).max(1));
+    if duration > config.profiling.max_duration {
+        return Err(AppError::ValidationError(format!(
+            "CPU profiles last at most {} seconds",
+            config.profiling.max_duration.as_secs()
+        )));
+    }
+
+    let Ok(_profiling) = CPU_PROFILE.try_lock() else {
+        return Err(AppError::RateLimited(
+            "A CPU profile is already being taken".to_string(),
+        ));
+    };
+
+    info!("Taking a CPU profile for {:?}", duration);
+
+    let guard = ProfilerGuardBuilder::default()
+        .frequency(config.profiling.frequency)
+        .blocklist(BLOCKLIST)
+        .build()
+        .map_err(|e| AppError::InternalError(format!("Failed to start the CPU profiler: {}", e)))?;
+    tokio::time::sleep(duration).await;
+
+    // Symbolizing takes a while, and the profiler keeps sampling until the guard is dropped
+    let body = tokio::task::spawn_blocking(move || {
+        let report = guard.report().build().map_err(|e| {
+            AppError::InternalError(format!("Failed to build the CPU profile: {}", e))
+        })?;
+        drop(guard);
+
+        let mut body = Vec::new();
+        match format {
+            ProfileFormat::Pprof => report
+                .pprof()
+                .map_err(|e| {
+                    AppError::InternalError(format!("Failed to encode the CPU profile: {}", e))
+                })?
+                .encode(&mut body)
+                .map_err(|e| {
+                    AppError::InternalError(format!("Failed to encode the CPU profile: {}", e))
+                })?,
+            // A flame graph of no samples would be an empty document
+            ProfileFormat::Flamegraph if report.data.is_empty() => {
+                return Err(AppError::NotFound(
+                    "No CPU samples were taken, the service was idle".to_string(),
+                ));
+            }
+            ProfileFormat::Flamegraph => report.flamegraph(&mut body).map_err(|e| {
+                AppError::InternalError(format!("Failed to draw the CPU flame graph: {}", e))
+            })?,
+        }
+        Ok::<_, AppError>(body)
+    })
+    .await
+    .map_err(|e| AppError::InternalError(format!("CPU profile task failed: {}", e)))??;
+
+    Ok(profile_response(format, "cpu.pb", body))
+}
+
+/// Sampled allocations still in use, giving a gzipped pprof profile or an SVG flame graph
+#[instrument]
+pub async fn heap_profile(Query(params): Query<HeapProfileParams>) -> Result<Response> {
+    let format = ProfileFormat::parse(params.format.as_deref())?;
+    let Some(ctl) = jemalloc_pprof::PROF_CTL.as_ref().cloned() else {
+        return Err(AppError::InternalError(
+            "Heap profiling is not available".to_string(),
+        ));
+    };
+
+    info!("Taking a heap profile");
+
+    let body = tokio::task::spawn_blocking(move || {
+        let mut ctl = ctl.blocking_lock();
+        if !ctl.activated() {
+            return Err(AppError::InternalError(
+                "Heap sampling is not active".to_string(),
+            ));
+        }
+        match format {
+            ProfileFormat::Pprof => ctl.dump_pprof(),
+            ProfileFormat::Flamegraph => ctl.dump_flamegraph(),
+        }
+        .map_err(|e| AppError::InternalError(format!("Failed to dump the heap profile: {}", e)))
+    })
+    .await
+    .map_err(|e| AppError::InternalError(format!("Heap profile task failed: {}", e)))??;
+
+    Ok(profile_response(format, "heap.pb.gz", body))
+}
+
+fn profile_response(format: ProfileFormat, filename: &str, body: Vec<u8>) -> Response {
+    match format {
+        ProfileFormat::Pprof => (
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", filename),
+                ),
+            ],
+            body,
+        )
+            .into_response(),
+        ProfileFormat::Flamegraph => {
+            ([(header::CONTENT_TYPE, "image/svg+xml".to_string())], body).into_response()
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Json, Query},
+    http::StatusCode,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, instrument, warn};
+use uuid::Uuid;
+
+use crate::config::{AppConfig, Secret};
+use crate::error::{AppError, Result};
+use crate::models::{IngestResponse, RawData};
+use crate::pipeline::Pipeline;
+
+/// Settings for the Google Pub/Sub push endpoint
+#[derive(Debug, Clone)]
+pub struct PubSubConfig {
+    /// Token the push subscription appends to the endpoint URL as `?token=`
+    pub verification_token: Secret,
+
+    /// Source every item from the subscription must have; any when unset
+    pub source: Option<String>,
+
+    /// Content type every item from the subscription must have; any when unset
+    pub content_type: Option<String>,
+}
+
+/// Pub/Sub push request envelope
+#[derive(Debug, Deserialize)]
+pub struct PushRequest {
+    pub message: PushMessage,
+    pub subscription: String,
+}
+
+/// Message delivered in a push request
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushMessage {
+    /// Base64-encoded message body
+    #[serde(default)]
+    pub data: Option<String>,
+
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
+
+    pub message_id: String,
+}
+
+/// Query parameters of a push request
+#[derive(Debug, Deserialize)]
+pub struct PushParams {
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Receive a Google Pub/Sub push delivery.
+///
+/// A body that is a full `RawData` item is ingested as is; otherwise `source` and
+/// `content_type` are taken from the message attributes, or the configured ones, and the
+/// body becomes the payload. Items whose source or content type differs from the configured
+/// one are rejected either way.
+#[instrument(skip_all)]
+pub async fn pubsub_push(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    Query(params): Query<PushParams>,
+    Json(request): Json<PushRequest>,
+) -> Result<(StatusCode, Json<IngestResponse>)> {
+    let pubsub = config
+        .pubsub
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Pub/Sub push is not configured".to_string()))?;
+
+    if !params
+        .token
+        .is_some_and(|token| pubsub.verification_token.matches(&token))
+    {
+        warn!(
+            "Rejected Pub/Sub push from {} with an invalid token",
+            request.subscription
+        );
+        return Err(AppError::PolicyViolation(
+            "Invalid Pub/Sub verification token".to_string(),
+        ));
+    }
+
+    let message = request.message;
+    let data = STANDARD
+        .decode(message.data.as_deref().unwrap_or_default())
+        .map_err(|e| AppError::ValidationError(format!("Invalid base64 message data: {}", e)))?;
+
+    // Pub/Sub delivers at least once, so the item ID is derived from the message ID
+    let id = Uuid::new_v5(
+        &Uuid::NAMESPACE_OID,
+        format!("pubsub:{}", message.message_id).as_bytes(),
+    );
+
+    let body = serde_json::from_slice::<Value>(&data)
+        .unwrap_or_else(|_| json!({ "text": String::from_utf8_lossy(&data) }));
+
+    let mut item = match serde_json::from_value::<RawData>(body.clone()) {
+        Ok(mut item) => {
+            item.id = id;
+            item
+        }
+        Err(_) => {
+            let attribute = |name: &str, configured: &Option<String>| {
+                message
+                    .attributes
+                    .get(name)
+                    .or(configured.as_ref())
+                    .cloned()
+                    .ok_or_else(|| {
+                        AppError::ValidationError(format!("Message has no {} attribute", name))
+                    })
+            };
+            RawData::builder()
+                .id(id)
+                .source(attribute("source", &pubsub.source)?)
+                .content_type(attribute("content_type", &pubsub.content_type)?)
+                .payload(body)
+                .metadata(json!({
+                    "pubsub": {
+                        "subscription": request.subscription,
+                        "message_id": message.message_id,
+                        "attributes": message.attributes,
+                    }
+                }))
+                .build()?
+        }
+    };
+
+    enforce("source", &item.source, &pubsub.source)?;
+    enforce("content type", &item.content_type, &pubsub.content_type)?;
+
+    let acks = pipeline.process(&mut item).await?;
+
+    debug!(
+        "Ingested Pub/Sub message {} as {}",
+        message.message_id, item.id
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(IngestResponse::published(item.id, &acks)),
+    ))
+}
+
+/// Reject an item whose field differs from the value configured for the subscription
+fn enforce(field: &str, value: &str, configured: &Option<String>) -> Result<()> {
+    match configured {
+        Some(configured) if configured != value => Err(AppError::PolicyViolation(format!(
+            "Pub/Sub {} {} does not match the subscription's {}",
+            field, value, configured
+        ))),
+        _ => Ok(()),
+    }
+}
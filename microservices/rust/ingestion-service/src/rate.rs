@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+
+/// What happens to messages over a content type's rate cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatePolicy {
+    /// Refuse the item with `429`
+    Reject,
+
+    /// Hold the item until the cap allows it, up to the maximum delay
+    Delay,
+}
+
+impl RatePolicy {
+    /// Parse a policy name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "reject" => Some(Self::Reject),
+            "delay" => Some(Self::Delay),
+            _ => None,
+        }
+    }
+}
+
+/// Settings for capping the publish rate of content types
+#[derive(Debug, Clone)]
+pub struct RateCapConfig {
+    /// Messages per second allowed for each capped content type
+    pub content_types: Vec<(String, f64)>,
+
+    /// What happens to messages over a cap
+    pub policy: RatePolicy,
+
+    /// Longest an item is held under the `delay` policy before it is refused instead
+    pub max_delay: Duration,
+}
+
+/// Schedule of one content type, as the time its next message may be sent once any burst
+/// allowance is used up
+struct Schedule {
+    /// Messages per second
+    rate: f64,
+
+    /// Time between messages at the capped rate
+    interval: Duration,
+
+    /// How far ahead of schedule messages may be sent, one second's worth of messages
+    burst: Duration,
+
+    next: Instant,
+}
+
+/// Caps the rate messages of a content type are published at, for downstream consumers
+/// known to fall over beyond a certain throughput.
+///
+/// Caps apply to every producer together, in addition to per-source quotas, and count
+/// messages rather than items, so each chunk of a chunked item counts. A content type may
+/// send a burst of up to one second's worth of messages after being idle. Caps are kept per
+/// replica.
+pub struct RateCaps {
+    policy: RatePolicy,
+    max_delay: Duration,
+    schedules: Mutex<HashMap<String, Schedule>>,
+}
+
+impl RateCaps {
+    /// Create the caps, each content type starting with its full burst allowance
+    pub fn new(config: &RateCapConfig) -> Self {
+        let now = Instant::now();
+        let schedules = config
+            .content_types
+            .iter()
+            .map(|(content_type, rate)| {
+                let interval = Duration::from_secs_f64(1.0 / rate);
+                let schedule = Schedule {
+                    rate: *rate,
+                    interval,
+                    burst: interval.mul_f64((rate - 1.0).max(0.0)),
+                    next: now,
+                };
+                (content_type.clone(), schedule)
+            })
+            .collect();
+
+        Self {
+            policy: config.policy,
+            max_delay: config.max_delay,
+            schedules: Mutex::new(schedules),
+        }
+    }
+
+    /// Wait until `messages` messages of a content type may be published, or refuse them
+    /// when the policy or the maximum delay does not allow waiting.
+    ///
+    /// Returns how long the messages were held.
+    pub async fn acquire(&self, content_type: &str, messages: usize) -> Result<Duration> {
+        let wait = {
+            let mut schedules = self.schedules.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(schedule) = schedules.get_mut(content_type) else {
+                return Ok(Duration::ZERO);
+            };
+
+            let now = Instant::now();
+            let next = schedule.next.max(now);
+            let wait = next.saturating_duration_since(now + schedule.burst);
+            let limit = match self.policy {
+                RatePolicy::Reject => Duration::ZERO,
+                RatePolicy::Delay => self.max_delay,
+            };
+            if wait > limit {
+                throttled!(warn!(
+                    "Content type {} is over its rate cap of {} messages per second",
+                    content_type, schedule.rate
+                ));
+                return Err(AppError::RateLimited(format!(
+                    "Content type {} is over its rate cap of {} messages per second, retry in {} ms",
+                    content_type,
+                    schedule.rate,
+                    wait.as_millis().max(1)
+                )));
+            }
+
+            // Reserved before waiting, so concurrent items queue up behind each other
+            schedule.next = next + schedule.interval * messages as u32;
+            wait
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(wait)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(rate: f64, policy: RatePolicy, max_delay: Duration) -> RateCaps {
+        RateCaps::new(&RateCapConfig {
+            content_types: vec![("news_article".to_string(), rate)],
+            policy,
+            max_delay,
+        })
+    }
+
+    #[tokio::test]
+    async fn allows_a_burst_of_one_seconds_worth_then_rejects() {
+        let caps = caps(10.0, RatePolicy::Reject, Duration::ZERO);
+
+        for _ in 0..10 {
+            assert_eq!(
+                caps.acquire("news_article", 1).await.unwrap(),
+                Duration::ZERO
+            );
+        }
+        match caps.acquire("news_article", 1).await {
+            Err(AppError::RateLimited(message)) => assert!(message.contains("retry in")),
+            other => panic!("expected the cap to reject, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_every_message_of_an_item() {
+        let caps = caps(10.0, RatePolicy::Reject, Duration::ZERO);
+
+        caps.acquire("news_article", 10).await.unwrap();
+        assert!(caps.acquire("news_article", 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refills_at_the_capped_rate() {
+        let caps = caps(10.0, RatePolicy::Reject, Duration::ZERO);
+
+        caps.acquire("news_article", 10).await.unwrap();
+        assert!(caps.acquire("news_article", 1).await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        caps.acquire("news_article", 1).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rates_below_one_per_second_have_no_burst() {
+        let caps = caps(0.5, RatePolicy::Reject, Duration::ZERO);
+
+        caps.acquire("news_article", 1).await.unwrap();
+        assert!(caps.acquire("news_article", 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delay_policy_holds_items_over_the_cap() {
+        let caps = caps(10.0, RatePolicy::Delay, Duration::from_secs(1));
+
+        caps.acquire("news_article", 10).await.unwrap();
+        let started = Instant::now();
+        let waited = caps.acquire("news_article", 1).await.unwrap();
+        assert!(waited > Duration::from_millis(50), "held for {:?}", waited);
+        assert!(started.elapsed() >= waited);
+    }
+
+    #[tokio::test]
+    async fn delay_policy_rejects_beyond_the_maximum_delay() {
+        let caps = caps(10.0, RatePolicy::Delay, Duration::from_millis(50));
+
+        caps.acquire("news_article", 10).await.unwrap();
+        assert!(matches!(
+            caps.acquire("news_article", 1).await,
+            Err(AppError::RateLimited(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn uncapped_content_types_are_not_held() {
+        let caps = caps(0.5, RatePolicy::Reject, Duration::ZERO);
+
+        for _ in 0..100 {
+            assert_eq!(caps.acquire("event", 1).await.unwrap(), Duration::ZERO);
+        }
+    }
+}
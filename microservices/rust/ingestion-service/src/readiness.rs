@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+use crate::models::{DependencyCounters, DependencyStatus};
+
+/// How often watched connections are checked
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// State of a dependency as last observed
+struct Observed {
+    /// Whether the last call succeeded, none before the first
+    up: Option<bool>,
+    error: Option<String>,
+    since: DateTime<Utc>,
+    since_instant: Instant,
+    outages: u64,
+    downtime: Duration,
+}
+
+/// A backend the service talks to, whose calls report whether it is reachable
+pub struct Dependency {
+    name: String,
+    required: bool,
+    observed: Mutex<Observed>,
+}
+
+impl Dependency {
+    /// Record a successful call
+    pub fn up(&self) {
+        let mut observed = self.observed.lock().unwrap_or_else(|e| e.into_inner());
+        match observed.up {
+            Some(true) => return,
+            Some(false) => {
+                let outage = observed.since_instant.elapsed();
+                observed.downtime += outage;
+                info!("{} is back after {:?}", self.name, outage);
+            }
+            None => {}
+        }
+        observed.up = Some(true);
+        observed.error = None;
+        observed.since = Utc::now();
+        observed.since_instant = Instant::now();
+    }
+
+    /// Record a failed call, keeping the latest error
+    pub fn down(&self, error: impl Display) {
+        let mut observed = self.observed.lock().unwrap_or_else(|e| e.into_inner());
+        observed.error = Some(error.to_string());
+        if observed.up == Some(false) {
+            return;
+        }
+        warn!(
+            "{} is down, readiness is {}: {}",
+            self.name,
+            if self.required { "failing" } else { "degraded" },
+            error
+        );
+        observed.up = Some(false);
+        observed.outages += 1;
+        observed.since = Utc::now();
+        observed.since_instant = Instant::now();
+    }
+
+    /// Record the outcome of a call
+    pub fn record<T, E: Display>(&self, result: &std::result::Result<T, E>) {
+        match result {
+            Ok(_) => self.up(),
+            Err(e) => self.down(e),
+        }
+    }
+
+    fn status(&self) -> DependencyStatus {
+        let observed = self.observed.lock().unwrap_or_else(|e| e.into_inner());
+        DependencyStatus {
+            status: match observed.up {
+                Some(true) => "up",
+                Some(false) => "down",
+                None => "unknown",
+            }
+            .to_string(),
+            required: self.required,
+            error: observed.error.clone(),
+            since: observed.since,
+        }
+    }
+
+    fn counters(&self) -> DependencyCounters {
+        let observed = self.observed.lock().unwrap_or_else(|e| e.into_inner());
+        let ongoing = match observed.up {
+            Some(false) => observed.since_instant.elapsed(),
+            _ => Duration::ZERO,
+        };
+        DependencyCounters {
+            up: observed.up != Some(false),
+            outages: observed.outages,
+            downtime_seconds: (observed.downtime + ongoing).as_secs_f64(),
+        }
+    }
+}
+
+/// Backends the service depends on, and which of them it cannot serve traffic without.
+///
+/// Components register the backends they use and report the outcome of their calls, or have
+/// a connection watched, so the readiness check aggregates the latest state without calling
+/// any backend itself. A required dependency that is down, or not yet reached, fails the
+/// check; an optional one only degrades it.
+pub struct Readiness {
+    /// Dependencies named as required, replacing each dependency's default
+    configured: Option<Vec<String>>,
+    dependencies: Mutex<BTreeMap<String, Arc<Dependency>>>,
+}
+
+impl Readiness {
+    /// Create the registry; `required` overrides which dependencies are required
+    pub fn new(required: Option<Vec<String>>) -> Self {
+        Self {
+            configured: required,
+            dependencies: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Register a dependency, required by default when `required` is set
+    pub fn register(&self, name: &str, required: bool) -> Arc<Dependency> {
+        let required = match &self.configured {
+            Some(configured) => configured.iter().any(|configured| configured == name),
+            None => required,
+        };
+        let dependency = Arc::new(Dependency {
+            name: name.to_string(),
+            required,
+            observed: Mutex::new(Observed {
+                up: None,
+                error: None,
+                since: Utc::now(),
+                since_instant: Instant::now(),
+                outages: 0,
+                downtime: Duration::ZERO,
+            }),
+        });
+        self.dependencies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), dependency.clone());
+        dependency
+    }
+
+    /// Register a dependency whose connection state is checked every second
+    pub fn watch(&self, name: &str, required: bool, connected: impl Fn() -> bool + Send + 'static) {
+        let dependency = self.register(name, required);
+        tokio::spawn(async move {
+            loop {
+                if connected() {
+                    dependency.up();
+                } else {
+                    dependency.down("disconnected");
+                }
+                tokio::time::sleep(WATCH_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Warn about required dependencies that were configured but never registered, and log
+    /// which dependencies readiness requires
+    pub fn check_configured(&self) {
+        let dependencies = self.dependencies.lock().unwrap_or_else(|e| e.into_inner());
+        for name in self.configured.iter().flatten() {
+            if !dependencies.contains_key(name) {
+                warn!(
+                    "Ignoring READINESS_REQUIRED entry {}, no such dependency is in use",
+                    name
+                );
+            }
+        }
+        let required: Vec<&str> = dependencies
+            .values()
+            .filter(|dependency| dependency.required)
+            .map(|dependency| dependency.name.as_str())
+            .collect();
+        if !dependencies.is_empty() {
+            info!(
+                "Readiness depends on {}, requiring {}",
+                dependencies.keys().cloned().collect::<Vec<_>>().join(", "),
+                if required.is_empty() {
+                    "none".to_string()
+                } else {
+                    required.join(", ")
+                }
+            );
+        }
+    }
+
+    /// State of every dependency
+    pub fn statuses(&self) -> BTreeMap<String, DependencyStatus> {
+        self.dependencies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, dependency)| (name.clone(), dependency.status()))
+            .collect()
+    }
+
+    /// Availability counters of every dependency
+    pub fn counters(&self) -> BTreeMap<String, DependencyCounters> {
+        self.dependencies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, dependency)| (name.clone(), dependency.counters()))
+            .collect()
+    }
+}
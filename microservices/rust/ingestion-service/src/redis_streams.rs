@@ -0,0 +1,423 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::async_trait;
+use bytes::Bytes;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::streams::StreamReadReply;
+use redis::{Client, Cmd, RedisError, Value};
+use tokio::sync::Notify;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::bus::{InFlight, MessageBus, OutgoingMessage};
+use crate::config::Secret;
+use crate::encoding::{CONTENT_TYPE_HEADER, SOURCE_HEADER};
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::NatsStats;
+use crate::nats::{Headers, PublishAck};
+
+/// Field of a stream entry holding the message body; the other fields are its headers
+pub const PAYLOAD_FIELD: &str = "payload";
+
+/// Field of a probe naming the stream its reply is expected on
+pub const REPLY_TO_FIELD: &str = "reply_to";
+
+/// How often the connection is checked
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Settings for publishing to Redis Streams instead of NATS
+#[derive(Debug, Clone)]
+pub struct RedisStreamsConfig {
+    /// Redis URL, e.g. `redis://:password@redis:6379/0`
+    pub url: Secret,
+
+    /// Key of the stream messages are added to, with `{subject}`, `{source}` and
+    /// `{content_type}` placeholders
+    pub stream_key: String,
+
+    /// Entries each stream is trimmed to, none to keep every entry
+    pub max_len: Option<u64>,
+
+    /// Age past which entries are trimmed, instead of by count
+    pub max_age: Option<Duration>,
+
+    /// Whether to trim exactly, rather than let Redis trim whole nodes for speed
+    pub exact_trim: bool,
+
+    /// Longest wait for Redis to answer a command
+    pub response_timeout: Duration,
+
+    /// Longest wait between reconnection attempts
+    pub max_reconnect_delay: Duration,
+}
+
+impl RedisStreamsConfig {
+    /// Check the URL and stream key, so a typo is reported as such rather than as an
+    /// unreachable server
+    pub fn check(&self) -> Result<()> {
+        self.client()?;
+        if self.stream_key.is_empty() {
+            return Err(AppError::ValidationError(
+                "REDIS_STREAM_KEY cannot be empty".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn client(&self) -> Result<Client> {
+        Client::open(self.url.expose())
+            .map_err(|e| AppError::ValidationError(format!("Invalid REDIS_URL: {}", e)))
+    }
+}
+
+/// [`MessageBus`] adding messages to Redis Streams, so a small single-node deployment can
+/// run without a NATS cluster.
+///
+/// Each message is one `XADD` entry, with its headers as fields and its body in the
+/// `payload` field, trimmed by length or age as it is added. Ingested items go to the
+/// stream rendered from their source and content type, read from the `Ingest-Source` and
+/// `Ingest-Content-Type` headers; messages without those headers, such as retractions and
+/// processor probes, use their NATS subject for both `{source}` and `{content_type}`.
+/// Failures use the NATS error variants so they map to the same statuses. A lost
+/// connection is re-established in the background. Stream keys are prefixed for simulation
+/// and namespacing like NATS subjects.
+pub struct RedisStreamsBus {
+    config: RedisStreamsConfig,
+    key_prefix: String,
+    client: Client,
+    connection: ConnectionManager,
+    connected: AtomicBool,
+    reconnected: Notify,
+    reconnects: AtomicU64,
+    pending_bytes: AtomicU64,
+}
+
+impl RedisStreamsBus {
+    /// Connect to Redis, then keep checking the connection in the background. Stream keys
+    /// are prefixed with `key_prefix`, as from [`subject_prefix`](crate::nats::subject_prefix).
+    pub async fn connect(config: RedisStreamsConfig, key_prefix: String) -> Result<Arc<Self>> {
+        let client = config.client()?;
+        info!(
+            "Connecting to Redis at {}",
+            client.get_connection_info().addr()
+        );
+
+        let manager_config = ConnectionManagerConfig::new()
+            .set_max_delay(config.max_reconnect_delay)
+            .set_number_of_retries(1)
+            .set_connection_timeout(Some(config.response_timeout))
+            .set_response_timeout(Some(config.response_timeout));
+        let mut connection = ConnectionManager::new_with_config(client.clone(), manager_config)
+            .await
+            .map_err(|e| AppError::NatsConnectionError(format!("Redis unreachable: {}", e)))?;
+        redis::cmd("PING")
+            .query_async::<()>(&mut connection)
+            .await
+            .map_err(|e| AppError::NatsConnectionError(format!("Redis unreachable: {}", e)))?;
+
+        info!(
+            "Publishing to Redis stream {}{}",
+            key_prefix, config.stream_key
+        );
+
+        let bus = Arc::new(Self {
+            config,
+            key_prefix,
+            client,
+            connection,
+            connected: AtomicBool::new(true),
+            reconnected: Notify::new(),
+            reconnects: AtomicU64::new(0),
+            pending_bytes: AtomicU64::new(0),
+        });
+        tokio::spawn(bus.clone().supervise());
+
+        Ok(bus)
+    }
+
+    /// Ping Redis, tracking when the connection drops and comes back; the connection manager
+    /// reconnects on its own once a command fails
+    async fn supervise(self: Arc<Self>) {
+        loop {
+            tokio::time::sleep(PING_INTERVAL).await;
+            match self.ping().await {
+                Ok(_) => {
+                    if !self.connected.swap(true, Ordering::Relaxed) {
+                        self.reconnects.fetch_add(1, Ordering::Relaxed);
+                        info!("Reconnected to Redis");
+                        self.reconnected.notify_one();
+                    }
+                }
+                Err(e) => {
+                    if self.connected.swap(false, Ordering::Relaxed) {
+                        warn!("Disconnected from Redis, reconnecting: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Round trip time of a `PING`
+    async fn ping(&self) -> std::result::Result<Duration, RedisError> {
+        let started = Instant::now();
+        redis::cmd("PING")
+            .query_async::<()>(&mut self.connection.clone())
+            .await?;
+        Ok(started.elapsed())
+    }
+
+    /// Fail fast while Redis is unreachable, so the message is spooled or buffered
+    fn check_connected(&self) -> Result<()> {
+        if self.connected.load(Ordering::Relaxed) {
+            Ok(())
+        } else {
+            Err(AppError::NatsConnectionError(
+                "Redis is disconnected".to_string(),
+            ))
+        }
+    }
+
+    /// Key of the stream a message is added to
+    fn stream_key(&self, subject: &str, headers: &Headers) -> String {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        let source = header(SOURCE_HEADER).unwrap_or(subject);
+        let content_type = header(CONTENT_TYPE_HEADER).unwrap_or(subject);
+
+        let key = self
+            .config
+            .stream_key
+            .replace("{subject}", subject)
+            .replace("{source}", source)
+            .replace("{content_type}", content_type);
+        format!("{}{}", self.key_prefix, key)
+    }
+
+    /// `XADD` of a message, trimming the stream as configured
+    fn xadd(&self, key: &str, headers: &Headers, payload: &[u8]) -> Cmd {
+        let mut cmd = redis::cmd("XADD");
+        cmd.arg(key);
+
+        let threshold = if self.config.exact_trim { "=" } else { "~" };
+        if let Some(max_age) = self.config.max_age {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            cmd.arg("MINID")
+                .arg(threshold)
+                .arg(now.saturating_sub(max_age).as_millis() as u64);
+        } else if let Some(max_len) = self.config.max_len {
+            cmd.arg("MAXLEN").arg(threshold).arg(max_len);
+        }
+
+        cmd.arg("*");
+        for (name, value) in headers.iter().filter(|(name, _)| name != PAYLOAD_FIELD) {
+            cmd.arg(name).arg(value);
+        }
+        cmd.arg(PAYLOAD_FIELD).arg(payload);
+        cmd
+    }
+
+    /// Whether a command failed for want of a connection, marking the connection down if so
+    fn lost_connection(&self, e: &RedisError) -> bool {
+        let lost = e.is_io_error()
+            || e.is_connection_dropped()
+            || e.is_connection_refusal()
+            || e.is_timeout();
+        if lost {
+            self.connected.store(false, Ordering::Relaxed);
+        }
+        lost
+    }
+
+    /// Error of a failed command, marking the connection down when it was lost
+    fn error(&self, key: &str, e: RedisError) -> AppError {
+        if self.lost_connection(&e) {
+            AppError::NatsConnectionError(format!("Redis unreachable: {}", e))
+        } else {
+            throttled!(error!("Failed to add to Redis stream {}: {}", key, e));
+            AppError::NatsPublishError(format!("Failed to add to Redis stream {}: {}", key, e))
+        }
+    }
+}
+
+#[async_trait]
+impl MessageBus for RedisStreamsBus {
+    #[instrument(skip(self, headers, payload), fields(subject = %subject))]
+    async fn publish(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+    ) -> Result<Option<PublishAck>> {
+        self.check_connected()?;
+        let _in_flight = InFlight::new(&self.pending_bytes, payload.len());
+        let key = self.stream_key(subject, headers);
+
+        debug!("Adding message to Redis stream {}", key);
+
+        self.xadd(&key, headers, &payload)
+            .query_async::<String>(&mut self.connection.clone())
+            .await
+            .map_err(|e| self.error(&key, e))?;
+        Ok(None)
+    }
+
+    /// The whole batch is sent as one pipeline, so it costs one round trip
+    async fn publish_batch(
+        &self,
+        messages: Vec<OutgoingMessage>,
+    ) -> Vec<Result<Option<PublishAck>>> {
+        if !self.is_connected() {
+            return messages
+                .iter()
+                .map(|_| {
+                    Err(AppError::NatsConnectionError(
+                        "Redis is disconnected".to_string(),
+                    ))
+                })
+                .collect();
+        }
+        let bytes = messages.iter().map(|message| message.payload.len()).sum();
+        let _in_flight = InFlight::new(&self.pending_bytes, bytes);
+
+        let keys: Vec<String> = messages
+            .iter()
+            .map(|message| self.stream_key(&message.subject, &message.headers))
+            .collect();
+        let mut pipe = redis::pipe();
+        pipe.ignore_errors();
+        for (message, key) in messages.iter().zip(&keys) {
+            pipe.add_command(self.xadd(key, &message.headers, &message.payload));
+        }
+
+        match pipe
+            .query_async::<Vec<redis::RedisResult<Value>>>(&mut self.connection.clone())
+            .await
+        {
+            Ok(results) => results
+                .into_iter()
+                .zip(&keys)
+                .map(|(result, key)| result.map(|_| None).map_err(|e| self.error(key, e)))
+                .collect(),
+            // The pipeline itself failed, so every message shares its error
+            Err(e) => {
+                let lost = self.lost_connection(&e);
+                keys.iter()
+                    .map(|key| match lost {
+                        true => Err(AppError::NatsConnectionError(format!(
+                            "Redis unreachable: {}",
+                            e
+                        ))),
+                        false => Err(AppError::NatsPublishError(format!(
+                            "Failed to add to Redis stream {}: {}",
+                            key, e
+                        ))),
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The request names a stream of its own in its `reply_to` field, read on a connection
+    /// of its own since the read blocks, and removed afterwards
+    async fn request(
+        &self,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+        timeout: Duration,
+    ) -> Result<Option<Bytes>> {
+        self.check_connected()?;
+        let key = self.stream_key(subject, headers);
+        let reply_key = format!("{}:reply:{}", key, Uuid::new_v4());
+
+        let mut connection = self
+            .client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| self.error(&key, e))?;
+
+        let mut headers = headers.clone();
+        headers.push((REPLY_TO_FIELD.to_string(), reply_key.clone()));
+        self.xadd(&key, &headers, &payload)
+            .query_async::<String>(&mut connection)
+            .await
+            .map_err(|e| self.error(&key, e))?;
+
+        let reply = redis::cmd("XREAD")
+            .arg("COUNT")
+            .arg(1)
+            .arg("BLOCK")
+            .arg(timeout.as_millis().max(1) as u64)
+            .arg("STREAMS")
+            .arg(&reply_key)
+            .arg("0")
+            .query_async::<Option<StreamReadReply>>(&mut connection)
+            .await;
+
+        if let Err(e) = redis::cmd("DEL")
+            .arg(&reply_key)
+            .query_async::<()>(&mut connection)
+            .await
+        {
+            debug!("Failed to remove Redis reply stream {}: {}", reply_key, e);
+        }
+
+        let reply = reply.map_err(|e| self.error(&reply_key, e))?;
+        Ok(reply
+            .and_then(|reply| reply.keys.into_iter().next())
+            .and_then(|stream| stream.ids.into_iter().next())
+            .and_then(|entry| match entry.map.get(PAYLOAD_FIELD) {
+                Some(Value::BulkString(body)) => Some(Bytes::from(body.clone())),
+                Some(Value::SimpleString(body)) => Some(Bytes::from(body.clone())),
+                _ => None,
+            }))
+    }
+
+    async fn health(&self) -> NatsStats {
+        let rtt = if self.is_connected() {
+            self.ping().await.ok()
+        } else {
+            None
+        };
+        NatsStats {
+            state: if rtt.is_some() {
+                "connected"
+            } else {
+                "disconnected"
+            }
+            .to_string(),
+            server_name: None,
+            server_version: None,
+            server_address: Some(self.client.get_connection_info().addr().to_string()),
+            rtt_ms: rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            pending_bytes: self.pending_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    async fn reconnected(&self) {
+        self.reconnected.notified().await
+    }
+
+    fn max_payload(&self) -> Option<usize> {
+        None
+    }
+
+    /// Every publish already waits for Redis to store it, so nothing is left in flight
+    async fn drain(&self) -> Result<()> {
+        Ok(())
+    }
+}
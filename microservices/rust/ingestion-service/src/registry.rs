@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::config::Secret;
+use crate::error::{AppError, Result};
+use crate::http::{self, ProxyConfig};
+use crate::models::RawData;
+use crate::nats::Headers;
+use crate::sources::{
+    SourceManifest, SCHEMA_ID_HEADER, SCHEMA_SUBJECT_HEADER, SCHEMA_VERSION_HEADER,
+};
+
+/// First byte of a Confluent wire format message
+const AVRO_MAGIC_BYTE: u8 = 0;
+
+/// Magic byte and schema ID ahead of the Avro datum
+const AVRO_HEADER_LEN: usize = 5;
+
+/// Request header pinning a producer to a schema version of the content types it sends
+pub const IF_SCHEMA_VERSION_HEADER: &str = "If-Schema-Version";
+
+/// Settings for validating payloads against a Confluent-compatible schema registry
+#[derive(Debug, Clone)]
+pub struct SchemaRegistryConfig {
+    /// Registry base URL, e.g. `http://schema-registry:8081`
+    pub url: String,
+
+    /// Basic auth user
+    pub username: Option<String>,
+
+    /// Basic auth password
+    pub password: Option<Secret>,
+
+    /// Content types validated against the registry
+    pub content_types: Vec<String>,
+
+    /// Appended to the content type to form its subject, following the topic name strategy
+    pub subject_suffix: String,
+
+    /// How long a fetched schema is used before the registry is asked again
+    pub cache_ttl: Duration,
+
+    /// Request timeout for the registry
+    pub timeout: Duration,
+}
+
+/// Schema version as returned by `GET /subjects/{subject}/versions/latest`
+#[derive(Deserialize)]
+struct SchemaVersionResponse {
+    subject: String,
+    id: u64,
+    version: u32,
+    schema: String,
+    /// Absent for Avro, the registry's original schema type
+    #[serde(default, rename = "schemaType")]
+    schema_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompatibilityResponse {
+    is_compatible: bool,
+
+    #[serde(default)]
+    messages: Vec<String>,
+}
+
+/// Compiled form of a registered schema
+enum SchemaValidator {
+    Json(jsonschema::Validator),
+    Avro(apache_avro::Schema),
+}
+
+/// A schema version fetched from the registry
+pub struct RegisteredSchema {
+    id: u64,
+    subject: String,
+    version: u32,
+    validator: SchemaValidator,
+}
+
+impl RegisteredSchema {
+    fn validate(&self, payload: &Value) -> std::result::Result<(), String> {
+        match &self.validator {
+            SchemaValidator::Json(validator) => {
+                validator.validate(payload).map_err(|e| e.to_string())
+            }
+            SchemaValidator::Avro(schema) => apache_avro::types::Value::try_from(payload.clone())
+                .and_then(|value| value.resolve(schema))
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Headers identifying this schema version
+    pub fn headers(&self) -> Headers {
+        vec![
+            (SCHEMA_ID_HEADER.to_string(), self.id.to_string()),
+            (SCHEMA_SUBJECT_HEADER.to_string(), self.subject.clone()),
+            (SCHEMA_VERSION_HEADER.to_string(), self.version.to_string()),
+        ]
+    }
+
+    /// Encode a payload as Avro in the Confluent wire format: a zero magic byte, the
+    /// schema ID as a big-endian 32-bit integer, then the Avro binary datum
+    pub fn encode_avro(&self, payload: &Value) -> Result<Vec<u8>> {
+        let SchemaValidator::Avro(schema) = &self.validator else {
+            return Err(AppError::InternalError(format!(
+                "Subject {} does not hold an Avro schema",
+                self.subject
+            )));
+        };
+        let id = u32::try_from(self.id).map_err(|_| {
+            AppError::InternalError(format!(
+                "Schema ID {} does not fit the wire format",
+                self.id
+            ))
+        })?;
+
+        let mut message = Vec::with_capacity(AVRO_HEADER_LEN + 256);
+        message.push(AVRO_MAGIC_BYTE);
+        message.extend_from_slice(&id.to_be_bytes());
+
+        apache_avro::types::Value::try_from(payload.clone())
+            .and_then(|value| value.resolve(schema))
+            .and_then(|value| {
+                apache_avro::writer::datum::GenericDatumWriter::builder(schema)
+                    .build()?
+                    .write_value(&mut message, value)
+            })
+            .map_err(|e| AppError::InternalError(format!("Avro serialization error: {}", e)))?;
+
+        Ok(message)
+    }
+}
+
+/// Client for a Confluent-compatible schema registry.
+///
+/// Payloads of configured content types are validated against the latest version of their
+/// subject, with JSON Schema and Avro subjects supported. Fetched versions are cached for
+/// `cache_ttl`; while the registry is unreachable, an expired version keeps being used.
+pub struct SchemaRegistryClient {
+    http: reqwest::Client,
+    config: SchemaRegistryConfig,
+    cache: Mutex<HashMap<String, (Instant, Arc<RegisteredSchema>)>>,
+
+    /// Whether a pinned version of a subject is compatible, by the latest version it was
+    /// checked against
+    pinned: Mutex<HashMap<(String, u32), (u32, bool)>>,
+}
+
+impl SchemaRegistryClient {
+    /// Create a new registry client
+    pub fn new(config: SchemaRegistryConfig, proxy: &ProxyConfig) -> Result<Self> {
+        let http = http::client_builder(proxy)?
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| AppError::InternalError(format!("Failed to build HTTP client: {}", e)))?;
+
+        info!(
+            "Validating {} content types against schema registry {}",
+            config.content_types.len(),
+            config.url
+        );
+
+        Ok(Self {
+            http,
+            config,
+            cache: Mutex::new(HashMap::new()),
+            pinned: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Validate an item against its content type's subject.
+    ///
+    /// Returns the schema version used, or none when the content type is not governed by
+    /// the registry.
+    pub async fn validate(&self, item: &RawData) -> Result<Option<Arc<RegisteredSchema>>> {
+        if !self.config.content_types.contains(&item.content_type) {
+            return Ok(None);
+        }
+
+        let subject = format!("{}{}", item.content_type, self.config.subject_suffix);
+        let schema = self.latest(&subject).await?;
+
+        schema.validate(&item.payload).map_err(|e| {
+            AppError::ValidationError(format!(
+                "Payload does not match schema {} version {}: {}",
+                schema.subject, schema.version, e
+            ))
+        })?;
+
+        Ok(Some(schema))
+    }
+
+    /// Check that the local schemas of a manifest are compatible with the latest version
+    /// of the registry subjects they reference, under each subject's compatibility level
+    pub async fn check_manifest(&self, manifest: &SourceManifest) -> Result<()> {
+        for definition in &manifest.sources {
+            let (Some(schema), Some(subject)) = (
+                &definition.schema,
+                definition
+                    .schema_registry
+                    .as_ref()
+                    .and_then(|r| r.subject.as_ref()),
+            ) else {
+                continue;
+            };
+
+            let url = format!(
+                "{}/compatibility/subjects/{}/versions/latest",
+                self.config.url.trim_end_matches('/'),
+                subject
+            );
+            let body = json!({ "schema": schema.to_string(), "schemaType": "JSON" });
+            let response: CompatibilityResponse = self
+                .request(self.http.post(url).json(&body))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(registry_error)?
+                .json()
+                .await
+                .map_err(registry_error)?;
+
+            if !response.is_compatible {
+                return Err(AppError::ValidationError(format!(
+                    "Schema of source {} is incompatible with registry subject {}: {}",
+                    definition.name,
+                    subject,
+                    response.messages.join("; ")
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that a producer pinned to a version of a content type's subject may keep sending:
+    /// the version is the latest, or the registry finds it compatible with the latest under
+    /// the subject's compatibility level. Content types the registry does not govern pass.
+    pub async fn check_pinned(&self, content_type: &str, version: u32) -> Result<()> {
+        if !self
+            .config
+            .content_types
+            .contains(&content_type.to_string())
+        {
+            return Ok(());
+        }
+
+        let subject = format!("{}{}", content_type, self.config.subject_suffix);
+        let latest = self.latest(&subject).await?.version;
+        if version == latest {
+            return Ok(());
+        }
+        if version > latest {
+            return Err(AppError::PreconditionFailed(format!(
+                "Schema {} has no version {}, the latest is {}",
+                subject, version, latest
+            )));
+        }
+
+        let key = (subject.clone(), version);
+        let cached = self
+            .pinned
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+            .copied();
+        let compatible = match cached {
+            Some((checked_against, compatible)) if checked_against == latest => compatible,
+            _ => {
+                let compatible = self.is_compatible(&subject, version).await?;
+                self.pinned
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(key, (latest, compatible));
+                compatible
+            }
+        };
+
+        if !compatible {
+            return Err(AppError::PreconditionFailed(format!(
+                "Schema {} version {} is incompatible with the latest version {}; upgrade and send {}: {}",
+                subject, version, latest, IF_SCHEMA_VERSION_HEADER, latest
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Whether a version of a subject is compatible with its latest version
+    async fn is_compatible(&self, subject: &str, version: u32) -> Result<bool> {
+        let base = self.config.url.trim_end_matches('/');
+        let response = self
+            .request(self.http.get(format!(
+                "{}/subjects/{}/versions/{}",
+                base, subject, version
+            )))
+            .send()
+            .await
+            .map_err(registry_error)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(AppError::PreconditionFailed(format!(
+                "Schema {} has no version {}",
+                subject, version
+            )));
+        }
+        let pinned: SchemaVersionResponse = response
+            .error_for_status()
+            .map_err(registry_error)?
+            .json()
+            .await
+            .map_err(registry_error)?;
+
+        let body = json!({
+            "schema": pinned.schema,
+            "schemaType": pinned.schema_type.as_deref().unwrap_or("AVRO"),
+        });
+        let response: CompatibilityResponse = self
+            .request(
+                self.http
+                    .post(format!(
+                        "{}/compatibility/subjects/{}/versions/latest",
+                        base, subject
+                    ))
+                    .json(&body),
+            )
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(registry_error)?
+            .json()
+            .await
+            .map_err(registry_error)?;
+
+        if !response.is_compatible {
+            info!(
+                "Schema {} version {} is incompatible with the latest version: {}",
+                subject,
+                version,
+                response.messages.join("; ")
+            );
+        }
+
+        Ok(response.is_compatible)
+    }
+
+    /// The latest version of a subject, from the cache while it is fresh
+    async fn latest(&self, subject: &str) -> Result<Arc<RegisteredSchema>> {
+        let cached = self
+            .cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(subject)
+            .cloned();
+        if let Some((fetched, schema)) = &cached {
+            if fetched.elapsed() < self.config.cache_ttl {
+                return Ok(schema.clone());
+            }
+        }
+
+        match self.fetch(subject).await {
+            Ok(schema) => {
+                let schema = Arc::new(schema);
+                self.cache
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(subject.to_string(), (Instant::now(), schema.clone()));
+                Ok(schema)
+            }
+            // Keep validating with the last known version rather than rejecting everything
+            Err(AppError::FetchError(e)) => match cached {
+                Some((_, schema)) => {
+                    warn!(
+                        "Using cached schema for {} as the registry is unreachable: {}",
+                        subject, e
+                    );
+                    Ok(schema)
+                }
+                None => Err(AppError::FetchError(e)),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn fetch(&self, subject: &str) -> Result<RegisteredSchema> {
+        let url = format!(
+            "{}/subjects/{}/versions/latest",
+            self.config.url.trim_end_matches('/'),
+            subject
+        );
+        let response = self
+            .request(self.http.get(url))
+            .send()
+            .await
+            .map_err(registry_error)?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(AppError::ValidationError(format!(
+                "No schema is registered under subject {}",
+                subject
+            )));
+        }
+
+        let version: SchemaVersionResponse = response
+            .error_for_status()
+            .map_err(registry_error)?
+            .json()
+            .await
+            .map_err(registry_error)?;
+
+        let validator = match version.schema_type.as_deref().unwrap_or("AVRO") {
+            "JSON" => serde_json::from_str(&version.schema)
+                .map_err(|e| e.to_string())
+                .and_then(|schema: Value| {
+                    jsonschema::validator_for(&schema).map_err(|e| e.to_string())
+                })
+                .map(SchemaValidator::Json),
+            "AVRO" => apache_avro::Schema::parse_str(&version.schema)
+                .map_err(|e| e.to_string())
+                .map(SchemaValidator::Avro),
+            other => Err(format!("schema type {} is not supported", other)),
+        }
+        .map_err(|e| {
+            AppError::InternalError(format!(
+                "Unusable schema {} version {}: {}",
+                version.subject, version.version, e
+            ))
+        })?;
+
+        debug!(
+            "Fetched schema {} version {} (id {})",
+            version.subject, version.version, version.id
+        );
+
+        Ok(RegisteredSchema {
+            id: version.id,
+            subject: version.subject,
+            version: version.version,
+            validator,
+        })
+    }
+
+    fn request(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.config.username {
+            Some(username) => {
+                request.basic_auth(username, self.config.password.as_ref().map(Secret::expose))
+            }
+            None => request,
+        }
+    }
+}
+
+fn registry_error(e: reqwest::Error) -> AppError {
+    AppError::FetchError(format!("Schema registry request failed: {}", e))
+}
@@ -0,0 +1,514 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::Utc;
+use tracing::{info, warn};
+
+use crate::bus::MessageBus;
+use crate::expiry;
+use crate::nats::Headers;
+use crate::stats::IngestStats;
+
+/// Pause between flush attempts while messages are buffered and no reconnect was seen
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A message waiting for NATS to come back
+struct BufferedMessage {
+    source: String,
+    subject: String,
+    headers: Headers,
+    payload: Bytes,
+    priority: bool,
+}
+
+/// Messages of one source, with the bytes it may still send in its current turn
+#[derive(Default)]
+struct SourceQueue {
+    messages: VecDeque<BufferedMessage>,
+    deficit: usize,
+}
+
+/// Deficit round robin across sources.
+///
+/// Each source with waiting messages takes turns sending up to `quantum` bytes, carrying
+/// unused allowance into its next turn, so a backfill with a deep backlog cannot hold up
+/// sources with a few messages. Each source's messages stay in order.
+struct FairQueue {
+    quantum: usize,
+    sources: HashMap<String, SourceQueue>,
+    /// Sources with waiting messages, the one whose turn it is first
+    active: VecDeque<String>,
+    len: usize,
+}
+
+impl FairQueue {
+    fn new(quantum: usize) -> Self {
+        Self {
+            quantum,
+            sources: HashMap::new(),
+            active: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn push_back(&mut self, message: BufferedMessage) {
+        let queue = self.sources.entry(message.source.clone()).or_default();
+        if queue.messages.is_empty() {
+            self.active.push_back(message.source.clone());
+        }
+        queue.messages.push_back(message);
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<BufferedMessage> {
+        loop {
+            let source = self.active.front()?;
+            let queue = self.sources.get_mut(source)?;
+            let size = queue
+                .messages
+                .front()
+                .map_or(0, |message| message.payload.len());
+
+            // Out of allowance for this turn, so the next source goes first
+            if size > queue.deficit {
+                queue.deficit += self.quantum;
+                self.active.rotate_left(1);
+                continue;
+            }
+
+            queue.deficit -= size;
+            let message = queue.messages.pop_front();
+            if queue.messages.is_empty() {
+                // An idle source does not save up allowance
+                if let Some(source) = self.active.pop_front() {
+                    self.sources.remove(&source);
+                }
+            }
+            self.len -= 1;
+            return message;
+        }
+    }
+
+    /// Put back a message whose republish failed, refunding its bytes so its source's turn
+    /// resumes where it stopped
+    fn push_front(&mut self, message: BufferedMessage) {
+        let queue = self.sources.entry(message.source.clone()).or_default();
+        if queue.messages.is_empty() {
+            self.active.push_front(message.source.clone());
+        } else if let Some(position) = self
+            .active
+            .iter()
+            .position(|source| *source == message.source)
+        {
+            self.active.remove(position);
+            self.active.push_front(message.source.clone());
+        }
+        queue.deficit += message.payload.len();
+        queue.messages.push_front(message);
+        self.len += 1;
+    }
+}
+
+/// Buffered messages, with priority messages kept apart so they can jump the line
+struct Queues {
+    priority: FairQueue,
+    normal: FairQueue,
+
+    /// Priority messages republished since the last normal one
+    streak: usize,
+
+    /// Source of the message being republished, which is out of the queues meanwhile
+    in_flight: Option<String>,
+}
+
+impl Queues {
+    fn len(&self) -> usize {
+        self.priority.len + self.normal.len
+    }
+
+    /// Next message to republish: priority messages first, except that a normal message
+    /// gets through after every `burst` priority ones
+    fn pop(&mut self, burst: usize) -> Option<BufferedMessage> {
+        if !self.priority.is_empty() && (self.normal.is_empty() || self.streak < burst) {
+            // Only priority messages that held a normal one back count towards the burst
+            self.streak = if self.normal.is_empty() {
+                0
+            } else {
+                self.streak + 1
+            };
+            return self.priority.pop();
+        }
+
+        self.streak = 0;
+        self.normal.pop()
+    }
+
+    /// Put back a message whose republish failed, ahead of the rest of its queue
+    fn push_front(&mut self, message: BufferedMessage) {
+        if message.priority {
+            self.streak = self.streak.saturating_sub(1);
+            self.priority.push_front(message);
+        } else {
+            self.normal.push_front(message);
+        }
+    }
+}
+
+/// Bounded in-memory queue of messages that failed to publish during a NATS outage.
+///
+/// Messages are republished as soon as the client reconnects. Priority messages go first,
+/// with a normal message let through after every `burst` of them so a steady stream of
+/// priority traffic cannot starve the rest. Within each class, sources share the flush
+/// fairly and each source's order is kept. Messages past their `Ingest-Expires-At` when
+/// their turn comes are dropped rather than republished. Unlike the disk spool, the queue
+/// does not survive a restart; it covers deployments without a spool through short outages.
+pub struct RepublishBuffer {
+    capacity: usize,
+    burst: usize,
+    queues: Mutex<Queues>,
+}
+
+impl RepublishBuffer {
+    /// Create a buffer holding at most `capacity` messages, republishing at most `burst`
+    /// priority messages in a row while normal ones wait and up to `quantum` bytes per
+    /// source turn
+    pub fn new(capacity: usize, burst: usize, quantum: usize) -> Self {
+        let quantum = quantum.max(1);
+        Self {
+            capacity,
+            burst: burst.max(1),
+            queues: Mutex::new(Queues {
+                priority: FairQueue::new(quantum),
+                normal: FairQueue::new(quantum),
+                streak: 0,
+                in_flight: None,
+            }),
+        }
+    }
+
+    /// Number of messages waiting to be republished
+    pub fn len(&self) -> usize {
+        self.queues.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Whether nothing is waiting to be republished
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether messages of a source are waiting to be republished
+    pub fn contains_source(&self, source: &str) -> bool {
+        let queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+        queues.in_flight.as_deref() == Some(source)
+            || queues.priority.sources.contains_key(source)
+            || queues.normal.sources.contains_key(source)
+    }
+
+    /// Queue a message, returning false when the buffer is full
+    pub fn push(
+        &self,
+        source: &str,
+        subject: &str,
+        headers: &Headers,
+        payload: Bytes,
+        priority: bool,
+    ) -> bool {
+        let mut queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+        if queues.len() >= self.capacity {
+            return false;
+        }
+
+        let message = BufferedMessage {
+            source: source.to_string(),
+            subject: subject.to_string(),
+            headers: headers.clone(),
+            payload,
+            priority,
+        };
+        if priority {
+            queues.priority.push_back(message);
+        } else {
+            queues.normal.push_back(message);
+        }
+        true
+    }
+
+    /// Republish buffered messages until the buffer is empty or a publish fails, counting
+    /// expired messages in `stats`
+    async fn flush(&self, bus: &dyn MessageBus, stats: &IngestStats) -> usize {
+        let mut flushed = 0;
+        let mut expired = 0;
+
+        loop {
+            let message = {
+                let mut queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+                let message = queues.pop(self.burst);
+                queues.in_flight = message.as_ref().map(|message| message.source.clone());
+                message
+            };
+            let Some(message) = message else {
+                break;
+            };
+
+            if expiry::is_expired(&message.headers, Utc::now()) {
+                stats.record_expired(Some(&message.source));
+                expired += 1;
+                continue;
+            }
+
+            let published = bus
+                .publish(&message.subject, &message.headers, message.payload.clone())
+                .await;
+
+            let mut queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+            queues.in_flight = None;
+            if let Err(e) = published {
+                warn!(
+                    "Republish interrupted, {} messages still buffered: {}",
+                    queues.len() + 1,
+                    e
+                );
+                queues.push_front(message);
+                break;
+            }
+            flushed += 1;
+        }
+
+        if expired > 0 {
+            warn!("Dropped {} buffered messages past their expiry", expired);
+        }
+
+        flushed
+    }
+
+    /// Republish what is buffered once more before shutting down, giving the number of
+    /// messages left behind
+    pub async fn drain(&self, bus: &dyn MessageBus, stats: &IngestStats) -> usize {
+        if !self.is_empty() && bus.is_connected() {
+            self.flush(bus, stats).await;
+        }
+        self.len()
+    }
+
+    /// Spawn the background task that flushes the buffer whenever NATS reconnects, counting
+    /// expired messages in `stats`
+    pub fn spawn_flusher(self: Arc<Self>, bus: Arc<dyn MessageBus>, stats: Arc<IngestStats>) {
+        tokio::spawn(async move {
+            loop {
+                // A reconnect flushes at once; the timer covers flushes that failed midway
+                tokio::select! {
+                    _ = bus.reconnected() => {}
+                    _ = tokio::time::sleep(RETRY_INTERVAL) => {}
+                }
+
+                if !self.is_empty() && bus.is_connected() {
+                    let flushed = self.flush(bus.as_ref(), &stats).await;
+                    if flushed > 0 {
+                        info!(
+                            "Republished {} messages buffered during the NATS outage",
+                            flushed
+                        );
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nats::{NatsClient, SimulationMode};
+
+    fn message(source: &str, bytes: usize, priority: bool) -> BufferedMessage {
+        BufferedMessage {
+            source: source.to_string(),
+            subject: format!("ingest.{}", source),
+            headers: Headers::new(),
+            payload: Bytes::from(vec![0; bytes]),
+            priority,
+        }
+    }
+
+    fn queues(quantum: usize) -> Queues {
+        Queues {
+            priority: FairQueue::new(quantum),
+            normal: FairQueue::new(quantum),
+            streak: 0,
+            in_flight: None,
+        }
+    }
+
+    fn sources(queue: &mut FairQueue) -> Vec<String> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|message| message.source)
+            .collect()
+    }
+
+    #[test]
+    fn noisy_source_does_not_starve_the_others() {
+        let mut queue = FairQueue::new(100);
+        for _ in 0..50 {
+            queue.push_back(message("backfill", 100, false));
+        }
+        queue.push_back(message("news-api", 100, false));
+        queue.push_back(message("arxiv", 100, false));
+
+        let order = sources(&mut queue);
+        assert_eq!(order.len(), 52);
+        // Each quiet source is through after one turn of the noisy one
+        let position = |source: &str| order.iter().position(|s| s == source).unwrap();
+        assert!(position("news-api") <= 2);
+        assert!(position("arxiv") <= 3);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn sources_share_bytes_rather_than_messages() {
+        let mut queue = FairQueue::new(1000);
+        for _ in 0..20 {
+            queue.push_back(message("small", 100, false));
+        }
+        for _ in 0..2 {
+            queue.push_back(message("large", 1000, false));
+        }
+
+        // One turn of the large source takes as many bytes as ten small messages
+        let mut expected = vec!["small"; 10];
+        expected.push("large");
+        expected.extend(["small"; 10]);
+        expected.push("large");
+        assert_eq!(sources(&mut queue), expected);
+    }
+
+    #[test]
+    fn keeps_each_source_in_order_and_refunds_failed_messages() {
+        let mut queue = FairQueue::new(100);
+        for index in 0..3 {
+            let mut message = message("news-api", 100, false);
+            message.subject = format!("ingest.{}", index);
+            queue.push_back(message);
+        }
+        queue.push_back(message("arxiv", 100, false));
+
+        // A failed republish goes back to the front, keeping its source's turn
+        let first = queue.pop().unwrap();
+        assert_eq!(first.subject, "ingest.0");
+        queue.push_front(first);
+
+        let subjects: Vec<String> = std::iter::from_fn(|| queue.pop())
+            .filter(|message| message.source == "news-api")
+            .map(|message| message.subject)
+            .collect();
+        assert_eq!(subjects, vec!["ingest.0", "ingest.1", "ingest.2"]);
+    }
+
+    #[test]
+    fn normal_message_gets_through_after_a_priority_burst() {
+        let mut queues = queues(1000);
+        for _ in 0..10 {
+            queues.priority.push_back(message("alerts", 10, true));
+        }
+        queues.normal.push_back(message("news-api", 10, false));
+        queues.normal.push_back(message("news-api", 10, false));
+
+        let priority: Vec<bool> = std::iter::from_fn(|| queues.pop(3))
+            .map(|message| message.priority)
+            .collect();
+        assert_eq!(
+            priority,
+            vec![true, true, true, false, true, true, true, false, true, true, true, true]
+        );
+        assert_eq!(queues.len(), 0);
+    }
+
+    #[test]
+    fn priority_messages_without_normal_ones_waiting_do_not_count_towards_the_burst() {
+        let mut queues = queues(1000);
+        for _ in 0..5 {
+            queues.priority.push_back(message("alerts", 10, true));
+        }
+        for _ in 0..5 {
+            assert!(queues.pop(2).unwrap().priority);
+        }
+        assert_eq!(queues.streak, 0);
+
+        // A normal message arriving now waits for a full burst, not what is left of one
+        queues.priority.push_back(message("alerts", 10, true));
+        queues.priority.push_back(message("alerts", 10, true));
+        queues.priority.push_back(message("alerts", 10, true));
+        queues.normal.push_back(message("news-api", 10, false));
+        let priority: Vec<bool> = std::iter::from_fn(|| queues.pop(2))
+            .map(|message| message.priority)
+            .collect();
+        assert_eq!(priority, vec![true, true, false, true]);
+    }
+
+    #[test]
+    fn empty_queues_give_nothing() {
+        let mut queue = FairQueue::new(100);
+        assert!(queue.is_empty());
+        assert!(queue.pop().is_none());
+
+        let mut queues = queues(100);
+        assert!(queues.pop(3).is_none());
+        assert_eq!(queues.streak, 0);
+    }
+
+    #[tokio::test]
+    async fn drains_to_empty() {
+        let bus = NatsClient::new(
+            Vec::new(),
+            Some(SimulationMode::Null),
+            None,
+            None,
+            1,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let stats = IngestStats::default();
+        let buffer = RepublishBuffer::new(10, 3, 100);
+
+        assert_eq!(buffer.drain(&bus, &stats).await, 0);
+
+        assert!(buffer.push(
+            "news-api",
+            "ingest.a",
+            &Headers::new(),
+            Bytes::from("{}"),
+            false
+        ));
+        assert!(buffer.push(
+            "alerts",
+            "ingest.b",
+            &Headers::new(),
+            Bytes::from("{}"),
+            true
+        ));
+        assert!(buffer.contains_source("news-api"));
+        assert_eq!(buffer.drain(&bus, &stats).await, 0);
+        assert!(buffer.is_empty());
+        assert!(!buffer.contains_source("news-api"));
+
+        // Draining again has nothing left to do
+        assert_eq!(buffer.drain(&bus, &stats).await, 0);
+    }
+
+    #[test]
+    fn refuses_messages_once_full() {
+        let buffer = RepublishBuffer::new(2, 3, 100);
+        for _ in 0..2 {
+            assert!(buffer.push("news-api", "ingest.a", &Headers::new(), Bytes::new(), false));
+        }
+        assert!(!buffer.push("alerts", "ingest.b", &Headers::new(), Bytes::new(), true));
+        assert_eq!(buffer.len(), 2);
+    }
+}
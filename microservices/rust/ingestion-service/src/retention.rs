@@ -0,0 +1,156 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Extension, Query},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::error::{AppError, Result};
+use crate::pipeline::Pipeline;
+
+/// Limits applied to one local store
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Entries older than this are purged
+    pub max_age: Option<Duration>,
+
+    /// Oldest entries are purged until the store is at most this large
+    pub max_bytes: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Whether the policy limits anything
+    pub fn is_set(&self) -> bool {
+        self.max_age.is_some() || self.max_bytes.is_some()
+    }
+}
+
+/// Settings for purging local stores
+#[derive(Debug, Clone)]
+pub struct RetentionConfig {
+    /// Pause between background purges
+    pub interval: Duration,
+
+    /// Policy for the disk spool
+    pub spool: RetentionPolicy,
+
+    /// Policy for the item history
+    pub history: RetentionPolicy,
+}
+
+/// What one purge removed from a store
+#[derive(Debug, Clone, Serialize)]
+pub struct PurgeReport {
+    /// Store that was purged
+    pub store: String,
+
+    /// Entries removed
+    pub purged_records: u64,
+
+    /// Disk space handed back to the filesystem
+    pub reclaimed_bytes: u64,
+}
+
+impl PurgeReport {
+    /// Create a report for a store
+    pub fn new(store: &str, purged_records: u64, reclaimed_bytes: u64) -> Self {
+        Self {
+            store: store.to_string(),
+            purged_records,
+            reclaimed_bytes,
+        }
+    }
+}
+
+/// Applies retention policies to the spool and history store.
+///
+/// Purges run in the background every `interval` and on demand through the admin API;
+/// what they remove is added to the `retention` counters in `/stats`.
+pub struct Retention {
+    config: RetentionConfig,
+    pipeline: Arc<Pipeline>,
+}
+
+impl Retention {
+    /// Create a new retention runner
+    pub fn new(config: RetentionConfig, pipeline: Arc<Pipeline>) -> Self {
+        Self { config, pipeline }
+    }
+
+    /// Purge every store with a policy, or only `store` when given
+    pub async fn purge(&self, store: Option<&str>) -> Result<Vec<PurgeReport>> {
+        if let Some(store) = store {
+            if !matches!(store, "spool" | "history") {
+                return Err(AppError::ValidationError(format!(
+                    "Unknown store {}",
+                    store
+                )));
+            }
+        }
+        let selected = |name: &str| store.is_none_or(|store| store == name);
+
+        let mut reports = Vec::new();
+
+        if let Some(spool) = self.pipeline.spool() {
+            if selected("spool") && self.config.spool.is_set() {
+                reports.push(spool.purge(&self.config.spool).await?);
+            }
+        }
+
+        if let Some(history) = self.pipeline.history() {
+            if selected("history") && self.config.history.is_set() {
+                reports.push(history.purge(&self.config.history).await?);
+            }
+        }
+
+        for report in &reports {
+            self.pipeline.stats().record_purge(report);
+        }
+
+        Ok(reports)
+    }
+
+    /// Spawn the background purge task
+    pub fn spawn(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(self.config.interval).await;
+                match self.purge(None).await {
+                    Ok(reports) => {
+                        for report in reports
+                            .iter()
+                            .filter(|r| r.purged_records > 0 || r.reclaimed_bytes > 0)
+                        {
+                            info!(
+                                "Purged {} records from the {}, reclaiming {} bytes",
+                                report.purged_records, report.store, report.reclaimed_bytes
+                            );
+                        }
+                    }
+                    Err(e) => warn!("Retention purge failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+/// Query parameters for an on-demand purge
+#[derive(Debug, Deserialize)]
+pub struct PurgeParams {
+    /// `spool` or `history`; every store when absent
+    pub store: Option<String>,
+}
+
+/// Apply the retention policies now
+#[instrument(skip(retention))]
+pub async fn purge_stores(
+    Extension(retention): Extension<Arc<Retention>>,
+    Query(params): Query<PurgeParams>,
+) -> Result<Json<Vec<PurgeReport>>> {
+    let reports = retention.purge(params.store.as_deref()).await?;
+    info!("Admin purge completed for {} stores", reports.len());
+    Ok(Json(reports))
+}
@@ -0,0 +1,422 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::audit::{AuditEntry, AuditLog};
+use crate::bus::MessageBus;
+use crate::encoding::{WireFormat, FORMAT_HEADER};
+use crate::error::{AppError, Result};
+use crate::keys::{self, KeyRing, KeyRingConfig};
+use crate::nats::{Headers, PublishRetryPolicy, MSG_ID_HEADER};
+use crate::pipeline::Pipeline;
+
+/// Subject retractions are published to unless `RETRACTION_SUBJECT` is set
+pub const DEFAULT_RETRACTION_SUBJECT: &str = "ingest.retract";
+
+/// Header carrying the HMAC-SHA256 of a retraction body, as `sha256=<hex>`
+pub const SIGNATURE_HEADER: &str = "Ingest-Signature";
+
+/// Header naming the key a retraction was signed with
+pub const SIGNATURE_KEY_HEADER: &str = "Ingest-Signature-Key";
+
+/// Settings for publishing retractions
+#[derive(Debug, Clone)]
+pub struct RetractionConfig {
+    /// Keys retraction bodies are signed with, shared with consumers that act on them
+    pub signing_keys: KeyRingConfig,
+
+    /// Subject retractions are published to
+    pub subject: String,
+}
+
+/// Signal that a previously ingested item must be purged downstream, e.g. for a GDPR
+/// deletion request or bad data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Retraction {
+    /// Derived from the retracted item's ID, so repeated retractions of an item are stored once
+    pub id: Uuid,
+
+    /// ID of the retracted item
+    pub item_id: Uuid,
+
+    /// Source of the retracted item, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    /// Content type of the retracted item, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+
+    /// Why the item is retracted
+    pub reason: String,
+
+    /// Who asked for the retraction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_by: Option<String>,
+
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Retraction {
+    /// ID of the retraction of an item
+    pub fn id_for(item_id: Uuid) -> Uuid {
+        Uuid::new_v5(&item_id, b"retraction")
+    }
+}
+
+/// Publishes signed retractions and records them in the audit log
+pub struct Retractor {
+    config: RetractionConfig,
+    signing_keys: Arc<KeyRing>,
+    bus: Arc<dyn MessageBus>,
+    audit: Arc<AuditLog>,
+    publish_retry: PublishRetryPolicy,
+}
+
+impl Retractor {
+    /// Create a retractor, rejecting a subject that is not a plain NATS subject
+    pub fn new(
+        config: RetractionConfig,
+        bus: Arc<dyn MessageBus>,
+        audit: Arc<AuditLog>,
+        publish_retry: PublishRetryPolicy,
+    ) -> Result<Self> {
+        let valid = config.subject.split('.').all(|token| {
+            !token.is_empty()
+                && !token.contains(|c: char| c == '*' || c == '>' || c.is_whitespace())
+        });
+        if !valid {
+            return Err(AppError::ValidationError(format!(
+                "Invalid retraction subject {}: subjects may not contain `*`, `>`, whitespace or empty tokens",
+                config.subject
+            )));
+        }
+
+        let signing_keys = KeyRing::open(
+            "retraction signing",
+            config.signing_keys.clone(),
+            keys::any_key,
+        )?;
+
+        info!("Publishing signed retractions to {}", config.subject);
+
+        Ok(Self {
+            config,
+            signing_keys,
+            bus,
+            audit,
+            publish_retry,
+        })
+    }
+
+    /// Subject retractions are published to
+    pub fn subject(&self) -> &str {
+        &self.config.subject
+    }
+
+    /// Publish a signed retraction of an item, remove it from the history and record it in
+    /// the audit log.
+    ///
+    /// When publishing fails, the retraction is spooled if the spool is enabled.
+    pub async fn retract(
+        &self,
+        pipeline: &Pipeline,
+        item_id: Uuid,
+        request: RetractRequest,
+    ) -> Result<RetractResponse> {
+        if request.reason.trim().is_empty() {
+            return Err(AppError::ValidationError(
+                "A retraction needs a reason".to_string(),
+            ));
+        }
+
+        // The history knows the item better than the caller does
+        let known = match pipeline.history() {
+            Some(history) => history.describe(item_id).await?,
+            None => None,
+        };
+        let (source, content_type) = match known {
+            Some((source, content_type)) => (Some(source), Some(content_type)),
+            None => (request.source, request.content_type),
+        };
+
+        let retraction = Retraction {
+            id: Retraction::id_for(item_id),
+            item_id,
+            source,
+            content_type,
+            reason: request.reason,
+            requested_by: request.requested_by,
+            timestamp: Utc::now(),
+        };
+
+        let body = serde_json::to_vec(&retraction)
+            .map_err(|e| AppError::InternalError(format!("JSON serialization error: {}", e)))?;
+        let (key_id, signature) = self.sign(&body)?;
+        let headers: Headers = vec![
+            (MSG_ID_HEADER.to_string(), retraction.id.to_string()),
+            (
+                FORMAT_HEADER.to_string(),
+                WireFormat::Json.as_str().to_string(),
+            ),
+            (SIGNATURE_HEADER.to_string(), signature),
+            (SIGNATURE_KEY_HEADER.to_string(), key_id),
+        ];
+
+        let published = self
+            .bus
+            .publish_with_retry(
+                self.subject(),
+                &headers,
+                body.clone().into(),
+                &self.publish_retry,
+            )
+            .await;
+        let spooled = match (published, pipeline.spool()) {
+            (Ok(_), _) => false,
+            (Err(e), Some(spool)) => {
+                warn!(
+                    "Spooling retraction of item {} after publish failure: {}",
+                    item_id, e
+                );
+                spool
+                    .append(
+                        retraction.source.as_deref(),
+                        self.subject(),
+                        &headers,
+                        &body,
+                    )
+                    .await?;
+                true
+            }
+            (Err(e), None) => return Err(e),
+        };
+
+        let history_removed = match pipeline.history() {
+            Some(history) => history.remove(item_id).await?,
+            None => false,
+        };
+
+        self.audit
+            .record(&AuditEntry {
+                timestamp: retraction.timestamp,
+                action: "retract".to_string(),
+                actor: retraction.requested_by.clone(),
+                details: json!({
+                    "retraction_id": retraction.id,
+                    "item_id": item_id,
+                    "source": retraction.source,
+                    "content_type": retraction.content_type,
+                    "reason": retraction.reason,
+                    "history_removed": history_removed,
+                    "spooled": spooled,
+                }),
+            })
+            .await?;
+
+        info!("Retracted item {}", item_id);
+
+        Ok(RetractResponse {
+            status: "retracted".to_string(),
+            id: retraction.id,
+            item_id,
+            history_removed,
+            spooled,
+            timestamp: retraction.timestamp,
+        })
+    }
+
+    /// Audit log retractions are recorded in
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// HMAC-SHA256 of a body under the active signing key, as sent in the signature header,
+    /// with the ID of the key
+    pub fn sign(&self, body: &[u8]) -> Result<(String, String)> {
+        let key = self.signing_keys.current()?;
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(key.secret.expose().as_bytes()).map_err(|e| {
+                AppError::InternalError(format!("Invalid retraction signing key {}: {}", key.id, e))
+            })?;
+        mac.update(body);
+        Ok((
+            key.id,
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+        ))
+    }
+}
+
+/// Body of a retraction request
+#[derive(Debug, Deserialize)]
+pub struct RetractRequest {
+    /// Why the item is retracted
+    pub reason: String,
+
+    /// Who asked for the retraction, recorded in the audit log
+    #[serde(default)]
+    pub requested_by: Option<String>,
+
+    /// Source of the item, used when the local history does not know it
+    #[serde(default)]
+    pub source: Option<String>,
+
+    /// Content type of the item, used when the local history does not know it
+    #[serde(default)]
+    pub content_type: Option<String>,
+}
+
+/// Response to a retraction request
+#[derive(Debug, Clone, Serialize)]
+pub struct RetractResponse {
+    pub status: String,
+
+    /// ID of the published retraction
+    pub id: Uuid,
+
+    pub item_id: Uuid,
+
+    /// Whether the item was removed from the local history
+    pub history_removed: bool,
+
+    /// Whether the retraction was spooled to be published once NATS is back
+    pub spooled: bool,
+
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Publish a signed retraction of a previously ingested item
+#[instrument(skip(retractor, pipeline, request))]
+pub async fn retract(
+    Extension(retractor): Extension<Arc<Retractor>>,
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Path(item_id): Path<Uuid>,
+    Json(request): Json<RetractRequest>,
+) -> Result<(StatusCode, Json<RetractResponse>)> {
+    let response = retractor.retract(&pipeline, item_id, request).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(response)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::keys::KeyVersion;
+    use crate::nats::{NatsClient, SimulationMode};
+
+    async fn retractor(entries: &[&str]) -> Retractor {
+        let bus = NatsClient::new(
+            Vec::new(),
+            Some(SimulationMode::Null),
+            None,
+            None,
+            1,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let config = RetractionConfig {
+            signing_keys: KeyRingConfig {
+                keys: entries
+                    .iter()
+                    .map(|entry| KeyVersion::parse(entry).unwrap())
+                    .collect(),
+                file: None,
+                active: None,
+                refresh_interval: Duration::from_secs(60),
+            },
+            subject: DEFAULT_RETRACTION_SUBJECT.to_string(),
+        };
+        let retry = PublishRetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            jitter: 0.0,
+        };
+        let audit = Arc::new(AuditLog::open(None).await.unwrap());
+        Retractor::new(config, Arc::new(bus), audit, retry).unwrap()
+    }
+
+    /// Check a signature as a consumer holding the shared secret would
+    fn verify(secret: &str, body: &[u8], signature: &str) -> bool {
+        let Some(signature) = signature
+            .strip_prefix("sha256=")
+            .and_then(|hex| hex::decode(hex).ok())
+        else {
+            return false;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    #[tokio::test]
+    async fn signatures_verify_under_the_signing_key() {
+        let retractor = retractor(&["k1=shared-secret"]).await;
+        let body = br#"{"item_id":"00000000-0000-0000-0000-000000000001"}"#;
+
+        let (key_id, signature) = retractor.sign(body).unwrap();
+        assert_eq!(key_id, "k1");
+        assert!(verify("shared-secret", body, &signature));
+        // Signing is deterministic, so a retried publish carries the same signature
+        assert_eq!(retractor.sign(body).unwrap().1, signature);
+    }
+
+    #[tokio::test]
+    async fn tampered_bodies_and_signatures_fail_verification() {
+        let retractor = retractor(&["k1=shared-secret"]).await;
+        let body = br#"{"reason":"gdpr"}"#;
+        let (_, signature) = retractor.sign(body).unwrap();
+
+        assert!(!verify(
+            "shared-secret",
+            br#"{"reason":"spam"}"#,
+            &signature
+        ));
+        assert!(!verify("other-secret", body, &signature));
+
+        let mut tampered = signature.clone().into_bytes();
+        let last = tampered.last_mut().unwrap();
+        *last = if *last == b'0' { b'1' } else { b'0' };
+        assert!(!verify(
+            "shared-secret",
+            body,
+            &String::from_utf8(tampered).unwrap()
+        ));
+        assert!(!verify(
+            "shared-secret",
+            body,
+            signature.trim_start_matches("sha256=")
+        ));
+    }
+
+    #[tokio::test]
+    async fn signs_with_the_newest_key_after_rotation() {
+        let retractor = retractor(&[
+            "k1@2020-01-01T00:00:00Z=old-secret",
+            "k2=new-secret",
+            "k3=next-secret",
+        ])
+        .await;
+        let body = b"{}";
+
+        let (key_id, signature) = retractor.sign(body).unwrap();
+        assert_eq!(key_id, "k2");
+        assert!(verify("new-secret", body, &signature));
+        assert!(!verify("old-secret", body, &signature));
+    }
+}
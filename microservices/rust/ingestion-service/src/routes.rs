@@ -1,119 +1,832 @@
 use axum::{
-    extract::{Json, Extension},
-    http::StatusCode,
+    extract::{Extension, Json, Path, Query},
+    http::{HeaderMap, StatusCode},
 };
-use chrono::Utc;
-use tracing::{info, warn, error, instrument};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::Deserialize;
+use serde_json::json;
 use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, instrument, warn};
+use utoipa::OpenApi;
+use uuid::Uuid;
 
-use crate::models::{RawData, BatchRawData, IngestResponse, BatchIngestResponse, HealthResponse};
-use crate::nats::NatsClient;
-use crate::error::{Result, AppError};
+use crate::batch;
+use crate::buffers::{BufferPool, PooledJson};
+use crate::bus::MessageBus;
+use crate::cache::ResponseCache;
+use crate::config::AppConfig;
+use crate::encoding::{self, WireFormat};
+use crate::error::{AppError, ErrorResponse, Result};
+use crate::fetch::UrlFetcher;
+use crate::idempotency::Claim;
+use crate::logging::throttled;
+use crate::models::{
+    BatchIngestResponse, BatchRawData, BatchStatsResponse, HealthDependencies, HealthResponse,
+    IngestResponse, ProbeResponse, RawData, ReadyResponse, SchemaResponse, SequenceGap,
+    StatsResponse, UrlIngestRequest,
+};
+use crate::openapi::ApiDoc;
+use crate::pipeline::Pipeline;
+use crate::readiness::Readiness;
+use crate::registry::IF_SCHEMA_VERSION_HEADER;
+use crate::schedule;
+use crate::sequence;
+use crate::subject;
 
 /// Health check endpoint
+///
+/// Reports `degraded` while the NATS connection is down, along with the connection's state
+/// and statistics.
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "status",
+    responses((status = 200, description = "Service is running", body = HealthResponse))
+)]
+#[instrument(skip_all)]
+pub async fn health_check(
+    Extension(cache): Extension<Arc<ResponseCache<HealthResponse>>>,
+    Extension(bus): Extension<Arc<dyn MessageBus>>,
+) -> Json<HealthResponse> {
+    let response = cache
+        .get_or_refresh(|| async {
+            let nats = bus.health().await;
+            let status = match nats.state.as_str() {
+                "connected" | "simulated" => "operational",
+                _ => "degraded",
+            };
+            HealthResponse {
+                service: "ingestion-service".to_string(),
+                status: status.to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                timestamp: Utc::now(),
+                nats_server: nats.server_name.clone(),
+                dependencies: HealthDependencies { nats },
+            }
+        })
+        .await;
+
+    Json(response)
+}
+
+/// Readiness check endpoint
+///
+/// Reports not ready while a spool backlog recovered at startup is still above the
+/// configured threshold, or while a required dependency is down or not yet reached.
+/// Optional dependencies that are down leave the service ready but `degraded`.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "status",
+    responses(
+        (status = 200, description = "Service is ready for traffic, possibly degraded", body = ReadyResponse),
+        (status = 503, description = "Spool backlog is still draining or a required dependency is down", body = ReadyResponse)
+    )
+)]
 #[instrument(skip_all)]
-pub async fn health_check() -> Json<HealthResponse> {
-    let response = HealthResponse {
-        service: "ingestion-service".to_string(),
-        status: "operational".to_string(),
-        version: env!("CARGO_PKG_VERSION").to_string(),
+pub async fn readiness_check(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Extension(readiness): Extension<Arc<Readiness>>,
+) -> (StatusCode, Json<ReadyResponse>) {
+    let (spool_ready, spool_pending) = match pipeline.spool() {
+        Some(spool) => (spool.is_ready(), spool.pending()),
+        None => (true, 0),
+    };
+
+    let dependencies = readiness.statuses();
+    let required_down = dependencies
+        .values()
+        .any(|d| d.required && d.status != "up");
+    let optional_down = dependencies
+        .values()
+        .any(|d| !d.required && d.status == "down");
+    let ready = spool_ready && !required_down;
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let response = ReadyResponse {
+        ready,
+        status: match (ready, optional_down) {
+            (false, _) => "unavailable",
+            (true, true) => "degraded",
+            (true, false) => "ready",
+        }
+        .to_string(),
+        spool_pending,
+        dependencies,
         timestamp: Utc::now(),
     };
-    
+
+    (status, Json(response))
+}
+
+/// Ingestion statistics endpoint
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "status",
+    responses((status = 200, description = "Ingestion counters", body = StatsResponse))
+)]
+#[instrument(skip_all)]
+pub async fn stats(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Extension(readiness): Extension<Arc<Readiness>>,
+    Extension(cache): Extension<Arc<ResponseCache<StatsResponse>>>,
+) -> Json<StatsResponse> {
+    let response = cache
+        .get_or_refresh(|| async {
+            let mut stats = pipeline.stats().snapshot();
+            stats.dependencies = readiness.counters();
+            stats
+        })
+        .await;
+
     Json(response)
 }
 
+/// Ingestion counters of a batch sent in `Ingest-Batch-Id`
+///
+/// Counters are kept in memory per replica for the most recently active batches, so a
+/// batch spread over several replicas is the sum of their counters.
+#[utoipa::path(
+    get,
+    path = "/stats/batches/{id}",
+    tag = "status",
+    params(("id" = String, Path, description = "Batch ID")),
+    responses(
+        (status = 200, description = "Batch counters", body = BatchStatsResponse),
+        (status = 404, description = "No item of the batch was processed recently by this replica", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(pipeline))]
+pub async fn batch_stats(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Path(id): Path<String>,
+) -> Result<Json<BatchStatsResponse>> {
+    pipeline
+        .stats()
+        .batch(&id)
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Batch {} has not been seen", id)))
+}
+
+/// Query parameters of the schema endpoint
+#[derive(Debug, Deserialize)]
+pub struct SchemaParams {
+    /// Envelope version the consumer reads, as `major.minor`
+    pub version: Option<String>,
+}
+
+/// Envelope version and encodings of published messages
+///
+/// Consumers can pass the envelope version they were built against to learn whether they
+/// can read published messages, which holds while the major versions match. Newer minor
+/// versions only add fields.
+#[utoipa::path(
+    get,
+    path = "/schema",
+    tag = "status",
+    params(("version" = Option<String>, Query, description = "Envelope version the consumer reads, e.g. `1.0`")),
+    responses(
+        (status = 200, description = "Envelope version, and whether the consumer's version is compatible", body = SchemaResponse),
+        (status = 400, description = "The version is not `major.minor`", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(config))]
+pub async fn schema(
+    Extension(config): Extension<Arc<AppConfig>>,
+    Query(params): Query<SchemaParams>,
+) -> Result<Json<SchemaResponse>> {
+    let compatible = params
+        .version
+        .as_deref()
+        .map(encoding::is_compatible)
+        .transpose()?;
+    if compatible == Some(false) {
+        throttled!(warn!(
+            "Consumer asked about envelope version {}, which is incompatible with {}",
+            params.version.as_deref().unwrap_or_default(),
+            encoding::ENVELOPE_VERSION
+        ));
+    }
+
+    Ok(Json(SchemaResponse {
+        version: encoding::ENVELOPE_VERSION.to_string(),
+        default_format: config.wire_format.default.as_str().to_string(),
+        formats: config
+            .wire_format
+            .content_types
+            .iter()
+            .map(|(content_type, format)| (content_type.clone(), format.as_str().to_string()))
+            .collect(),
+        cloudevents: config
+            .cloudevents
+            .as_ref()
+            .map(|cloudevents| cloudevents.mode.as_str().to_string()),
+        compatible,
+        timestamp: Utc::now(),
+    }))
+}
+
+/// OpenAPI document describing the producer-facing API
+pub async fn openapi_spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Header marking a probe, so processors can reply without processing it
+pub const PROBE_HEADER: &str = "Ingest-Probe";
+
+/// Probe the processors of a content type over NATS request-reply.
+///
+/// A synthetic item is sent to `ingest.probe.{content_type}`, encoded like real messages of
+/// the content type, and the route reports whether any processor replied. Nothing is
+/// published to the ingest subjects.
+#[instrument(skip(bus, buffers, config))]
+pub async fn probe_processors(
+    Path(content_type): Path<String>,
+    Extension(bus): Extension<Arc<dyn MessageBus>>,
+    Extension(buffers): Extension<Arc<BufferPool>>,
+    Extension(config): Extension<Arc<AppConfig>>,
+) -> Result<Json<ProbeResponse>> {
+    // The content type becomes a single subject token, as it does when ingesting
+    let token = subject::token("content type", &content_type, config.subject_tokens)?.into_owned();
+
+    let probe = RawData::builder()
+        .source("ingest-probe")
+        .content_type(content_type.clone())
+        .payload(json!({ "probe": true }))
+        .build()?;
+    // The probe payload cannot match a registry schema, so Avro content types are probed in JSON
+    let format = match config.wire_format.format_for(&content_type) {
+        WireFormat::Avro => WireFormat::Json,
+        format => format,
+    };
+    let headers = vec![
+        (PROBE_HEADER.to_string(), "true".to_string()),
+        (
+            encoding::FORMAT_HEADER.to_string(),
+            format.as_str().to_string(),
+        ),
+    ];
+    let subject = format!("ingest.probe.{}", token);
+
+    let started = Instant::now();
+    let reply = bus
+        .request(
+            &subject,
+            &headers,
+            format.encode(&probe, &buffers, None)?,
+            config.probe_timeout,
+        )
+        .await?;
+    let latency_ms = reply
+        .is_some()
+        .then(|| started.elapsed().as_millis() as u64);
+
+    if reply.is_some() {
+        debug!("Processors of {} replied to probe", content_type);
+    } else {
+        warn!("No processor of {} replied to probe", content_type);
+    }
+
+    Ok(Json(ProbeResponse {
+        content_type,
+        subject,
+        responded: reply.is_some(),
+        latency_ms,
+        timestamp: Utc::now(),
+    }))
+}
+
 /// Ingest a single data item
-#[instrument(skip(nats_client, payload), fields(source = %payload.source, content_type = %payload.content_type))]
+#[utoipa::path(
+    post,
+    path = "/ingest",
+    tag = "ingest",
+    request_body = RawData,
+    params(
+        ("Ingest-Batch-Id" = Option<String>, Header, description = "Batch the item belongs to, attached to its messages and tracked in `/stats/batches/{id}`"),
+        ("If-Schema-Version" = Option<u32>, Header, description = "Schema version the producer was built against; refused with 412 once the registry has moved on incompatibly"),
+        ("Ingest-Deliver-At" = Option<String>, Header, description = "RFC 3339 time to publish the item at, when scheduled delivery is enabled"),
+        ("Ingest-Deliver-After" = Option<u32>, Header, description = "Seconds from now to publish the item after, when scheduled delivery is enabled")
+    ),
+    responses(
+        (status = 201, description = "Item ingested", body = IngestResponse),
+        (status = 202, description = "Item scheduled for delivery at `deliver_at`", body = IngestResponse),
+        (status = 400, description = "Invalid item", body = ErrorResponse),
+        (status = 403, description = "The item's data classification forbids it", body = ErrorResponse),
+        (status = 409, description = "The item is already being ingested by another request", body = ErrorResponse),
+        (status = 412, description = "The pinned schema version is incompatible with the latest one", body = ErrorResponse),
+        (status = 423, description = "Ingestion is paused for the source or content type", body = ErrorResponse),
+        (status = 429, description = "Source quota exceeded", body = ErrorResponse),
+        (status = 500, description = "Publishing failed", body = ErrorResponse),
+        (status = 503, description = "NATS is unavailable, or the stream expected to store the content type is missing", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(pipeline, headers, payload), fields(source = %payload.source, content_type = %payload.content_type))]
 pub async fn ingest_data(
-    Extension(nats_client): Extension<Arc<NatsClient>>,
-    Json(payload): Json<RawData>,
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    headers: HeaderMap,
+    PooledJson(mut payload): PooledJson<RawData>,
 ) -> Result<(StatusCode, Json<IngestResponse>)> {
-    info!("Processing ingestion request: id={}", payload.id);
-    
+    debug!("Processing ingestion request: id={}", payload.id);
+
+    if let Some(batch_id) = batch::from_headers(&headers)? {
+        batch::tag(&mut payload, &batch_id);
+    }
+    let deliver_at = requested_delivery(&pipeline, &headers)?;
+
+    check_schema_version(&pipeline, &headers, std::slice::from_ref(&payload)).await?;
+
     // Validate input
-    if payload.source.is_empty() {
-        warn!("Empty source field in ingestion request");
-        return Err(AppError::ValidationError("Source field cannot be empty".to_string()));
-    }
-    
-    if payload.content_type.is_empty() {
-        warn!("Empty content_type field in ingestion request");
-        return Err(AppError::ValidationError("Content type field cannot be empty".to_string()));
-    }
-    
-    if payload.payload.is_null() {
-        warn!("Empty payload in ingestion request");
-        return Err(AppError::ValidationError("Payload cannot be null".to_string()));
-    }
-    
-    // Determine the appropriate NATS subject based on content type
-    let subject = format!("ingest.raw.{}", payload.content_type);
-    
-    // Publish to NATS
-    nats_client.publish(&subject, &payload).await?;
-    
+    if let Err(e) = validate(&payload) {
+        pipeline.reject(&payload, &e);
+        return Err(e);
+    }
+    let sequence_gap = pipeline.check_sequence(&payload);
+
+    // Held as is until due, once it passes the checks it will face then; scheduling again
+    // under the same ID replaces the item, so retries need no idempotency claim
+    if let Some((schedule, deliver_at)) = pipeline.schedule().zip(deliver_at) {
+        if let Err(e) = pipeline.check_admission(&payload).await {
+            pipeline.reject(&payload, &e);
+            return Err(e);
+        }
+        schedule.add(&payload, deliver_at).await?;
+        debug!("Scheduled item {} for {}", payload.id, deliver_at);
+        let response = IngestResponse {
+            sequence_gap,
+            ..IngestResponse::scheduled(payload.id, deliver_at)
+        };
+        return Ok((StatusCode::ACCEPTED, Json(response)));
+    }
+
+    // A retried submission gets the response of the original one
+    let claim = match pipeline.idempotency() {
+        Some(idempotency) => idempotency.claim(payload.id).await,
+        None => Claim::Unchecked,
+    };
+    match claim {
+        Claim::Done(response) => {
+            debug!(
+                "Item {} was already ingested, returning the original response",
+                payload.id
+            );
+            return Ok((StatusCode::CREATED, Json(response)));
+        }
+        Claim::InFlight => {
+            return Err(AppError::Duplicate(format!(
+                "Item {} is already being ingested",
+                payload.id
+            )));
+        }
+        Claim::New(_) | Claim::Unchecked => {}
+    }
+
+    // Persisted before answering, then published from the outbox in the background, so it
+    // is refused now if it would be when published
+    if let Some(local_outbox) = pipeline.local_outbox() {
+        if let Err(e) = pipeline.check_admission(&payload).await {
+            pipeline.reject(&payload, &e);
+            if let Some(idempotency) = pipeline.idempotency() {
+                idempotency.release(payload.id, &claim).await;
+            }
+            return Err(e);
+        }
+        let response = IngestResponse {
+            sequence_gap,
+            ..IngestResponse::published(payload.id, &[])
+        };
+        let added = local_outbox.add(&[&payload]).await;
+        if let Some(idempotency) = pipeline.idempotency() {
+            match &added {
+                Ok(()) => idempotency.complete(payload.id, &claim, &response).await,
+                Err(_) => idempotency.release(payload.id, &claim).await,
+            }
+        }
+        added?;
+        debug!("Persisted item {} to the local outbox", payload.id);
+        return Ok((StatusCode::CREATED, Json(response)));
+    }
+
+    // Pre-process and publish to NATS
+    let acks = match pipeline.process(&mut payload).await {
+        Ok(acks) => acks,
+        Err(e) => {
+            if let Some(idempotency) = pipeline.idempotency() {
+                idempotency.release(payload.id, &claim).await;
+            }
+            return Err(e);
+        }
+    };
+
     // Create response
     let response = IngestResponse {
-        status: "success".to_string(),
-        id: payload.id,
-        timestamp: Utc::now(),
+        sequence_gap,
+        ..IngestResponse::published(payload.id, &acks)
     };
-    
-    info!("Successfully ingested data with id: {}", payload.id);
-    
+    if let Some(idempotency) = pipeline.idempotency() {
+        idempotency.complete(payload.id, &claim, &response).await;
+    }
+
+    debug!("Successfully ingested data with id: {}", payload.id);
+
     Ok((StatusCode::CREATED, Json(response)))
 }
 
 /// Batch ingest multiple data items
-#[instrument(skip(nats_client, payload), fields(item_count = %payload.items.len()))]
+#[utoipa::path(
+    post,
+    path = "/ingest/batch",
+    tag = "ingest",
+    request_body = BatchRawData,
+    params(
+        ("Ingest-Batch-Id" = Option<String>, Header, description = "Batch the items belong to, attached to their messages and tracked in `/stats/batches/{id}`"),
+        ("If-Schema-Version" = Option<u32>, Header, description = "Schema version the producer was built against; refused with 412 once the registry has moved on incompatibly"),
+        ("Ingest-Deliver-At" = Option<String>, Header, description = "RFC 3339 time to publish the items at, when scheduled delivery is enabled"),
+        ("Ingest-Deliver-After" = Option<u32>, Header, description = "Seconds from now to publish the items after, when scheduled delivery is enabled")
+    ),
+    responses(
+        (status = 201, description = "Batch processed; `ids` lists the items that were ingested", body = BatchIngestResponse),
+        (status = 202, description = "Batch scheduled for delivery at `deliver_at`; `ids` lists the items that were scheduled", body = BatchIngestResponse),
+        (status = 400, description = "Empty batch", body = ErrorResponse),
+        (status = 412, description = "The pinned schema version is incompatible with the latest one", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(pipeline, headers, payload), fields(item_count = %payload.items.len()))]
 pub async fn ingest_batch(
-    Extension(nats_client): Extension<Arc<NatsClient>>,
-    Json(payload): Json<BatchRawData>,
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    headers: HeaderMap,
+    PooledJson(mut payload): PooledJson<BatchRawData>,
 ) -> Result<(StatusCode, Json<BatchIngestResponse>)> {
-    info!("Processing batch ingestion request with {} items", payload.items.len());
-    
+    debug!(
+        "Processing batch ingestion request with {} items",
+        payload.items.len()
+    );
+
     if payload.items.is_empty() {
-        warn!("Empty batch in ingestion request");
-        return Err(AppError::ValidationError("Batch contains no items".to_string()));
-    }
-    
-    let mut successful_ids = Vec::with_capacity(payload.items.len());
-    
-    // Process each item
-    for item in payload.items.iter() {
-        // Validate item
-        if item.source.is_empty() || item.content_type.is_empty() || item.payload.is_null() {
-            error!("Invalid item in batch, id: {}", item.id);
-            continue;
+        throttled!(warn!("Empty batch in ingestion request"));
+        return Err(AppError::ValidationError(
+            "Batch contains no items".to_string(),
+        ));
+    }
+
+    if let Some(batch_id) = batch::from_headers(&headers)? {
+        for item in payload.items.iter_mut() {
+            batch::tag(item, &batch_id);
         }
-        
-        // Determine subject
-        let subject = format!("ingest.raw.{}", item.content_type);
-        
-        // Publish to NATS
-        match nats_client.publish(&subject, item).await {
-            Ok(_) => {
-                successful_ids.push(item.id);
-                info!("Successfully published item {}", item.id);
-            },
-            Err(e) => {
-                error!("Failed to publish item {}: {}", item.id, e);
-                // Continue processing other items even if one fails
+    }
+    let deliver_at = requested_delivery(&pipeline, &headers)?;
+
+    check_schema_version(&pipeline, &headers, &payload.items).await?;
+
+    let total = payload.items.len();
+    if let Some((schedule, deliver_at)) = pipeline.schedule().zip(deliver_at) {
+        let mut scheduled_ids = Vec::with_capacity(total);
+        let mut sequence_gaps = Vec::new();
+        for item in &payload.items {
+            if let Err(e) = validate(item) {
+                throttled!(error!("Invalid item in batch, id: {}", item.id));
+                pipeline.reject(item, &e);
+                continue;
+            }
+            sequence_gaps.extend(pipeline.check_sequence(item));
+            if let Err(e) = pipeline.check_admission(item).await {
+                throttled!(error!("Item {} in batch was refused: {}", item.id, e));
+                pipeline.reject(item, &e);
+                continue;
             }
+            schedule.add(item, deliver_at).await?;
+            scheduled_ids.push(item.id);
         }
+        debug!(
+            "Scheduled {}/{} items for {}",
+            scheduled_ids.len(),
+            total,
+            deliver_at
+        );
+
+        let response = BatchIngestResponse {
+            status: "scheduled".to_string(),
+            count: scheduled_ids.len(),
+            ids: scheduled_ids,
+            timestamp: Utc::now(),
+            deliver_at: Some(deliver_at),
+            sequence_gaps,
+        };
+        return Ok((StatusCode::ACCEPTED, Json(response)));
     }
-    
+
+    let (successful_ids, sequence_gaps) = ingest_items(&pipeline, &mut payload.items).await;
+
     // Create response
     let response = BatchIngestResponse {
         status: "success".to_string(),
         count: successful_ids.len(),
         ids: successful_ids,
         timestamp: Utc::now(),
+        deliver_at: None,
+        sequence_gaps,
     };
-    
-    info!("Batch ingestion completed: {}/{} items successful", 
-          response.count, payload.items.len());
-    
+
+    debug!(
+        "Batch ingestion completed: {}/{} items successful",
+        response.count, total
+    );
+
     Ok((StatusCode::CREATED, Json(response)))
 }
+
+/// Cancel a scheduled item before it is published, e.g. when an embargoed release is pulled
+#[utoipa::path(
+    delete,
+    path = "/ingest/scheduled/{id}",
+    tag = "ingest",
+    params(("id" = Uuid, Path, description = "ID of the scheduled item")),
+    responses(
+        (status = 204, description = "Item cancelled"),
+        (status = 404, description = "No such item is pending on this replica, or it was already published", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(pipeline))]
+pub async fn cancel_scheduled(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode> {
+    let cancelled = match pipeline.schedule() {
+        Some(schedule) => schedule.cancel(id).await?,
+        None => false,
+    };
+    if !cancelled {
+        return Err(AppError::NotFound(format!("Item {} is not scheduled", id)));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Validate, deduplicate and publish a batch of items, returning the IDs of the items that
+/// were ingested and the gaps in their sequence numbers. Items that fail are logged and
+/// skipped.
+pub async fn ingest_items(
+    pipeline: &Pipeline,
+    items: &mut [RawData],
+) -> (Vec<Uuid>, Vec<SequenceGap>) {
+    let mut successful_ids = Vec::with_capacity(items.len());
+
+    // Validate each item
+    let mut valid = Vec::with_capacity(items.len());
+    let mut sequence_gaps = Vec::new();
+    for item in items.iter_mut() {
+        if let Err(e) = validate(item) {
+            throttled!(error!("Invalid item in batch, id: {}", item.id));
+            pipeline.reject(item, &e);
+            continue;
+        }
+        sequence_gaps.extend(pipeline.check_sequence(item));
+        valid.push(item);
+    }
+
+    // Skip items a retried batch already ingested
+    let mut claims = Vec::with_capacity(valid.len());
+    if let Some(idempotency) = pipeline.idempotency() {
+        let claimed = join_all(valid.iter().map(|item| idempotency.claim(item.id))).await;
+        let mut fresh = Vec::with_capacity(valid.len());
+        for (item, claim) in valid.into_iter().zip(claimed) {
+            match claim {
+                Claim::Done(_) => {
+                    debug!("Item {} was already ingested", item.id);
+                    successful_ids.push(item.id);
+                }
+                Claim::InFlight => throttled!(warn!(
+                    "Skipping item {}, it is already being ingested",
+                    item.id
+                )),
+                Claim::New(_) | Claim::Unchecked => {
+                    fresh.push(item);
+                    claims.push(claim);
+                }
+            }
+        }
+        valid = fresh;
+    }
+
+    // Persisted together before answering, then published from the outbox in the background,
+    // leaving out items that would be refused when published
+    if let Some(local_outbox) = pipeline.local_outbox() {
+        let mut admitted = Vec::with_capacity(valid.len());
+        for (index, item) in valid.iter().enumerate() {
+            let claim = claims.get(index);
+            match pipeline.check_admission(item).await {
+                Ok(()) => admitted.push((&**item, claim)),
+                Err(e) => {
+                    throttled!(error!("Item {} in batch was refused: {}", item.id, e));
+                    pipeline.reject(item, &e);
+                    if let Some((idempotency, claim)) = pipeline.idempotency().zip(claim) {
+                        idempotency.release(item.id, claim).await;
+                    }
+                }
+            }
+        }
+
+        let items: Vec<&RawData> = admitted.iter().map(|(item, _)| *item).collect();
+        let added = local_outbox.add(&items).await;
+        for (item, claim) in admitted {
+            let idempotency = pipeline.idempotency().zip(claim);
+            match &added {
+                Ok(()) => {
+                    if let Some((idempotency, claim)) = idempotency {
+                        idempotency
+                            .complete(item.id, claim, &IngestResponse::published(item.id, &[]))
+                            .await;
+                    }
+                    successful_ids.push(item.id);
+                }
+                Err(e) => {
+                    if let Some((idempotency, claim)) = idempotency {
+                        idempotency.release(item.id, claim).await;
+                    }
+                    throttled!(error!(
+                        "Failed to persist item {} to the local outbox: {}",
+                        item.id, e
+                    ));
+                }
+            }
+        }
+        return (successful_ids, sequence_gaps);
+    }
+
+    // Pre-process and publish to NATS, pipelining the publishes of the whole batch
+    let results = pipeline.process_batch(&mut valid).await;
+    for (index, (item, result)) in valid.iter().zip(results).enumerate() {
+        let idempotency = pipeline.idempotency().zip(claims.get(index));
+        match result {
+            Ok(acks) => {
+                if let Some((idempotency, claim)) = idempotency {
+                    idempotency
+                        .complete(item.id, claim, &IngestResponse::published(item.id, &acks))
+                        .await;
+                }
+                successful_ids.push(item.id);
+                debug!("Successfully published item {}", item.id);
+            }
+            Err(e) => {
+                if let Some((idempotency, claim)) = idempotency {
+                    idempotency.release(item.id, claim).await;
+                }
+                throttled!(error!("Failed to ingest item {}: {}", item.id, e));
+                // Other items are unaffected when one fails
+            }
+        }
+    }
+
+    (successful_ids, sequence_gaps)
+}
+
+/// Fetch a URL and ingest its content
+#[utoipa::path(
+    post,
+    path = "/ingest/url",
+    tag = "ingest",
+    request_body = UrlIngestRequest,
+    responses(
+        (status = 201, description = "URL content ingested", body = IngestResponse),
+        (status = 400, description = "Invalid request or URL", body = ErrorResponse),
+        (status = 403, description = "Fetch blocked by robots.txt, SSRF protection or the data classification", body = ErrorResponse),
+        (status = 502, description = "Fetching the URL failed", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(pipeline, fetcher, request), fields(source = %request.source, content_type = %request.content_type, url = %request.url))]
+pub async fn ingest_url(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Extension(fetcher): Extension<Arc<UrlFetcher>>,
+    Json(request): Json<UrlIngestRequest>,
+) -> Result<(StatusCode, Json<IngestResponse>)> {
+    debug!("Processing URL ingestion request: id={}", request.id);
+
+    if request.source.is_empty() || request.content_type.is_empty() {
+        throttled!(warn!(
+            "Missing source or content_type in URL ingestion request"
+        ));
+        return Err(AppError::ValidationError(
+            "Source and content type fields cannot be empty".to_string(),
+        ));
+    }
+
+    // Sensitive data must be pushed by its producer, not pulled from wherever a URL points
+    pipeline.check_fetch(&request.source, &request.content_type)?;
+
+    let mut item = RawData::builder()
+        .id(request.id)
+        .source(request.source)
+        .content_type(request.content_type)
+        .payload(fetcher.fetch(&request.url).await?)
+        .metadata(request.metadata)
+        .build()?;
+
+    let acks = pipeline.process(&mut item).await?;
+
+    let response = IngestResponse::published(item.id, &acks);
+
+    debug!("Successfully ingested URL content with id: {}", item.id);
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Delivery time a request asks its items to be published at, refused when scheduled
+/// delivery is not enabled rather than publishing them early
+fn requested_delivery(pipeline: &Pipeline, headers: &HeaderMap) -> Result<Option<DateTime<Utc>>> {
+    match pipeline.schedule() {
+        Some(schedule) => schedule.deliver_at(headers),
+        None if schedule::from_headers(headers)?.is_some() => {
+            Err(AppError::ValidationError(format!(
+                "Scheduled delivery is not enabled, so {} and {} are not accepted",
+                schedule::DELIVER_AT_HEADER,
+                schedule::DELIVER_AFTER_HEADER
+            )))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Check a request's `If-Schema-Version` against the content types of its items, so a
+/// producer pinned to an outdated schema is refused as a whole rather than item by item
+async fn check_schema_version(
+    pipeline: &Pipeline,
+    headers: &HeaderMap,
+    items: &[RawData],
+) -> Result<()> {
+    let Some(value) = headers.get(IF_SCHEMA_VERSION_HEADER) else {
+        return Ok(());
+    };
+    let version = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .ok_or_else(|| {
+            AppError::ValidationError(format!(
+                "{} must be a schema version number",
+                IF_SCHEMA_VERSION_HEADER
+            ))
+        })?;
+    let Some(registry) = pipeline.schema_registry() else {
+        return Ok(());
+    };
+
+    let mut content_types: Vec<&str> = items
+        .iter()
+        .map(|item| item.content_type.as_str())
+        .collect();
+    content_types.sort_unstable();
+    content_types.dedup();
+
+    for content_type in content_types {
+        if let Err(e) = registry.check_pinned(content_type, version).await {
+            warn!(
+                "Refusing {} items pinned to schema version {}: {}",
+                content_type, version, e
+            );
+            for item in items
+                .iter()
+                .filter(|item| item.content_type == content_type)
+            {
+                pipeline.reject(item, &e);
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate the required fields of an item
+pub fn validate(item: &RawData) -> Result<()> {
+    if item.source.is_empty() {
+        throttled!(warn!("Empty source field in ingestion request"));
+        return Err(AppError::ValidationError(
+            "Source field cannot be empty".to_string(),
+        ));
+    }
+
+    if item.content_type.is_empty() {
+        throttled!(warn!("Empty content_type field in ingestion request"));
+        return Err(AppError::ValidationError(
+            "Content type field cannot be empty".to_string(),
+        ));
+    }
+
+    if item.payload.is_null() {
+        throttled!(warn!("Empty payload in ingestion request"));
+        return Err(AppError::ValidationError(
+            "Payload cannot be null".to_string(),
+        ));
+    }
+
+    sequence::sequence(item)?;
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+use crate::nats::{Headers, NatsClient};
+use crate::subject::{SubjectTemplate, TokenPolicy};
+
+/// Prefix of the headers carrying a route's consumer hints
+pub const HINT_HEADER_PREFIX: &str = "Ingest-Hint-";
+
+/// Settings for routing content types to their own subjects and streams
+#[derive(Debug, Clone)]
+pub struct RoutingConfig {
+    /// JSON or YAML routing table, by file extension
+    pub file: PathBuf,
+
+    /// How often the table is re-read
+    pub refresh_interval: Duration,
+}
+
+/// Routing table file: one route per content type
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoutingTable {
+    #[serde(default)]
+    content_types: BTreeMap<String, RouteDefinition>,
+}
+
+/// How items of one content type are published
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteDefinition {
+    /// Subject template in the syntax of `NATS_SUBJECT_TEMPLATE`
+    #[serde(default)]
+    subject: Option<String>,
+
+    /// JetStream stream created to capture `subject`
+    #[serde(default)]
+    stream: Option<String>,
+
+    /// Hints for consumers, e.g. a priority, sent as `Ingest-Hint-{name}` headers
+    #[serde(default)]
+    hints: BTreeMap<String, String>,
+
+    /// Largest encoded message of the content type
+    #[serde(default)]
+    max_payload_bytes: Option<usize>,
+}
+
+/// A compiled route of a content type
+#[derive(Debug)]
+pub struct Route {
+    subject: Option<SubjectTemplate>,
+    stream: Option<String>,
+    headers: Headers,
+    max_payload_bytes: Option<usize>,
+}
+
+impl Route {
+    /// Subject for an item, unless the route leaves the subject to the default routing
+    pub fn subject(&self, item: &RawData) -> Option<Result<String>> {
+        self.subject.as_ref().map(|template| template.render(item))
+    }
+
+    /// Consumer hint headers of the route
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Largest encoded message of the content type
+    pub fn max_payload_bytes(&self) -> Option<usize> {
+        self.max_payload_bytes
+    }
+}
+
+type Routes = HashMap<String, Arc<Route>>;
+
+/// Per-content-type routes read from a table that operators edit, so traffic such as
+/// `research_paper` and `news_article` can be sent to different streams without a release.
+///
+/// A route may set a subject, a stream capturing it, consumer hints and a payload limit.
+/// The table is re-read periodically; a table that fails to parse, or whose new streams
+/// cannot be created, is ignored and the previous routes stay in place.
+pub struct ContentRouter {
+    config: RoutingConfig,
+    environment: String,
+    policy: TokenPolicy,
+    routes: RwLock<Arc<Routes>>,
+}
+
+impl ContentRouter {
+    /// Load the routing table
+    pub fn open(
+        config: RoutingConfig,
+        environment: &str,
+        policy: TokenPolicy,
+    ) -> Result<Arc<Self>> {
+        let routes = load(&config, environment, policy)?;
+        info!(
+            "Loaded {} content type routes from {}",
+            routes.len(),
+            config.file.display()
+        );
+
+        Ok(Arc::new(Self {
+            config,
+            environment: environment.to_string(),
+            policy,
+            routes: RwLock::new(Arc::new(routes)),
+        }))
+    }
+
+    /// Route of a content type, if it has one
+    pub fn get(&self, content_type: &str) -> Option<Arc<Route>> {
+        self.routes().get(content_type).cloned()
+    }
+
+    /// Create the streams of every route that names one
+    pub async fn provision(&self, nats_client: &NatsClient) -> Result<()> {
+        provision(&self.routes(), nats_client).await
+    }
+
+    /// Spawn the background task re-reading the table
+    pub fn spawn_refresher(self: Arc<Self>, nats_client: Arc<NatsClient>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.refresh_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let reloaded = match load(&self.config, &self.environment, self.policy) {
+                    Ok(routes) => provision(&routes, &nats_client).await.map(|_| routes),
+                    Err(e) => Err(e),
+                };
+                match reloaded {
+                    Ok(routes) => {
+                        let mut current = self.routes.write().unwrap_or_else(|e| e.into_inner());
+                        if describe(&current) != describe(&routes) {
+                            info!("Reloaded {} content type routes", routes.len());
+                        }
+                        *current = Arc::new(routes);
+                    }
+                    Err(e) => warn!(
+                        "Keeping current content type routes, reloading failed: {}",
+                        e
+                    ),
+                }
+            }
+        });
+    }
+
+    fn routes(&self) -> Arc<Routes> {
+        self.routes
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// Read and compile the routing table
+fn load(config: &RoutingConfig, environment: &str, policy: TokenPolicy) -> Result<Routes> {
+    let body = std::fs::read(&config.file).map_err(|e| {
+        AppError::ValidationError(format!(
+            "Failed to read routing table {}: {}",
+            config.file.display(),
+            e
+        ))
+    })?;
+    let table: RoutingTable = match config.file.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_slice(&body)
+            .map_err(|e| AppError::ValidationError(format!("Invalid YAML routing table: {}", e)))?,
+        _ => serde_json::from_slice(&body)
+            .map_err(|e| AppError::ValidationError(format!("Invalid JSON routing table: {}", e)))?,
+    };
+
+    let mut routes = Routes::new();
+    for (content_type, definition) in table.content_types {
+        let invalid = |reason: String| {
+            AppError::ValidationError(format!(
+                "Invalid route of content type {}: {}",
+                content_type, reason
+            ))
+        };
+
+        let subject = definition
+            .subject
+            .as_deref()
+            .map(|subject| SubjectTemplate::parse(subject, environment, policy))
+            .transpose()?;
+
+        if let Some(stream) = &definition.stream {
+            if subject.is_none() {
+                return Err(invalid("a stream needs a subject to capture".to_string()));
+            }
+            if stream.is_empty()
+                || stream.contains(|c: char| c == '.' || c == '*' || c == '>' || c.is_whitespace())
+            {
+                return Err(invalid(format!("invalid stream name {:?}", stream)));
+            }
+        }
+
+        let mut headers = Headers::new();
+        for (name, value) in definition.hints {
+            if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+                return Err(invalid(format!(
+                    "hint names are letters, digits and `-`, not {:?}",
+                    name
+                )));
+            }
+            if value.contains(|c: char| c.is_control()) {
+                return Err(invalid(format!(
+                    "hint {} contains control characters",
+                    name
+                )));
+            }
+            headers.push((format!("{}{}", HINT_HEADER_PREFIX, name), value));
+        }
+
+        if definition.max_payload_bytes == Some(0) {
+            return Err(invalid("max_payload_bytes must be positive".to_string()));
+        }
+
+        routes.insert(
+            content_type,
+            Arc::new(Route {
+                subject,
+                stream: definition.stream,
+                headers,
+                max_payload_bytes: definition.max_payload_bytes,
+            }),
+        );
+    }
+
+    Ok(routes)
+}
+
+/// Create the streams of routes that name one
+async fn provision(routes: &Routes, nats_client: &NatsClient) -> Result<()> {
+    for route in routes.values() {
+        if let (Some(stream), Some(subject)) = (&route.stream, &route.subject) {
+            nats_client
+                .ensure_stream(stream, &subject.stream_subject())
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Summary of routes to tell whether a reload changed anything
+fn describe(routes: &Routes) -> BTreeMap<&str, String> {
+    routes
+        .iter()
+        .map(|(content_type, route)| (content_type.as_str(), format!("{:?}", route)))
+        .collect()
+}
@@ -0,0 +1,383 @@
+use serde_json::Value;
+
+/// How embedded HTML should be sanitized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SanitizeMode {
+    /// Strip all markup and keep only the readable text
+    Text,
+
+    /// Keep a small set of formatting tags without attributes, escaping the text around them
+    Safe,
+}
+
+impl SanitizeMode {
+    /// Parse a mode name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "text" => Some(Self::Text),
+            "safe" => Some(Self::Safe),
+            _ => None,
+        }
+    }
+}
+
+/// Elements whose content is dropped entirely
+const DROPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "iframe", "object", "template",
+];
+
+/// Elements that are kept in safe mode
+const SAFE_ELEMENTS: &[&str] = &[
+    "p",
+    "br",
+    "b",
+    "i",
+    "em",
+    "strong",
+    "u",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "code",
+    "pre",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+/// Elements that imply a line break when converted to text
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "br",
+    "div",
+    "li",
+    "tr",
+    "blockquote",
+    "pre",
+    "section",
+    "article",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+/// Sanitize every string inside a JSON value in place
+pub fn sanitize_value(value: &mut Value, mode: SanitizeMode) {
+    match value {
+        Value::String(s) if s.contains('<') || s.contains('&') => {
+            *s = sanitize_html(s, mode);
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| sanitize_value(v, mode)),
+        Value::Object(map) => map.values_mut().for_each(|v| sanitize_value(v, mode)),
+        _ => {}
+    }
+}
+
+/// Sanitize a single HTML fragment
+pub fn sanitize_html(input: &str, mode: SanitizeMode) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find('<') {
+        push_text(&mut out, &rest[..start], mode);
+        rest = &rest[start..];
+
+        // Comments and doctype declarations are always removed
+        if let Some(body) = rest.strip_prefix("<!--") {
+            rest = body.find("-->").map_or("", |end| &body[end + 3..]);
+            continue;
+        }
+
+        let Some(end) = rest.find('>') else {
+            // Unterminated tag, treat the remainder as text
+            push_text(&mut out, rest, mode);
+            rest = "";
+            break;
+        };
+
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        if name.is_empty() {
+            continue;
+        }
+
+        // A self-closing element such as `<script/>` has no content to drop
+        if !closing && DROPPED_ELEMENTS.contains(&name.as_str()) {
+            if !tag.trim_end().ends_with('/') {
+                rest = skip_element(rest, &name);
+            }
+            continue;
+        }
+
+        match mode {
+            SanitizeMode::Safe if SAFE_ELEMENTS.contains(&name.as_str()) => {
+                out.push('<');
+                if closing {
+                    out.push('/');
+                }
+                out.push_str(&name);
+                out.push('>');
+            }
+            SanitizeMode::Text
+                if BLOCK_ELEMENTS.contains(&name.as_str())
+                    && !out.is_empty()
+                    && !out.ends_with('\n') =>
+            {
+                out.push('\n');
+            }
+            _ => {}
+        }
+    }
+
+    push_text(&mut out, rest, mode);
+
+    match mode {
+        SanitizeMode::Text => out.trim().to_string(),
+        SanitizeMode::Safe => out,
+    }
+}
+
+/// Skip past the closing tag of a dropped element. Without one, only the opening tag is
+/// dropped, so an unclosed element does not swallow the rest of the document.
+fn skip_element<'a>(rest: &'a str, name: &str) -> &'a str {
+    let closing = format!("</{}", name);
+    let lower = rest.to_ascii_lowercase();
+
+    match lower.find(&closing) {
+        Some(pos) => rest[pos..]
+            .find('>')
+            .map_or("", |end| &rest[pos + end + 1..]),
+        None => rest,
+    }
+}
+
+/// Append a run of text, decoding entities in text mode and escaping markup in safe mode
+fn push_text(out: &mut String, text: &str, mode: SanitizeMode) {
+    match mode {
+        SanitizeMode::Text => {
+            let decoded = decode_entities(text);
+            let mut last_space = out.ends_with(char::is_whitespace);
+            for c in decoded.chars() {
+                if c.is_whitespace() {
+                    if !last_space {
+                        out.push(' ');
+                        last_space = true;
+                    }
+                } else {
+                    out.push(c);
+                    last_space = false;
+                }
+            }
+        }
+        // Decoded first, so entities already in the text are not escaped twice
+        SanitizeMode::Safe => {
+            for c in decode_entities(text).chars() {
+                match c {
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    '&' => out.push_str("&amp;"),
+                    c => out.push(c),
+                }
+            }
+        }
+    }
+}
+
+/// Decode the common named and numeric HTML entities
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_script_content() {
+        let html = "<p>Hello</p><script>alert(1)</script><p>world</p>";
+        assert_eq!(sanitize_html(html, SanitizeMode::Text), "Hello\nworld");
+        assert_eq!(
+            sanitize_html(html, SanitizeMode::Safe),
+            "<p>Hello</p><p>world</p>"
+        );
+    }
+
+    #[test]
+    fn matches_closing_tags_case_insensitively() {
+        assert_eq!(
+            sanitize_html("a<SCRIPT>x()</Script>b", SanitizeMode::Text),
+            "ab"
+        );
+    }
+
+    #[test]
+    fn keeps_text_after_self_closing_dropped_element() {
+        assert_eq!(
+            sanitize_html("before<script/>after", SanitizeMode::Text),
+            "beforeafter"
+        );
+        assert_eq!(
+            sanitize_html("before<iframe src=\"x\" />after", SanitizeMode::Text),
+            "beforeafter"
+        );
+        assert_eq!(
+            sanitize_html("<b>before</b><script />after", SanitizeMode::Safe),
+            "<b>before</b>after"
+        );
+    }
+
+    #[test]
+    fn keeps_text_after_unclosed_dropped_element() {
+        assert_eq!(
+            sanitize_html(
+                "<p>before</p><iframe src=\"x\"><p>after</p>",
+                SanitizeMode::Text
+            ),
+            "before\nafter"
+        );
+        assert_eq!(
+            sanitize_html("before<style>after", SanitizeMode::Safe),
+            "beforeafter"
+        );
+    }
+
+    #[test]
+    fn strips_attributes_in_safe_mode() {
+        assert_eq!(
+            sanitize_html(
+                "<p onclick=\"x\">Hi <a href=\"y\">there</a></p>",
+                SanitizeMode::Safe
+            ),
+            "<p>Hi there</p>"
+        );
+    }
+
+    #[test]
+    fn decodes_entities_in_text_mode() {
+        assert_eq!(
+            sanitize_html(
+                "Fish &amp; chips &#x263A; &#65; &bogus;",
+                SanitizeMode::Text
+            ),
+            "Fish & chips \u{263A} A &bogus;"
+        );
+        assert_eq!(sanitize_html("a &amp; b", SanitizeMode::Safe), "a &amp; b");
+    }
+
+    #[test]
+    fn escapes_markup_in_text_in_safe_mode() {
+        assert_eq!(
+            sanitize_html("<p>1 &lt; 2 & 3 > 2</p>", SanitizeMode::Safe),
+            "<p>1 &lt; 2 &amp; 3 &gt; 2</p>"
+        );
+        assert_eq!(
+            sanitize_html("&lt;script&gt;alert(1)&lt;/script&gt;", SanitizeMode::Safe),
+            "&lt;script&gt;alert(1)&lt;/script&gt;"
+        );
+        assert_eq!(
+            sanitize_html("Fish &bogus; &#65;", SanitizeMode::Safe),
+            "Fish &amp;bogus; A"
+        );
+    }
+
+    #[test]
+    fn escapes_unterminated_tags_in_safe_mode() {
+        assert_eq!(
+            sanitize_html("<p>Hi</p><img src=x onerror=alert(1)", SanitizeMode::Safe),
+            "<p>Hi</p>&lt;img src=x onerror=alert(1)"
+        );
+        assert_eq!(
+            sanitize_html("<b>bold</b><scr", SanitizeMode::Safe),
+            "<b>bold</b>&lt;scr"
+        );
+    }
+
+    #[test]
+    fn safe_mode_output_is_stable() {
+        let html = "<p onclick=\"x\">a &amp; b <i>c</i></p><img src=x onerror=y";
+        let once = sanitize_html(html, SanitizeMode::Safe);
+        assert_eq!(sanitize_html(&once, SanitizeMode::Safe), once);
+    }
+
+    #[test]
+    fn removes_comments_and_collapses_whitespace() {
+        assert_eq!(
+            sanitize_html("a <!-- hidden --> \n\t b", SanitizeMode::Text),
+            "a b"
+        );
+        assert_eq!(
+            sanitize_html("a <!-- unterminated", SanitizeMode::Text),
+            "a"
+        );
+    }
+
+    #[test]
+    fn sanitizes_nested_json_strings() {
+        let mut value =
+            serde_json::json!({"title": "<b>x</b>", "tags": ["<i>y</i>", 1], "plain": "z"});
+        sanitize_value(&mut value, SanitizeMode::Text);
+        assert_eq!(
+            value,
+            serde_json::json!({"title": "x", "tags": ["y", 1], "plain": "z"})
+        );
+    }
+}
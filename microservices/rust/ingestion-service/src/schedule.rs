@@ -0,0 +1,311 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::encryption::{self, Cipher};
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+
+/// Header holding the RFC 3339 time an item is to be published at
+pub const DELIVER_AT_HEADER: &str = "Ingest-Deliver-At";
+
+/// Header holding how many seconds from now an item is to be published
+pub const DELIVER_AFTER_HEADER: &str = "Ingest-Deliver-After";
+
+/// Most due items published per poll
+const DELIVERY_BATCH: i64 = 100;
+
+/// Settings for delivering items at a requested time
+#[derive(Debug, Clone)]
+pub struct ScheduleConfig {
+    /// SQLite database file holding items until they are due
+    pub path: PathBuf,
+
+    /// Furthest ahead an item may be scheduled
+    pub max_delay: Duration,
+
+    /// How often due items are looked for
+    pub poll_interval: Duration,
+}
+
+/// Items held back until the time their producer asked for, e.g. embargoed press releases.
+///
+/// Items are validated and checked for admission when scheduled, and go through the whole
+/// pipeline, including quotas, pauses and schema validation, when they are due. Items that
+/// can then never be published are dead-lettered. They live in a local SQLite database,
+/// encrypted at rest when a cipher is given, so they survive restarts but are only
+/// delivered by the replica that accepted them.
+pub struct Schedule {
+    config: ScheduleConfig,
+    conn: Arc<Mutex<Connection>>,
+    cipher: Option<Arc<Cipher>>,
+}
+
+impl Schedule {
+    /// Open the database, creating the schema if needed
+    pub fn open(config: ScheduleConfig, cipher: Option<Arc<Cipher>>) -> Result<Self> {
+        if let Some(dir) = config
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(dir).map_err(schedule_error)?;
+        }
+
+        let conn = Connection::open(&config.path).map_err(schedule_error)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS scheduled (
+                 id TEXT PRIMARY KEY,
+                 deliver_at_ms INTEGER NOT NULL,
+                 item TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS scheduled_deliver_at ON scheduled (deliver_at_ms);",
+        )
+        .map_err(schedule_error)?;
+
+        let pending: i64 = conn
+            .query_row("SELECT count(*) FROM scheduled", [], |row| row.get(0))
+            .map_err(schedule_error)?;
+        info!(
+            "Holding scheduled items in {}, {} pending",
+            config.path.display(),
+            pending
+        );
+
+        Ok(Self {
+            config,
+            conn: Arc::new(Mutex::new(conn)),
+            cipher,
+        })
+    }
+
+    /// Time an ingest request asks its items to be published at, if later than now.
+    ///
+    /// Times in the past mean the items are published right away.
+    pub fn deliver_at(&self, headers: &HeaderMap) -> Result<Option<DateTime<Utc>>> {
+        let Some(deliver_at) = from_headers(headers)? else {
+            return Ok(None);
+        };
+
+        let now = Utc::now();
+        let max_delay =
+            chrono::Duration::from_std(self.config.max_delay).unwrap_or(chrono::Duration::MAX);
+        if deliver_at - now > max_delay {
+            return Err(AppError::ValidationError(format!(
+                "Items may be scheduled at most {} seconds ahead",
+                self.config.max_delay.as_secs()
+            )));
+        }
+
+        Ok((deliver_at > now).then_some(deliver_at))
+    }
+
+    /// Hold an item until it is due, replacing any scheduled item with the same ID
+    pub async fn add(&self, item: &RawData, deliver_at: DateTime<Utc>) -> Result<()> {
+        let id = item.id.to_string();
+        let deliver_at_ms = deliver_at.timestamp_millis();
+        let item = self.seal(item)?;
+
+        self.blocking(move |conn| {
+            conn.prepare_cached(
+                "INSERT OR REPLACE INTO scheduled (id, deliver_at_ms, item) VALUES (?1, ?2, ?3)",
+            )?
+            .execute(params![id, deliver_at_ms, item])
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Drop a scheduled item before it is published, returning whether it was pending
+    pub async fn cancel(&self, id: Uuid) -> Result<bool> {
+        let removed = self.remove(id).await?;
+        if removed {
+            info!("Cancelled scheduled item {}", id);
+        }
+        Ok(removed)
+    }
+
+    /// Publish due items every poll interval
+    pub fn spawn(self: Arc<Self>, pipeline: Arc<Pipeline>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.poll_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.deliver_due(&pipeline).await {
+                    throttled!(warn!("Failed to deliver scheduled items: {}", e));
+                }
+            }
+        });
+    }
+
+    /// Publish the items that are due, keeping those that failed for a reason that may pass
+    async fn deliver_due(&self, pipeline: &Pipeline) -> Result<()> {
+        loop {
+            let now_ms = Utc::now().timestamp_millis();
+            let due: Vec<(String, String)> = self
+                .blocking(move |conn| {
+                    let mut statement = conn.prepare_cached(
+                        "SELECT id, item FROM scheduled WHERE deliver_at_ms <= ?1 ORDER BY deliver_at_ms LIMIT ?2",
+                    )?;
+                    let rows = statement.query_map(params![now_ms, DELIVERY_BATCH], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                    rows.collect()
+                })
+                .await?;
+            if due.is_empty() {
+                return Ok(());
+            }
+            let count = due.len();
+
+            let mut items = Vec::with_capacity(count);
+            for (id, stored) in due {
+                match self.open_item(&stored) {
+                    Ok(item) => items.push(item),
+                    Err(e) => {
+                        error!("Dropping scheduled item {}, it cannot be read: {}", id, e);
+                        self.blocking(move |conn| {
+                            conn.execute("DELETE FROM scheduled WHERE id = ?1", params![id])
+                        })
+                        .await?;
+                    }
+                }
+            }
+            let mut refs: Vec<&mut RawData> = items.iter_mut().collect();
+            let results = pipeline.process_batch(&mut refs).await;
+
+            let mut retrying = 0;
+            for (item, result) in items.iter().zip(results) {
+                match result {
+                    Ok(_) => info!("Published scheduled item {}", item.id),
+                    Err(e) if retryable(&e) => {
+                        throttled!(warn!(
+                            "Scheduled item {} is due but failed, retrying: {}",
+                            item.id, e
+                        ));
+                        retrying += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        throttled!(error!(
+                            "Giving up on scheduled item {}, it cannot be published: {}",
+                            item.id, e
+                        ));
+                        pipeline.dead_letter(item, &e).await;
+                    }
+                }
+                self.remove(item.id).await?;
+            }
+
+            // Retried on the next poll, rather than straight away
+            if retrying > 0 || count < DELIVERY_BATCH as usize {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn remove(&self, id: Uuid) -> Result<bool> {
+        let id = id.to_string();
+        self.blocking(move |conn| {
+            conn.prepare_cached("DELETE FROM scheduled WHERE id = ?1")?
+                .execute(params![id])
+                .map(|deleted| deleted > 0)
+        })
+        .await
+    }
+
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&conn.lock().unwrap_or_else(|e| e.into_inner())))
+            .await
+            .map_err(|e| AppError::InternalError(format!("Schedule task failed: {}", e)))?
+            .map_err(schedule_error)
+    }
+
+    /// Serialize an item for storage, encrypting it when a cipher is configured
+    fn seal(&self, item: &RawData) -> Result<String> {
+        let json = serde_json::to_string(item).map_err(schedule_error)?;
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(json.as_bytes()),
+            None => Ok(json),
+        }
+    }
+
+    /// Read a stored item, which is plain JSON if it was written before encryption was enabled
+    fn open_item(&self, stored: &str) -> Result<RawData> {
+        if !encryption::is_encrypted(stored) {
+            return serde_json::from_str(stored).map_err(schedule_error);
+        }
+
+        let cipher = self.cipher.as_ref().ok_or_else(|| {
+            AppError::InternalError(
+                "Scheduled item is encrypted but no encryption keys are configured".to_string(),
+            )
+        })?;
+        serde_json::from_slice(&cipher.decrypt(stored)?).map_err(schedule_error)
+    }
+}
+
+/// Delivery time requested in `Ingest-Deliver-At` or `Ingest-Deliver-After`, if any
+pub fn from_headers(headers: &HeaderMap) -> Result<Option<DateTime<Utc>>> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .map(|value| {
+                value.to_str().map(str::trim).map_err(|_| {
+                    AppError::ValidationError(format!("{} must be printable ASCII", name))
+                })
+            })
+            .transpose()
+    };
+
+    match (header(DELIVER_AT_HEADER)?, header(DELIVER_AFTER_HEADER)?) {
+        (Some(_), Some(_)) => Err(AppError::ValidationError(format!(
+            "Send either {} or {}, not both",
+            DELIVER_AT_HEADER, DELIVER_AFTER_HEADER
+        ))),
+        (Some(at), None) => DateTime::parse_from_rfc3339(at)
+            .map(|at| Some(at.with_timezone(&Utc)))
+            .map_err(|_| {
+                AppError::ValidationError(format!("{} must be an RFC 3339 time", DELIVER_AT_HEADER))
+            }),
+        (None, Some(after)) => after
+            .parse::<u32>()
+            .map(|seconds| Some(Utc::now() + chrono::Duration::seconds(seconds.into())))
+            .map_err(|_| {
+                AppError::ValidationError(format!(
+                    "{} must be a number of seconds",
+                    DELIVER_AFTER_HEADER
+                ))
+            }),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Whether an item that failed to publish may succeed on a later attempt
+pub fn retryable(error: &AppError) -> bool {
+    matches!(
+        error,
+        AppError::NatsConnectionError(_)
+            | AppError::NatsPublishError(_)
+            | AppError::StreamUnavailable(_)
+            | AppError::DeadlineExceeded(_)
+            | AppError::RateLimited(_)
+            | AppError::Paused(_)
+    )
+}
+
+fn schedule_error(e: impl std::fmt::Display) -> AppError {
+    AppError::InternalError(format!("Schedule store error: {}", e))
+}
@@ -0,0 +1,517 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Extension, Path},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, instrument};
+
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+
+/// Settings for per-source validator scripts
+#[derive(Debug, Clone)]
+pub struct ScriptConfig {
+    /// Directory of `{source}.rhai` scripts installed at startup
+    pub dir: Option<PathBuf>,
+
+    /// Most operations one run of a script may take
+    pub max_operations: u64,
+
+    /// Longest one run of a script may take
+    pub timeout: Duration,
+
+    /// Largest accepted script source
+    pub max_script_bytes: usize,
+}
+
+thread_local! {
+    /// When the script running on this thread must stop
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// A compiled validator script
+struct ScriptValidator {
+    source: String,
+    ast: AST,
+    sha256: String,
+    updated_at: DateTime<Utc>,
+}
+
+impl ScriptValidator {
+    fn info(&self, source: &str) -> ScriptValidatorInfo {
+        ScriptValidatorInfo {
+            source: source.to_string(),
+            sha256: self.sha256.clone(),
+            bytes: self.source.len(),
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+/// Summary of a source's validator script
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptValidatorInfo {
+    pub source: String,
+
+    /// SHA-256 of the script, to tell which revision is installed
+    pub sha256: String,
+
+    /// Size of the script
+    pub bytes: usize,
+
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Per-source validators written in Rhai, for rules JSON Schema cannot express such as
+/// cross-field checks and checksums.
+///
+/// A script sees the item as `payload`, `metadata`, `source`, `content_type` and `id`, and
+/// accepts it by returning `true` or nothing. Returning `false`, returning a string or
+/// throwing rejects the item, the string or thrown value becoming the reason. Scripts cannot
+/// reach files or the network, and each run is cut short after `max_operations` operations
+/// or `timeout`. Strings over 64 KiB and arrays or maps over 10,000 entries are beyond a
+/// script's limits, so an item is rejected if its validator reads such a value. Validators are installed from a directory at startup and through the admin
+/// API; like imported sources, changes made through the API are kept in memory only.
+pub struct ScriptValidators {
+    engine: Engine,
+    config: ScriptConfig,
+    scripts: RwLock<BTreeMap<String, Arc<ScriptValidator>>>,
+}
+
+impl ScriptValidators {
+    /// Create the validators, installing the scripts of the startup directory if configured
+    pub fn open(config: ScriptConfig) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(config.max_operations)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(64 * 1024)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000)
+            .disable_symbol("eval")
+            .on_print(|text| debug!("Validator script printed: {}", text))
+            .on_debug(|text, _, _| debug!("Validator script debug: {}", text))
+            .on_progress(|_| {
+                DEADLINE
+                    .get()
+                    .filter(|deadline| Instant::now() >= *deadline)
+                    .map(|_| Dynamic::from("time limit exceeded"))
+            });
+
+        let validators = Self {
+            engine,
+            config,
+            scripts: RwLock::new(BTreeMap::new()),
+        };
+
+        if let Some(dir) = &validators.config.dir {
+            let entries = std::fs::read_dir(dir).map_err(|e| {
+                AppError::ValidationError(format!(
+                    "Failed to read validator script directory {}: {}",
+                    dir.display(),
+                    e
+                ))
+            })?;
+            for entry in entries {
+                let path = entry
+                    .map_err(|e| {
+                        AppError::ValidationError(format!(
+                            "Failed to read {}: {}",
+                            dir.display(),
+                            e
+                        ))
+                    })?
+                    .path();
+                let Some(source) = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .filter(|_| path.extension().is_some_and(|e| e == "rhai"))
+                else {
+                    continue;
+                };
+                let script = std::fs::read_to_string(&path).map_err(|e| {
+                    AppError::ValidationError(format!(
+                        "Failed to read validator script {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+                validators.put(source, &script)?;
+            }
+        }
+
+        Ok(validators)
+    }
+
+    /// Compile and install a source's validator, replacing any it had
+    pub fn put(&self, source: &str, script: &str) -> Result<ScriptValidatorInfo> {
+        if source.trim().is_empty() {
+            return Err(AppError::ValidationError(
+                "Source name cannot be empty".to_string(),
+            ));
+        }
+        if script.len() > self.config.max_script_bytes {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Validator script of {} bytes exceeds the limit of {} bytes",
+                script.len(),
+                self.config.max_script_bytes
+            )));
+        }
+
+        let ast = self.engine.compile(script).map_err(|e| {
+            AppError::ValidationError(format!(
+                "Invalid validator script for source {}: {}",
+                source, e
+            ))
+        })?;
+
+        let validator = Arc::new(ScriptValidator {
+            source: script.to_string(),
+            ast,
+            sha256: hex::encode(Sha256::digest(script.as_bytes())),
+            updated_at: Utc::now(),
+        });
+        let info = validator.info(source);
+        self.scripts
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(source.to_string(), validator);
+
+        info!(
+            "Installed validator script {} for source {}",
+            info.sha256, source
+        );
+
+        Ok(info)
+    }
+
+    /// Source of a source's validator script
+    pub fn get(&self, source: &str) -> Option<String> {
+        self.scripts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(source)
+            .map(|validator| validator.source.clone())
+    }
+
+    /// Remove a source's validator, returning whether it had one
+    pub fn remove(&self, source: &str) -> bool {
+        let removed = self
+            .scripts
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(source)
+            .is_some();
+        if removed {
+            info!("Removed validator script for source {}", source);
+        }
+        removed
+    }
+
+    /// Installed validators, by source
+    pub fn list(&self) -> Vec<ScriptValidatorInfo> {
+        self.scripts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(source, validator)| validator.info(source))
+            .collect()
+    }
+
+    /// Run an item's source validator, if it has one
+    pub fn check(&self, item: &RawData) -> Result<()> {
+        let Some(validator) = self
+            .scripts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&item.source)
+            .cloned()
+        else {
+            return Ok(());
+        };
+
+        let rejected = |reason: String| {
+            AppError::ValidationError(format!(
+                "Rejected by the validator of source {}: {}",
+                item.source, reason
+            ))
+        };
+
+        let mut scope = Scope::new();
+        scope.push_constant("payload", to_dynamic(&item.payload)?);
+        scope.push_constant("metadata", to_dynamic(&item.metadata)?);
+        scope.push_constant("source", item.source.clone());
+        scope.push_constant("content_type", item.content_type.clone());
+        scope.push_constant("id", item.id.to_string());
+
+        DEADLINE.set(Some(Instant::now() + self.config.timeout));
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &validator.ast);
+        DEADLINE.set(None);
+
+        match result {
+            Ok(value) if value.is_unit() || value.as_bool() == Ok(true) => Ok(()),
+            Ok(value) if value.as_bool() == Ok(false) => {
+                Err(rejected("validation failed".to_string()))
+            }
+            Ok(value) if value.is_string() => Err(rejected(value.to_string())),
+            Ok(value) => {
+                throttled!(warn!(
+                    "Validator of source {} returned a {} instead of a bool or string",
+                    item.source,
+                    value.type_name()
+                ));
+                Err(rejected(
+                    "validator returned an unexpected value".to_string(),
+                ))
+            }
+            Err(e) => match e.unwrap_inner() {
+                EvalAltResult::ErrorRuntime(reason, _) => Err(rejected(reason.to_string())),
+                EvalAltResult::ErrorTooManyOperations(_) | EvalAltResult::ErrorTerminated(..) => {
+                    throttled!(warn!(
+                        "Validator of source {} exceeded its limits on item {}",
+                        item.source, item.id
+                    ));
+                    Err(rejected("validator exceeded its limits".to_string()))
+                }
+                other => {
+                    throttled!(warn!(
+                        "Validator of source {} failed on item {}: {}",
+                        item.source, item.id, other
+                    ));
+                    Err(rejected(format!("validator failed: {}", other)))
+                }
+            },
+        }
+    }
+}
+
+fn to_dynamic(value: &serde_json::Value) -> Result<Dynamic> {
+    rhai::serde::to_dynamic(value).map_err(|e| {
+        AppError::InternalError(format!("Failed to pass item to validator script: {}", e))
+    })
+}
+
+/// List the installed validator scripts
+pub async fn list_validators(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+) -> Json<serde_json::Value> {
+    Json(json!({ "validators": pipeline.validators().list() }))
+}
+
+/// Fetch a source's validator script
+#[instrument(skip(pipeline))]
+pub async fn get_validator(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Path(source): Path<String>,
+) -> Result<Response> {
+    let script = pipeline
+        .validators()
+        .get(&source)
+        .ok_or_else(|| AppError::NotFound(format!("Source {} has no validator script", source)))?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        script,
+    )
+        .into_response())
+}
+
+/// Install a source's validator script, sent as the request body
+#[instrument(skip(pipeline, script))]
+pub async fn put_validator(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Path(source): Path<String>,
+    script: String,
+) -> Result<Json<ScriptValidatorInfo>> {
+    Ok(Json(pipeline.validators().put(&source, &script)?))
+}
+
+/// Remove a source's validator script
+#[instrument(skip(pipeline))]
+pub async fn delete_validator(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Path(source): Path<String>,
+) -> Result<StatusCode> {
+    if !pipeline.validators().remove(&source) {
+        return Err(AppError::NotFound(format!(
+            "Source {} has no validator script",
+            source
+        )));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validators(max_operations: u64, timeout: Duration) -> ScriptValidators {
+        ScriptValidators::open(ScriptConfig {
+            dir: None,
+            max_operations,
+            timeout,
+            max_script_bytes: 1024,
+        })
+        .unwrap()
+    }
+
+    fn item(payload: serde_json::Value) -> RawData {
+        RawData::builder()
+            .source("crm")
+            .content_type("contact")
+            .payload(payload)
+            .build()
+            .unwrap()
+    }
+
+    fn reason(result: Result<()>) -> String {
+        match result {
+            Err(AppError::ValidationError(reason)) => reason,
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn accepts_and_rejects_items_as_the_script_says() {
+        let validators = validators(10_000, Duration::from_secs(1));
+        validators
+            .put(
+                "crm",
+                r#"if payload.total != payload.net + payload.tax { "total does not add up" }"#,
+            )
+            .unwrap();
+
+        assert!(validators
+            .check(&item(json!({"net": 10, "tax": 2, "total": 12})))
+            .is_ok());
+        assert!(
+            reason(validators.check(&item(json!({"net": 10, "tax": 2, "total": 13}))))
+                .ends_with("total does not add up")
+        );
+        // Items of other sources are not checked
+        let mut other = item(json!({}));
+        other.source = "erp".to_string();
+        assert!(validators.check(&other).is_ok());
+    }
+
+    #[test]
+    fn false_thrown_and_unexpected_values_reject() {
+        let validators = validators(10_000, Duration::from_secs(1));
+
+        validators.put("crm", "false").unwrap();
+        assert!(reason(validators.check(&item(json!({})))).ends_with("validation failed"));
+
+        validators.put("crm", r#"throw "bad checksum""#).unwrap();
+        assert!(reason(validators.check(&item(json!({})))).ends_with("bad checksum"));
+
+        validators.put("crm", "42").unwrap();
+        assert!(reason(validators.check(&item(json!({}))))
+            .ends_with("validator returned an unexpected value"));
+    }
+
+    #[test]
+    fn rejects_malformed_scripts() {
+        let validators = validators(10_000, Duration::from_secs(1));
+
+        for script in ["if payload.total {", "let = 1;", "eval(\"true\")"] {
+            assert!(
+                matches!(
+                    validators.put("crm", script),
+                    Err(AppError::ValidationError(_))
+                ),
+                "{:?} was accepted",
+                script
+            );
+        }
+        assert!(validators.put(" ", "true").is_err());
+        assert!(validators.list().is_empty());
+    }
+
+    #[test]
+    fn rejects_oversized_scripts() {
+        let validators = validators(10_000, Duration::from_secs(1));
+
+        let script = format!("true // {}", "x".repeat(1024));
+        assert!(matches!(
+            validators.put("crm", &script),
+            Err(AppError::PayloadTooLarge(_))
+        ));
+        assert_eq!(validators.get("crm"), None);
+    }
+
+    #[test]
+    fn a_failed_put_keeps_the_installed_script() {
+        let validators = validators(10_000, Duration::from_secs(1));
+        validators.put("crm", "true").unwrap();
+
+        assert!(validators.put("crm", "if {").is_err());
+        assert_eq!(validators.get("crm").as_deref(), Some("true"));
+    }
+
+    #[test]
+    fn scripts_are_cut_short_after_their_operation_limit() {
+        let validators = validators(1_000, Duration::from_secs(60));
+        validators.put("crm", "loop {}").unwrap();
+
+        assert!(
+            reason(validators.check(&item(json!({})))).ends_with("validator exceeded its limits")
+        );
+    }
+
+    #[test]
+    fn scripts_are_cut_short_after_their_timeout() {
+        let validators = validators(u64::MAX, Duration::from_millis(50));
+        validators.put("crm", "loop {}").unwrap();
+
+        let started = Instant::now();
+        assert!(
+            reason(validators.check(&item(json!({})))).ends_with("validator exceeded its limits")
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn scripts_cannot_build_oversized_values() {
+        let validators = validators(1_000_000, Duration::from_secs(5));
+        validators
+            .put("crm", r#"let s = "x"; loop { s += s; }"#)
+            .unwrap();
+
+        assert!(validators.check(&item(json!({}))).is_err());
+    }
+
+    #[test]
+    fn values_over_the_script_limits_reject_items_whose_script_reads_them() {
+        let validators = validators(10_000, Duration::from_secs(1));
+        let oversized = item(json!({"notes": "x".repeat(100_000), "total": 12}));
+
+        validators
+            .put(
+                "crm",
+                r#"if payload.notes.len() > 100 { "notes are too long" }"#,
+            )
+            .unwrap();
+        assert!(reason(validators.check(&oversized)).contains("validator failed"));
+
+        validators.put("crm", "payload.total > 0").unwrap();
+        assert!(validators.check(&oversized).is_ok());
+    }
+}
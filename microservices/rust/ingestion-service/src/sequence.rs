@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use tracing::debug;
+
+use crate::error::{AppError, Result};
+use crate::models::{RawData, SequenceGap};
+
+/// Metadata field holding a producer's sequence number for the item
+pub const SEQUENCE_FIELD: &str = "sequence";
+
+/// Sources tracked before the longest idle are evicted
+const MAX_TRACKED_SOURCES: usize = 10_000;
+
+/// Sequence number an item was sent with, if any
+pub fn sequence(item: &RawData) -> Result<Option<u64>> {
+    match item.metadata.get(SEQUENCE_FIELD) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(value) => value.as_u64().map(Some).ok_or_else(|| {
+            AppError::ValidationError(format!(
+                "metadata.{} must be a non-negative integer",
+                SEQUENCE_FIELD
+            ))
+        }),
+    }
+}
+
+/// Last sequence number seen from each source, to spot items a producer lost or resent.
+///
+/// Sequences are per replica and since the service started, so the first item of a source is
+/// never a gap, and a source spread over several replicas sees gaps on each. Sources are
+/// client-supplied, so once too many are tracked the longest idle half is forgotten, and
+/// their next items count as their first.
+#[derive(Default)]
+pub struct SequenceTracker {
+    last: Mutex<HashMap<String, (u64, Instant)>>,
+}
+
+impl SequenceTracker {
+    /// Check an item's sequence number against the last one from its source, giving the gap
+    /// or regression it reveals.
+    ///
+    /// The same number as the last one is taken as a retry of that item. After a gap or
+    /// regression the item's number becomes the last one, so a producer that restarted its
+    /// count is reported once.
+    pub fn observe(&self, item: &RawData, sequence: u64) -> Option<SequenceGap> {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        if last.len() >= MAX_TRACKED_SOURCES && !last.contains_key(&item.source) {
+            let mut seen: Vec<Instant> = last.values().map(|(_, seen)| *seen).collect();
+            seen.sort_unstable();
+            let cutoff = seen[seen.len() / 2];
+            last.retain(|_, (_, seen)| *seen > cutoff);
+            debug!(
+                "Evicted idle sequence sources, {} still tracked",
+                last.len()
+            );
+        }
+        let (previous, _) = last.insert(item.source.clone(), (sequence, Instant::now()))?;
+        let expected = previous.saturating_add(1);
+
+        if sequence == previous || sequence == expected {
+            return None;
+        }
+        Some(SequenceGap {
+            id: item.id,
+            source: item.source.clone(),
+            kind: if sequence > expected {
+                "gap"
+            } else {
+                "regression"
+            }
+            .to_string(),
+            expected,
+            received: sequence,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn item(source: &str, sequence: u64) -> RawData {
+        RawData::builder()
+            .source(source)
+            .content_type("news_article")
+            .payload(json!({}))
+            .metadata(json!({ SEQUENCE_FIELD: sequence }))
+            .build()
+            .unwrap()
+    }
+
+    fn observe(tracker: &SequenceTracker, source: &str, sequence: u64) -> Option<SequenceGap> {
+        tracker.observe(&item(source, sequence), sequence)
+    }
+
+    #[test]
+    fn consecutive_and_repeated_sequences_are_not_gaps() {
+        let tracker = SequenceTracker::default();
+
+        assert!(observe(&tracker, "news-api", 7).is_none());
+        assert!(observe(&tracker, "news-api", 8).is_none());
+        assert!(observe(&tracker, "news-api", 8).is_none());
+        assert!(observe(&tracker, "news-api", 9).is_none());
+    }
+
+    #[test]
+    fn skipped_sequences_are_a_gap() {
+        let tracker = SequenceTracker::default();
+        observe(&tracker, "news-api", 1);
+
+        let gap = observe(&tracker, "news-api", 5).unwrap();
+        assert_eq!(gap.kind, "gap");
+        assert_eq!(gap.expected, 2);
+        assert_eq!(gap.received, 5);
+
+        // The gap is reported once, and counting continues from the new number
+        assert!(observe(&tracker, "news-api", 6).is_none());
+    }
+
+    #[test]
+    fn earlier_sequences_are_a_regression() {
+        let tracker = SequenceTracker::default();
+        observe(&tracker, "news-api", 10);
+
+        let gap = observe(&tracker, "news-api", 3).unwrap();
+        assert_eq!(gap.kind, "regression");
+        assert_eq!(gap.expected, 11);
+        assert_eq!(gap.received, 3);
+
+        // A producer that restarted its count is reported once
+        assert!(observe(&tracker, "news-api", 4).is_none());
+    }
+
+    #[test]
+    fn sources_are_tracked_separately() {
+        let tracker = SequenceTracker::default();
+        observe(&tracker, "news-api", 1);
+        observe(&tracker, "arxiv", 100);
+
+        assert!(observe(&tracker, "news-api", 2).is_none());
+        assert!(observe(&tracker, "arxiv", 101).is_none());
+    }
+
+    #[test]
+    fn idle_sources_are_evicted_once_too_many_are_tracked() {
+        let tracker = SequenceTracker::default();
+        observe(&tracker, "idle", 1);
+        for index in 1..MAX_TRACKED_SOURCES {
+            observe(&tracker, &format!("source-{}", index), 1);
+        }
+        assert_eq!(tracker.last.lock().unwrap().len(), MAX_TRACKED_SOURCES);
+
+        observe(&tracker, "new", 1);
+        let tracked = tracker.last.lock().unwrap().len();
+        assert!(tracked <= MAX_TRACKED_SOURCES / 2 + 1);
+
+        // The idle source was forgotten, so its next item counts as its first
+        assert!(observe(&tracker, "idle", 50).is_none());
+    }
+}
@@ -0,0 +1,275 @@
+use tracing::info;
+
+use crate::error::Result;
+use crate::models::RawData;
+use crate::nats::{Headers, NatsClient};
+
+/// Header carrying the shard a message was routed to
+pub const SHARD_HEADER: &str = "Ingest-Shard";
+
+/// Header carrying the total number of shards for the content type
+pub const SHARD_COUNT_HEADER: &str = "Ingest-Shard-Count";
+
+/// Which value messages are hashed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardKey {
+    /// The item's source
+    Source,
+
+    /// The item's `partition_key`, or for older clients `metadata.partition_key`, falling
+    /// back to the source when both are absent
+    PartitionKey,
+}
+
+impl ShardKey {
+    /// Parse a key name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "source" => Some(Self::Source),
+            "partition_key" => Some(Self::PartitionKey),
+            _ => None,
+        }
+    }
+}
+
+/// Settings for sharding hot content types across several JetStream streams
+#[derive(Debug, Clone)]
+pub struct ShardConfig {
+    /// Content types that are sharded, with their number of streams
+    pub content_types: Vec<(String, u32)>,
+
+    /// Value messages are hashed on
+    pub key: ShardKey,
+}
+
+/// Routes items of sharded content types to one of N streams by consistent hashing.
+///
+/// Shard `n` of a content type is published to `ingest.raw.{content_type}.{n}` and captured
+/// by the stream `INGEST_{CONTENT_TYPE}_{n}`. Jump consistent hashing keeps most keys on
+/// their shard when the count changes.
+pub struct Sharder {
+    config: ShardConfig,
+}
+
+impl Sharder {
+    /// Create a new sharder
+    pub fn new(config: ShardConfig) -> Self {
+        Self { config }
+    }
+
+    /// Create the streams for every shard that does not exist yet
+    pub async fn provision(&self, nats_client: &NatsClient) -> Result<()> {
+        for (content_type, count) in &self.config.content_types {
+            for shard in 0..*count {
+                nats_client
+                    .ensure_stream(
+                        &stream_name(content_type, shard),
+                        &shard_subject(content_type, shard),
+                    )
+                    .await?;
+            }
+        }
+
+        info!(
+            "Provisioned {} shards for {} content types",
+            self.config
+                .content_types
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<u32>(),
+            self.config.content_types.len()
+        );
+
+        Ok(())
+    }
+
+    /// Subject and headers for an item of a sharded content type.
+    ///
+    /// Items of an `ordered` source are hashed on the source whatever the key, so they all
+    /// land on one shard and keep their order there.
+    pub fn route(&self, item: &RawData, ordered: bool) -> Option<(String, Headers)> {
+        let count = self
+            .config
+            .content_types
+            .iter()
+            .find(|(content_type, _)| *content_type == item.content_type)
+            .map(|(_, count)| *count)?;
+
+        let key = match self.config.key {
+            ShardKey::PartitionKey if !ordered => item
+                .partition_key
+                .as_deref()
+                .or_else(|| item.metadata.get("partition_key").and_then(|v| v.as_str()))
+                .unwrap_or(&item.source),
+            _ => item.source.as_str(),
+        };
+
+        let shard = jump_hash(fnv1a(key.as_bytes()), count);
+        let headers = vec![
+            (SHARD_HEADER.to_string(), shard.to_string()),
+            (SHARD_COUNT_HEADER.to_string(), count.to_string()),
+        ];
+
+        Some((shard_subject(&item.content_type, shard), headers))
+    }
+}
+
+fn shard_subject(content_type: &str, shard: u32) -> String {
+    format!("ingest.raw.{}.{}", content_type, shard)
+}
+
+/// Stream names may not contain `.`, `*`, `>` or whitespace
+fn stream_name(content_type: &str, shard: u32) -> String {
+    let content_type: String = content_type
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("INGEST_{}_{}", content_type, shard)
+}
+
+/// Jump consistent hash (Lamping and Veach)
+fn jump_hash(mut key: u64, buckets: u32) -> u32 {
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b.max(0) as u32
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sharder(key: ShardKey) -> Sharder {
+        Sharder::new(ShardConfig {
+            content_types: vec![("customer_event".to_string(), 64)],
+            key,
+        })
+    }
+
+    fn item(partition_key: Option<&str>, metadata: serde_json::Value) -> RawData {
+        let mut item = RawData::builder()
+            .source("crm")
+            .content_type("customer_event")
+            .payload(json!({}))
+            .metadata(metadata)
+            .build()
+            .unwrap();
+        item.partition_key = partition_key.map(str::to_string);
+        item
+    }
+
+    fn subject(sharder: &Sharder, item: &RawData, ordered: bool) -> String {
+        sharder.route(item, ordered).unwrap().0
+    }
+
+    fn subject_of(key: &str) -> String {
+        shard_subject("customer_event", jump_hash(fnv1a(key.as_bytes()), 64))
+    }
+
+    #[test]
+    fn partition_key_is_hashed_in_preference_to_the_source() {
+        let sharder = sharder(ShardKey::PartitionKey);
+
+        let keyed = item(
+            Some("customer-4711"),
+            json!({ "partition_key": "customer-42" }),
+        );
+        assert_eq!(
+            subject(&sharder, &keyed, false),
+            subject_of("customer-4711")
+        );
+
+        let legacy = item(None, json!({ "partition_key": "customer-42" }));
+        assert_eq!(subject(&sharder, &legacy, false), subject_of("customer-42"));
+
+        let unkeyed = item(None, json!({}));
+        assert_eq!(subject(&sharder, &unkeyed, false), subject_of("crm"));
+    }
+
+    #[test]
+    fn partition_key_is_ignored_for_ordered_sources_and_source_sharding() {
+        let keyed = item(Some("customer-4711"), json!({}));
+        assert_ne!(subject_of("customer-4711"), subject_of("crm"));
+
+        assert_eq!(
+            subject(&sharder(ShardKey::PartitionKey), &keyed, true),
+            subject_of("crm")
+        );
+        assert_eq!(
+            subject(&sharder(ShardKey::Source), &keyed, false),
+            subject_of("crm")
+        );
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn jump_hash_values_are_pinned() {
+        // Shards must not move between releases, or per-key order breaks across an upgrade
+        assert_eq!(jump_hash(0, 1), 0);
+        assert_eq!(jump_hash(1, 10), 6);
+        assert_eq!(jump_hash(256, 1024), 520);
+        assert_eq!(jump_hash(0xdead_beef, 100), 87);
+        assert_eq!(jump_hash(u64::MAX, 1000), 313);
+    }
+
+    #[test]
+    fn jump_hash_stays_in_range() {
+        for buckets in 1..=64 {
+            for index in 0..1000 {
+                let key = fnv1a(format!("key-{}", index).as_bytes());
+                assert!(jump_hash(key, buckets) < buckets);
+            }
+        }
+    }
+
+    #[test]
+    fn adding_a_shard_only_moves_keys_to_it() {
+        let keys: Vec<u64> = (0..10_000)
+            .map(|index| fnv1a(format!("customer-{}", index).as_bytes()))
+            .collect();
+
+        for buckets in 1..=32 {
+            let mut moved = 0;
+            for &key in &keys {
+                let before = jump_hash(key, buckets);
+                let after = jump_hash(key, buckets + 1);
+                if after != before {
+                    assert_eq!(after, buckets, "a key moved between existing shards");
+                    moved += 1;
+                }
+            }
+            // About one key in n + 1 moves to the new shard
+            let expected = keys.len() / (buckets as usize + 1);
+            assert!(
+                moved > expected / 2 && moved < expected * 2,
+                "{} keys moved going to {} shards",
+                moved,
+                buckets + 1
+            );
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use crate::classification::Classification;
+use crate::error::{AppError, Result};
+use crate::logging::throttled;
+use crate::models::RawData;
+use crate::nats::Headers;
+
+/// Header carrying the registry ID of the schema a payload was validated against
+pub const SCHEMA_ID_HEADER: &str = "Ingest-Schema-Id";
+
+/// Header carrying the registry subject the schema is registered under
+pub const SCHEMA_SUBJECT_HEADER: &str = "Ingest-Schema-Subject";
+
+/// Header carrying the version of the schema within its registry subject
+pub const SCHEMA_VERSION_HEADER: &str = "Ingest-Schema-Version";
+
+/// Per-source ingestion limits
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceQuota {
+    /// Maximum items accepted per minute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_items_per_minute: Option<u32>,
+
+    /// Maximum serialized payload size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_payload_bytes: Option<usize>,
+}
+
+/// Per-source routing overrides
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceRouting {
+    /// Subject used instead of the one rendered from the subject template
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+
+    /// Publish the source's items one at a time, in the order they were accepted, to a
+    /// single shard
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ordered: bool,
+}
+
+/// Where a source's schema is registered in a schema registry, following the Confluent
+/// model of a global ID and a versioned subject
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaRegistryRef {
+    /// Global schema ID
+    pub id: u64,
+
+    /// Subject the schema is registered under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+
+    /// Version within the subject
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+}
+
+/// Registered producer source and the rules applied to its items
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceDefinition {
+    /// Source name as sent in `RawData.source`
+    pub name: String,
+
+    /// Human-readable description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Tenant the source's usage is charged to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+
+    /// Sensitivity of the source's data, enforced at ingest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classification: Option<Classification>,
+
+    /// Content types the source may send; empty allows any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content_types: Vec<String>,
+
+    /// JSON Schema every payload from this source must satisfy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+
+    /// Registry entry of `schema`, stamped into the headers of validated messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_registry: Option<SchemaRegistryRef>,
+
+    /// Ingestion limits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<SourceQuota>,
+
+    /// Routing overrides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routing: Option<SourceRouting>,
+}
+
+/// Reviewed manifest describing every registered source
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceManifest {
+    /// Source definitions
+    #[serde(default)]
+    pub sources: Vec<SourceDefinition>,
+}
+
+/// Serialization formats accepted and produced for manifests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Json,
+    Yaml,
+}
+
+impl ManifestFormat {
+    /// Pick the format from a MIME type, defaulting to JSON
+    pub fn from_mime(mime: &str) -> Self {
+        if mime.contains("yaml") {
+            Self::Yaml
+        } else {
+            Self::Json
+        }
+    }
+
+    /// MIME type for responses in this format
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Yaml => "application/yaml",
+        }
+    }
+}
+
+impl SourceManifest {
+    /// Parse a manifest in the given format
+    pub fn parse(body: &[u8], format: ManifestFormat) -> Result<Self> {
+        match format {
+            ManifestFormat::Json => serde_json::from_slice(body)
+                .map_err(|e| AppError::ValidationError(format!("Invalid JSON manifest: {}", e))),
+            ManifestFormat::Yaml => serde_yaml::from_slice(body)
+                .map_err(|e| AppError::ValidationError(format!("Invalid YAML manifest: {}", e))),
+        }
+    }
+
+    /// Read a manifest file; `.yaml` and `.yml` files are YAML, anything else JSON
+    pub async fn read(path: &Path) -> Result<Self> {
+        let body = tokio::fs::read(path).await.map_err(|e| {
+            AppError::ValidationError(format!(
+                "Failed to read source manifest {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let format = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => ManifestFormat::Yaml,
+            _ => ManifestFormat::Json,
+        };
+        Self::parse(&body, format)
+    }
+
+    /// Render the manifest in the given format
+    pub fn render(&self, format: ManifestFormat) -> Result<String> {
+        match format {
+            ManifestFormat::Json => serde_json::to_string_pretty(self)
+                .map_err(|e| AppError::InternalError(format!("Failed to render manifest: {}", e))),
+            ManifestFormat::Yaml => serde_yaml::to_string(self)
+                .map_err(|e| AppError::InternalError(format!("Failed to render manifest: {}", e))),
+        }
+    }
+}
+
+/// A registered source with its compiled schema
+struct RegisteredSource {
+    definition: SourceDefinition,
+    validator: Option<jsonschema::Validator>,
+}
+
+/// Fixed one-minute window counter for per-source rate quotas
+struct RateWindow {
+    started: Instant,
+    count: u32,
+}
+
+/// Registry of known sources, their schemas, quotas and routing overrides.
+///
+/// Items from unregistered sources pass through unchecked.
+#[derive(Default)]
+pub struct SourceRegistry {
+    sources: RwLock<BTreeMap<String, RegisteredSource>>,
+    windows: Mutex<HashMap<String, RateWindow>>,
+}
+
+impl SourceRegistry {
+    /// Import a manifest, replacing all sources or merging into the existing set.
+    ///
+    /// The whole manifest is validated before anything is applied.
+    pub fn import(&self, manifest: SourceManifest, replace: bool) -> Result<usize> {
+        let mut compiled = BTreeMap::new();
+
+        for definition in manifest.sources {
+            if definition.name.trim().is_empty() {
+                return Err(AppError::ValidationError(
+                    "Source name cannot be empty".to_string(),
+                ));
+            }
+
+            let validator = definition
+                .schema
+                .as_ref()
+                .map(|schema| {
+                    jsonschema::validator_for(schema).map_err(|e| {
+                        AppError::ValidationError(format!(
+                            "Invalid schema for source {}: {}",
+                            definition.name, e
+                        ))
+                    })
+                })
+                .transpose()?;
+
+            if definition.schema_registry.is_some() && validator.is_none() {
+                return Err(AppError::ValidationError(format!(
+                    "Source {} has a schema_registry entry but no schema",
+                    definition.name
+                )));
+            }
+
+            if compiled.contains_key(&definition.name) {
+                return Err(AppError::ValidationError(format!(
+                    "Duplicate source {}",
+                    definition.name
+                )));
+            }
+
+            compiled.insert(
+                definition.name.clone(),
+                RegisteredSource {
+                    definition,
+                    validator,
+                },
+            );
+        }
+
+        let count = compiled.len();
+        let mut sources = self.sources.write().unwrap_or_else(|e| e.into_inner());
+        if replace {
+            *sources = compiled;
+        } else {
+            sources.extend(compiled);
+        }
+
+        info!(
+            "Imported {} source definitions ({} registered)",
+            count,
+            sources.len()
+        );
+
+        Ok(count)
+    }
+
+    /// Export all registered sources as a manifest
+    pub fn export(&self) -> SourceManifest {
+        let sources = self.sources.read().unwrap_or_else(|e| e.into_inner());
+        SourceManifest {
+            sources: sources.values().map(|s| s.definition.clone()).collect(),
+        }
+    }
+
+    /// Check an item against its source's content types, schema and quotas
+    pub fn check(&self, item: &RawData) -> Result<()> {
+        self.check_item(item, true)
+    }
+
+    /// Check an item as [`SourceRegistry::check`] does, without counting it against its
+    /// source's quota, for items checked when accepted and counted when published
+    pub fn check_uncounted(&self, item: &RawData) -> Result<()> {
+        self.check_item(item, false)
+    }
+
+    fn check_item(&self, item: &RawData, count: bool) -> Result<()> {
+        let sources = self.sources.read().unwrap_or_else(|e| e.into_inner());
+        let Some(source) = sources.get(&item.source) else {
+            return Ok(());
+        };
+        let definition = &source.definition;
+
+        if !definition.content_types.is_empty()
+            && !definition.content_types.contains(&item.content_type)
+        {
+            return Err(AppError::ValidationError(format!(
+                "Source {} may not send content type {}",
+                item.source, item.content_type
+            )));
+        }
+
+        if let Some(validator) = &source.validator {
+            if let Err(e) = validator.validate(&item.payload) {
+                return Err(AppError::ValidationError(format!(
+                    "Payload does not match schema: {}",
+                    e
+                )));
+            }
+        }
+
+        let quota = definition.quota.clone().unwrap_or_default();
+
+        if let Some(max_bytes) = quota.max_payload_bytes {
+            let size = serde_json::to_vec(&item.payload)
+                .map(|v| v.len())
+                .unwrap_or(0);
+            if size > max_bytes {
+                return Err(AppError::ValidationError(format!(
+                    "Payload of {} bytes exceeds the {} byte limit for source {}",
+                    size, max_bytes, item.source
+                )));
+            }
+        }
+
+        if let Some(max_per_minute) = quota.max_items_per_minute {
+            let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+            let window = windows.entry(item.source.clone()).or_insert(RateWindow {
+                started: Instant::now(),
+                count: 0,
+            });
+
+            if window.started.elapsed() >= Duration::from_secs(60) {
+                window.started = Instant::now();
+                window.count = 0;
+            }
+
+            if window.count >= max_per_minute {
+                throttled!(warn!(
+                    "Source {} exceeded its quota of {} items per minute",
+                    item.source, max_per_minute
+                ));
+                return Err(AppError::RateLimited(format!(
+                    "Source {} exceeded its quota of {} items per minute",
+                    item.source, max_per_minute
+                )));
+            }
+            if count {
+                window.count += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Headers identifying the registered schema a source's payloads were validated against
+    pub fn schema_headers(&self, source: &str) -> Headers {
+        let sources = self.sources.read().unwrap_or_else(|e| e.into_inner());
+        let Some(registry) = sources
+            .get(source)
+            .and_then(|s| s.definition.schema_registry.as_ref())
+        else {
+            return Headers::new();
+        };
+
+        let mut headers = vec![(SCHEMA_ID_HEADER.to_string(), registry.id.to_string())];
+        if let Some(subject) = &registry.subject {
+            headers.push((SCHEMA_SUBJECT_HEADER.to_string(), subject.clone()));
+        }
+        if let Some(version) = registry.version {
+            headers.push((SCHEMA_VERSION_HEADER.to_string(), version.to_string()));
+        }
+        headers
+    }
+
+    /// Tenant a source is assigned to
+    pub fn tenant(&self, source: &str) -> Option<String> {
+        let sources = self.sources.read().unwrap_or_else(|e| e.into_inner());
+        sources.get(source)?.definition.tenant.clone()
+    }
+
+    /// Classification a source is labelled with
+    pub fn classification(&self, source: &str) -> Option<Classification> {
+        let sources = self.sources.read().unwrap_or_else(|e| e.into_inner());
+        sources.get(source)?.definition.classification
+    }
+
+    /// Whether a source's items must be published in the order they were accepted
+    pub fn is_ordered(&self, source: &str) -> bool {
+        let sources = self.sources.read().unwrap_or_else(|e| e.into_inner());
+        sources
+            .get(source)
+            .and_then(|s| s.definition.routing.as_ref())
+            .is_some_and(|routing| routing.ordered)
+    }
+
+    /// Subject override configured for a source
+    pub fn subject_override(&self, source: &str) -> Option<String> {
+        let sources = self.sources.read().unwrap_or_else(|e| e.into_inner());
+        sources
+            .get(source)?
+            .definition
+            .routing
+            .as_ref()?
+            .subject
+            .clone()
+    }
+}
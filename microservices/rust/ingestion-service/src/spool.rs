@@ -0,0 +1,891 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, SeekFrom};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::bus::MessageBus;
+use crate::encryption::{self, Cipher};
+use crate::error::{AppError, Result};
+use crate::expiry;
+use crate::nats::Headers;
+use crate::retention::{PurgeReport, RetentionPolicy};
+use crate::stats::IngestStats;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+
+/// Maximum number of records republished per drain pass
+const DRAIN_BATCH: usize = 1000;
+
+/// Settings for the on-disk spool of messages that could not be published
+#[derive(Debug, Clone)]
+pub struct SpoolConfig {
+    /// Directory holding the spool files
+    pub dir: PathBuf,
+
+    /// Whether every append is flushed to disk before returning
+    pub fsync: bool,
+
+    /// Pause between drain passes
+    pub drain_interval: Duration,
+
+    /// Keep `/ready` failing after a restart until the backlog drops to this many records
+    pub ready_threshold: Option<u64>,
+
+    /// Append through io_uring where the build supports it
+    pub io_uring: bool,
+}
+
+/// A message waiting to be republished
+#[derive(Debug, Serialize, Deserialize)]
+struct SpoolRecord {
+    subject: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    headers: Headers,
+    payload: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spooled_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+}
+
+struct SpoolFile {
+    file: File,
+    len: u64,
+    offset: u64,
+}
+
+/// What startup recovery found in the spool
+#[derive(Debug, Clone, Default)]
+struct RecoveryReport {
+    /// Records waiting to be republished
+    recovered: u64,
+
+    /// Corrupt records moved to `spool.quarantine`
+    quarantined: u64,
+
+    /// Bytes moved to `spool.quarantine`
+    quarantined_bytes: u64,
+}
+
+/// Append-only disk spool for messages that failed to publish.
+///
+/// Records are appended to `spool.log` and republished in order by a background drainer,
+/// which tracks its progress in `spool.offset` and truncates both files once caught up.
+/// Each line carries a CRC32 of its record, so records torn by a crash or damaged on disk
+/// are detected and quarantined instead of being republished or blocking the backlog.
+pub struct Spool {
+    config: SpoolConfig,
+    file: Mutex<SpoolFile>,
+    cipher: Option<Arc<Cipher>>,
+    /// Held while draining or purging, since purging rewrites the offsets a drain relies on
+    exclusive: Mutex<()>,
+    pending: AtomicU64,
+    drained_once: AtomicBool,
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    uring: Option<uring::UringWriter>,
+}
+
+impl Spool {
+    /// Open the spool, recovering any backlog left by a previous run.
+    ///
+    /// Payloads are encrypted at rest when a cipher is given.
+    pub async fn open(config: SpoolConfig, cipher: Option<Arc<Cipher>>) -> Result<Self> {
+        fs::create_dir_all(&config.dir).await.map_err(spool_error)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(config.dir.join("spool.log"))
+            .await
+            .map_err(spool_error)?;
+        let len = file.metadata().await.map_err(spool_error)?.len();
+
+        let offset = fs::read_to_string(config.dir.join("spool.offset"))
+            .await
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .filter(|&offset| offset <= len)
+            .unwrap_or(0);
+
+        let mut spool_file = SpoolFile { file, len, offset };
+        let recovery = recover(&config, &mut spool_file).await?;
+
+        if recovery.quarantined > 0 {
+            warn!(
+                "Quarantined {} corrupt spool records ({} bytes) to {}",
+                recovery.quarantined,
+                recovery.quarantined_bytes,
+                config.dir.join("spool.quarantine").display()
+            );
+        }
+        if recovery.recovered > 0 {
+            info!(
+                "Recovered {} spooled messages from {}",
+                recovery.recovered,
+                config.dir.display()
+            );
+        }
+
+        // io_uring is only used when the kernel allows it; otherwise appends go through tokio
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        let uring = match config.io_uring {
+            true => match uring::UringWriter::start(config.dir.join("spool.log")).await {
+                Ok(writer) => {
+                    info!("Appending to the spool through io_uring");
+                    Some(writer)
+                }
+                Err(e) => {
+                    warn!(
+                        "io_uring is unavailable, appending to the spool through tokio: {}",
+                        e
+                    );
+                    None
+                }
+            },
+            false => None,
+        };
+        #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+        if config.io_uring {
+            warn!("SPOOL_IO_URING needs a Linux build with the io-uring feature, appending through tokio");
+        }
+
+        Ok(Self {
+            config,
+            file: Mutex::new(spool_file),
+            cipher,
+            exclusive: Mutex::new(()),
+            pending: AtomicU64::new(recovery.recovered),
+            drained_once: AtomicBool::new(false),
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            uring,
+        })
+    }
+
+    /// Number of records waiting to be republished
+    pub fn pending(&self) -> u64 {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Whether the spool backlog allows the service to report ready.
+    ///
+    /// Once the backlog has drained below the threshold the service stays ready, so a
+    /// later NATS outage doesn't take every replica out of rotation at once.
+    pub fn is_ready(&self) -> bool {
+        let Some(threshold) = self.config.ready_threshold else {
+            return true;
+        };
+
+        if self.drained_once.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        if self.pending() <= threshold {
+            self.drained_once.store(true, Ordering::Relaxed);
+            return true;
+        }
+
+        false
+    }
+
+    /// Append a message to the spool, with the source it came from when known
+    pub async fn append(
+        &self,
+        source: Option<&str>,
+        subject: &str,
+        headers: &Headers,
+        payload: &[u8],
+    ) -> Result<()> {
+        let record = SpoolRecord {
+            subject: subject.to_string(),
+            headers: headers.clone(),
+            payload: match &self.cipher {
+                Some(cipher) => cipher.encrypt(payload)?,
+                None => STANDARD.encode(payload),
+            },
+            spooled_at: Some(Utc::now()),
+            source: source.map(str::to_string),
+        };
+        let line = encode_line(&record)?;
+
+        let mut spool = self.file.lock().await;
+        let written = line.len() as u64;
+        self.write_line(&mut spool, line).await?;
+        spool.len += written;
+        self.pending.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Republish spooled messages until the spool is empty or a publish fails.
+    ///
+    /// Messages past their `Ingest-Expires-At` are dropped instead, and counted in `stats`.
+    pub async fn drain(&self, bus: &dyn MessageBus, stats: &IngestStats) -> Result<usize> {
+        let _exclusive = self.exclusive.lock().await;
+        let mut drained = 0;
+        let mut expired = 0;
+        let mut quarantined = 0;
+
+        loop {
+            let records = {
+                let mut spool = self.file.lock().await;
+                let offset = spool.offset;
+                read_records(&mut spool, offset, DRAIN_BATCH).await?
+            };
+            if records.is_empty() {
+                break;
+            }
+
+            for (end, record) in records {
+                match record {
+                    // Unreadable, already logged; skipped like a delivered record
+                    None => {}
+                    Some(record) if expiry::is_expired(&record.headers, Utc::now()) => {
+                        stats.record_expired(record.source.as_deref());
+                        expired += 1;
+                    }
+                    Some(record) => match self.decode_payload(&record.payload) {
+                        Ok(payload) => {
+                            bus.publish(&record.subject, &record.headers, payload.into())
+                                .await?;
+                            drained += 1;
+                        }
+                        // Such as a record whose key left the ring; it would block every pass
+                        Err(e) => {
+                            error!("Quarantining spool record ending at offset {}: {}", end, e);
+                            quarantine(&self.config, &encode_line(&record)?).await?;
+                            quarantined += 1;
+                        }
+                    },
+                }
+
+                let mut spool = self.file.lock().await;
+                spool.offset = end;
+                self.pending.fetch_sub(1, Ordering::Relaxed);
+            }
+
+            self.checkpoint().await?;
+        }
+
+        if expired > 0 {
+            warn!("Dropped {} spooled messages past their expiry", expired);
+        }
+        if quarantined > 0 {
+            warn!(
+                "Quarantined {} spooled messages that could not be decoded to {}",
+                quarantined,
+                self.config.dir.join("spool.quarantine").display()
+            );
+        }
+
+        Ok(drained)
+    }
+
+    /// Write a line at the end of the spool, flushing it to disk when configured
+    async fn write_line(&self, spool: &mut SpoolFile, line: Vec<u8>) -> Result<()> {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(writer) = &self.uring {
+            return writer
+                .write(line, spool.len, self.config.fsync)
+                .await
+                .map_err(spool_error);
+        }
+
+        spool.file.write_all(&line).await.map_err(spool_error)?;
+        spool.file.flush().await.map_err(spool_error)?;
+        if self.config.fsync {
+            spool.file.sync_data().await.map_err(spool_error)?;
+        }
+        Ok(())
+    }
+
+    /// Recover a record's payload bytes, decrypting them if they were encrypted
+    fn decode_payload(&self, payload: &str) -> Result<Vec<u8>> {
+        if encryption::is_encrypted(payload) {
+            return match &self.cipher {
+                Some(cipher) => cipher.decrypt(payload),
+                None => Err(AppError::InternalError(
+                    "Spool record is encrypted but no encryption keys are configured".to_string(),
+                )),
+            };
+        }
+
+        STANDARD
+            .decode(payload)
+            .map_err(|e| AppError::InternalError(format!("Corrupt spool record: {}", e)))
+    }
+
+    /// Persist the drain offset, truncating the spool once everything has been republished
+    async fn checkpoint(&self) -> Result<()> {
+        let mut spool = self.file.lock().await;
+
+        if spool.offset >= spool.len {
+            spool.file.set_len(0).await.map_err(spool_error)?;
+            spool.len = 0;
+            spool.offset = 0;
+        }
+
+        fs::write(
+            self.config.dir.join("spool.offset"),
+            spool.offset.to_string(),
+        )
+        .await
+        .map_err(spool_error)
+    }
+
+    /// Drop pending records outside the retention policy and compact the spool file.
+    ///
+    /// Records are dropped oldest first: those spooled longer ago than `max_age`, then as
+    /// many as needed to bring the pending bytes under `max_bytes`. Already republished
+    /// records still in the file are reclaimed too.
+    pub async fn purge(&self, policy: &RetentionPolicy) -> Result<PurgeReport> {
+        let _exclusive = self.exclusive.lock().await;
+        let mut spool = self.file.lock().await;
+
+        let cutoff = policy
+            .max_age
+            .and_then(|age| chrono::Duration::from_std(age).ok())
+            .map(|age| Utc::now() - age);
+        let mut start = spool.offset;
+        let mut purged = 0;
+
+        'scan: loop {
+            let records = read_records(&mut spool, start, DRAIN_BATCH).await?;
+            if records.is_empty() {
+                break;
+            }
+            for (end, record) in records {
+                // Records spooled before timestamps were recorded never expire by age
+                let spooled_at = record.as_ref().and_then(|record| record.spooled_at);
+                let expired =
+                    matches!((cutoff, spooled_at), (Some(cutoff), Some(at)) if at < cutoff);
+                let oversized = policy.max_bytes.is_some_and(|max| spool.len - start > max);
+                if !expired && !oversized {
+                    break 'scan;
+                }
+                start = end;
+                purged += 1;
+            }
+        }
+
+        if start == 0 {
+            return Ok(PurgeReport::new("spool", 0, 0));
+        }
+
+        // Copy the records still pending into a fresh file and swap it in
+        let kept = spool.len - start;
+        let mut remaining = Vec::with_capacity(kept as usize);
+        spool
+            .file
+            .seek(SeekFrom::Start(start))
+            .await
+            .map_err(spool_error)?;
+        (&mut spool.file)
+            .take(kept)
+            .read_to_end(&mut remaining)
+            .await
+            .map_err(spool_error)?;
+
+        let path = self.config.dir.join("spool.log");
+        let compacted = self.config.dir.join("spool.log.compact");
+        fs::write(&compacted, &remaining)
+            .await
+            .map_err(spool_error)?;
+        File::open(&compacted)
+            .await
+            .map_err(spool_error)?
+            .sync_all()
+            .await
+            .map_err(spool_error)?;
+        fs::rename(&compacted, &path).await.map_err(spool_error)?;
+
+        let reclaimed = spool.len - remaining.len() as u64;
+        spool.file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(spool_error)?;
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(writer) = &self.uring {
+            writer.reopen().await.map_err(spool_error)?;
+        }
+        spool.len = remaining.len() as u64;
+        spool.offset = 0;
+        drop(spool);
+
+        self.pending.fetch_sub(purged, Ordering::Relaxed);
+        self.checkpoint().await?;
+
+        if purged > 0 {
+            warn!(
+                "Purged {} spooled messages under the retention policy",
+                purged
+            );
+        }
+
+        Ok(PurgeReport::new("spool", purged, reclaimed))
+    }
+
+    /// Spawn the background task that keeps draining the spool, counting expired messages
+    /// in `stats`
+    pub fn spawn_drainer(self: Arc<Self>, bus: Arc<dyn MessageBus>, stats: Arc<IngestStats>) {
+        tokio::spawn(async move {
+            loop {
+                if self.pending() > 0 {
+                    match self.drain(bus.as_ref(), &stats).await {
+                        Ok(0) => {}
+                        Ok(count) => info!("Republished {} spooled messages", count),
+                        Err(e) => warn!(
+                            "Spool drain interrupted, {} messages pending: {}",
+                            self.pending(),
+                            e
+                        ),
+                    }
+                }
+                tokio::time::sleep(self.config.drain_interval).await;
+            }
+        });
+    }
+}
+
+/// Read up to `limit` lines from offset `from`, a line at a time.
+///
+/// Each line comes with the file offset just past it, and its record, or `None` when it is
+/// unreadable so it can be skipped. A partial trailing line is still being written and is
+/// left for the next pass.
+async fn read_records(
+    spool: &mut SpoolFile,
+    from: u64,
+    limit: usize,
+) -> Result<Vec<(u64, Option<SpoolRecord>)>> {
+    if from >= spool.len {
+        return Ok(Vec::new());
+    }
+
+    spool
+        .file
+        .seek(SeekFrom::Start(from))
+        .await
+        .map_err(spool_error)?;
+    let mut reader = BufReader::new((&mut spool.file).take(spool.len - from));
+    let mut records = Vec::new();
+    let mut position = from;
+    let mut line = Vec::new();
+
+    while records.len() < limit {
+        line.clear();
+        let read = reader
+            .read_until(b'\n', &mut line)
+            .await
+            .map_err(spool_error)?;
+        if read == 0 || !line.ends_with(b"\n") {
+            break;
+        }
+        position += read as u64;
+
+        match decode_line(&line) {
+            Ok(record) => records.push((position, Some(record))),
+            Err(e) => {
+                error!(
+                    "Skipping unreadable spool record at offset {}: {}",
+                    position, e
+                );
+                records.push((position, None));
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Check every pending line, moving corrupt ones to the quarantine.
+///
+/// When anything is quarantined the spool is rewritten with only the good records, so a
+/// torn tail left by a crash is gone before new records are appended after it.
+async fn recover(config: &SpoolConfig, spool: &mut SpoolFile) -> Result<RecoveryReport> {
+    let mut report = RecoveryReport::default();
+    if spool.offset >= spool.len {
+        return Ok(report);
+    }
+
+    let mut buffer = Vec::with_capacity((spool.len - spool.offset) as usize);
+    spool
+        .file
+        .seek(SeekFrom::Start(spool.offset))
+        .await
+        .map_err(spool_error)?;
+    (&mut spool.file)
+        .take(spool.len - spool.offset)
+        .read_to_end(&mut buffer)
+        .await
+        .map_err(spool_error)?;
+
+    let mut good = Vec::with_capacity(buffer.len());
+    let mut corrupt = Vec::new();
+    let mut position = spool.offset;
+
+    for line in buffer.split_inclusive(|&b| b == b'\n') {
+        position += line.len() as u64;
+
+        // Nothing is being written during startup, so a line without a newline is torn
+        let decoded = match line.ends_with(b"\n") {
+            true => decode_line(line),
+            false => Err("incomplete record".to_string()),
+        };
+
+        match decoded {
+            Ok(_) => {
+                report.recovered += 1;
+                good.extend_from_slice(line);
+            }
+            Err(e) => {
+                warn!("Corrupt spool record ending at offset {}: {}", position, e);
+                report.quarantined += 1;
+                report.quarantined_bytes += line.len() as u64;
+                corrupt.extend_from_slice(line);
+                if !line.ends_with(b"\n") {
+                    corrupt.push(b'\n');
+                }
+            }
+        }
+    }
+
+    if corrupt.is_empty() {
+        return Ok(report);
+    }
+
+    quarantine(config, &corrupt).await?;
+
+    let path = config.dir.join("spool.log");
+    let compacted = config.dir.join("spool.log.compact");
+    fs::write(&compacted, &good).await.map_err(spool_error)?;
+    File::open(&compacted)
+        .await
+        .map_err(spool_error)?
+        .sync_all()
+        .await
+        .map_err(spool_error)?;
+    fs::rename(&compacted, &path).await.map_err(spool_error)?;
+
+    spool.file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .open(&path)
+        .await
+        .map_err(spool_error)?;
+    spool.len = good.len() as u64;
+    spool.offset = 0;
+    fs::write(config.dir.join("spool.offset"), "0")
+        .await
+        .map_err(spool_error)?;
+
+    Ok(report)
+}
+
+/// Append lines to `spool.quarantine`, flushed to disk before they leave the spool
+async fn quarantine(config: &SpoolConfig, lines: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(config.dir.join("spool.quarantine"))
+        .await
+        .map_err(spool_error)?;
+    file.write_all(lines).await.map_err(spool_error)?;
+    file.sync_data().await.map_err(spool_error)
+}
+
+/// Encode a record as a spool line: its CRC32 in hex, a space, and its JSON
+fn encode_line(record: &SpoolRecord) -> Result<Vec<u8>> {
+    let json = serde_json::to_vec(record)
+        .map_err(|e| AppError::InternalError(format!("Failed to encode spool record: {}", e)))?;
+
+    let mut line = format!("{:08x} ", crc32fast::hash(&json)).into_bytes();
+    line.extend_from_slice(&json);
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Decode and verify a spool line; lines written before checksums were added are plain JSON
+fn decode_line(line: &[u8]) -> std::result::Result<SpoolRecord, String> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+
+    let json = if line.starts_with(b"{") {
+        line
+    } else {
+        let (crc, json) = line
+            .split_first_chunk::<8>()
+            .and_then(|(crc, rest)| Some((crc, rest.strip_prefix(b" ")?)))
+            .ok_or_else(|| "missing checksum".to_string())?;
+        let expected = std::str::from_utf8(crc)
+            .ok()
+            .and_then(|crc| u32::from_str_radix(crc, 16).ok())
+            .ok_or_else(|| "malformed checksum".to_string())?;
+        if crc32fast::hash(json) != expected {
+            return Err("checksum mismatch".to_string());
+        }
+        json
+    };
+
+    serde_json::from_slice(json).map_err(|e| e.to_string())
+}
+
+fn spool_error(e: std::io::Error) -> AppError {
+    AppError::InternalError(format!("Spool I/O error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::encryption::EncryptionConfig;
+    use crate::keys::{KeyRingConfig, KeyVersion};
+    use crate::nats::{NatsClient, SimulationMode};
+
+    /// A spool directory removed when the test ends
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("spool-test-{}", uuid::Uuid::new_v4())))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn config(dir: &Path) -> SpoolConfig {
+        SpoolConfig {
+            dir: dir.to_path_buf(),
+            fsync: false,
+            drain_interval: Duration::from_secs(1),
+            ready_threshold: None,
+            io_uring: false,
+        }
+    }
+
+    fn cipher(entry: &str) -> Arc<Cipher> {
+        let config = EncryptionConfig {
+            keys: KeyRingConfig {
+                keys: vec![KeyVersion::parse(entry).unwrap()],
+                file: None,
+                active: None,
+                refresh_interval: Duration::from_secs(60),
+            },
+        };
+        Arc::new(Cipher::new(&config).unwrap())
+    }
+
+    /// A bus that discards every message, as in null simulation mode
+    async fn null_bus() -> NatsClient {
+        NatsClient::new(
+            Vec::new(),
+            Some(SimulationMode::Null),
+            None,
+            None,
+            1,
+            None,
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn record(subject: &str) -> SpoolRecord {
+        SpoolRecord {
+            subject: subject.to_string(),
+            headers: Vec::new(),
+            payload: STANDARD.encode(b"{}"),
+            spooled_at: None,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn verifies_line_checksums() {
+        let line = encode_line(&record("ingest.a")).unwrap();
+        assert_eq!(decode_line(&line).unwrap().subject, "ingest.a");
+
+        let mut damaged = line.clone();
+        let last = damaged.len() - 3;
+        damaged[last] ^= 0x20;
+        assert_eq!(decode_line(&damaged).unwrap_err(), "checksum mismatch");
+
+        assert_eq!(
+            decode_line(b"zzzzzzzz {}\n").unwrap_err(),
+            "malformed checksum"
+        );
+        assert_eq!(decode_line(b"short\n").unwrap_err(), "missing checksum");
+        // Written before checksums were added
+        assert_eq!(
+            decode_line(br#"{"subject":"ingest.b","payload":""}"#)
+                .unwrap()
+                .subject,
+            "ingest.b"
+        );
+    }
+
+    #[tokio::test]
+    async fn recovers_the_backlog_after_a_restart() {
+        let dir = TempDir::new();
+        let spool = Spool::open(config(&dir.0), None).await.unwrap();
+        for subject in ["ingest.a", "ingest.b", "ingest.c"] {
+            spool
+                .append(Some("test"), subject, &Vec::new(), b"{}")
+                .await
+                .unwrap();
+        }
+        drop(spool);
+
+        let spool = Spool::open(config(&dir.0), None).await.unwrap();
+        assert_eq!(spool.pending(), 3);
+        assert!(!dir.0.join("spool.quarantine").exists());
+
+        let drained = spool
+            .drain(&null_bus().await, &IngestStats::default())
+            .await
+            .unwrap();
+        assert_eq!(drained, 3);
+        assert_eq!(spool.pending(), 0);
+        assert_eq!(std::fs::metadata(dir.0.join("spool.log")).unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn quarantines_corrupt_and_torn_records_on_recovery() {
+        let dir = TempDir::new();
+        let spool = Spool::open(config(&dir.0), None).await.unwrap();
+        spool
+            .append(None, "ingest.a", &Vec::new(), b"{}")
+            .await
+            .unwrap();
+        drop(spool);
+
+        let mut damaged = encode_line(&record("ingest.damaged")).unwrap();
+        damaged[0] = if damaged[0] == b'0' { b'1' } else { b'0' };
+        let good = encode_line(&record("ingest.b")).unwrap();
+        let torn = &encode_line(&record("ingest.torn")).unwrap()[..20];
+
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.0.join("spool.log"))
+            .unwrap();
+        for bytes in [&damaged[..], &good[..], torn] {
+            std::io::Write::write_all(&mut log, bytes).unwrap();
+        }
+        drop(log);
+
+        let spool = Spool::open(config(&dir.0), None).await.unwrap();
+        assert_eq!(spool.pending(), 2);
+
+        let quarantined = lines(&dir.0.join("spool.quarantine"));
+        assert_eq!(quarantined.len(), 2);
+        assert!(quarantined[0].contains("ingest.damaged"));
+        assert_eq!(quarantined[1].as_bytes(), torn);
+
+        // The spool is rewritten with only the good records, in order
+        let subjects: Vec<String> = lines(&dir.0.join("spool.log"))
+            .iter()
+            .map(|line| decode_line(line.as_bytes()).unwrap().subject)
+            .collect();
+        assert_eq!(subjects, ["ingest.a", "ingest.b"]);
+
+        // Appends after recovery are not glued to the torn tail
+        spool
+            .append(None, "ingest.c", &Vec::new(), b"{}")
+            .await
+            .unwrap();
+        assert_eq!(
+            spool
+                .drain(&null_bus().await, &IngestStats::default())
+                .await
+                .unwrap(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn encrypts_records_at_rest() {
+        let dir = TempDir::new();
+        let key = format!("k1={}", STANDARD.encode([7u8; 32]));
+        let spool = Spool::open(config(&dir.0), Some(cipher(&key)))
+            .await
+            .unwrap();
+        spool
+            .append(None, "ingest.a", &Vec::new(), b"{\"secret\":true}")
+            .await
+            .unwrap();
+
+        let stored = decode_line(lines(&dir.0.join("spool.log"))[0].as_bytes()).unwrap();
+        assert!(encryption::is_encrypted(&stored.payload));
+        assert!(!stored.payload.contains("secret"));
+
+        assert_eq!(
+            spool
+                .drain(&null_bus().await, &IngestStats::default())
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn quarantines_records_that_cannot_be_decrypted() {
+        let dir = TempDir::new();
+        let old_key = format!("old={}", STANDARD.encode([1u8; 32]));
+        let spool = Spool::open(config(&dir.0), Some(cipher(&old_key)))
+            .await
+            .unwrap();
+        spool
+            .append(None, "ingest.a", &Vec::new(), b"{}")
+            .await
+            .unwrap();
+        drop(spool);
+
+        // A plaintext record behind it, written before encryption was enabled
+        let spool = Spool::open(config(&dir.0), None).await.unwrap();
+        spool
+            .append(None, "ingest.b", &Vec::new(), b"{}")
+            .await
+            .unwrap();
+        drop(spool);
+
+        // Reopened after the old key left the ring
+        let new_key = format!("new={}", STANDARD.encode([2u8; 32]));
+        let spool = Spool::open(config(&dir.0), Some(cipher(&new_key)))
+            .await
+            .unwrap();
+        assert_eq!(spool.pending(), 2);
+
+        let drained = spool
+            .drain(&null_bus().await, &IngestStats::default())
+            .await
+            .unwrap();
+        assert_eq!(drained, 1);
+        assert_eq!(spool.pending(), 0);
+
+        let quarantined = lines(&dir.0.join("spool.quarantine"));
+        assert_eq!(quarantined.len(), 1);
+        assert!(quarantined[0].contains("enc:v1:old:"));
+    }
+}
@@ -0,0 +1,117 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tokio::sync::{mpsc, oneshot};
+use tokio_uring::fs::{File, OpenOptions};
+
+enum Command {
+    Write {
+        line: Vec<u8>,
+        pos: u64,
+        fsync: bool,
+        done: oneshot::Sender<io::Result<()>>,
+    },
+    Reopen {
+        done: oneshot::Sender<io::Result<()>>,
+    },
+}
+
+/// Writes spool records through io_uring on a dedicated thread.
+///
+/// Writes and fsyncs are submitted to the kernel ring directly instead of hopping through
+/// tokio's blocking pool, which is what limits the standard path at high spool rates.
+/// Records are written at explicit offsets, so callers must serialize appends.
+pub struct UringWriter {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl UringWriter {
+    /// Start the writer for the spool file at `path`, failing when io_uring is unavailable,
+    /// e.g. on older kernels or under seccomp profiles that block it
+    pub async fn start(path: PathBuf) -> io::Result<Self> {
+        let (commands, mut receiver) = mpsc::unbounded_channel();
+        let (ready_tx, ready_rx) = oneshot::channel();
+
+        std::thread::Builder::new()
+            .name("spool-uring".to_string())
+            .spawn(move || {
+                let runtime = match tokio_uring::Runtime::new(&tokio_uring::builder()) {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+
+                runtime.block_on(async move {
+                    let mut file = match open(&path).await {
+                        Ok(file) => file,
+                        Err(e) => {
+                            let _ = ready_tx.send(Err(e));
+                            return;
+                        }
+                    };
+                    let _ = ready_tx.send(Ok(()));
+
+                    // Runs until the spool is dropped and the channel closes
+                    while let Some(command) = receiver.recv().await {
+                        match command {
+                            Command::Write {
+                                line,
+                                pos,
+                                fsync,
+                                done,
+                            } => {
+                                let result = match file.write_all_at(line, pos).await.0 {
+                                    Ok(()) if fsync => file.sync_data().await,
+                                    result => result,
+                                };
+                                let _ = done.send(result);
+                            }
+                            Command::Reopen { done } => {
+                                let result = open(&path).await.map(|reopened| file = reopened);
+                                let _ = done.send(result);
+                            }
+                        }
+                    }
+                });
+            })?;
+
+        ready_rx
+            .await
+            .map_err(|_| io::Error::other("io_uring writer thread exited"))??;
+
+        Ok(Self { commands })
+    }
+
+    /// Write `line` at `pos`, flushing it to disk first when `fsync` is set
+    pub async fn write(&self, line: Vec<u8>, pos: u64, fsync: bool) -> io::Result<()> {
+        let (done, result) = oneshot::channel();
+        self.send(Command::Write {
+            line,
+            pos,
+            fsync,
+            done,
+        })?;
+        result.await.map_err(|_| writer_gone())?
+    }
+
+    /// Open the spool file again after it was replaced by compaction
+    pub async fn reopen(&self) -> io::Result<()> {
+        let (done, result) = oneshot::channel();
+        self.send(Command::Reopen { done })?;
+        result.await.map_err(|_| writer_gone())?
+    }
+
+    fn send(&self, command: Command) -> io::Result<()> {
+        self.commands.send(command).map_err(|_| writer_gone())
+    }
+}
+
+async fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).open(path).await
+}
+
+fn writer_gone() -> io::Error {
+    io::Error::other("io_uring writer thread exited")
+}
@@ -0,0 +1,433 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{header, redirect, Client, ClientBuilder, Response, Url};
+use tracing::warn;
+
+use crate::error::{AppError, Result};
+
+/// Maximum number of redirects followed for user-supplied URLs
+const MAX_REDIRECTS: usize = 5;
+
+/// Settings for protecting user-supplied URLs against server-side request forgery
+#[derive(Debug, Clone, Default)]
+pub struct SsrfConfig {
+    /// Whether private, loopback and link-local destinations are blocked
+    pub enabled: bool,
+
+    /// Hosts (exact or `.suffix`) and CIDR ranges that are always allowed
+    pub allowlist: Vec<String>,
+}
+
+/// Validates destinations of user-supplied URLs
+#[derive(Debug, Clone)]
+pub struct SsrfGuard {
+    enabled: bool,
+    hosts: Vec<String>,
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl SsrfGuard {
+    /// Build a guard from configuration
+    pub fn new(config: &SsrfConfig) -> Self {
+        let mut hosts = Vec::new();
+        let mut networks = Vec::new();
+
+        for entry in &config.allowlist {
+            match parse_network(entry) {
+                Some(network) => networks.push(network),
+                None => hosts.push(entry.to_ascii_lowercase()),
+            }
+        }
+
+        Self {
+            enabled: config.enabled,
+            hosts,
+            networks,
+        }
+    }
+
+    /// Apply the guard's resolver and redirect policy to an HTTP client builder.
+    ///
+    /// While the guard is enabled the client does not follow redirects itself, as they are
+    /// followed by [`SsrfGuard::get`] once their target is checked.
+    pub fn apply(self, builder: ClientBuilder) -> ClientBuilder {
+        if !self.enabled {
+            return builder.redirect(redirect::Policy::limited(MAX_REDIRECTS));
+        }
+
+        builder
+            .dns_resolver(Arc::new(self))
+            .redirect(redirect::Policy::none())
+    }
+
+    /// Send a GET to a URL already passed through [`SsrfGuard::check_url`], following
+    /// redirects after checking each target the same way.
+    ///
+    /// The target is resolved here rather than left to the guard's resolver, which a proxy
+    /// bypasses.
+    pub async fn get(&self, client: &Client, url: Url) -> Result<Response> {
+        let mut url = url;
+        for _ in 0..=MAX_REDIRECTS {
+            let response = client
+                .get(url.clone())
+                .send()
+                .await
+                .map_err(|e| AppError::FetchError(format!("Failed to fetch {}: {}", url, e)))?;
+
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|v| v.to_str().ok());
+            let Some(location) =
+                location.filter(|_| self.enabled && response.status().is_redirection())
+            else {
+                return Ok(response);
+            };
+            let target = url.join(location).map_err(|e| {
+                AppError::FetchError(format!("Invalid redirect from {}: {}", url, e))
+            })?;
+
+            if let Err(e) = self.check_url(&target).await {
+                warn!("Blocked redirect to {}", target);
+                return Err(e);
+            }
+            url = target;
+        }
+
+        Err(AppError::FetchError(format!(
+            "Too many redirects fetching {}",
+            url
+        )))
+    }
+
+    /// Validate a URL before fetching, resolving its host to check every address.
+    ///
+    /// This also covers requests sent through a proxy, where our resolver is not used.
+    pub async fn check_url(&self, url: &Url) -> Result<()> {
+        self.check_literal(url)?;
+
+        if !self.enabled || self.host_allowed(url) {
+            return Ok(());
+        }
+
+        let Some(host) = url.host_str() else {
+            return Ok(());
+        };
+
+        let port = url.port_or_known_default().unwrap_or(0);
+        let addrs = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| AppError::ValidationError(format!("Failed to resolve {}: {}", host, e)))?;
+
+        for addr in addrs {
+            if !self.ip_allowed(addr.ip()) {
+                return Err(blocked(host));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reject URLs that are not http(s) or whose host is a forbidden IP literal
+    fn check_literal(&self, url: &Url) -> Result<()> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(AppError::ValidationError(
+                "Only http and https URLs can be fetched".to_string(),
+            ));
+        }
+
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let ip = match url.host() {
+            Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+            Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+            _ => return Ok(()),
+        };
+
+        if self.ip_allowed(ip) {
+            Ok(())
+        } else {
+            Err(blocked(&ip.to_string()))
+        }
+    }
+
+    fn host_allowed(&self, url: &Url) -> bool {
+        url.host_str()
+            .map(|host| self.name_allowed(host))
+            .unwrap_or(false)
+    }
+
+    fn name_allowed(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.hosts
+            .iter()
+            .any(|allowed| match allowed.strip_prefix('.') {
+                Some(suffix) => host == suffix || host.ends_with(allowed.as_str()),
+                None => host == *allowed,
+            })
+    }
+
+    fn ip_allowed(&self, ip: IpAddr) -> bool {
+        !is_internal(ip)
+            || self
+                .networks
+                .iter()
+                .any(|&(network, prefix)| in_network(ip, network, prefix))
+    }
+}
+
+impl Resolve for SsrfGuard {
+    fn resolve(&self, name: Name) -> Resolving {
+        let guard = self.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+
+            if guard.name_allowed(&host) {
+                return Ok(Box::new(addrs.into_iter()) as Addrs);
+            }
+
+            // Every address must be public, otherwise a rebinding host could slip one in
+            if addrs.iter().any(|addr| !guard.ip_allowed(addr.ip())) {
+                warn!(
+                    "Blocked connection to {} resolving to an internal address",
+                    host
+                );
+                return Err(Box::new(blocked(&host)) as Box<dyn std::error::Error + Send + Sync>);
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn blocked(host: &str) -> AppError {
+    AppError::PolicyViolation(format!(
+        "Destination {} resolves to a blocked internal address",
+        host
+    ))
+}
+
+/// Whether an address belongs to a private, loopback, link-local or otherwise internal range
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_v4(ip),
+        IpAddr::V6(ip) => is_internal_v6(ip) || embedded_v4(ip).is_some_and(is_internal_v4),
+    }
+}
+
+/// IPv4 address carried in an IPv4-mapped (`::ffff:a.b.c.d`), IPv4-compatible (`::a.b.c.d`)
+/// or NAT64 (`64:ff9b::a.b.c.d`) address, each of which reaches that IPv4 host
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    match ip.segments() {
+        [0, 0, 0, 0, 0, 0xffff, ..] | [0, 0, 0, 0, 0, 0, ..] | [0x64, 0xff9b, 0, 0, 0, 0, ..] => {
+            let [.., a, b, c, d] = ip.octets();
+            Some(Ipv4Addr::new(a, b, c, d))
+        }
+        _ => None,
+    }
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        // Benchmarking and reserved ranges
+        || (a == 198 && (b == 18 || b == 19))
+        || a >= 240
+}
+
+fn is_internal_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link-local fe80::/10
+        || (first & 0xffc0) == 0xfe80
+        // Documentation 2001:db8::/32
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+}
+
+/// Parse a CIDR (`10.0.0.0/8`) or bare IP allowlist entry
+fn parse_network(entry: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, prefix.parse::<u8>().ok()?),
+        None => {
+            let addr = entry.parse::<IpAddr>().ok()?;
+            (addr, if addr.is_ipv4() { 32 } else { 128 })
+        }
+    };
+    Some((addr, prefix))
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX
+                .checked_shl(32 - prefix.min(32) as u32)
+                .unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX
+                .checked_shl(128 - prefix.min(128) as u32)
+                .unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(allowlist: &[&str]) -> SsrfGuard {
+        SsrfGuard::new(&SsrfConfig {
+            enabled: true,
+            allowlist: allowlist.iter().map(|entry| entry.to_string()).collect(),
+        })
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn classifies_internal_ipv4() {
+        for internal in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "198.18.0.1",
+            "255.255.255.255",
+            "224.0.0.1",
+            "240.0.0.1",
+        ] {
+            assert!(is_internal(ip(internal)), "{} should be internal", internal);
+        }
+        for public in ["8.8.8.8", "1.1.1.1", "100.128.0.1", "172.32.0.1"] {
+            assert!(!is_internal(ip(public)), "{} should be public", public);
+        }
+    }
+
+    #[test]
+    fn classifies_internal_ipv6() {
+        for internal in [
+            "::1",
+            "::",
+            "fc00::1",
+            "fd12::1",
+            "fe80::1",
+            "ff02::1",
+            "2001:db8::1",
+        ] {
+            assert!(is_internal(ip(internal)), "{} should be internal", internal);
+        }
+        for public in ["2606:4700::1111", "2001:4860:4860::8888"] {
+            assert!(!is_internal(ip(public)), "{} should be public", public);
+        }
+    }
+
+    #[test]
+    fn classifies_ipv4_embedded_in_ipv6() {
+        for internal in [
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "::127.0.0.1",
+            "::169.254.169.254",
+            "64:ff9b::10.0.0.1",
+        ] {
+            assert!(is_internal(ip(internal)), "{} should be internal", internal);
+        }
+        for public in ["::ffff:8.8.8.8", "64:ff9b::8.8.8.8"] {
+            assert!(!is_internal(ip(public)), "{} should be public", public);
+        }
+    }
+
+    #[test]
+    fn matches_cidr_ranges() {
+        let (network, prefix) = parse_network("10.0.0.0/8").unwrap();
+        assert!(in_network(ip("10.255.0.1"), network, prefix));
+        assert!(!in_network(ip("11.0.0.1"), network, prefix));
+
+        let (network, prefix) = parse_network("fd00::/8").unwrap();
+        assert!(in_network(ip("fd12::1"), network, prefix));
+        assert!(!in_network(ip("fe80::1"), network, prefix));
+        assert!(!in_network(ip("10.0.0.1"), network, prefix));
+
+        assert_eq!(parse_network("192.168.1.5"), Some((ip("192.168.1.5"), 32)));
+        assert_eq!(
+            parse_network("0.0.0.0/0").map(|(_, prefix)| prefix),
+            Some(0)
+        );
+        assert!(in_network(ip("8.8.8.8"), ip("0.0.0.0"), 0));
+        assert_eq!(parse_network("internal.example.com"), None);
+    }
+
+    #[test]
+    fn allowlist_admits_networks_and_hosts() {
+        let guard = guard(&["10.1.0.0/16", "wiki.internal", ".corp.example"]);
+
+        assert!(guard.ip_allowed(ip("10.1.2.3")));
+        assert!(!guard.ip_allowed(ip("10.2.0.1")));
+        assert!(guard.ip_allowed(ip("8.8.8.8")));
+
+        assert!(guard.name_allowed("wiki.internal"));
+        assert!(guard.name_allowed("WIKI.internal"));
+        assert!(!guard.name_allowed("other.internal"));
+        assert!(guard.name_allowed("corp.example"));
+        assert!(guard.name_allowed("docs.corp.example"));
+        assert!(!guard.name_allowed("evilcorp.example"));
+    }
+
+    #[test]
+    fn rejects_internal_literals_and_other_schemes() {
+        let guard = guard(&[]);
+        let check = |url: &str| guard.check_literal(&Url::parse(url).unwrap());
+
+        assert!(check("http://8.8.8.8/").is_ok());
+        assert!(check("https://example.com/").is_ok());
+        assert!(check("http://127.0.0.1/").is_err());
+        assert!(check("http://[::ffff:169.254.169.254]/").is_err());
+        assert!(check("http://[64:ff9b::a00:1]/").is_err());
+        assert!(check("file:///etc/passwd").is_err());
+    }
+
+    #[tokio::test]
+    async fn checks_resolved_addresses() {
+        let guard = guard(&[]);
+        assert!(guard
+            .check_url(&Url::parse("http://localhost/").unwrap())
+            .await
+            .is_err());
+
+        let allowed = SsrfGuard::new(&SsrfConfig {
+            enabled: true,
+            allowlist: vec!["localhost".to_string()],
+        });
+        assert!(allowed
+            .check_url(&Url::parse("http://localhost/").unwrap())
+            .await
+            .is_ok());
+    }
+}
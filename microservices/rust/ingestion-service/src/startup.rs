@@ -0,0 +1,100 @@
+use std::fmt;
+use std::process::ExitCode;
+
+/// Why the service stopped, reported through the process exit code so orchestration and
+/// runbooks can tell failure classes apart. Codes follow `sysexits.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureClass {
+    /// Configuration is invalid, e.g. a bad key, manifest or table name
+    Config,
+
+    /// A listening socket could not be bound
+    Bind,
+
+    /// The NATS server could not be reached or refused stream setup
+    BusUnreachable,
+
+    /// The disk spool could not be recovered
+    SpoolCorruption,
+
+    /// Anything else, including failures after startup
+    Other,
+}
+
+impl FailureClass {
+    /// Process exit code for this class
+    pub fn exit_code(self) -> u8 {
+        match self {
+            // EX_CONFIG
+            Self::Config => 78,
+            // EX_OSERR
+            Self::Bind => 71,
+            // EX_UNAVAILABLE
+            Self::BusUnreachable => 69,
+            // EX_DATAERR
+            Self::SpoolCorruption => 65,
+            Self::Other => 1,
+        }
+    }
+
+    /// Name used in logs
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Config => "config",
+            Self::Bind => "bind",
+            Self::BusUnreachable => "bus_unreachable",
+            Self::SpoolCorruption => "spool_corruption",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// An error that stopped the service, tagged with its failure class
+#[derive(Debug)]
+pub struct StartupError {
+    pub class: FailureClass,
+    pub error: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl StartupError {
+    /// Exit code to end the process with
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.class.exit_code())
+    }
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+/// Errors not classified at the call site count as `Other`
+impl<E> From<E> for StartupError
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    fn from(error: E) -> Self {
+        Self {
+            class: FailureClass::Other,
+            error: Box::new(error),
+        }
+    }
+}
+
+/// Tag a startup step's error with its failure class
+pub trait Classify<T> {
+    fn classify(self, class: FailureClass) -> Result<T, StartupError>;
+}
+
+impl<T, E> Classify<T> for Result<T, E>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    fn classify(self, class: FailureClass) -> Result<T, StartupError> {
+        self.map_err(|error| StartupError {
+            class,
+            error: Box::new(error),
+        })
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::batch;
+use crate::models::{
+    BatchStatsResponse, DatagramCounters, IngestCounters, RawData, RetentionCounters, SequenceGap,
+    StatsResponse,
+};
+use crate::retention::PurgeReport;
+
+/// Batches whose counters are kept; the least recently active are forgotten first
+const MAX_TRACKED_BATCHES: usize = 10_000;
+
+/// Outcome of processing a single item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The item was published
+    Published,
+
+    /// The item failed validation or pre-processing
+    Rejected,
+
+    /// The item was valid but could not be published
+    Failed,
+
+    /// The item was written to the disk spool for later publishing
+    Spooled,
+}
+
+impl Outcome {
+    /// Lowercase name used in exported analytics
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Published => "published",
+            Outcome::Rejected => "rejected",
+            Outcome::Failed => "failed",
+            Outcome::Spooled => "spooled",
+        }
+    }
+}
+
+#[derive(Default)]
+struct StatsState {
+    totals: IngestCounters,
+    by_content_type: BTreeMap<String, IngestCounters>,
+    by_source: BTreeMap<String, IngestCounters>,
+    datagrams: Option<DatagramCounters>,
+    retention: BTreeMap<String, RetentionCounters>,
+    batches: BatchTracker,
+}
+
+/// Counters of one batch
+struct BatchState {
+    counters: IngestCounters,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+
+    /// Position in `BatchTracker::recent`
+    seq: u64,
+}
+
+/// Counters of the most recently active batches
+#[derive(Default)]
+struct BatchTracker {
+    batches: HashMap<String, BatchState>,
+
+    /// Batch IDs by when they were last active, oldest first
+    recent: BTreeMap<u64, String>,
+    next_seq: u64,
+}
+
+impl BatchTracker {
+    fn record(&mut self, id: &str, outcome: Outcome) {
+        let now = Utc::now();
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        match self.batches.get_mut(id) {
+            Some(state) => {
+                self.recent.remove(&state.seq);
+                state.seq = seq;
+                state.last_seen = now;
+                state.counters.record(outcome);
+            }
+            None => {
+                if self.batches.len() >= MAX_TRACKED_BATCHES {
+                    if let Some((_, oldest)) = self.recent.pop_first() {
+                        self.batches.remove(&oldest);
+                    }
+                }
+                let mut counters = IngestCounters::default();
+                counters.record(outcome);
+                self.batches.insert(
+                    id.to_string(),
+                    BatchState {
+                        counters,
+                        first_seen: now,
+                        last_seen: now,
+                        seq,
+                    },
+                );
+            }
+        }
+        self.recent.insert(seq, id.to_string());
+    }
+}
+
+/// In-memory ingestion counters since process start
+pub struct IngestStats {
+    started: Instant,
+    state: Mutex<StatsState>,
+}
+
+impl IngestCounters {
+    fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Published => self.published += 1,
+            Outcome::Rejected => self.rejected += 1,
+            Outcome::Failed => self.failed += 1,
+            Outcome::Spooled => self.spooled += 1,
+        }
+    }
+}
+
+impl Default for IngestStats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            state: Mutex::new(StatsState::default()),
+        }
+    }
+}
+
+impl IngestStats {
+    /// Record the outcome for an item
+    pub fn record(&self, item: &RawData, outcome: Outcome) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.totals.record(outcome);
+        state
+            .by_content_type
+            .entry(item.content_type.clone())
+            .or_default()
+            .record(outcome);
+        state
+            .by_source
+            .entry(item.source.clone())
+            .or_default()
+            .record(outcome);
+        if let Some(id) = batch::batch_id(item) {
+            state.batches.record(id, outcome);
+        }
+    }
+
+    /// Count an item that failed to publish and was dead-lettered
+    pub fn record_dead_letter(&self, item: &RawData) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.totals.dead_lettered += 1;
+        state
+            .by_content_type
+            .entry(item.content_type.clone())
+            .or_default()
+            .dead_lettered += 1;
+        state
+            .by_source
+            .entry(item.source.clone())
+            .or_default()
+            .dead_lettered += 1;
+        if let Some(batch) = batch::batch_id(item).and_then(|id| state.batches.batches.get_mut(id))
+        {
+            batch.counters.dead_lettered += 1;
+        }
+    }
+
+    /// Count a spooled or buffered message dropped once past its expiry, against its source
+    /// when known
+    pub fn record_expired(&self, source: Option<&str>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.totals.expired += 1;
+        if let Some(source) = source {
+            state
+                .by_source
+                .entry(source.to_string())
+                .or_default()
+                .expired += 1;
+        }
+    }
+
+    /// Count a gap or regression in a source's sequence numbers
+    pub fn record_sequence_gap(&self, item: &RawData, gap: &SequenceGap) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let StatsState {
+            totals,
+            by_content_type,
+            by_source,
+            ..
+        } = &mut *state;
+        for counters in [
+            totals,
+            by_content_type
+                .entry(item.content_type.clone())
+                .or_default(),
+            by_source.entry(item.source.clone()).or_default(),
+        ] {
+            if gap.received > gap.expected {
+                counters.sequence_gaps += 1;
+                counters.missed_sequences += gap.received - gap.expected;
+            } else {
+                counters.sequence_regressions += 1;
+            }
+        }
+    }
+
+    /// Counters of a batch, unless it was never seen or has been forgotten
+    pub fn batch(&self, id: &str) -> Option<BatchStatsResponse> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .batches
+            .batches
+            .get(id)
+            .map(|batch| BatchStatsResponse {
+                batch_id: id.to_string(),
+                totals: batch.counters.clone(),
+                first_seen: batch.first_seen,
+                last_seen: batch.last_seen,
+                timestamp: Utc::now(),
+            })
+    }
+
+    /// Update the UDP listener counters
+    pub fn record_datagrams(&self, update: impl FnOnce(&mut DatagramCounters)) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        update(
+            state
+                .datagrams
+                .get_or_insert_with(DatagramCounters::default),
+        );
+    }
+
+    /// Add a retention purge to its store's counters
+    pub fn record_purge(&self, report: &PurgeReport) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let counters = state.retention.entry(report.store.clone()).or_default();
+        counters.purges += 1;
+        counters.purged_records += report.purged_records;
+        counters.reclaimed_bytes += report.reclaimed_bytes;
+    }
+
+    /// Time since the service started
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Take a snapshot of the current counters
+    pub fn snapshot(&self) -> StatsResponse {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        StatsResponse {
+            service: "ingestion-service".to_string(),
+            uptime_seconds: self.uptime().as_secs(),
+            totals: state.totals.clone(),
+            by_content_type: state.by_content_type.clone(),
+            by_source: state.by_source.clone(),
+            datagrams: state.datagrams.clone(),
+            retention: state.retention.clone(),
+            dependencies: BTreeMap::new(),
+            timestamp: Utc::now(),
+        }
+    }
+}
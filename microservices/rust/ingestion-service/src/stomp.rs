@@ -0,0 +1,304 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension,
+    },
+    response::Response,
+};
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use crate::config::{AppConfig, Secret};
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+use crate::routes;
+
+/// Settings for the STOMP-over-WebSocket endpoint
+#[derive(Debug, Clone)]
+pub struct StompConfig {
+    /// Passcode clients must send in their CONNECT frame
+    pub passcode: Option<Secret>,
+}
+
+/// A STOMP frame
+#[derive(Debug, Default)]
+struct Frame {
+    command: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Frame {
+    fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// First value of a header, which takes precedence over repeats per the spec
+    fn get(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Parse one frame, without its terminating NUL
+    fn parse(data: &[u8]) -> Option<Self> {
+        let data = match data.iter().position(|&b| b != b'\n' && b != b'\r') {
+            Some(start) => &data[start..],
+            None => return None,
+        };
+
+        let header_end = data
+            .windows(2)
+            .position(|w| w == b"\n\n")
+            .map(|p| (p, 2))
+            .or_else(|| {
+                data.windows(4)
+                    .position(|w| w == b"\r\n\r\n")
+                    .map(|p| (p, 4))
+            })?;
+        let (head, body) = (&data[..header_end.0], &data[header_end.0 + header_end.1..]);
+
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.lines();
+        let command = lines.next()?.trim().to_string();
+        let escaped = command != "CONNECT";
+
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| {
+                if escaped {
+                    (unescape(name), unescape(value))
+                } else {
+                    (name.to_string(), value.to_string())
+                }
+            })
+            .collect();
+
+        Some(Self {
+            command,
+            headers,
+            body: body.to_vec(),
+        })
+    }
+
+    fn render(&self) -> String {
+        // CONNECTED headers are sent unescaped for 1.0 clients
+        let escaped = self.command != "CONNECTED";
+        let mut out = format!("{}\n", self.command);
+        for (name, value) in &self.headers {
+            if escaped {
+                out.push_str(&format!("{}:{}\n", escape(name), escape(value)));
+            } else {
+                out.push_str(&format!("{}:{}\n", name, value));
+            }
+        }
+        out.push('\n');
+        out.push_str(&String::from_utf8_lossy(&self.body));
+        out.push('\0');
+        out
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('c') => out.push(':'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace(':', "\\c")
+}
+
+/// Upgrade to a WebSocket speaking the STOMP 1.2 `v12.stomp` subprotocol
+pub async fn stomp_socket(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade
+        .protocols(["v12.stomp", "v11.stomp", "v10.stomp"])
+        .on_upgrade(move |socket| async move {
+            if let Err(e) = handle_socket(socket, &pipeline, &config).await {
+                debug!("STOMP session ended: {}", e);
+            }
+        })
+}
+
+async fn handle_socket(
+    mut socket: WebSocket,
+    pipeline: &Pipeline,
+    config: &AppConfig,
+) -> Result<()> {
+    let mut connected = false;
+
+    while let Some(message) = socket.recv().await {
+        let data = match message.map_err(|e| AppError::InternalError(e.to_string()))? {
+            Message::Text(text) => text.into_bytes(),
+            Message::Binary(data) => data,
+            Message::Close(_) => break,
+            Message::Ping(_) | Message::Pong(_) => continue,
+        };
+
+        for chunk in data.split(|&b| b == 0) {
+            // Bare newlines between frames are heart-beats
+            let Some(frame) = Frame::parse(chunk) else {
+                continue;
+            };
+
+            let reply = match frame.command.as_str() {
+                "CONNECT" | "STOMP" => match connect(&frame, config) {
+                    Ok(reply) => {
+                        connected = true;
+                        reply
+                    }
+                    Err(e) => {
+                        send(&mut socket, error_frame(&frame, &e.to_string())).await?;
+                        return Err(e);
+                    }
+                },
+                _ if !connected => {
+                    let e = AppError::PolicyViolation("CONNECT required".to_string());
+                    send(&mut socket, error_frame(&frame, &e.to_string())).await?;
+                    return Err(e);
+                }
+                "SEND" => match ingest(&frame, pipeline).await {
+                    Ok(()) => match frame.get("receipt") {
+                        Some(receipt) => Frame::new("RECEIPT").header("receipt-id", receipt),
+                        None => continue,
+                    },
+                    // Errors close the session per the spec
+                    Err(e) => {
+                        warn!("STOMP SEND to {:?} failed: {}", frame.get("destination"), e);
+                        send(&mut socket, error_frame(&frame, &e.to_string())).await?;
+                        return Err(e);
+                    }
+                },
+                "DISCONNECT" => {
+                    if let Some(receipt) = frame.get("receipt") {
+                        send(
+                            &mut socket,
+                            Frame::new("RECEIPT").header("receipt-id", receipt),
+                        )
+                        .await?;
+                    }
+                    return Ok(());
+                }
+                other => {
+                    let e =
+                        AppError::ValidationError(format!("Unsupported STOMP command {}", other));
+                    send(&mut socket, error_frame(&frame, &e.to_string())).await?;
+                    return Err(e);
+                }
+            };
+
+            send(&mut socket, reply).await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn connect(frame: &Frame, config: &AppConfig) -> Result<Frame> {
+    let passcode = config
+        .stomp
+        .as_ref()
+        .and_then(|stomp| stomp.passcode.as_ref());
+    if let Some(expected) = passcode {
+        if !frame.get("passcode").is_some_and(|p| expected.matches(p)) {
+            return Err(AppError::PolicyViolation(
+                "Invalid STOMP passcode".to_string(),
+            ));
+        }
+    }
+
+    info!("STOMP client connected as {:?}", frame.get("login"));
+
+    Ok(Frame::new("CONNECTED")
+        .header("version", "1.2")
+        .header("heart-beat", "0,0")
+        .header(
+            "server",
+            concat!("ingestion-service/", env!("CARGO_PKG_VERSION")),
+        ))
+}
+
+/// Map a SEND frame to an item.
+///
+/// A body holding a full `RawData` item is used as is; otherwise the last segment of the
+/// destination is the content type, the `source` header the source, and the body the payload.
+async fn ingest(frame: &Frame, pipeline: &Pipeline) -> Result<()> {
+    let body: Value = serde_json::from_slice(&frame.body)
+        .map_err(|e| AppError::ValidationError(format!("SEND body is not JSON: {}", e)))?;
+
+    let mut item = match serde_json::from_value::<RawData>(body.clone()) {
+        Ok(item) => item,
+        Err(_) => {
+            let content_type = frame
+                .get("destination")
+                .and_then(|d| d.rsplit('/').find(|s| !s.is_empty()))
+                .ok_or_else(|| {
+                    AppError::ValidationError("SEND frame has no destination".to_string())
+                })?;
+            let source = frame.get("source").ok_or_else(|| {
+                AppError::ValidationError("SEND frame has no source header".to_string())
+            })?;
+
+            RawData::builder()
+                .source(source)
+                .content_type(content_type)
+                .payload(body)
+                .build()?
+        }
+    };
+
+    if let Err(e) = routes::validate(&item) {
+        pipeline.reject(&item, &e);
+        return Err(e);
+    }
+
+    pipeline.process(&mut item).await.map(|_| ())
+}
+
+fn error_frame(frame: &Frame, message: &str) -> Frame {
+    let error = Frame::new("ERROR").header("message", message);
+    match frame.get("receipt") {
+        Some(receipt) => error.header("receipt-id", receipt),
+        None => error,
+    }
+}
+
+async fn send(socket: &mut WebSocket, frame: Frame) -> Result<()> {
+    socket
+        .send(Message::Text(frame.render()))
+        .await
+        .map_err(|e| AppError::InternalError(format!("Failed to send STOMP frame: {}", e)))
+}
@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+
+/// Subject template used unless `NATS_SUBJECT_TEMPLATE` is set
+pub const DEFAULT_SUBJECT_TEMPLATE: &str = "ingest.raw.{content_type}";
+
+/// How values that cannot form a subject token are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPolicy {
+    /// Reject the item
+    Reject,
+
+    /// Percent-encode the offending characters, and `%` itself so distinct values stay distinct
+    Escape,
+}
+
+impl TokenPolicy {
+    /// Parse a policy name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "reject" => Some(Self::Reject),
+            "escape" => Some(Self::Escape),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Source,
+    ContentType,
+}
+
+/// Template for the subject of each published message, e.g.
+/// `ingest.{environment}.{source}.{content_type}`.
+///
+/// Placeholders may stand alone or share a token with literal text. Values filled in per
+/// message must form valid subject tokens, so an item whose source or content type contains
+/// `.`, `*`, `>`, whitespace or control characters is rejected, or has them escaped, rather
+/// than being published somewhere unexpected.
+#[derive(Debug, Clone)]
+pub struct SubjectTemplate {
+    segments: Vec<Segment>,
+    policy: TokenPolicy,
+}
+
+impl SubjectTemplate {
+    /// Parse a template for an environment, rejecting unknown placeholders and invalid subjects
+    pub fn parse(template: &str, environment: &str, policy: TokenPolicy) -> Result<Self> {
+        let invalid = |reason: &str| {
+            AppError::ValidationError(format!("Invalid subject template {}: {}", template, reason))
+        };
+
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid("unclosed placeholder"))?
+                + start;
+            segments.push(match &rest[start + 1..end] {
+                "environment" => Segment::Literal(
+                    token("environment", environment, TokenPolicy::Reject)?.into_owned(),
+                ),
+                "source" => Segment::Source,
+                "content_type" => Segment::ContentType,
+                other => return Err(invalid(&format!("unknown placeholder {{{}}}", other))),
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        let literals = segments.iter().filter_map(|segment| match segment {
+            Segment::Literal(literal) => Some(literal),
+            _ => None,
+        });
+        for literal in literals {
+            if literal.contains(|c: char| c == '}' || (c != '.' && !is_token_char(c))) {
+                return Err(invalid(
+                    "subjects may not contain `}`, `*`, `>`, whitespace or control characters",
+                ));
+            }
+        }
+
+        let template = Self { segments, policy };
+        if template.stream_subject().split('.').any(str::is_empty) {
+            return Err(invalid("subjects may not have empty tokens"));
+        }
+
+        Ok(template)
+    }
+
+    /// Subject for an item
+    pub fn render(&self, item: &RawData) -> Result<String> {
+        let mut subject = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => subject.push_str(literal),
+                Segment::Source => subject.push_str(&token("source", &item.source, self.policy)?),
+                Segment::ContentType => {
+                    subject.push_str(&token("content type", &item.content_type, self.policy)?)
+                }
+            }
+        }
+
+        Ok(subject)
+    }
+
+    /// Subject filter matching every subject the template renders to
+    pub fn stream_subject(&self) -> String {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => rendered.push_str(literal),
+                // Tokens with per-message values become wildcards below
+                Segment::Source | Segment::ContentType => rendered.push('\0'),
+            }
+        }
+
+        rendered
+            .split('.')
+            .map(|token| if token.contains('\0') { "*" } else { token })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// Whether a subject filter, which may hold `*` and `>` wildcards, matches a subject
+pub fn matches(filter: &str, subject: &str) -> bool {
+    let mut subject_tokens = subject.split('.');
+    for token in filter.split('.') {
+        match (token, subject_tokens.next()) {
+            (">", Some(_)) => return true,
+            ("*", Some(_)) => {}
+            (token, Some(subject_token)) if token == subject_token => {}
+            _ => return false,
+        }
+    }
+    subject_tokens.next().is_none()
+}
+
+/// Check that a value fills exactly one subject token, escaping it under
+/// [`TokenPolicy::Escape`]
+pub fn token<'a>(name: &str, value: &'a str, policy: TokenPolicy) -> Result<Cow<'a, str>> {
+    if value.is_empty() {
+        return Err(AppError::ValidationError(format!(
+            "The {} cannot be empty in a subject",
+            name
+        )));
+    }
+
+    let valid = value.chars().all(is_token_char);
+    match policy {
+        TokenPolicy::Reject if !valid => Err(AppError::ValidationError(format!(
+            "The {} {:?} cannot be used in a subject, which needs it without `.`, `*`, `>`, whitespace, control or invisible formatting characters",
+            name, value
+        ))),
+        TokenPolicy::Escape if !valid || value.contains('%') => Ok(Cow::Owned(escape(value))),
+        _ => Ok(Cow::Borrowed(value)),
+    }
+}
+
+/// Whether a character may appear in a subject token: not a separator, wildcard, whitespace
+/// or control character, which would split, widen or corrupt the subject, nor an invisible
+/// formatting character such as a zero-width space or bidi override, which would make two
+/// subjects look alike
+fn is_token_char(c: char) -> bool {
+    !(c == '.' || c == '*' || c == '>' || c.is_whitespace() || c.is_control() || is_invisible(c))
+}
+
+fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{00AD}' | '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}')
+}
+
+/// Percent-encode the UTF-8 bytes of characters that may not appear in a token, and of `%`
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if is_token_char(c) && c != '%' {
+            escaped.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                let _ = write!(escaped, "%{:02X}", byte);
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn item(source: &str, content_type: &str) -> RawData {
+        let mut item = RawData::builder()
+            .source("placeholder")
+            .content_type("placeholder")
+            .payload(json!({}))
+            .build()
+            .unwrap();
+        item.source = source.to_string();
+        item.content_type = content_type.to_string();
+        item
+    }
+
+    fn template(policy: TokenPolicy) -> SubjectTemplate {
+        SubjectTemplate::parse(
+            "ingest.{environment}.{source}.{content_type}",
+            "prod",
+            policy,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn renders_valid_values_unchanged_under_either_policy() {
+        for policy in [TokenPolicy::Reject, TokenPolicy::Escape] {
+            assert_eq!(
+                template(policy)
+                    .render(&item("news-api", "news_article"))
+                    .unwrap(),
+                "ingest.prod.news-api.news_article"
+            );
+        }
+    }
+
+    #[test]
+    fn reject_refuses_values_that_are_not_one_token() {
+        let template = template(TokenPolicy::Reject);
+        for source in [
+            "news.api",
+            "news*",
+            "news>",
+            "news api",
+            "news\tapi",
+            "news\u{200B}api",
+        ] {
+            let result = template.render(&item(source, "news_article"));
+            assert!(
+                matches!(result, Err(AppError::ValidationError(_))),
+                "{:?} was not rejected",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn escape_percent_encodes_values_that_are_not_one_token() {
+        let template = template(TokenPolicy::Escape);
+        let render = |source: &str| template.render(&item(source, "news_article")).unwrap();
+
+        assert_eq!(render("news.api"), "ingest.prod.news%2Eapi.news_article");
+        assert_eq!(render("news*"), "ingest.prod.news%2A.news_article");
+        assert_eq!(render("news>"), "ingest.prod.news%3E.news_article");
+        assert_eq!(render("news api"), "ingest.prod.news%20api.news_article");
+        assert_eq!(render("a\u{200B}b"), "ingest.prod.a%E2%80%8Bb.news_article");
+    }
+
+    #[test]
+    fn escape_keeps_distinct_values_distinct() {
+        let template = template(TokenPolicy::Escape);
+        let render = |source: &str| template.render(&item(source, "news_article")).unwrap();
+
+        // `%` is escaped too, so a value cannot pass itself off as an escaped one
+        assert_eq!(render("100%"), "ingest.prod.100%25.news_article");
+        assert_ne!(render("a.b"), render("a%2Eb"));
+    }
+
+    #[test]
+    fn empty_values_are_refused_under_either_policy() {
+        for policy in [TokenPolicy::Reject, TokenPolicy::Escape] {
+            assert!(token("source", "", policy).is_err());
+            assert!(template(policy).render(&item("news-api", "")).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for template in [
+            "ingest.{unknown}",
+            "ingest.{source",
+            "ingest..{source}",
+            "ingest.{source}.",
+            "ingest.*.{source}",
+            "ingest.>",
+            "ingest raw.{source}",
+        ] {
+            assert!(
+                SubjectTemplate::parse(template, "prod", TokenPolicy::Reject).is_err(),
+                "{} was accepted",
+                template
+            );
+        }
+        assert!(
+            SubjectTemplate::parse("ingest.{environment}", "pr.od", TokenPolicy::Escape).is_err()
+        );
+    }
+
+    #[test]
+    fn stream_subject_covers_rendered_subjects() {
+        let template = SubjectTemplate::parse(
+            "ingest.{source}-{content_type}.raw",
+            "prod",
+            TokenPolicy::Escape,
+        )
+        .unwrap();
+        assert_eq!(template.stream_subject(), "ingest.*.raw");
+
+        let subject = template.render(&item("news.api", "news_article")).unwrap();
+        assert!(matches(&template.stream_subject(), &subject));
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("ingest.*.raw", "ingest.news.raw"));
+        assert!(!matches("ingest.*.raw", "ingest.news.extra.raw"));
+        assert!(matches("ingest.>", "ingest.news.raw"));
+        assert!(!matches("ingest.>", "ingest"));
+        assert!(!matches("ingest.raw", "ingest.raw.extra"));
+    }
+}
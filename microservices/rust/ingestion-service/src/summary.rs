@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::trace::TraceContextExt;
+use tracing::{info, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::models::RawData;
+use crate::stats::Outcome;
+
+/// Target of the summary lines, to filter or route them apart from other logs
+pub const SUMMARY_TARGET: &str = "request_summary";
+
+/// Stages of the pipeline timed in request summaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Checks, validation, pre-processing and routing
+    Admit,
+    /// Encoding, embedding and offloading of the item's messages
+    Encode,
+    /// Waiting for the content type's rate cap
+    Throttle,
+    /// Publishing, including retries, spooling and buffering
+    Publish,
+}
+
+/// What happened while handling one request, collected by the pipeline as it goes
+#[derive(Debug, Default)]
+struct RequestSummary {
+    source: Option<String>,
+    content_type: Option<String>,
+    tenant: Option<String>,
+    outcomes: [u64; 4],
+    bytes: u64,
+    stages: BTreeMap<Stage, Duration>,
+}
+
+impl RequestSummary {
+    fn items(&self) -> u64 {
+        self.outcomes.iter().sum()
+    }
+}
+
+tokio::task_local! {
+    static SUMMARY: Arc<Mutex<RequestSummary>>;
+}
+
+/// Run `f` on the summary of the request being handled, if any; items from the TCP, UDP,
+/// STOMP and email listeners are not part of a request
+fn with_summary(f: impl FnOnce(&mut RequestSummary)) {
+    let _ = SUMMARY.try_with(|summary| f(&mut summary.lock().unwrap_or_else(|e| e.into_inner())));
+}
+
+/// Count an item's outcome towards the current request's summary
+pub fn record_item(item: &RawData, tenant: Option<String>, outcome: Outcome) {
+    with_summary(|summary| {
+        // Several sources or content types in one request are summarised as `mixed`
+        for (field, value) in [
+            (&mut summary.source, &item.source),
+            (&mut summary.content_type, &item.content_type),
+        ] {
+            match field {
+                None => *field = Some(value.clone()),
+                Some(current) if current != value && current != "mixed" => {
+                    *field = Some("mixed".to_string())
+                }
+                Some(_) => {}
+            }
+        }
+        if summary.tenant.is_none() {
+            summary.tenant = tenant;
+        }
+        summary.outcomes[outcome as usize] += 1;
+    });
+}
+
+/// Count bytes of a message accepted for delivery towards the current request's summary
+pub fn record_bytes(bytes: usize) {
+    with_summary(|summary| summary.bytes += bytes as u64);
+}
+
+/// Add time spent in a pipeline stage to the current request's summary
+pub fn record_stage(stage: Stage, elapsed: Duration) {
+    with_summary(|summary| *summary.stages.entry(stage).or_default() += elapsed);
+}
+
+/// Log exactly one structured line per request, with who sent it, what it carried, how it
+/// ended and where the time went, for log-based metrics
+pub async fn log_summary(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let request_bytes = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+    let trace_id = Span::current()
+        .context()
+        .span()
+        .span_context()
+        .trace_id()
+        .to_string();
+
+    let summary = Arc::new(Mutex::new(RequestSummary::default()));
+    let response = SUMMARY.scope(summary.clone(), next.run(request)).await;
+
+    let summary = summary.lock().unwrap_or_else(|e| e.into_inner());
+    let status = response.status();
+    let [published, rejected, failed, spooled] = summary.outcomes;
+    let outcome = if status.is_server_error() {
+        "failed"
+    } else if status.is_client_error() {
+        "rejected"
+    } else if rejected + failed > 0 {
+        "partial"
+    } else {
+        "success"
+    };
+    let stage_ms = |stage| {
+        summary
+            .stages
+            .get(&stage)
+            .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+    };
+
+    info!(
+        target: SUMMARY_TARGET,
+        method = %method,
+        route = %route,
+        status = status.as_u16(),
+        outcome,
+        trace_id = %trace_id,
+        tenant = summary.tenant.as_deref().unwrap_or("-"),
+        source = summary.source.as_deref().unwrap_or("-"),
+        content_type = summary.content_type.as_deref().unwrap_or("-"),
+        items = summary.items(),
+        published,
+        rejected,
+        failed,
+        spooled,
+        request_bytes,
+        published_bytes = summary.bytes,
+        admit_ms = stage_ms(Stage::Admit),
+        encode_ms = stage_ms(Stage::Encode),
+        throttle_ms = stage_ms(Stage::Throttle),
+        publish_ms = stage_ms(Stage::Publish),
+        duration_ms = started.elapsed().as_secs_f64() * 1000.0,
+        "Request completed"
+    );
+
+    response
+}
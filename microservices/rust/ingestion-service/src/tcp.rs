@@ -0,0 +1,187 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+use crate::config::Secret;
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+use crate::routes;
+
+/// Settings for the newline-delimited JSON TCP listener
+#[derive(Debug, Clone)]
+pub struct TcpIngestConfig {
+    /// Address the listener binds to
+    pub addr: SocketAddr,
+
+    /// Token clients must send as an `AUTH <token>` first line
+    pub auth_token: Option<Secret>,
+
+    /// Longest accepted record; longer lines close the connection
+    pub max_line_bytes: usize,
+}
+
+/// Bind the listener and spawn the task accepting connections.
+///
+/// Each line is one `RawData` record, validated and processed like an HTTP ingest. Nothing
+/// is written back apart from an error line when authentication fails, so emitters that
+/// never read from the socket cannot stall.
+pub async fn spawn(config: TcpIngestConfig, pipeline: Arc<Pipeline>) -> Result<()> {
+    let listener = TcpListener::bind(config.addr).await.map_err(|e| {
+        AppError::InternalError(format!(
+            "Failed to bind TCP listener on {}: {}",
+            config.addr, e
+        ))
+    })?;
+    info!("NDJSON TCP listener on {}", config.addr);
+
+    let config = Arc::new(config);
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let config = config.clone();
+                    let pipeline = pipeline.clone();
+                    tokio::spawn(async move {
+                        match handle_connection(stream, &config, &pipeline).await {
+                            Ok(count) => debug!(
+                                "TCP connection from {} closed after {} records",
+                                peer, count
+                            ),
+                            Err(e) => warn!("TCP connection from {} closed: {}", peer, e),
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept TCP connection: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    config: &TcpIngestConfig,
+    pipeline: &Pipeline,
+) -> Result<usize> {
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    let mut authenticated = config.auth_token.is_none();
+    let mut count = 0;
+
+    loop {
+        if !read_line(&mut reader, &mut line, config.max_line_bytes).await? {
+            return Ok(count);
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        if !authenticated {
+            let token = text.strip_prefix("AUTH ").map(str::trim);
+            if !token
+                .is_some_and(|token| config.auth_token.as_ref().is_some_and(|t| t.matches(token)))
+            {
+                let _ = reader
+                    .get_mut()
+                    .write_all(b"{\"error\":\"authentication required\"}\n")
+                    .await;
+                return Err(AppError::PolicyViolation(
+                    "Invalid or missing AUTH preamble".to_string(),
+                ));
+            }
+            authenticated = true;
+            continue;
+        }
+
+        let mut item: RawData = match serde_json::from_str(text) {
+            Ok(item) => item,
+            Err(e) => {
+                warn!("Skipping unparseable TCP record: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = routes::validate(&item) {
+            pipeline.reject(&item, &e);
+            warn!("Rejected TCP record {}: {}", item.id, e);
+            continue;
+        }
+
+        match pipeline.process(&mut item).await {
+            Ok(_) => count += 1,
+            Err(e) => warn!("Failed to ingest TCP record {}: {}", item.id, e),
+        }
+    }
+}
+
+/// Read the next line into `line`, returning false at the end of the stream. Reads stop
+/// just past `max_line_bytes`, so an endless line cannot exhaust memory.
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+    max_line_bytes: usize,
+) -> Result<bool> {
+    line.clear();
+    let read = reader
+        .take(max_line_bytes as u64 + 1)
+        .read_until(b'\n', line)
+        .await
+        .map_err(tcp_error)?;
+
+    if line.len() > max_line_bytes {
+        return Err(AppError::ValidationError(format!(
+            "Record exceeds {} bytes",
+            max_line_bytes
+        )));
+    }
+    Ok(read > 0)
+}
+
+fn tcp_error(e: std::io::Error) -> AppError {
+    AppError::InternalError(format!("TCP read error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_lines_up_to_the_limit() {
+        let mut reader = &b"{\"a\":1}\n\nlast"[..];
+        let mut line = Vec::new();
+
+        assert!(read_line(&mut reader, &mut line, 8).await.unwrap());
+        assert_eq!(line, b"{\"a\":1}\n");
+        assert!(read_line(&mut reader, &mut line, 8).await.unwrap());
+        assert_eq!(line, b"\n");
+        assert!(read_line(&mut reader, &mut line, 8).await.unwrap());
+        assert_eq!(line, b"last");
+        assert!(!read_line(&mut reader, &mut line, 8).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn rejects_lines_over_the_limit() {
+        let mut line = Vec::new();
+
+        let mut reader = &b"123456789\n"[..];
+        assert!(matches!(
+            read_line(&mut reader, &mut line, 8).await,
+            Err(AppError::ValidationError(_))
+        ));
+        assert_eq!(line.len(), 9, "reading stops one byte past the limit");
+
+        // Without a newline, as from an emitter that never ends its record
+        let endless = vec![b'x'; 64 * 1024];
+        let mut reader = &endless[..];
+        assert!(read_line(&mut reader, &mut line, 1024).await.is_err());
+        assert_eq!(line.len(), 1025);
+    }
+}
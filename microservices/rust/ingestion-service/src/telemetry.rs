@@ -0,0 +1,293 @@
+use std::sync::OnceLock;
+
+use axum::http::{HeaderMap, Request};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{Link, SpanKind, TraceContextExt, TraceId, TracerProvider};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{
+    SamplingDecision, SamplingResult, SdkTracer, SdkTracerProvider, ShouldSample,
+};
+use tower_http::trace::{DefaultMakeSpan, MakeSpan};
+use tracing::{info, Level, Span, Subscriber};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::nats::Headers;
+
+/// W3C trace context header, joined by `tracestate` when the trace carries vendor state
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// What a sampling rule applies to
+#[derive(Debug, Clone, PartialEq)]
+pub enum SamplingSelector {
+    /// Requests to a path, or under a prefix when the pattern ends in `*`, e.g. `/admin/*`
+    Route(String),
+
+    /// Items of a source
+    Source(String),
+
+    /// Messages spooled or buffered after a failed publish
+    Error,
+}
+
+/// Share of traces sampled for what a selector matches
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingRule {
+    pub selector: SamplingSelector,
+
+    /// From `0.0` for none to `1.0` for all
+    pub rate: f64,
+}
+
+impl SamplingRule {
+    /// Parse a rule as used in configuration, e.g. `route:/ingest=0.01`, `source:lab=1` or
+    /// `error=1`
+    pub fn parse(value: &str) -> Option<Self> {
+        let (selector, rate) = value.trim().rsplit_once('=')?;
+        let rate = rate
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|rate| (0.0..=1.0).contains(rate))?;
+        let selector = match selector.trim().split_once(':') {
+            Some(("route", path)) if path.starts_with('/') => {
+                SamplingSelector::Route(path.to_string())
+            }
+            Some(("source", source)) if !source.is_empty() => {
+                SamplingSelector::Source(source.to_string())
+            }
+            None if selector.trim() == "error" => SamplingSelector::Error,
+            _ => return None,
+        };
+        Some(Self { selector, rate })
+    }
+}
+
+/// Which traces are marked as sampled in the trace context passed on to consumers
+#[derive(Debug, Clone)]
+pub struct TraceSampling {
+    /// Rules in order of precedence; the first matching route rule applies
+    pub rules: Vec<SamplingRule>,
+
+    /// Share of traces sampled for requests no route rule matches
+    pub rate: f64,
+}
+
+impl TraceSampling {
+    fn route_rate(&self, path: &str) -> f64 {
+        self.rules
+            .iter()
+            .find_map(|rule| match &rule.selector {
+                SamplingSelector::Route(pattern) => match pattern.strip_suffix('*') {
+                    Some(prefix) => path.starts_with(prefix),
+                    None => path == pattern,
+                }
+                .then_some(rule.rate),
+                _ => None,
+            })
+            .unwrap_or(self.rate)
+    }
+
+    fn source_rate(&self, source: &str) -> Option<f64> {
+        self.rules.iter().find_map(|rule| match &rule.selector {
+            SamplingSelector::Source(name) if name == source => Some(rule.rate),
+            _ => None,
+        })
+    }
+
+    fn error_rate(&self) -> Option<f64> {
+        self.rules
+            .iter()
+            .find_map(|rule| (rule.selector == SamplingSelector::Error).then_some(rule.rate))
+    }
+}
+
+static SAMPLING: OnceLock<TraceSampling> = OnceLock::new();
+
+/// Set the sampling rules; until then every trace is sampled
+pub fn set_sampling(sampling: TraceSampling) {
+    if sampling.rate < 1.0 || !sampling.rules.is_empty() {
+        info!(
+            "Sampling {}% of traces, with {} rules",
+            sampling.rate * 100.0,
+            sampling.rules.len()
+        );
+    }
+    let _ = SAMPLING.set(sampling);
+}
+
+/// Layer giving tracing spans OpenTelemetry trace and span IDs.
+///
+/// Spans are not exported; the IDs exist so the W3C trace context can be passed on to NATS
+/// consumers, continuing the trace of the producer when its request carried one. Whether
+/// the trace context is marked as sampled follows [`TraceSampling`], so consumers that
+/// export their spans keep a bounded share of traces.
+pub fn layer<S>() -> OpenTelemetryLayer<S, SdkTracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let provider = SdkTracerProvider::builder()
+        .with_sampler(RuleSampler)
+        .build();
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+}
+
+/// Samples request spans by route, and otherwise follows the parent, so a producer's
+/// decision carries over to the requests it traces
+#[derive(Debug, Clone)]
+struct RuleSampler;
+
+impl ShouldSample for RuleSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        _span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        _links: &[Link],
+    ) -> SamplingResult {
+        let parent = parent_context
+            .map(|cx| cx.span().span_context().clone())
+            .filter(|parent| parent.is_valid());
+
+        let sampled = match (SAMPLING.get(), parent) {
+            (_, Some(parent)) => parent.is_sampled(),
+            (None, None) => true,
+            (Some(sampling), None) if name == "request" => {
+                let uri = attributes
+                    .iter()
+                    .find(|kv| kv.key.as_str() == "uri")
+                    .map(|kv| kv.value.as_str())
+                    .unwrap_or_default();
+                let path = uri.split('?').next().unwrap_or_default();
+                sample(sampling.route_rate(path), trace_id)
+            }
+            (Some(sampling), None) => sample(sampling.rate, trace_id),
+        };
+
+        SamplingResult {
+            decision: if sampled {
+                SamplingDecision::RecordAndSample
+            } else {
+                SamplingDecision::Drop
+            },
+            attributes: Vec::new(),
+            trace_state: parent_context
+                .map(|cx| cx.span().span_context().trace_state().clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Whether a trace falls in the sampled share, decided by its ID so every span of the trace
+/// agrees
+fn sample(rate: f64, trace_id: TraceId) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    let low = u64::from_be_bytes(trace_id.to_bytes()[8..].try_into().unwrap_or_default());
+    ((low >> 11) as f64) < rate * (1u64 << 53) as f64
+}
+
+/// Span for an HTTP request, continuing the trace context the request carries.
+///
+/// Recorded at info level, below which spans get no trace IDs.
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    let span = DefaultMakeSpan::new().level(Level::INFO).make_span(request);
+
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HttpHeaders(request.headers()))
+    });
+    // The span is not entered yet, so this fails only when it is disabled
+    let _ = span.set_parent(parent);
+
+    span
+}
+
+/// Add the current span's `traceparent` and `tracestate` to message headers, unless they
+/// already carry a trace context
+pub fn inject(headers: &mut Headers) {
+    if headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case(TRACEPARENT_HEADER))
+    {
+        return;
+    }
+
+    let context = Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut MessageHeaders(headers))
+    });
+}
+
+struct HttpHeaders<'a>(&'a HeaderMap);
+
+impl Extractor for HttpHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+struct MessageHeaders<'a>(&'a mut Headers);
+
+impl Injector for MessageHeaders<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        // The propagator sets an empty `tracestate` when there is no vendor state
+        if !value.is_empty() {
+            self.0.push((key.to_string(), value));
+        }
+    }
+}
+
+/// Sample an item's messages at its source's rate when a rule names the source, whatever
+/// the request's decision, so a source under investigation can be traced on its own
+pub fn sample_source(headers: &mut Headers, source: &str) {
+    if let Some(rate) = SAMPLING
+        .get()
+        .and_then(|sampling| sampling.source_rate(source))
+    {
+        resample(headers, |trace_id, _| sample(rate, trace_id));
+    }
+}
+
+/// Mark a message's trace context as sampled when the `error` rule picks its trace, for a
+/// message spooled or buffered after a failed publish
+pub fn sample_failure(headers: &mut Headers) {
+    if let Some(rate) = SAMPLING.get().and_then(|sampling| sampling.error_rate()) {
+        resample(headers, |trace_id, sampled| {
+            sampled || sample(rate, trace_id)
+        });
+    }
+}
+
+/// Rewrite the sampled flag of a `traceparent` header, `version-traceid-spanid-flags`
+fn resample(headers: &mut Headers, decide: impl Fn(TraceId, bool) -> bool) {
+    for (_, value) in headers
+        .iter_mut()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(TRACEPARENT_HEADER))
+    {
+        let parts: Vec<&str> = value.split('-').collect();
+        let [version, trace_id, span_id, flags] = parts[..] else {
+            continue;
+        };
+        let (Ok(trace_id), Ok(flags)) =
+            (TraceId::from_hex(trace_id), u8::from_str_radix(flags, 16))
+        else {
+            continue;
+        };
+        let flags = if decide(trace_id, flags & 1 == 1) {
+            flags | 1
+        } else {
+            flags & !1
+        };
+        *value = format!("{}-{:032x}-{}-{:02x}", version, trace_id, span_id, flags);
+    }
+}
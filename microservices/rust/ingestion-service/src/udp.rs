@@ -0,0 +1,128 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::error::{AppError, Result};
+use crate::models::RawData;
+use crate::pipeline::Pipeline;
+
+/// Settings for the UDP telemetry listener
+#[derive(Debug, Clone)]
+pub struct UdpIngestConfig {
+    /// Address the listener binds to
+    pub addr: SocketAddr,
+
+    /// Source assigned to batches
+    pub source: String,
+
+    /// Content type assigned to batches
+    pub content_type: String,
+
+    /// Most records per published batch
+    pub batch_size: usize,
+
+    /// Longest a record waits before its batch is flushed
+    pub flush_interval: Duration,
+
+    /// Records buffered between the socket and the batcher before new ones are dropped
+    pub queue_capacity: usize,
+}
+
+/// Bind the listener and spawn the receive and batching tasks.
+///
+/// Each datagram holds one or more newline-separated records, statsd-style. Records are
+/// parsed as JSON when possible and kept as strings otherwise, then published in batches as
+/// a single item with a `records` array. Delivery is best effort: records are dropped when
+/// the queue is full or their batch fails, and counted in `/stats`.
+pub async fn spawn(config: UdpIngestConfig, pipeline: Arc<Pipeline>) -> Result<()> {
+    let socket = UdpSocket::bind(config.addr).await.map_err(|e| {
+        AppError::InternalError(format!(
+            "Failed to bind UDP listener on {}: {}",
+            config.addr, e
+        ))
+    })?;
+    info!("UDP telemetry listener on {}", config.addr);
+
+    let (tx, rx) = mpsc::channel(config.queue_capacity.max(1));
+    pipeline.stats().record_datagrams(|_| {});
+
+    tokio::spawn(receive(socket, tx, pipeline.clone()));
+    tokio::spawn(batch(config, rx, pipeline));
+
+    Ok(())
+}
+
+async fn receive(socket: UdpSocket, tx: mpsc::Sender<Value>, pipeline: Arc<Pipeline>) {
+    let mut buffer = vec![0u8; 65_535];
+
+    loop {
+        let len = match socket.recv(&mut buffer).await {
+            Ok(len) => len,
+            Err(e) => {
+                warn!("UDP receive failed: {}", e);
+                continue;
+            }
+        };
+
+        let (mut received, mut dropped) = (0, 0);
+        for line in String::from_utf8_lossy(&buffer[..len]).lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            received += 1;
+
+            let record =
+                serde_json::from_str(line).unwrap_or_else(|_| Value::String(line.to_string()));
+            if tx.try_send(record).is_err() {
+                dropped += 1;
+            }
+        }
+
+        pipeline.stats().record_datagrams(|counters| {
+            counters.received += received;
+            counters.dropped_overflow += dropped;
+        });
+    }
+}
+
+async fn batch(config: UdpIngestConfig, mut rx: mpsc::Receiver<Value>, pipeline: Arc<Pipeline>) {
+    while let Some(first) = rx.recv().await {
+        let mut records = vec![first];
+        let deadline = Instant::now() + config.flush_interval;
+
+        while records.len() < config.batch_size {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(record)) => records.push(record),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let count = records.len() as u64;
+        let result = RawData::builder()
+            .source(config.source.clone())
+            .content_type(config.content_type.clone())
+            .payload(json!({ "count": count, "records": records }))
+            .build();
+
+        let result = match result {
+            Ok(mut item) => pipeline.process(&mut item).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+
+        pipeline.stats().record_datagrams(|counters| match &result {
+            Ok(()) => counters.forwarded += count,
+            Err(_) => counters.dropped_failed += count,
+        });
+
+        if let Err(e) = result {
+            warn!("Dropped batch of {} UDP records: {}", count, e);
+        }
+    }
+}
@@ -0,0 +1,756 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::fs;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::batch;
+use crate::encryption::Cipher;
+use crate::error::{AppError, ErrorResponse, Result};
+use crate::models::{BatchIngestResponse, RawData, UploadSessionResponse};
+use crate::pipeline::Pipeline;
+use crate::routes;
+
+/// Settings for session-based bulk uploads
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    /// Directory holding open sessions and their chunks
+    pub dir: PathBuf,
+
+    /// How long a session stays open after its last change, and how long a committed
+    /// session keeps answering repeated commits
+    pub ttl: Duration,
+
+    /// Largest accepted chunk
+    pub max_chunk_bytes: usize,
+
+    /// Most items a session may hold
+    pub max_items: usize,
+}
+
+/// State of a session, kept as `session.json` in its directory
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+
+    /// Items in each uploaded chunk, by sequence number
+    chunks: BTreeMap<u32, usize>,
+
+    /// Response of the commit, repeated to clients retrying it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    committed: Option<BatchIngestResponse>,
+}
+
+impl Manifest {
+    fn items(&self) -> usize {
+        self.chunks.values().sum()
+    }
+
+    fn response(&self) -> UploadSessionResponse {
+        UploadSessionResponse {
+            id: self.id,
+            status: if self.committed.is_some() {
+                "committed"
+            } else {
+                "open"
+            }
+            .to_string(),
+            chunks: self.chunks.keys().copied().collect(),
+            items: self.items(),
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+/// Bulk uploads spread over several requests.
+///
+/// A client opens a session, uploads NDJSON chunks to it and then commits it, publishing
+/// every item with the session ID as a shared batch ID, or aborts it. Chunks are numbered
+/// by the client, so a chunk whose response was lost is simply uploaded again, and are
+/// published in order of their numbers. Sessions live on local disk, encrypted at rest
+/// when a cipher is given, so they survive restarts but not a move to another replica.
+pub struct UploadSessions {
+    config: UploadConfig,
+    cipher: Option<Arc<Cipher>>,
+
+    /// Serialises changes to each session, so a commit sees every chunk acknowledged before it
+    locks: Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl UploadSessions {
+    /// Open the session directory, keeping sessions left by a previous run
+    pub async fn open(config: UploadConfig, cipher: Option<Arc<Cipher>>) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .await
+            .map_err(upload_error)?;
+
+        info!("Upload sessions stored in {}", config.dir.display());
+
+        Ok(Self {
+            config,
+            cipher,
+            locks: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Largest accepted chunk
+    pub fn max_chunk_bytes(&self) -> usize {
+        self.config.max_chunk_bytes
+    }
+
+    /// Spawn the background task removing expired sessions
+    pub fn spawn_sweeper(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.ttl.min(Duration::from_secs(60)));
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.sweep().await {
+                    warn!("Failed to remove expired upload sessions: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Start a session
+    pub async fn create(&self) -> Result<UploadSessionResponse> {
+        let now = Utc::now();
+        let manifest = Manifest {
+            id: Uuid::new_v4(),
+            created_at: now,
+            expires_at: now + self.ttl(),
+            chunks: BTreeMap::new(),
+            committed: None,
+        };
+
+        fs::create_dir_all(self.session_dir(manifest.id))
+            .await
+            .map_err(upload_error)?;
+        self.save(&manifest).await?;
+
+        info!("Opened upload session {}", manifest.id);
+
+        Ok(manifest.response())
+    }
+
+    /// Describe a session
+    pub async fn get(&self, id: Uuid) -> Result<UploadSessionResponse> {
+        Ok(self.load(id).await?.response())
+    }
+
+    /// Store a chunk of NDJSON items, replacing an earlier upload of the same chunk.
+    ///
+    /// Every line is parsed and validated now, so a bad item fails its chunk rather than
+    /// the commit. Items are stored with their IDs and timestamps filled in, so a repeated
+    /// commit publishes them unchanged.
+    pub async fn put_chunk(
+        &self,
+        pipeline: &Pipeline,
+        id: Uuid,
+        seq: u32,
+        body: &[u8],
+    ) -> Result<UploadSessionResponse> {
+        let mut items = Vec::new();
+        for (index, line) in body.split(|&b| b == b'\n').enumerate() {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let item = serde_json::from_slice::<RawData>(line)
+                .map_err(|e| AppError::ValidationError(e.to_string()))
+                .and_then(|item| routes::validate(&item).map(|_| item))
+                // Restricted items must not reach the disk unless it is encrypted
+                .and_then(|item| pipeline.check_classification(&item).map(|_| item))
+                .map_err(|e| line_error(seq, index, e))?;
+            items.push(item);
+        }
+        if items.is_empty() {
+            return Err(AppError::ValidationError(format!(
+                "Chunk {} contains no items",
+                seq
+            )));
+        }
+
+        let mut stored = Vec::new();
+        for item in &items {
+            serde_json::to_writer(&mut stored, item).map_err(upload_error)?;
+            stored.push(b'\n');
+        }
+        let stored = match &self.cipher {
+            Some(cipher) => cipher.encrypt(&stored)?.into_bytes(),
+            None => stored,
+        };
+
+        let lock = self.lock(id);
+        let _guard = lock.lock().await;
+
+        let mut manifest = self.load_open(id).await?;
+        let total =
+            manifest.items() - manifest.chunks.get(&seq).copied().unwrap_or(0) + items.len();
+        if total > self.config.max_items {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Upload sessions hold at most {} items",
+                self.config.max_items
+            )));
+        }
+
+        self.write(&self.chunk_path(id, seq), &stored).await?;
+        manifest.chunks.insert(seq, items.len());
+        manifest.expires_at = Utc::now() + self.ttl();
+        self.save(&manifest).await?;
+
+        debug!(
+            "Stored chunk {} of upload session {} with {} items",
+            seq,
+            id,
+            items.len()
+        );
+
+        Ok(manifest.response())
+    }
+
+    /// Publish every item of a session, in chunk order, under the session ID as batch ID.
+    ///
+    /// Repeating the commit of a committed session returns the original response.
+    pub async fn commit(&self, pipeline: &Pipeline, id: Uuid) -> Result<BatchIngestResponse> {
+        let lock = self.lock(id);
+        let _guard = lock.lock().await;
+
+        let mut manifest = self.load(id).await?;
+        if let Some(response) = manifest.committed.take() {
+            info!(
+                "Upload session {} was already committed, returning the original response",
+                id
+            );
+            return Ok(response);
+        }
+        if manifest.chunks.is_empty() {
+            return Err(AppError::ValidationError(format!(
+                "Upload session {} has no chunks",
+                id
+            )));
+        }
+
+        let mut items = Vec::with_capacity(manifest.items());
+        for seq in manifest.chunks.keys() {
+            let stored = fs::read(self.chunk_path(id, *seq))
+                .await
+                .map_err(upload_error)?;
+            let stored = match &self.cipher {
+                Some(cipher) => cipher.decrypt(&String::from_utf8_lossy(&stored))?,
+                None => stored,
+            };
+            for line in stored
+                .split(|&b| b == b'\n')
+                .filter(|line| !line.is_empty())
+            {
+                items.push(serde_json::from_slice::<RawData>(line).map_err(upload_error)?);
+            }
+        }
+
+        let count = items.len();
+        for (index, item) in items.iter_mut().enumerate() {
+            batch::tag(item, &id.to_string());
+            item.metadata["batch"]["index"] = json!(index);
+            item.metadata["batch"]["count"] = json!(count);
+        }
+
+        let (ids, sequence_gaps) = routes::ingest_items(pipeline, &mut items).await;
+        let response = BatchIngestResponse {
+            status: "success".to_string(),
+            count: ids.len(),
+            ids,
+            timestamp: Utc::now(),
+            deliver_at: None,
+            sequence_gaps,
+        };
+
+        info!(
+            "Committed upload session {}: {}/{} items ingested",
+            id, response.count, count
+        );
+
+        // Keep the response for retried commits, but not the items
+        for seq in manifest.chunks.keys() {
+            if let Err(e) = fs::remove_file(self.chunk_path(id, *seq)).await {
+                warn!(
+                    "Failed to remove chunk {} of committed upload session {}: {}",
+                    seq, id, e
+                );
+            }
+        }
+        manifest.expires_at = Utc::now() + self.ttl();
+        manifest.committed = Some(response);
+        self.save(&manifest).await?;
+
+        Ok(manifest.committed.expect("commit response was just stored"))
+    }
+
+    /// Discard a session and its chunks
+    pub async fn abort(&self, id: Uuid) -> Result<()> {
+        let lock = self.lock(id);
+        let _guard = lock.lock().await;
+
+        self.load(id).await?;
+        self.remove(id).await?;
+
+        info!("Aborted upload session {}", id);
+
+        Ok(())
+    }
+
+    /// Remove expired sessions
+    async fn sweep(&self) -> Result<()> {
+        let now = Utc::now();
+        let mut entries = fs::read_dir(&self.config.dir).await.map_err(upload_error)?;
+        while let Some(entry) = entries.next_entry().await.map_err(upload_error)? {
+            let Some(id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| Uuid::parse_str(name).ok())
+            else {
+                continue;
+            };
+
+            let lock = self.lock(id);
+            let _guard = lock.lock().await;
+            match self.read(id).await {
+                Ok(manifest) if manifest.expires_at > now => continue,
+                Ok(manifest) if manifest.committed.is_none() => {
+                    warn!(
+                        "Upload session {} expired with {} uncommitted items",
+                        id,
+                        manifest.items()
+                    )
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Removing unreadable upload session {}: {}", id, e),
+            }
+            self.remove(id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Read a session's manifest, treating expired sessions as gone
+    async fn load(&self, id: Uuid) -> Result<Manifest> {
+        let manifest = self.read(id).await?;
+        if manifest.expires_at <= Utc::now() {
+            return Err(not_found(id));
+        }
+
+        Ok(manifest)
+    }
+
+    async fn read(&self, id: Uuid) -> Result<Manifest> {
+        let body = match fs::read(self.session_dir(id).join("session.json")).await {
+            Ok(body) => body,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(not_found(id)),
+            Err(e) => return Err(upload_error(e)),
+        };
+
+        serde_json::from_slice(&body).map_err(upload_error)
+    }
+
+    async fn load_open(&self, id: Uuid) -> Result<Manifest> {
+        let manifest = self.load(id).await?;
+        if manifest.committed.is_some() {
+            return Err(AppError::Duplicate(format!(
+                "Upload session {} is already committed",
+                id
+            )));
+        }
+
+        Ok(manifest)
+    }
+
+    async fn save(&self, manifest: &Manifest) -> Result<()> {
+        let body = serde_json::to_vec(manifest).map_err(upload_error)?;
+        self.write(&self.session_dir(manifest.id).join("session.json"), &body)
+            .await
+    }
+
+    /// Replace a file atomically, so a crash leaves either the old or the new content
+    async fn write(&self, path: &std::path::Path, body: &[u8]) -> Result<()> {
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, body).await.map_err(upload_error)?;
+        fs::rename(&temp, path).await.map_err(upload_error)
+    }
+
+    async fn remove(&self, id: Uuid) -> Result<()> {
+        self.locks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&id);
+        match fs::remove_dir_all(self.session_dir(id)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(upload_error(e)),
+        }
+    }
+
+    fn lock(&self, id: Uuid) -> Arc<tokio::sync::Mutex<()>> {
+        self.locks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(id)
+            .or_default()
+            .clone()
+    }
+
+    fn ttl(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.config.ttl).unwrap_or(chrono::Duration::MAX)
+    }
+
+    fn session_dir(&self, id: Uuid) -> PathBuf {
+        self.config.dir.join(id.to_string())
+    }
+
+    fn chunk_path(&self, id: Uuid, seq: u32) -> PathBuf {
+        self.session_dir(id).join(format!("{:010}.ndjson", seq))
+    }
+}
+
+fn line_error(seq: u32, index: usize, error: AppError) -> AppError {
+    let message = format!("Line {} of chunk {}: {}", index + 1, seq, error);
+    match error {
+        AppError::PolicyViolation(_) => AppError::PolicyViolation(message),
+        _ => AppError::ValidationError(message),
+    }
+}
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!(
+        "Upload session {} does not exist or has expired",
+        id
+    ))
+}
+
+fn upload_error(e: impl std::fmt::Display) -> AppError {
+    AppError::InternalError(format!("Upload session storage error: {}", e))
+}
+
+/// Open an upload session
+#[utoipa::path(
+    post,
+    path = "/ingest/sessions",
+    tag = "ingest",
+    responses((status = 201, description = "Session opened", body = UploadSessionResponse))
+)]
+#[instrument(skip_all)]
+pub async fn open_session(
+    Extension(sessions): Extension<Arc<UploadSessions>>,
+) -> Result<(StatusCode, Json<UploadSessionResponse>)> {
+    Ok((StatusCode::CREATED, Json(sessions.create().await?)))
+}
+
+/// Describe an upload session, listing the chunks received so far
+#[utoipa::path(
+    get,
+    path = "/ingest/sessions/{id}",
+    tag = "ingest",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "Session state", body = UploadSessionResponse),
+        (status = 404, description = "Unknown or expired session", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(sessions))]
+pub async fn get_session(
+    Extension(sessions): Extension<Arc<UploadSessions>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<UploadSessionResponse>> {
+    Ok(Json(sessions.get(id).await?))
+}
+
+/// Upload a chunk of newline-delimited `RawData` items to a session
+#[utoipa::path(
+    put,
+    path = "/ingest/sessions/{id}/chunks/{seq}",
+    tag = "ingest",
+    params(
+        ("id" = Uuid, Path, description = "Session ID"),
+        ("seq" = u32, Path, description = "Chunk number; chunks are published in order of their numbers")
+    ),
+    request_body(content = String, content_type = "application/x-ndjson", description = "One item per line"),
+    responses(
+        (status = 200, description = "Chunk stored", body = UploadSessionResponse),
+        (status = 400, description = "Invalid item in the chunk", body = ErrorResponse),
+        (status = 403, description = "An item's data classification forbids it", body = ErrorResponse),
+        (status = 404, description = "Unknown or expired session", body = ErrorResponse),
+        (status = 409, description = "The session is already committed", body = ErrorResponse),
+        (status = 413, description = "The chunk or session is too large", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(sessions, pipeline, body), fields(bytes = body.len()))]
+pub async fn put_chunk(
+    Extension(sessions): Extension<Arc<UploadSessions>>,
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Path((id, seq)): Path<(Uuid, u32)>,
+    body: Bytes,
+) -> Result<Json<UploadSessionResponse>> {
+    Ok(Json(sessions.put_chunk(&pipeline, id, seq, &body).await?))
+}
+
+/// Commit an upload session, ingesting its items as one batch
+#[utoipa::path(
+    post,
+    path = "/ingest/sessions/{id}/commit",
+    tag = "ingest",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 201, description = "Session committed; `ids` lists the items that were ingested", body = BatchIngestResponse),
+        (status = 400, description = "The session has no chunks", body = ErrorResponse),
+        (status = 404, description = "Unknown or expired session", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(sessions, pipeline))]
+pub async fn commit_session(
+    Extension(sessions): Extension<Arc<UploadSessions>>,
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Path(id): Path<Uuid>,
+) -> Result<(StatusCode, Json<BatchIngestResponse>)> {
+    Ok((
+        StatusCode::CREATED,
+        Json(sessions.commit(&pipeline, id).await?),
+    ))
+}
+
+/// Abort an upload session, discarding its chunks
+#[utoipa::path(
+    delete,
+    path = "/ingest/sessions/{id}",
+    tag = "ingest",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 204, description = "Session aborted"),
+        (status = 404, description = "Unknown or expired session", body = ErrorResponse)
+    )
+)]
+#[instrument(skip(sessions))]
+pub async fn abort_session(
+    Extension(sessions): Extension<Arc<UploadSessions>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode> {
+    sessions.abort(id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::BufferPool;
+    use crate::config::AppConfig;
+    use crate::flow::FlowControl;
+    use crate::nats::{NatsClient, SimulationMode};
+    use crate::readiness::Readiness;
+    use crate::sources::SourceRegistry;
+
+    /// A session directory removed when the test ends
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("upload-test-{}", Uuid::new_v4())))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn sessions(dir: &TempDir, max_items: usize) -> UploadSessions {
+        let config = UploadConfig {
+            dir: dir.0.clone(),
+            ttl: Duration::from_secs(60),
+            max_chunk_bytes: 1024,
+            max_items,
+        };
+        UploadSessions::open(config, None).await.unwrap()
+    }
+
+    async fn pipeline() -> Pipeline {
+        let config = Arc::new(AppConfig::from_env());
+        let nats_client = NatsClient::new(
+            Vec::new(),
+            Some(SimulationMode::Null),
+            None,
+            None,
+            1,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let nats_client = Arc::new(nats_client);
+        Pipeline::new(
+            config.clone(),
+            &nats_client,
+            nats_client.clone(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Arc::new(SourceRegistry::default()),
+            Arc::new(FlowControl::default()),
+            Arc::new(BufferPool::new(config.buffer_pool.clone())),
+            &Readiness::new(None),
+        )
+        .unwrap()
+    }
+
+    fn line(title: &str) -> String {
+        json!({"source": "crm", "content_type": "contact", "payload": {"title": title}}).to_string()
+    }
+
+    fn chunk(lines: &[String]) -> Vec<u8> {
+        lines.join("\n").into_bytes()
+    }
+
+    fn message(result: Result<UploadSessionResponse>) -> String {
+        match result {
+            Err(AppError::ValidationError(message)) => message,
+            other => panic!("expected the chunk to be rejected, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_chunks_with_malformed_lines() {
+        let dir = TempDir::new();
+        let sessions = sessions(&dir, 100).await;
+        let pipeline = pipeline().await;
+        let id = sessions.create().await.unwrap().id;
+
+        for (body, expected) in [
+            (
+                chunk(&[line("a"), "{\"source\": ".to_string()]),
+                "Line 2 of chunk 0",
+            ),
+            (
+                chunk(&[line("a"), "[1, 2]".to_string()]),
+                "Line 2 of chunk 0",
+            ),
+            (
+                chunk(&[
+                    json!({"source": "", "content_type": "contact", "payload": {}}).to_string(),
+                ]),
+                "Line 1 of chunk 0",
+            ),
+            (b"\xff\xfe\n".to_vec(), "Line 1 of chunk 0"),
+        ] {
+            let message = message(sessions.put_chunk(&pipeline, id, 0, &body).await);
+            assert!(message.starts_with(expected), "{}", message);
+        }
+
+        // A rejected chunk leaves nothing behind
+        let session = sessions.get(id).await.unwrap();
+        assert!(session.chunks.is_empty());
+        assert_eq!(session.items, 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_chunks_without_items() {
+        let dir = TempDir::new();
+        let sessions = sessions(&dir, 100).await;
+        let pipeline = pipeline().await;
+        let id = sessions.create().await.unwrap().id;
+
+        for body in [&b""[..], b"\n\n", b" \r\n\t\n"] {
+            let message = message(sessions.put_chunk(&pipeline, id, 3, body).await);
+            assert_eq!(message, "Chunk 3 contains no items");
+        }
+        assert!(matches!(
+            sessions.commit(&pipeline, id).await,
+            Err(AppError::ValidationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_chunks_over_the_session_item_limit() {
+        let dir = TempDir::new();
+        let sessions = sessions(&dir, 3).await;
+        let pipeline = pipeline().await;
+        let id = sessions.create().await.unwrap().id;
+
+        sessions
+            .put_chunk(&pipeline, id, 0, &chunk(&[line("a"), line("b")]))
+            .await
+            .unwrap();
+        assert!(matches!(
+            sessions
+                .put_chunk(&pipeline, id, 1, &chunk(&[line("c"), line("d")]))
+                .await,
+            Err(AppError::PayloadTooLarge(_))
+        ));
+
+        // Uploading a chunk again counts only its new items
+        let session = sessions
+            .put_chunk(&pipeline, id, 0, &chunk(&[line("a"), line("b"), line("c")]))
+            .await
+            .unwrap();
+        assert_eq!(session.chunks, vec![0]);
+        assert_eq!(session.items, 3);
+    }
+
+    #[tokio::test]
+    async fn rejects_chunks_for_unknown_and_committed_sessions() {
+        let dir = TempDir::new();
+        let sessions = sessions(&dir, 100).await;
+        let pipeline = pipeline().await;
+        let body = chunk(&[line("a")]);
+
+        assert!(matches!(
+            sessions
+                .put_chunk(&pipeline, Uuid::new_v4(), 0, &body)
+                .await,
+            Err(AppError::NotFound(_))
+        ));
+
+        let id = sessions.create().await.unwrap().id;
+        sessions.put_chunk(&pipeline, id, 0, &body).await.unwrap();
+        let committed = sessions.commit(&pipeline, id).await.unwrap();
+        assert_eq!(committed.count, 1);
+        assert!(matches!(
+            sessions.put_chunk(&pipeline, id, 1, &body).await,
+            Err(AppError::Duplicate(_))
+        ));
+        // A retried commit gets the original response
+        assert_eq!(
+            sessions.commit(&pipeline, id).await.unwrap().ids,
+            committed.ids
+        );
+    }
+
+    #[tokio::test]
+    async fn sweeps_sessions_with_malformed_state() {
+        let dir = TempDir::new();
+        let sessions = sessions(&dir, 100).await;
+        let id = sessions.create().await.unwrap().id;
+        std::fs::write(sessions.session_dir(id).join("session.json"), b"{not json").unwrap();
+
+        assert!(matches!(
+            sessions.get(id).await,
+            Err(AppError::InternalError(_))
+        ));
+        sessions.sweep().await.unwrap();
+        assert!(matches!(sessions.get(id).await, Err(AppError::NotFound(_))));
+        assert!(!sessions.session_dir(id).exists());
+    }
+}
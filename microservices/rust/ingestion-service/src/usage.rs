@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{Extension, Query},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{info, instrument, warn};
+
+use crate::error::{AppError, Result};
+use crate::pipeline::Pipeline;
+
+/// Length of the periods usage is accounted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BillingPeriod {
+    Day,
+    Month,
+}
+
+impl BillingPeriod {
+    /// Parse a period name as used in configuration
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "day" | "daily" => Some(Self::Day),
+            "month" | "monthly" => Some(Self::Month),
+            _ => None,
+        }
+    }
+
+    /// Key of the period containing `at`, e.g. `2026-10` or `2026-10-15`
+    fn key(self, at: DateTime<Utc>) -> String {
+        match self {
+            Self::Day => at.format("%Y-%m-%d").to_string(),
+            Self::Month => at.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// Settings for per-tenant usage accounting
+#[derive(Debug, Clone)]
+pub struct UsageConfig {
+    /// SQLite database file
+    pub path: PathBuf,
+
+    /// Length of a billing period
+    pub period: BillingPeriod,
+
+    /// How often counted usage is written to the database
+    pub flush_interval: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct UsageKey {
+    period: String,
+    tenant: String,
+    source: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct UsageTotals {
+    messages: u64,
+    bytes: u64,
+}
+
+/// Messages and bytes published for one source of a tenant in a billing period
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRecord {
+    pub period: String,
+
+    /// Absent for sources not assigned to a tenant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+
+    pub source: String,
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// Filter for reading usage back out of the ledger
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UsageQuery {
+    /// Only this billing period, e.g. `2026-10`
+    pub period: Option<String>,
+
+    /// Only sources of this tenant
+    pub tenant: Option<String>,
+
+    /// Only this source
+    pub source: Option<String>,
+}
+
+/// Persistent count of the messages and bytes published per tenant and source, per
+/// billing period, for chargeback.
+///
+/// Usage is counted in memory and added to the SQLite ledger every `flush_interval`, on
+/// every query and at shutdown, so a crash loses at most one interval of counts. Messages
+/// are counted once accepted for delivery, including spooled and buffered ones; bytes are
+/// the encoded message bodies.
+pub struct UsageLedger {
+    config: UsageConfig,
+    conn: Arc<Mutex<Connection>>,
+    pending: Mutex<BTreeMap<UsageKey, UsageTotals>>,
+}
+
+impl UsageLedger {
+    /// Open the ledger, creating the schema if needed
+    pub fn open(config: UsageConfig) -> Result<Self> {
+        if let Some(dir) = config
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(dir).map_err(usage_error)?;
+        }
+
+        let conn = Connection::open(&config.path).map_err(usage_error)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS usage (
+                 period TEXT NOT NULL,
+                 tenant TEXT NOT NULL,
+                 source TEXT NOT NULL,
+                 messages INTEGER NOT NULL,
+                 bytes INTEGER NOT NULL,
+                 PRIMARY KEY (period, tenant, source)
+             );",
+        )
+        .map_err(usage_error)?;
+
+        info!(
+            "Accounting usage per {:?} in {}",
+            config.period,
+            config.path.display()
+        );
+
+        Ok(Self {
+            config,
+            conn: Arc::new(Mutex::new(conn)),
+            pending: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Count published messages against a source and its tenant in the current period
+    pub fn record(&self, tenant: Option<&str>, source: &str, messages: u64, bytes: u64) {
+        let key = UsageKey {
+            period: self.config.period.key(Utc::now()),
+            tenant: tenant.unwrap_or_default().to_string(),
+            source: source.to_string(),
+        };
+
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let totals = pending.entry(key).or_default();
+        totals.messages += messages;
+        totals.bytes += bytes;
+    }
+
+    /// Add counted usage to the ledger
+    pub async fn flush(&self) -> Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()));
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.clone();
+        let written = pending.clone();
+        let result = tokio::task::spawn_blocking(move || -> rusqlite::Result<()> {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            let transaction = conn.transaction()?;
+            {
+                let mut upsert = transaction.prepare_cached(
+                    "INSERT INTO usage (period, tenant, source, messages, bytes) VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT (period, tenant, source) DO UPDATE SET
+                         messages = messages + excluded.messages,
+                         bytes = bytes + excluded.bytes",
+                )?;
+                for (key, totals) in &written {
+                    upsert.execute(params![
+                        key.period,
+                        key.tenant,
+                        key.source,
+                        totals.messages as i64,
+                        totals.bytes as i64
+                    ])?;
+                }
+            }
+            transaction.commit()
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Usage task failed: {}", e)))?
+        .map_err(usage_error);
+
+        // Keep the counts for the next flush rather than losing them
+        if result.is_err() {
+            let mut current = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            for (key, totals) in pending {
+                let entry = current.entry(key).or_default();
+                entry.messages += totals.messages;
+                entry.bytes += totals.bytes;
+            }
+        }
+
+        result
+    }
+
+    /// Spawn the background task that flushes counted usage every `flush_interval`
+    pub fn spawn_flusher(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.flush_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.flush().await {
+                    warn!("Failed to write usage, retrying next interval: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Read matching usage, flushing counted usage first so it is up to date
+    pub async fn query(&self, query: UsageQuery) -> Result<Vec<UsageRecord>> {
+        self.flush().await?;
+
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || -> rusqlite::Result<Vec<UsageRecord>> {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            let mut statement = conn.prepare(
+                "SELECT period, tenant, source, messages, bytes FROM usage
+                 WHERE (?1 IS NULL OR period = ?1)
+                   AND (?2 IS NULL OR tenant = ?2)
+                   AND (?3 IS NULL OR source = ?3)
+                 ORDER BY period, tenant, source",
+            )?;
+            let rows =
+                statement.query_map(params![query.period, query.tenant, query.source], |row| {
+                    let tenant: String = row.get(1)?;
+                    Ok(UsageRecord {
+                        period: row.get(0)?,
+                        tenant: Some(tenant).filter(|t| !t.is_empty()),
+                        source: row.get(2)?,
+                        messages: row.get::<_, i64>(3)? as u64,
+                        bytes: row.get::<_, i64>(4)? as u64,
+                    })
+                })?;
+            rows.collect()
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Usage task failed: {}", e)))?
+        .map_err(usage_error)
+    }
+}
+
+/// Query parameters for usage reports
+#[derive(Debug, Deserialize)]
+pub struct UsageParams {
+    #[serde(flatten)]
+    pub query: UsageQuery,
+
+    /// `json` (default) or `csv`
+    pub format: Option<String>,
+}
+
+/// Report usage per tenant and source as JSON or CSV
+#[instrument(skip(pipeline))]
+pub async fn usage_report(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Query(params): Query<UsageParams>,
+) -> Result<Response> {
+    let Some(usage) = pipeline.usage() else {
+        return Err(AppError::InternalError(
+            "Usage accounting is not enabled".to_string(),
+        ));
+    };
+
+    let csv = match params.format.as_deref().unwrap_or("json") {
+        "json" => false,
+        "csv" => true,
+        other => {
+            return Err(AppError::ValidationError(format!(
+                "Unknown usage format {}",
+                other
+            )))
+        }
+    };
+
+    let records = usage.query(params.query).await?;
+
+    if !csv {
+        return Ok(Json(json!({ "usage": records })).into_response());
+    }
+
+    let mut body = String::from("period,tenant,source,messages,bytes\n");
+    for record in &records {
+        body.push_str(&format!(
+            "{},{},{},{},{}\n",
+            csv_field(&record.period),
+            csv_field(record.tenant.as_deref().unwrap_or_default()),
+            csv_field(&record.source),
+            record.messages,
+            record.bytes
+        ));
+    }
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"usage.csv\"",
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Quote a CSV field when it holds a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn usage_error(e: impl std::fmt::Display) -> AppError {
+    AppError::InternalError(format!("Usage ledger error: {}", e))
+}
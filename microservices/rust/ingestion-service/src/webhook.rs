@@ -0,0 +1,440 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use async_nats::jetstream::kv::{Operation, Store};
+
+use axum::{
+    body::Bytes,
+    extract::Extension,
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::error::{AppError, Result};
+use crate::keys::{self, KeyRing, KeyRingConfig};
+use crate::logging::throttled;
+use crate::models::{IngestResponse, RawData};
+use crate::nats::NatsClient;
+use crate::pipeline::Pipeline;
+
+/// Settings for webhook receivers
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Secrets GitHub signs deliveries with; any unexpired one is accepted, so the secret
+    /// can be rotated on GitHub and here independently
+    pub github_secrets: KeyRingConfig,
+
+    /// Content type assigned to GitHub deliveries
+    pub github_content_type: String,
+
+    /// How long delivery IDs are remembered for replay protection
+    pub replay_ttl: Duration,
+
+    /// JetStream key-value bucket delivery IDs are recorded in, namespaced like stream names
+    pub replay_kv_bucket: String,
+}
+
+/// A delivery claimed by [`ReplayGuard::claim`]
+#[derive(Debug)]
+pub enum ReplayClaim {
+    /// Recorded in the shared bucket at this revision
+    Shared(u64),
+
+    /// Recorded in this replica only, as the bucket was unavailable
+    Local,
+}
+
+/// Remembers recently seen delivery IDs so retried deliveries are not ingested twice.
+///
+/// IDs are recorded in a JetStream key-value bucket whose entries expire after the replay
+/// TTL, so a retry is rejected whichever replica it reaches. Until the bucket is open, and
+/// without NATS or while the bucket cannot be reached, they are remembered by this replica
+/// alone.
+pub struct ReplayGuard {
+    kv: Arc<OnceLock<Store>>,
+    ttl: Duration,
+    local: Mutex<LocalReplays>,
+}
+
+/// Delivery IDs remembered in memory, with the order they expire in
+#[derive(Default)]
+struct LocalReplays {
+    seen: HashMap<Uuid, Instant>,
+    expiry: VecDeque<(Instant, Uuid)>,
+}
+
+impl ReplayGuard {
+    /// Create the guard, opening the bucket in the background so startup does not wait on
+    /// JetStream, which may not be enabled on the server
+    pub fn open(config: &WebhookConfig, nats_client: Arc<NatsClient>) -> Self {
+        let kv = Arc::new(OnceLock::new());
+        tokio::spawn({
+            let kv = kv.clone();
+            let (bucket, ttl) = (config.replay_kv_bucket.clone(), config.replay_ttl);
+            async move {
+                match nats_client.key_value(&bucket, ttl).await {
+                    Ok(Some(store)) => {
+                        info!("Recording webhook deliveries in key-value bucket {}", bucket);
+                        let _ = kv.set(store);
+                    }
+                    Ok(None) => warn!("Webhook deliveries are only checked for replays within each replica without NATS"),
+                    Err(e) => warn!("Webhook deliveries are only checked for replays within each replica: {}", e),
+                }
+            }
+        });
+
+        Self {
+            kv,
+            ttl: config.replay_ttl,
+            local: Mutex::new(LocalReplays::default()),
+        }
+    }
+
+    /// Claim a delivery, by the ID of the item it becomes, returning `None` if it was
+    /// already seen within the TTL
+    pub async fn claim(&self, id: Uuid) -> Option<ReplayClaim> {
+        let Some(kv) = self.kv.get() else {
+            return self.claim_local(id);
+        };
+
+        match claim_shared(kv, id).await {
+            Ok(claim) => claim,
+            Err(e) => {
+                throttled!(warn!(
+                    "Checking webhook delivery {} for replays within this replica: {}",
+                    id, e
+                ));
+                self.claim_local(id)
+            }
+        }
+    }
+
+    /// Forget a delivery so a later retry is accepted
+    pub async fn release(&self, id: Uuid, claim: &ReplayClaim) {
+        match (claim, self.kv.get()) {
+            (ReplayClaim::Shared(revision), Some(kv)) => {
+                // Overwritten at the claimed revision rather than deleted, so a claim another
+                // replica has taken over since is left alone
+                if let Err(e) = kv.update(id.to_string(), Bytes::new(), *revision).await {
+                    throttled!(warn!("Failed to release webhook delivery {}: {}", id, e));
+                }
+            }
+            _ => {
+                self.local
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .seen
+                    .remove(&id);
+            }
+        }
+    }
+
+    fn claim_local(&self, id: Uuid) -> Option<ReplayClaim> {
+        let mut local = self.local.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+
+        // Expire from the front only, as IDs are remembered in the order they were claimed
+        while let Some(&(at, expired)) = local.expiry.front() {
+            if now.duration_since(at) < self.ttl {
+                break;
+            }
+            local.expiry.pop_front();
+            // A released and reclaimed ID has a later entry of its own
+            if local.seen.get(&expired) == Some(&at) {
+                local.seen.remove(&expired);
+            }
+        }
+
+        if local.seen.contains_key(&id) {
+            return None;
+        }
+        local.seen.insert(id, now);
+        local.expiry.push_back((now, id));
+        Some(ReplayClaim::Local)
+    }
+}
+
+/// Claim a delivery in the shared bucket with compare-and-set, so of several replicas
+/// receiving it only one succeeds
+async fn claim_shared(kv: &Store, id: Uuid) -> Result<Option<ReplayClaim>> {
+    let key = id.to_string();
+    let entry = kv.entry(key.as_str()).await.map_err(kv_error)?;
+
+    // Revision the entry must still be at for the claim to succeed; zero when absent. A
+    // released delivery is left as an empty value.
+    let revision = match entry {
+        None => 0,
+        Some(entry) if entry.operation == Operation::Put && !entry.value.is_empty() => {
+            return Ok(None)
+        }
+        Some(entry) => entry.revision,
+    };
+
+    match kv
+        .update(key.as_str(), Bytes::from_static(b"1"), revision)
+        .await
+    {
+        Ok(revision) => Ok(Some(ReplayClaim::Shared(revision))),
+        // Another replica claimed it first
+        Err(e) => {
+            debug!("Claim on webhook delivery {} raced: {}", id, e);
+            Ok(None)
+        }
+    }
+}
+
+fn kv_error(e: impl std::fmt::Display) -> AppError {
+    AppError::NatsConnectionError(format!("Webhook delivery bucket unavailable: {}", e))
+}
+
+/// Receives webhook deliveries from external providers
+pub struct WebhookReceiver {
+    config: WebhookConfig,
+    github_secrets: Arc<KeyRing>,
+    replays: ReplayGuard,
+}
+
+impl WebhookReceiver {
+    /// Create a new receiver
+    pub fn new(config: WebhookConfig, replays: ReplayGuard) -> Result<Self> {
+        Ok(Self {
+            github_secrets: KeyRing::open(
+                "GitHub webhook",
+                config.github_secrets.clone(),
+                keys::any_key,
+            )?,
+            config,
+            replays,
+        })
+    }
+
+    /// Check the `X-Hub-Signature-256` HMAC of a GitHub delivery
+    fn verify_github_signature(&self, headers: &HeaderMap, body: &[u8]) -> Result<()> {
+        let signature = header(headers, "x-hub-signature-256")?
+            .strip_prefix("sha256=")
+            .and_then(|hex_signature| hex::decode(hex_signature).ok())
+            .ok_or_else(|| AppError::PolicyViolation("Malformed webhook signature".to_string()))?;
+
+        for secret in self.github_secrets.verifying() {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.secret.expose().as_bytes())
+                .map_err(|e| {
+                    AppError::InternalError(format!("Invalid webhook secret {}: {}", secret.id, e))
+                })?;
+            mac.update(body);
+            if mac.verify_slice(&signature).is_ok() {
+                return Ok(());
+            }
+        }
+
+        Err(AppError::PolicyViolation(
+            "Webhook signature does not match".to_string(),
+        ))
+    }
+}
+
+/// Receive a GitHub webhook delivery
+#[instrument(skip_all)]
+pub async fn github_webhook(
+    Extension(pipeline): Extension<Arc<Pipeline>>,
+    Extension(receiver): Extension<Arc<WebhookReceiver>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<IngestResponse>)> {
+    receiver.verify_github_signature(&headers, &body)?;
+
+    let event = header(&headers, "x-github-event")?.to_string();
+    let delivery_id = header(&headers, "x-github-delivery")?.to_string();
+    let replay_key = format!("github:{}", delivery_id);
+    // Derive the ID from the delivery so the item is stable across redeliveries
+    let id = Uuid::new_v5(&Uuid::NAMESPACE_OID, replay_key.as_bytes());
+
+    let Some(claim) = receiver.replays.claim(id).await else {
+        warn!("Rejected replayed GitHub delivery {}", delivery_id);
+        return Err(AppError::Duplicate(format!(
+            "Delivery {} was already received",
+            delivery_id
+        )));
+    };
+
+    let result = async {
+        let body: serde_json::Value = serde_json::from_slice(&body)
+            .map_err(|e| AppError::ValidationError(format!("Invalid webhook body: {}", e)))?;
+
+        let mut item = RawData::builder()
+            .id(id)
+            .source("github")
+            .content_type(receiver.config.github_content_type.clone())
+            .payload(json!({
+                "event": event,
+                "delivery_id": delivery_id,
+                "body": body,
+            }))
+            .build()?;
+
+        let acks = pipeline.process(&mut item).await?;
+        Ok((item.id, acks))
+    }
+    .await;
+
+    match result {
+        Ok((id, acks)) => {
+            debug!("Ingested GitHub {} delivery {}", event, delivery_id);
+            Ok((
+                StatusCode::CREATED,
+                Json(IngestResponse::published(id, &acks)),
+            ))
+        }
+        Err(e) => {
+            // Let GitHub's retry through since this attempt was not ingested
+            receiver.replays.release(id, &claim).await;
+            Err(e)
+        }
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| AppError::ValidationError(format!("Missing {} header", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::keys::KeyVersion;
+
+    fn replays(ttl: Duration) -> ReplayGuard {
+        ReplayGuard {
+            kv: Arc::new(OnceLock::new()),
+            ttl,
+            local: Mutex::new(LocalReplays::default()),
+        }
+    }
+
+    fn receiver(secrets: &[&str]) -> WebhookReceiver {
+        let config = WebhookConfig {
+            github_secrets: KeyRingConfig {
+                keys: secrets
+                    .iter()
+                    .map(|entry| KeyVersion::parse(entry).unwrap())
+                    .collect(),
+                file: None,
+                active: None,
+                refresh_interval: Duration::from_secs(60),
+            },
+            github_content_type: "github_event".to_string(),
+            replay_ttl: Duration::from_secs(60),
+            replay_kv_bucket: "WEBHOOK_DELIVERIES".to_string(),
+        };
+        WebhookReceiver::new(config, replays(Duration::from_secs(60))).unwrap()
+    }
+
+    fn signed(secret: &str, body: &[u8]) -> HeaderMap {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-hub-signature-256",
+            HeaderValue::from_str(&signature).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn accepts_deliveries_signed_with_the_secret() {
+        let receiver = receiver(&["current=s3cret"]);
+        let body = br#"{"action":"opened"}"#;
+
+        assert!(receiver
+            .verify_github_signature(&signed("s3cret", body), body)
+            .is_ok());
+        assert!(matches!(
+            receiver.verify_github_signature(&signed("s3cret", body), b"{\"action\":\"closed\"}"),
+            Err(AppError::PolicyViolation(_))
+        ));
+        assert!(matches!(
+            receiver.verify_github_signature(&signed("wrong", body), body),
+            Err(AppError::PolicyViolation(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_and_malformed_signatures() {
+        let receiver = receiver(&["current=s3cret"]);
+
+        assert!(matches!(
+            receiver.verify_github_signature(&HeaderMap::new(), b"{}"),
+            Err(AppError::ValidationError(_))
+        ));
+
+        for malformed in ["sha1=abcd", "sha256=not-hex", "abcd"] {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-hub-signature-256", HeaderValue::from_static(malformed));
+            assert!(matches!(
+                receiver.verify_github_signature(&headers, b"{}"),
+                Err(AppError::PolicyViolation(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn accepts_either_secret_during_rotation() {
+        let receiver = receiver(&["new=n3w", "old=0ld", "retired@2020-01-01T00:00:00Z=r3tired"]);
+        let body = b"{}";
+
+        assert!(receiver
+            .verify_github_signature(&signed("n3w", body), body)
+            .is_ok());
+        assert!(receiver
+            .verify_github_signature(&signed("0ld", body), body)
+            .is_ok());
+        assert!(receiver
+            .verify_github_signature(&signed("r3tired", body), body)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_deliveries() {
+        let guard = replays(Duration::from_secs(60));
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+        assert!(matches!(guard.claim(first).await, Some(ReplayClaim::Local)));
+        assert!(guard.claim(first).await.is_none());
+        assert!(guard.claim(second).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn released_deliveries_can_be_retried() {
+        let guard = replays(Duration::from_secs(60));
+        let id = Uuid::new_v4();
+
+        let claim = guard.claim(id).await.unwrap();
+        guard.release(id, &claim).await;
+        assert!(guard.claim(id).await.is_some());
+        assert!(guard.claim(id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn forgets_deliveries_after_the_ttl() {
+        let guard = replays(Duration::ZERO);
+        let id = Uuid::new_v4();
+
+        assert!(guard.claim(id).await.is_some());
+        assert!(guard.claim(id).await.is_some());
+
+        let local = guard.local.lock().unwrap();
+        assert_eq!(local.seen.len(), 1);
+        assert_eq!(local.expiry.len(), 1);
+    }
+}
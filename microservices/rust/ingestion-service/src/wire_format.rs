@@ -0,0 +1,448 @@
+//! Snapshot tests pinning the JSON wire format of NATS messages and HTTP responses.
+//!
+//! A failing snapshot here means consumers will see a different shape. Accept the change
+//! with `cargo insta review` only together with a migration note in `WIRE_FORMAT.md`.
+
+use std::collections::BTreeMap;
+
+use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use utoipa::OpenApi;
+use uuid::Uuid;
+
+use crate::audit_mirror::AuditRecord;
+use crate::buffers::{BufferPool, BufferPoolConfig};
+use crate::chunk::{self, ChunkConfig};
+use crate::cloudevents::{CloudEventsConfig, CloudEventsMode};
+use crate::dead_letter::DeadLetter;
+use crate::document::DocumentManifest;
+use crate::encoding::{RawDataProto, WireFormat};
+use crate::error::AppError;
+use crate::heartbeat::{Heartbeat, QueueDepths};
+use crate::models::{
+    BatchIngestResponse, BatchStatsResponse, DependencyCounters, DependencyStatus,
+    HealthDependencies, HealthResponse, IngestCounters, IngestResponse, NatsStats, RawData,
+    ReadyResponse, SchemaResponse, SequenceGap, StatsResponse,
+};
+use crate::negotiate::{self, ResponseFormat};
+use crate::offload::ObjectPointer;
+use crate::retraction::Retraction;
+
+fn fixed_id() -> Uuid {
+    Uuid::parse_str("6f1c2a9e-3b4d-4e5f-8a7b-9c0d1e2f3a4b").unwrap()
+}
+
+fn fixed_time() -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+fn sample_item() -> RawData {
+    RawData {
+        id: fixed_id(),
+        source: "arxiv".to_string(),
+        content_type: "research_paper".to_string(),
+        payload: json!({
+            "title": "Example Research Paper",
+            "text": "one two three four five",
+        }),
+        timestamp: fixed_time(),
+        metadata: json!({ "author": "Jane Doe" }),
+        partition_key: None,
+    }
+}
+
+fn encode(format: WireFormat) -> bytes::Bytes {
+    let buffers = BufferPool::new(BufferPoolConfig {
+        buffers: 1,
+        max_capacity: 1024,
+    });
+    format.encode(&sample_item(), &buffers, None).unwrap()
+}
+
+#[test]
+fn nats_message() {
+    let message: serde_json::Value = serde_json::from_slice(&encode(WireFormat::Json)).unwrap();
+    insta::assert_json_snapshot!(message);
+}
+
+#[test]
+fn nats_message_msgpack() {
+    let message: serde_json::Value =
+        rmp_serde::from_slice(&encode(WireFormat::MessagePack)).unwrap();
+    insta::assert_json_snapshot!(message);
+}
+
+#[test]
+fn nats_message_protobuf() {
+    use prost::Message;
+
+    let message = RawDataProto::decode(encode(WireFormat::Protobuf)).unwrap();
+    insta::assert_json_snapshot!(json!({
+        "id": message.id,
+        "source": message.source,
+        "content_type": message.content_type,
+        "payload": String::from_utf8(message.payload).unwrap(),
+        "timestamp": message.timestamp,
+        "metadata": String::from_utf8(message.metadata).unwrap(),
+        "schema_version": message.schema_version,
+    }));
+}
+
+fn cloudevent(mode: CloudEventsMode) -> (crate::nats::Headers, bytes::Bytes) {
+    let config = CloudEventsConfig {
+        mode,
+        type_prefix: "ingest.".to_string(),
+        source_prefix: "/ingestion/".to_string(),
+    };
+    let buffers = BufferPool::new(BufferPoolConfig {
+        buffers: 1,
+        max_capacity: 1024,
+    });
+    config
+        .encode(&sample_item(), WireFormat::Json, &buffers, None)
+        .unwrap()
+}
+
+#[test]
+fn nats_cloudevent_structured() {
+    let (headers, body) = cloudevent(CloudEventsMode::Structured);
+    let event: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    insta::assert_json_snapshot!(json!({ "headers": headers, "event": event }));
+}
+
+#[test]
+fn nats_cloudevent_binary() {
+    let (headers, body) = cloudevent(CloudEventsMode::Binary);
+    let data: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    insta::assert_json_snapshot!(json!({ "headers": headers, "data": data }));
+}
+
+#[test]
+fn nats_chunk_messages() {
+    let config = ChunkConfig {
+        content_types: vec!["research_paper".to_string()],
+        text_field: "text".to_string(),
+        size: 3,
+        overlap: 1,
+    };
+
+    let chunks = chunk::chunk_item(&sample_item(), &config).unwrap();
+    insta::assert_json_snapshot!(chunks);
+}
+
+#[test]
+fn nats_offload_pointer() {
+    insta::assert_json_snapshot!(ObjectPointer {
+        bucket: "INGEST_PAYLOADS".to_string(),
+        name: fixed_id().to_string(),
+        digest: Some("SHA-256=47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU=".to_string()),
+        size: 4_194_304,
+    });
+}
+
+#[test]
+fn nats_retraction() {
+    insta::assert_json_snapshot!(Retraction {
+        id: Retraction::id_for(fixed_id()),
+        item_id: fixed_id(),
+        source: Some("arxiv".to_string()),
+        content_type: Some("research_paper".to_string()),
+        reason: "GDPR erasure request".to_string(),
+        requested_by: Some("privacy@example.com".to_string()),
+        timestamp: fixed_time(),
+    });
+}
+
+#[test]
+fn nats_dead_letter() {
+    insta::assert_json_snapshot!(DeadLetter {
+        item: sample_item(),
+        subject: "ingest.raw.research_paper".to_string(),
+        error: "Failed to publish message to NATS: JetStream acknowledgement timed out".to_string(),
+        failed_at: fixed_time(),
+    });
+}
+
+#[test]
+fn nats_document_manifest() {
+    insta::assert_json_snapshot!(DocumentManifest {
+        document_id: "1706.03762".to_string(),
+        source: "arxiv".to_string(),
+        content_type: "research_paper".to_string(),
+        part_count: 2,
+        parts: vec![
+            fixed_id(),
+            Uuid::parse_str("0b8e5f7d-2c1a-4f3e-9d6b-7a5c4e3f2d1b").unwrap()
+        ],
+        first_seen: fixed_time(),
+        completed_at: fixed_time() + chrono::Duration::seconds(30),
+    });
+}
+
+#[test]
+fn nats_audit_record() {
+    insta::assert_json_snapshot!(AuditRecord {
+        item: sample_item(),
+        subject: "ingest.raw.research_paper".to_string(),
+        outcome: "published".to_string(),
+        request_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+        requester: Some("lab-gateway@example.org".to_string()),
+        accepted_at: fixed_time(),
+    });
+}
+
+#[test]
+fn nats_heartbeat() {
+    insta::assert_json_snapshot!(Heartbeat {
+        instance_id: "ingestion-service-7d9f8b6c5-x2k4q".to_string(),
+        service: "ingestion-service".to_string(),
+        version: "0.1.0".to_string(),
+        uptime_seconds: 3600,
+        interval_seconds: 15,
+        queues: QueueDepths {
+            spool_pending: 12,
+            republish_pending: 3,
+        },
+        timestamp: fixed_time(),
+    });
+}
+
+#[test]
+fn ingest_response() {
+    insta::assert_json_snapshot!(IngestResponse {
+        status: "success".to_string(),
+        id: fixed_id(),
+        timestamp: fixed_time(),
+        stream: None,
+        sequences: Vec::new(),
+        deliver_at: None,
+        sequence_gap: None,
+    });
+}
+
+#[test]
+fn ingest_response_jetstream() {
+    insta::assert_json_snapshot!(IngestResponse {
+        status: "success".to_string(),
+        id: fixed_id(),
+        timestamp: fixed_time(),
+        stream: Some("INGEST".to_string()),
+        sequences: vec![41, 42],
+        deliver_at: None,
+        sequence_gap: None,
+    });
+}
+
+#[test]
+fn ingest_response_scheduled() {
+    insta::assert_json_snapshot!(IngestResponse {
+        status: "scheduled".to_string(),
+        id: fixed_id(),
+        timestamp: fixed_time(),
+        stream: None,
+        sequences: Vec::new(),
+        deliver_at: Some(fixed_time() + chrono::Duration::hours(6)),
+        sequence_gap: None,
+    });
+}
+
+#[test]
+fn ingest_response_sequence_gap() {
+    insta::assert_json_snapshot!(IngestResponse {
+        status: "success".to_string(),
+        id: fixed_id(),
+        timestamp: fixed_time(),
+        stream: None,
+        sequences: Vec::new(),
+        deliver_at: None,
+        sequence_gap: Some(SequenceGap {
+            id: fixed_id(),
+            source: "arxiv".to_string(),
+            kind: "gap".to_string(),
+            expected: 42,
+            received: 45,
+        }),
+    });
+}
+
+fn ingest_response_in(format: ResponseFormat) -> Vec<u8> {
+    let response = IngestResponse {
+        status: "success".to_string(),
+        id: fixed_id(),
+        timestamp: fixed_time(),
+        stream: None,
+        sequences: Vec::new(),
+        deliver_at: None,
+        sequence_gap: None,
+    };
+    negotiate::encode(&serde_json::to_value(response).unwrap(), format).unwrap()
+}
+
+#[test]
+fn ingest_response_msgpack() {
+    let response: serde_json::Value =
+        rmp_serde::from_slice(&ingest_response_in(ResponseFormat::MessagePack)).unwrap();
+    insta::assert_json_snapshot!(response);
+}
+
+#[test]
+fn ingest_response_cbor() {
+    let response: serde_json::Value =
+        ciborium::from_reader(&ingest_response_in(ResponseFormat::Cbor)[..]).unwrap();
+    insta::assert_json_snapshot!(response);
+}
+
+#[test]
+fn batch_ingest_response() {
+    insta::assert_json_snapshot!(BatchIngestResponse {
+        status: "success".to_string(),
+        count: 1,
+        ids: vec![fixed_id()],
+        timestamp: fixed_time(),
+        deliver_at: None,
+        sequence_gaps: Vec::new(),
+    });
+}
+
+#[test]
+fn health_response() {
+    insta::assert_json_snapshot!(HealthResponse {
+        service: "ingestion-service".to_string(),
+        status: "operational".to_string(),
+        version: "0.0.0".to_string(),
+        timestamp: fixed_time(),
+        nats_server: Some("nats-1".to_string()),
+        dependencies: HealthDependencies {
+            nats: NatsStats {
+                state: "connected".to_string(),
+                server_name: Some("nats-1".to_string()),
+                server_version: Some("2.10.7".to_string()),
+                server_address: Some("10.0.0.7:4222".to_string()),
+                rtt_ms: Some(0.42),
+                reconnects: 1,
+                pending_bytes: 2048,
+            },
+        },
+    });
+}
+
+#[test]
+fn ready_response() {
+    insta::assert_json_snapshot!(ReadyResponse {
+        ready: false,
+        status: "unavailable".to_string(),
+        spool_pending: 42,
+        dependencies: BTreeMap::from([
+            (
+                "amqp".to_string(),
+                DependencyStatus {
+                    status: "down".to_string(),
+                    required: true,
+                    error: Some("disconnected".to_string()),
+                    since: fixed_time(),
+                },
+            ),
+            (
+                "postgres".to_string(),
+                DependencyStatus {
+                    status: "up".to_string(),
+                    required: false,
+                    error: None,
+                    since: fixed_time(),
+                },
+            ),
+        ]),
+        timestamp: fixed_time(),
+    });
+}
+
+#[test]
+fn stats_response() {
+    let counters = IngestCounters {
+        published: 3,
+        rejected: 1,
+        failed: 1,
+        spooled: 2,
+        dead_lettered: 1,
+        expired: 1,
+        sequence_gaps: 1,
+        missed_sequences: 3,
+        sequence_regressions: 0,
+    };
+
+    insta::assert_json_snapshot!(StatsResponse {
+        service: "ingestion-service".to_string(),
+        uptime_seconds: 60,
+        totals: counters.clone(),
+        by_content_type: BTreeMap::from([("research_paper".to_string(), counters.clone())]),
+        by_source: BTreeMap::from([("arxiv".to_string(), counters)]),
+        datagrams: None,
+        retention: BTreeMap::new(),
+        dependencies: BTreeMap::from([(
+            "amqp".to_string(),
+            DependencyCounters {
+                up: false,
+                outages: 2,
+                downtime_seconds: 12.5,
+            },
+        )]),
+        timestamp: fixed_time(),
+    });
+}
+
+#[test]
+fn batch_stats_response() {
+    insta::assert_json_snapshot!(BatchStatsResponse {
+        batch_id: "export-2024-01-02".to_string(),
+        totals: IngestCounters {
+            published: 998,
+            rejected: 2,
+            failed: 0,
+            spooled: 0,
+            dead_lettered: 0,
+            expired: 0,
+            sequence_gaps: 0,
+            missed_sequences: 0,
+            sequence_regressions: 0,
+        },
+        first_seen: fixed_time(),
+        last_seen: fixed_time(),
+        timestamp: fixed_time(),
+    });
+}
+
+#[test]
+fn schema_response() {
+    insta::assert_json_snapshot!(SchemaResponse {
+        version: "1.0".to_string(),
+        default_format: "json".to_string(),
+        formats: BTreeMap::from([("research_paper".to_string(), "avro".to_string())]),
+        cloudevents: None,
+        compatible: Some(true),
+        timestamp: fixed_time(),
+    });
+}
+
+#[tokio::test]
+async fn error_response() {
+    let response = AppError::ValidationError("Payload cannot be null".to_string()).into_response();
+    let status = response.status().as_u16();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    insta::assert_json_snapshot!(json!({ "status": status, "body": body }));
+}
+
+#[test]
+fn openapi_document() {
+    let mut document = serde_json::to_value(crate::openapi::ApiDoc::openapi()).unwrap();
+    // The version follows Cargo.toml and is not part of the contract
+    document["info"]["version"] = json!("<version>");
+
+    insta::assert_json_snapshot!(document);
+}